@@ -0,0 +1,115 @@
+//! Accounts loaded from a keystore directory, looked up by address.
+
+use std::{
+    fs::{self, File},
+    path::Path,
+};
+
+use tracing::info;
+
+use crate::{keystore, Address, EthAccount, Password, Result};
+
+/// Set of loaded accounts, e.g. of a multi-account provider
+pub struct AccountSet {
+    accounts: Vec<Box<EthAccount>>,
+}
+
+impl AccountSet {
+    pub fn new(accounts: Vec<Box<EthAccount>>) -> Self {
+        AccountSet { accounts }
+    }
+
+    /// Loads every `*.json` keystore in `dir`; all of them have to be encrypted with `password`
+    pub fn load_dir<P, W>(dir: P, password: W) -> Result<Self>
+    where
+        P: AsRef<Path>,
+        W: Into<Password>,
+    {
+        let password = password.into();
+        let mut accounts = Vec::new();
+
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if !path.is_file() || path.extension().map_or(true, |ext| ext != "json") {
+                continue;
+            }
+            let (key_file, name) =
+                keystore::parse_labeled(serde_json::from_reader(File::open(&path)?)?)?;
+            let secret = key_file.to_secret_key(&password)?;
+            let mut account = EthAccount::from_secret(secret, Some(fs::canonicalize(&path)?));
+            account.set_name(name);
+            accounts.push(account);
+        }
+
+        info!(
+            count = accounts.len(),
+            "loaded {} accounts from {}",
+            accounts.len(),
+            dir.as_ref().display()
+        );
+        Ok(AccountSet::new(accounts))
+    }
+
+    /// Account with given address. Every loaded address is compared in constant time,
+    /// so the lookup time does not depend on which one, if any, matches.
+    pub fn by_address(&self, address: &Address) -> Option<&EthAccount> {
+        let mut found = None;
+        for account in self.accounts.iter() {
+            if account.address().ct_eq(address) {
+                found = Some(account.as_ref());
+            }
+        }
+        found
+    }
+
+    pub fn len(&self) -> usize {
+        self.accounts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &EthAccount> {
+        self.accounts.iter().map(|account| account.as_ref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::tempdir;
+
+    use super::AccountSet;
+    use crate::{Address, EthAccount};
+
+    #[test]
+    fn should_find_account_by_address() {
+        let dir = tempdir().unwrap();
+        let accounts: Vec<_> = (0..3)
+            .map(|i| {
+                EthAccount::load_or_generate(dir.path().join(format!("key-{}.json", i)), "pwd")
+                    .unwrap()
+            })
+            .collect();
+        fs::write(dir.path().join("README"), "not a keystore").unwrap();
+
+        let set = AccountSet::load_dir(dir.path(), "pwd").unwrap();
+        assert_eq!(set.len(), 3);
+
+        let found = set.by_address(accounts[1].address()).unwrap();
+        assert_eq!(found.address(), accounts[1].address());
+        assert_eq!(found.kestore_path(), accounts[1].kestore_path());
+
+        assert!(set.by_address(&Address::from([0u8; 20])).is_none());
+    }
+
+    #[test]
+    fn should_fail_loading_with_wrong_password() {
+        let dir = tempdir().unwrap();
+        EthAccount::load_or_generate(dir.path().join("key.json"), "pwd").unwrap();
+
+        assert!(AccountSet::load_dir(dir.path(), "other").is_err());
+    }
+}
@@ -1,13 +1,81 @@
-use rustc_hex::ToHex;
-use std::fmt;
+use rustc_hex::{FromHex, ToHex};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    str::FromStr,
+};
+use subtle::ConstantTimeEq;
+
+use crate::{keccak256, Error};
 
 /// Ethereum address
+///
+/// Derived `PartialEq` is not constant-time; use `ct_eq` to compare with secret-dependent values.
+#[derive(Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Address([u8; 20]);
 
 impl Address {
+    /// Derives address from uncompressed public key, without the `0x04` prefix
+    pub fn from_public(public: &[u8; 64]) -> Self {
+        Address::from(&keccak256(public)[12..])
+    }
+
     pub fn to_vec(&self) -> Vec<u8> {
         self.0.to_vec()
     }
+
+    /// Constant-time equality; compares all bytes regardless of where they differ
+    pub fn ct_eq(&self, other: &Address) -> bool {
+        self.0[..].ct_eq(&other.0[..]).into()
+    }
+
+    /// Hex digits in [EIP-55] mixed-case checksum encoding, without `0x`
+    ///
+    /// [EIP-55]: https://eips.ethereum.org/EIPS/eip-55
+    pub(crate) fn checksum_hex(&self) -> String {
+        let hex: String = self.0.to_hex();
+        let hash = keccak256(hex.as_bytes());
+
+        hex.chars()
+            .enumerate()
+            .map(|(i, ch)| {
+                let nibble = (hash[i / 2] >> if i % 2 == 0 { 4 } else { 0 }) & 0x0f;
+                if nibble >= 8 {
+                    ch.to_ascii_uppercase()
+                } else {
+                    ch
+                }
+            })
+            .collect()
+    }
+}
+
+/// Accepts 40 hex digits with optional `0x` prefix;
+/// mixed-case input must have valid EIP-55 checksum.
+impl FromStr for Address {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = if s.starts_with("0x") { &s[2..] } else { s };
+        if hex.len() != 40 {
+            return Err(Error::InvalidAddress(format!(
+                "{}: expected 40 hex digits",
+                s
+            )));
+        }
+
+        let bytes: Vec<u8> = hex
+            .from_hex()
+            .map_err(|e| Error::InvalidAddress(format!("{}: {}", s, e)))?;
+        let address = Address::from(bytes.as_ref());
+
+        let mixed_case = hex.chars().any(|ch| ch.is_ascii_lowercase())
+            && hex.chars().any(|ch| ch.is_ascii_uppercase());
+        if mixed_case && address.checksum_hex() != hex {
+            return Err(Error::InvalidAddress(format!("{}: invalid checksum", s)));
+        }
+        Ok(address)
+    }
 }
 
 impl From<[u8; 20]> for Address {
@@ -42,10 +110,74 @@ impl fmt::Debug for Address {
     }
 }
 
+/// Memoized [`Address::from_public`] for hot paths deriving addresses of the same keys,
+/// e.g. ones recovered from many signatures.
+///
+/// Holds at most `capacity` entries; the earliest inserted is evicted first.
+pub struct AddressCache {
+    capacity: usize,
+    entries: HashMap<[u8; 64], Address>,
+    order: VecDeque<[u8; 64]>,
+    hits: u64,
+    misses: u64,
+}
+
+impl AddressCache {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+
+        AddressCache {
+            capacity,
+            entries: HashMap::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn address(&mut self, public: &[u8; 64]) -> Address {
+        if let Some(address) = self.entries.get(public) {
+            self.hits += 1;
+            return address.clone();
+        }
+
+        self.misses += 1;
+        if self.order.len() == self.capacity {
+            if let Some(evicted) = self.order.pop_front() {
+                self.entries.remove(&evicted);
+            }
+        }
+        let address = Address::from_public(public);
+        self.entries.insert(*public, address.clone());
+        self.order.push_back(*public);
+        address
+    }
+
+    /// Number of lookups served from cache
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Number of lookups that derived the address
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::Address;
+    use super::AddressCache;
+    use crate::{keccak256, Address, Error, SecretKey};
     use rustc_hex::FromHex;
+    use std::collections::{BTreeSet, HashSet};
 
     #[test]
     fn should_convert_to_vec() {
@@ -63,6 +195,35 @@ mod tests {
         assert_eq!(&raw, addr.as_ref());
     }
 
+    #[test]
+    fn should_dedup_in_sets() {
+        let raw = [1, 2, 3, 4, 5, 6, 7, 8, 9, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0];
+        let addresses = [Address::from(raw), Address::from(raw)];
+
+        let hashed: HashSet<_> = addresses.iter().cloned().collect();
+        let ordered: BTreeSet<_> = addresses.iter().cloned().collect();
+
+        assert_eq!(hashed.len(), 1);
+        assert_eq!(ordered.len(), 1);
+        assert!(Address::from([0u8; 20]) < Address::from(raw));
+    }
+
+    #[test]
+    fn should_compare_in_constant_time() {
+        let raw = [1, 2, 3, 4, 5, 6, 7, 8, 9, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0];
+        let mut other = raw;
+        other[19] = 1;
+
+        let addr = Address::from(raw);
+        let same = Address::from(raw);
+        let different = Address::from(other);
+
+        assert!(addr.ct_eq(&same));
+        assert_eq!(addr.ct_eq(&same), addr.as_ref() == same.as_ref());
+        assert!(!addr.ct_eq(&different));
+        assert_eq!(addr.ct_eq(&different), addr.as_ref() == different.as_ref());
+    }
+
     #[test]
     fn should_have_display_impl() {
         let raw: Vec<u8> = "60f0dc62f0fac30a5beee9ac998590026923aa79"
@@ -88,4 +249,82 @@ mod tests {
             "Address(0x60f0dc62f0fac30a5beee9ac998590026923aa79)"
         );
     }
+
+    #[test]
+    fn should_parse_lowercase() {
+        let addr: Address = "60f0dc62f0fac30a5beee9ac998590026923aa79".parse().unwrap();
+
+        assert_eq!(
+            format!("{}", addr),
+            "0x60f0dc62f0fac30a5beee9ac998590026923aa79"
+        );
+        let prefixed: Address = "0x60f0dc62f0fac30a5beee9ac998590026923aa79"
+            .parse()
+            .unwrap();
+        assert!(addr.ct_eq(&prefixed));
+    }
+
+    #[test]
+    fn should_parse_checksummed() {
+        // examples from https://eips.ethereum.org/EIPS/eip-55
+        for s in &[
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+            "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+            "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+        ] {
+            let addr: Address = s.parse().unwrap();
+            assert_eq!(format!("0x{}", addr.checksum_hex()), *s);
+        }
+    }
+
+    #[test]
+    fn should_reject_wrong_length() {
+        for s in &["0x60f0dc62f0fac30a5beee9ac998590026923aa", "", "0x"] {
+            match s.parse::<Address>() {
+                Err(Error::InvalidAddress(_)) => (),
+                r => panic!("unexpected result for {:?}: {:?}", s, r),
+            }
+        }
+    }
+
+    #[test]
+    fn should_reject_bad_checksum() {
+        match "0x5aaeb6053F3E94C9b9A09f33669435E7Ef1BeAed".parse::<Address>() {
+            Err(Error::InvalidAddress(_)) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn should_derive_from_public() {
+        let secret = SecretKey::from_raw(&keccak256(b"cow")).unwrap();
+        let public = secret.public();
+
+        assert!(Address::from_public(public.bytes()).ct_eq(&public.address().as_ref().into()));
+    }
+
+    #[test]
+    fn should_serve_repeated_derivation_from_cache() {
+        let first = *SecretKey::from_raw(&keccak256(b"cow"))
+            .unwrap()
+            .public()
+            .bytes();
+        let second = *SecretKey::from_raw(&keccak256(b"horse"))
+            .unwrap()
+            .public()
+            .bytes();
+        let mut cache = AddressCache::new(1);
+
+        for _ in 0..100 {
+            assert!(cache.address(&first).ct_eq(&Address::from_public(&first)));
+        }
+        assert_eq!(cache.misses(), 1);
+        assert_eq!(cache.hits(), 99);
+
+        // evicts `first`
+        cache.address(&second);
+        assert_eq!(cache.len(), 1);
+        cache.address(&first);
+        assert_eq!(cache.misses(), 3);
+    }
 }
@@ -0,0 +1,346 @@
+//! [EIP-712] typed structured data hashing, compatible with `eth_signTypedData_v4`.
+//!
+//! [EIP-712]: https://eips.ethereum.org/EIPS/eip-712
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use rustc_hex::FromHex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{keccak256, Error, Message, Result};
+
+const DOMAIN_TYPE: &str = "EIP712Domain";
+
+/// Single member of a struct type
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemberType {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_name: String,
+}
+
+/// Typed data as passed to `eth_signTypedData_v4`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypedData {
+    pub types: BTreeMap<String, Vec<MemberType>>,
+    pub primary_type: String,
+    pub domain: Value,
+    pub message: Value,
+}
+
+fn error<T>(msg: String) -> Result<T> {
+    Err(Error::Eip712Error(msg))
+}
+
+/// Element type of an array type, e.g. `Person` for `Person[]` or `uint8[3]`
+fn array_element(type_name: &str) -> Option<&str> {
+    if type_name.ends_with(']') {
+        type_name.rfind('[').map(|pos| &type_name[..pos])
+    } else {
+        None
+    }
+}
+
+fn base_type(mut type_name: &str) -> &str {
+    while let Some(element) = array_element(type_name) {
+        type_name = element;
+    }
+    type_name
+}
+
+fn from_hex(value: &Value) -> Result<Vec<u8>> {
+    match value.as_str() {
+        Some(s) if s.starts_with("0x") => s[2..]
+            .from_hex()
+            .or_else(|e| error(format!("invalid hex value {}: {}", s, e))),
+        _ => error(format!("expected hex string, got {}", value)),
+    }
+}
+
+/// Parses decimal digits into big-endian 256 bit word
+fn parse_decimal(digits: &str) -> Option<[u8; 32]> {
+    let mut word = [0u8; 32];
+    if digits.is_empty() {
+        return None;
+    }
+
+    for ch in digits.chars() {
+        let mut carry = ch.to_digit(10)?;
+        for byte in word.iter_mut().rev() {
+            let v = u32::from(*byte) * 10 + carry;
+            *byte = v as u8;
+            carry = v >> 8;
+        }
+        if carry != 0 {
+            return None;
+        }
+    }
+    Some(word)
+}
+
+fn negate(word: &mut [u8; 32]) {
+    let mut carry = 1u16;
+    for byte in word.iter_mut().rev() {
+        let v = u16::from(!*byte) + carry;
+        *byte = v as u8;
+        carry = v >> 8;
+    }
+}
+
+fn encode_integer(value: &Value) -> Result<[u8; 32]> {
+    let text = match value {
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s.clone(),
+        _ => return error(format!("expected integer, got {}", value)),
+    };
+
+    if text.starts_with("0x") {
+        let bytes = from_hex(value)?;
+        if bytes.len() > 32 {
+            return error(format!("integer {} too big", text));
+        }
+        let mut word = [0u8; 32];
+        word[32 - bytes.len()..].copy_from_slice(&bytes);
+        return Ok(word);
+    }
+
+    let (negative, digits) = match text.starts_with('-') {
+        true => (true, &text[1..]),
+        false => (false, &text[..]),
+    };
+    let mut word = match parse_decimal(digits) {
+        Some(word) => word,
+        None => return error(format!("invalid integer {}", text)),
+    };
+    if negative {
+        negate(&mut word);
+    }
+    Ok(word)
+}
+
+fn encode_atomic(type_name: &str, value: &Value) -> Result<[u8; 32]> {
+    let mut word = [0u8; 32];
+
+    match type_name {
+        "address" => {
+            let bytes = from_hex(value)?;
+            if bytes.len() != 20 {
+                return error(format!("invalid address {}", value));
+            }
+            word[12..].copy_from_slice(&bytes);
+        }
+        "bool" => match value {
+            Value::Bool(b) => word[31] = *b as u8,
+            _ => return error(format!("expected bool, got {}", value)),
+        },
+        t if t.starts_with("bytes") => {
+            let bytes = from_hex(value)?;
+            if bytes.len() > 32 {
+                return error(format!("{} value too long", t));
+            }
+            word[..bytes.len()].copy_from_slice(&bytes);
+        }
+        t if t.starts_with("uint") || t.starts_with("int") => word = encode_integer(value)?,
+        t => return error(format!("unsupported type {}", t)),
+    }
+    Ok(word)
+}
+
+impl TypedData {
+    fn members(&self, type_name: &str) -> Result<&Vec<MemberType>> {
+        match self.types.get(type_name) {
+            Some(members) => Ok(members),
+            None => error(format!("unknown type {}", type_name)),
+        }
+    }
+
+    fn dependencies<'a>(&'a self, type_name: &'a str, found: &mut BTreeSet<&'a str>) {
+        let type_name = base_type(type_name);
+        if found.contains(type_name) {
+            return;
+        }
+        if let Some(members) = self.types.get(type_name) {
+            found.insert(type_name);
+            for member in members {
+                self.dependencies(&member.type_name, found);
+            }
+        }
+    }
+
+    /// Encodes type with all its referenced struct types,
+    /// e.g. `Mail(Person from,Person to,string contents)Person(string name,address wallet)`
+    pub fn encode_type(&self, type_name: &str) -> Result<String> {
+        let mut deps = BTreeSet::new();
+        self.dependencies(type_name, &mut deps);
+        deps.remove(type_name);
+
+        let mut encoded = String::new();
+        for name in Some(type_name).into_iter().chain(deps.into_iter()) {
+            let members: Vec<String> = self
+                .members(name)?
+                .iter()
+                .map(|m| format!("{} {}", m.type_name, m.name))
+                .collect();
+            encoded.push_str(&format!("{}({})", name, members.join(",")));
+        }
+        Ok(encoded)
+    }
+
+    pub fn type_hash(&self, type_name: &str) -> Result<[u8; 32]> {
+        Ok(keccak256(self.encode_type(type_name)?.as_bytes()))
+    }
+
+    fn encode_value(&self, type_name: &str, value: &Value) -> Result<[u8; 32]> {
+        if let Some(element) = array_element(type_name) {
+            let items = match value.as_array() {
+                Some(items) => items,
+                None => return error(format!("expected array for {}", type_name)),
+            };
+            let mut encoded = Vec::with_capacity(items.len() * 32);
+            for item in items {
+                encoded.extend_from_slice(&self.encode_value(element, item)?);
+            }
+            return Ok(keccak256(&encoded));
+        }
+
+        match type_name {
+            "string" => match value.as_str() {
+                Some(s) => Ok(keccak256(s.as_bytes())),
+                None => error(format!("expected string, got {}", value)),
+            },
+            "bytes" => Ok(keccak256(&from_hex(value)?)),
+            t if self.types.contains_key(t) => self.hash_struct(t, value),
+            t => encode_atomic(t, value),
+        }
+    }
+
+    /// Encodes struct members, each as 32 byte word
+    pub fn encode_data(&self, type_name: &str, value: &Value) -> Result<Vec<u8>> {
+        let mut encoded = self.type_hash(type_name)?.to_vec();
+
+        for member in self.members(type_name)? {
+            let field = match value.get(&member.name) {
+                Some(field) => field,
+                None => return error(format!("missing field {}.{}", type_name, member.name)),
+            };
+            encoded.extend_from_slice(&self.encode_value(&member.type_name, field)?);
+        }
+        Ok(encoded)
+    }
+
+    pub fn hash_struct(&self, type_name: &str, value: &Value) -> Result<[u8; 32]> {
+        Ok(keccak256(&self.encode_data(type_name, value)?))
+    }
+
+    pub fn domain_separator(&self) -> Result<[u8; 32]> {
+        self.hash_struct(DOMAIN_TYPE, &self.domain)
+    }
+
+    /// Message to be signed: `keccak256("\x19\x01" ‖ domainSeparator ‖ hashStruct(message))`
+    pub fn hash(&self) -> Result<Message> {
+        let mut data = vec![0x19, 0x01];
+        data.extend_from_slice(&self.domain_separator()?);
+        data.extend_from_slice(&self.hash_struct(&self.primary_type, &self.message)?);
+        Ok(keccak256(&data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethsign::SecretKey;
+    use rustc_hex::ToHex;
+
+    use super::TypedData;
+    use crate::keccak256;
+
+    // example from https://eips.ethereum.org/EIPS/eip-712
+    fn mail() -> TypedData {
+        serde_json::from_str(include_str!("../res/eip712-mail.json")).unwrap()
+    }
+
+    #[test]
+    fn should_encode_nested_type() {
+        let data = mail();
+
+        assert_eq!(
+            data.encode_type("Mail").unwrap(),
+            "Mail(Person from,Person to,string contents)Person(string name,address wallet)"
+        );
+        assert_eq!(
+            data.type_hash("Mail").unwrap().to_hex::<String>(),
+            "a0cedeb2dc280ba39b857546d74f5549c3a1d7bdc2dd96bf881f76108e23dac2"
+        );
+    }
+
+    #[test]
+    fn should_hash_example() {
+        let data = mail();
+
+        assert_eq!(
+            data.hash_struct("Mail", &data.message)
+                .unwrap()
+                .to_hex::<String>(),
+            "c52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e"
+        );
+        assert_eq!(
+            data.domain_separator().unwrap().to_hex::<String>(),
+            "f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f"
+        );
+        assert_eq!(
+            data.hash().unwrap().to_hex::<String>(),
+            "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"
+        );
+    }
+
+    #[test]
+    fn should_sign_example() {
+        let secret = SecretKey::from_raw(&keccak256(b"cow")).unwrap();
+        let sig = secret.sign(&mail().hash().unwrap()).unwrap();
+
+        assert_eq!(
+            sig.r.to_hex::<String>(),
+            "4355c47d63924e8a72e509b65029052eb6c299d53a04e167c5775fd466751c9d"
+        );
+        assert_eq!(
+            sig.s.to_hex::<String>(),
+            "07299936d304c153f6443dfa05f40ff007d72911b6f72307f996231605b91562"
+        );
+        // 28 in Ethereum notation
+        assert_eq!(sig.v, 1);
+    }
+
+    #[test]
+    fn should_hash_dynamic_bytes_and_arrays() {
+        let data: TypedData = serde_json::from_value(serde_json::json!({
+            "types": {
+                "EIP712Domain": [{"name": "name", "type": "string"}],
+                "Blob": [
+                    {"name": "data", "type": "bytes"},
+                    {"name": "tags", "type": "string[]"}
+                ]
+            },
+            "primaryType": "Blob",
+            "domain": {"name": "test"},
+            "message": {"data": "0x0102", "tags": ["a", "b"]}
+        }))
+        .unwrap();
+
+        let encoded = data.encode_data("Blob", &data.message).unwrap();
+        let mut tags = keccak256(b"a").to_vec();
+        tags.extend_from_slice(&keccak256(b"b"));
+
+        assert_eq!(encoded.len(), 96);
+        assert_eq!(&encoded[32..64], &keccak256(&[1, 2])[..]);
+        assert_eq!(&encoded[64..], &keccak256(&tags)[..]);
+    }
+
+    #[test]
+    fn should_reject_missing_field() {
+        let mut data = mail();
+        data.message.as_object_mut().unwrap().remove("contents");
+
+        assert!(data.hash().is_err());
+    }
+}
@@ -10,4 +10,16 @@ pub enum Error {
     Secp256k1Error(#[from] secp256k1::Error),
     #[error("Serde JSON error: {0}")]
     SerdeJsonError(#[from] serde_json::Error),
+    #[error("EIP-712 error: {0}")]
+    Eip712Error(String),
+    #[error("Invalid signature: {0}")]
+    InvalidSignature(String),
+    #[error("Invalid message length: {0}, expected 32 bytes")]
+    InvalidMessageLength(usize),
+    #[error("Invalid address: {0}")]
+    InvalidAddress(String),
+    #[error("Invalid secret key: {0}")]
+    InvalidSecretKey(String),
+    #[error("Invalid public key: {0}")]
+    InvalidPublicKey(String),
 }
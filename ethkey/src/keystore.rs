@@ -0,0 +1,237 @@
+//! Keystore file metadata, readable without the password.
+
+use std::{fs::File, path::Path};
+
+use ethsign::keyfile::KeyFile;
+use rustc_hex::ToHex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{Address, Result, KEYSTORE_VERSION};
+
+/// Cipher parameters of the encrypted key
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CipherParams {
+    pub iv: String,
+}
+
+/// Public part of a keystore file
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct KeystoreMeta {
+    pub id: String,
+    pub version: u64,
+    /// hex encoded, without `0x` prefix; not present in all keystore formats
+    pub address: Option<String>,
+    pub kdf: String,
+    pub cipher: String,
+    pub cipher_params: CipherParams,
+    /// whether the keystore holds a MAC of the encrypted key
+    pub has_mac: bool,
+}
+
+#[derive(Deserialize)]
+struct RawCrypto {
+    cipher: String,
+    cipherparams: CipherParams,
+    kdf: String,
+    #[serde(default)]
+    mac: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RawKeystore {
+    id: String,
+    version: u64,
+    #[serde(alias = "Crypto")]
+    crypto: RawCrypto,
+    #[serde(default)]
+    address: Option<String>,
+}
+
+/// Reads keystore metadata without decrypting the key
+pub fn inspect<P: AsRef<Path>>(file_path: P) -> Result<KeystoreMeta> {
+    let raw: RawKeystore = serde_json::from_reader(File::open(file_path)?)?;
+
+    Ok(KeystoreMeta {
+        id: raw.id,
+        version: raw.version,
+        address: raw.address,
+        kdf: raw.crypto.kdf,
+        cipher: raw.crypto.cipher,
+        cipher_params: raw.crypto.cipherparams,
+        has_mac: raw.crypto.mac.is_some(),
+    })
+}
+
+/// Form of the `address` field written to keystores
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressFormat {
+    /// lowercase hex of the address bytes, as geth writes it
+    Raw,
+    /// [EIP-55] mixed-case checksum hex, for display tools expecting it
+    ///
+    /// [EIP-55]: https://eips.ethereum.org/EIPS/eip-55
+    Checksummed,
+}
+
+impl Default for AddressFormat {
+    fn default() -> Self {
+        AddressFormat::Raw
+    }
+}
+
+impl AddressFormat {
+    /// `address` field value, without `0x` prefix
+    pub(crate) fn format(self, address: &Address) -> String {
+        match self {
+            AddressFormat::Raw => address.to_vec().to_hex(),
+            AddressFormat::Checksummed => address.checksum_hex(),
+        }
+    }
+}
+
+/// Parses keystore JSON, also returning its optional `name` label. The label is
+/// a non-standard extra field, as written by parity; geth ignores it.
+/// A checksummed `address` is verified and accepted as well as a raw one.
+pub(crate) fn parse_labeled(mut json: Value) -> Result<(KeyFile, Option<String>)> {
+    let name = json.get("name").and_then(Value::as_str).map(str::to_string);
+    if let Some(address) = json.get("address").and_then(Value::as_str) {
+        let address: Address = address.parse()?;
+        json["address"] = AddressFormat::Raw.format(&address).into();
+    }
+    Ok((serde_json::from_value(json)?, name))
+}
+
+/// Software a keystore file was most likely written by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeystoreFlavor {
+    Geth,
+    Parity,
+    Pyethereum,
+    Unknown,
+}
+
+impl KeystoreFlavor {
+    /// Flavors recognized by `detect_flavor`
+    pub fn supported() -> &'static [KeystoreFlavor] {
+        &[
+            KeystoreFlavor::Geth,
+            KeystoreFlavor::Parity,
+            KeystoreFlavor::Pyethereum,
+        ]
+    }
+}
+
+/// Guesses the keystore flavor from its file name and structure:
+///   * geth names files `UTC--<time>--<address>` and uses scrypt with the address stored,
+///   * parity adds `name` and `meta` fields,
+///   * pyethereum adds `version` to the `crypto` section.
+///
+/// Returns `Unknown` for keystores not matching any of them; fails only for
+/// unreadable files or invalid JSON.
+pub fn detect_flavor<P: AsRef<Path>>(file_path: P) -> Result<KeystoreFlavor> {
+    let path = file_path.as_ref();
+    let json: Value = serde_json::from_reader(File::open(path)?)?;
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("");
+
+    Ok(flavor_of(file_name, &json))
+}
+
+fn flavor_of(file_name: &str, json: &Value) -> KeystoreFlavor {
+    let crypto = match json.get("crypto").or_else(|| json.get("Crypto")) {
+        Some(crypto) if json["version"].as_u64() == Some(KEYSTORE_VERSION) => crypto,
+        _ => return KeystoreFlavor::Unknown,
+    };
+
+    if file_name.starts_with("UTC--") {
+        KeystoreFlavor::Geth
+    } else if json.get("name").is_some() || json.get("meta").is_some() {
+        KeystoreFlavor::Parity
+    } else if crypto.get("version").is_some() {
+        KeystoreFlavor::Pyethereum
+    } else if crypto["kdf"] == "scrypt" && json.get("address").is_some() {
+        KeystoreFlavor::Geth
+    } else {
+        KeystoreFlavor::Unknown
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::tempdir;
+
+    use super::{detect_flavor, inspect, CipherParams, KeystoreFlavor};
+
+    #[test]
+    fn should_inspect_parity_keystore() {
+        let meta = inspect("res/parity-keystore.json").unwrap();
+
+        assert_eq!(meta.id, "22a3741e-07b1-0cb0-80de-e6c56c17b276");
+        assert_eq!(meta.version, 3);
+        assert_eq!(
+            meta.address,
+            Some("005b3bcf82085eededd551f50de7892471ffb272".into())
+        );
+        assert_eq!(meta.kdf, "pbkdf2");
+        assert_eq!(meta.cipher, "aes-128-ctr");
+        assert_eq!(
+            meta.cipher_params,
+            CipherParams {
+                iv: "4aa67ba65757583f02e450c9c48c7404".into()
+            }
+        );
+        assert!(meta.has_mac);
+    }
+
+    #[test]
+    fn should_detect_flavor_of_bundled_keystores() {
+        assert_eq!(
+            detect_flavor("res/geth-keystore.json").unwrap(),
+            KeystoreFlavor::Geth
+        );
+        assert_eq!(
+            detect_flavor("res/parity-keystore.json").unwrap(),
+            KeystoreFlavor::Parity
+        );
+        assert_eq!(
+            detect_flavor("res/pyethereum-keystore.json").unwrap(),
+            KeystoreFlavor::Pyethereum
+        );
+        assert_eq!(
+            detect_flavor("res/eip712-mail.json").unwrap(),
+            KeystoreFlavor::Unknown
+        );
+        assert!(detect_flavor("res/no-such-keystore.json").is_err());
+    }
+
+    #[test]
+    fn should_detect_geth_by_file_name() {
+        let dir = tempdir().unwrap();
+        let path = dir
+            .path()
+            .join("UTC--2019-05-06T10-00-00.000000000Z--005b3bcf82085eededd551f50de7892471ffb272");
+        let mut json: serde_json::Value =
+            serde_json::from_str(include_str!("../res/parity-keystore.json")).unwrap();
+        json.as_object_mut().unwrap().remove("name");
+        json.as_object_mut().unwrap().remove("meta");
+        fs::write(&path, json.to_string()).unwrap();
+
+        assert_eq!(detect_flavor(&path).unwrap(), KeystoreFlavor::Geth);
+        assert!(!KeystoreFlavor::supported().contains(&KeystoreFlavor::Unknown));
+    }
+
+    #[test]
+    fn should_inspect_keystore_without_address() {
+        let meta = inspect("res/pyethereum-keystore.json").unwrap();
+
+        assert_eq!(meta.address, None);
+        assert_eq!(meta.kdf, "pbkdf2");
+        assert!(meta.has_mac);
+    }
+}
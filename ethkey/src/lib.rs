@@ -3,17 +3,32 @@
 //! ## Features
 //!   * random key pair generation
 //!   * key serialization/deserialization
-//!   * keystore password change
-//!   * signing and verification
+//!   * keystore password change and re-encryption with fresh KDF parameters
+//!   * keystore metadata inspection without password
+//!   * detection of the keystore flavor (geth, parity, pyethereum)
+//!   * account label kept in the keystore `name` field, as parity does
+//!   * keystore `address` written raw or [EIP-55] checksummed
+//!   * lookup of accounts loaded from a keystore directory by address
+//!   * signing and verification, also of 65 bytes encoded signatures
+//!   * public keys as 65 bytes `0x04`-prefixed or bare 64 bytes
+//!   * `v` encoded as 0/1, 27/28 or with [EIP-155] chain id
+//!   * [EIP-191] personal message signing, e.g. for login challenges
+//!   * [EIP-712] typed structured data signing
+//!   * signing bound to an application context string, see `context_message`
+//!   * `Signer` trait for other signing backends, e.g. hardware wallets (`external-signer` feature)
 //!
 //! [geth]: https://github.com/ethereum/go-ethereum
 //! [parity]: https://github.com/paritytech/parity-ethereum
 //! [pyethereum]: https://github.com/ethereum/pyethereum
+//! [EIP-712]: https://eips.ethereum.org/EIPS/eip-712
+//! [EIP-191]: https://eips.ethereum.org/EIPS/eip-191
+//! [EIP-155]: https://eips.ethereum.org/EIPS/eip-155
+//! [EIP-55]: https://eips.ethereum.org/EIPS/eip-55
 //!
 //! ## Usage
 //! ```toml
 //! [dependencies]
-//! ethkey = "0.3"
+//! ethkey = "0.4"
 //! ```
 //!
 //! ## Example
@@ -50,14 +65,27 @@ use ethsign::{
     Protected,
 };
 pub use ethsign::{PublicKey, SecretKey, Signature};
-use log::info;
-use rand::{thread_rng, RngCore};
+use rand::{rngs::OsRng, RngCore};
+use tracing::info;
 
-pub use address::Address;
+pub use accounts::AccountSet;
+pub use address::{Address, AddressCache};
 
+mod accounts;
 mod address;
+pub mod eip712;
 mod error;
+mod keystore;
+mod signature;
+mod signer;
 pub use error::Error;
+pub use keystore::{
+    detect_flavor, inspect, AddressFormat, CipherParams, KeystoreFlavor, KeystoreMeta,
+};
+pub use signature::{FormattedSignature, RecoveryId, SignatureExt, VFormat};
+#[cfg(feature = "external-signer")]
+pub use signer::ExternalSigner;
+pub use signer::Signer;
 
 pub type Result<T> = std::result::Result<T, error::Error>;
 
@@ -70,17 +98,28 @@ pub type Password = Protected;
 pub const KEY_ITERATIONS: u32 = 10240;
 pub const KEYSTORE_VERSION: u64 = 3;
 
+/// Order of the secp256k1 curve, big-endian; valid secret keys are in `1..SECP256K1_ORDER`
+pub const SECP256K1_ORDER: [u8; 32] = [
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+    0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36, 0x41, 0x41,
+];
+
 /// An Ethereum Account keys with store.
 /// Allows to generate a new key pair and save it to disk as well as read existing keyfile.
 /// Provides `sign` and `verify` operations for [ECC] on curve [Secp256k1].
 ///
 /// [ECC]: https://blog.cloudflare.com/a-relatively-easy-to-understand-primer-on-elliptic-curve-cryptography/
 /// [Secp256k1]: https://en.bitcoin.it/wiki/Secp256k1
+///
+/// Cloning duplicates the secret key; every copy overwrites its own key in memory on drop.
+#[derive(Clone)]
 pub struct EthAccount {
     secret: SecretKey,
     public: PublicKey,
     address: Address,
-    kestore_path: PathBuf,
+    kestore_path: Option<PathBuf>,
+    name: Option<String>,
+    address_format: AddressFormat,
 }
 
 impl EthAccount {
@@ -94,9 +133,26 @@ impl EthAccount {
         &self.address
     }
 
-    /// Key store path
-    pub fn kestore_path(&self) -> &PathBuf {
-        &self.kestore_path
+    /// Key store path; `None` for accounts not backed by a file.
+    /// Returns an `Option` since 0.4.
+    pub fn kestore_path(&self) -> Option<&PathBuf> {
+        self.kestore_path.as_ref()
+    }
+
+    /// Human readable label of the account, read from the keystore `name` field
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_ref().map(String::as_str)
+    }
+
+    /// sets the label; it is written to the keystore by `change_password` and `rekey`
+    pub fn set_name(&mut self, name: Option<String>) {
+        self.name = name;
+    }
+
+    /// sets the form of the keystore `address` field written by `change_password` and `rekey`;
+    /// raw by default, for geth compatibility
+    pub fn set_address_format(&mut self, format: AddressFormat) {
+        self.address_format = format;
     }
 
     /// signs given message with self secret key
@@ -104,11 +160,81 @@ impl EthAccount {
         self.secret.sign(msg).map_err(|err| err.into())
     }
 
+    /// signs given message, encoding the recovery id as `v` in given format
+    pub fn sign_with_v_format(&self, msg: &Message, format: VFormat) -> Result<FormattedSignature> {
+        FormattedSignature::new(&self.sign(msg)?, format)
+    }
+
+    /// signs given message slice; fails unless it is exactly 32 bytes long
+    pub fn sign_slice(&self, msg: &[u8]) -> Result<Signature> {
+        self.sign(&to_message(msg)?)
+    }
+
+    /// signs [EIP-712] typed structured data with self secret key
+    ///
+    /// [EIP-712]: https://eips.ethereum.org/EIPS/eip-712
+    pub fn sign_typed_data(&self, data: &eip712::TypedData) -> Result<Signature> {
+        self.sign(&data.hash()?)
+    }
+
+    /// signs arbitrary data as an [EIP-191] personal message, see `personal_message`
+    ///
+    /// [EIP-191]: https://eips.ethereum.org/EIPS/eip-191
+    pub fn sign_personal(&self, data: &[u8]) -> Result<Signature> {
+        self.sign(&personal_message(data))
+    }
+
+    /// signs data bound to an application `context`, see `context_message`;
+    /// the signature does not verify for the same data under other context
+    pub fn sign_with_context(&self, context: &str, data: &[u8]) -> Result<Signature> {
+        self.sign(&context_message(context, data))
+    }
+
+    /// signs a server-issued login `nonce` as a personal message; returns the signer
+    /// address with the 65 bytes `r ‖ s ‖ v` signature, verifiable by `verify_encoded`
+    /// against `personal_message(nonce)`
+    pub fn sign_login(&self, nonce: &[u8]) -> Result<(Address, [u8; 65])> {
+        let sig = self.sign_personal(nonce)?;
+        Ok((self.address.clone(), sig.to_rsv()))
+    }
+
     /// verifies signature for given message and self public key
     pub fn verify(&self, sig: &Signature, msg: &Message) -> Result<bool> {
         Ok(self.public.verify(sig, msg)?)
     }
 
+    /// verifies signature of data signed by `sign_with_context` under given `context`
+    pub fn verify_with_context(&self, sig: &Signature, context: &str, data: &[u8]) -> Result<bool> {
+        self.verify(sig, &context_message(context, data))
+    }
+
+    /// verifies many signatures with self public key, reusing one verification context;
+    /// results are the same as of `verify` called for each pair
+    pub fn verify_batch(&self, items: &[(Signature, Message)]) -> Result<Vec<bool>> {
+        use secp256k1::ecdsa::{RecoverableSignature, RecoveryId};
+
+        let secp = secp256k1::Secp256k1::verification_only();
+        items
+            .iter()
+            .map(|(sig, msg)| {
+                let mut compact = [0u8; 64];
+                compact[..32].copy_from_slice(&sig.r);
+                compact[32..].copy_from_slice(&sig.s);
+                let sig = RecoverableSignature::from_compact(
+                    &compact,
+                    RecoveryId::from_i32(i32::from(sig.v))?,
+                )?;
+                let signer = secp.recover_ecdsa(&secp256k1::Message::from_slice(msg)?, &sig)?;
+                Ok(signer.serialize_uncompressed()[1..] == self.public.bytes()[..])
+            })
+            .collect()
+    }
+
+    /// verifies signature for given message slice; fails unless it is exactly 32 bytes long
+    pub fn verify_slice(&self, sig: &Signature, msg: &[u8]) -> Result<bool> {
+        self.verify(sig, &to_message(msg)?)
+    }
+
     /// reads keys from disk or generates new ones and stores to disk; password needed
     pub fn load_or_generate<P, W>(file_path: P, password: W) -> Result<Box<Self>>
     where
@@ -116,40 +242,154 @@ impl EthAccount {
         W: Into<Password>,
     {
         let pwd = password.into();
-        let (secret, log_msg) = match File::open(&file_path) {
+        let (secret, name, event, log_msg) = match File::open(&file_path) {
             Ok(file) => {
-                let key_file: KeyFile = serde_json::from_reader(file)?;
+                let (key_file, name) = keystore::parse_labeled(serde_json::from_reader(file)?)?;
                 let secret = key_file.to_secret_key(&pwd)?;
-                (secret, "loaded")
+                (secret, name, "loaded", "loaded")
             }
             Err(_e) => {
-                let secret = SecretKey::from_raw(&random_bytes())?;
-                save_key(&secret, &file_path, pwd)?;
-                (secret, "generated and saved")
+                let secret = generate_secret();
+                save_key(
+                    &secret,
+                    &file_path,
+                    pwd,
+                    KEY_ITERATIONS,
+                    None,
+                    AddressFormat::default(),
+                )?;
+                (secret, None, "generated", "generated and saved")
             }
         };
 
-        let eth_account = EthAccount {
+        let mut eth_account = Self::from_secret(secret, Some(::std::fs::canonicalize(file_path)?));
+        eth_account.name = name;
+
+        info!(
+            address = %eth_account.address,
+            event,
+            "eth account {} {}",
+            eth_account,
+            log_msg
+        );
+
+        Ok(eth_account)
+    }
+
+    /// builds account from given secret key, deriving its public key and address;
+    /// `path` is where keys are stored on `change_password`, if any
+    pub fn from_secret(secret: SecretKey, path: Option<PathBuf>) -> Box<Self> {
+        Box::new(EthAccount {
             address: secret.public().address().as_ref().into(),
             public: secret.public(),
             secret,
-            kestore_path: ::std::fs::canonicalize(file_path)?,
-        };
+            kestore_path: path,
+            name: None,
+            address_format: AddressFormat::default(),
+        })
+    }
+
+    /// checks whether `password` decrypts the keystore at `file_path`, e.g. for login forms;
+    /// runs the KDF and MAC check without deriving the public key and address.
+    /// Returns `Ok(false)` on MAC mismatch; fails only for unreadable or malformed files.
+    pub fn check_password<P, W>(file_path: P, password: W) -> Result<bool>
+    where
+        P: AsRef<Path>,
+        W: Into<Password>,
+    {
+        let (key_file, _) =
+            keystore::parse_labeled(serde_json::from_reader(File::open(file_path)?)?)?;
+        match key_file.to_secret_key(&password.into()) {
+            Ok(_) => Ok(true),
+            Err(ethsign::Error::InvalidPassword) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
 
-        info!("eth account {} {}", eth_account, log_msg);
+    /// decrypts keys from keystore JSON held in memory, e.g. taken from an env var;
+    /// the account is not backed by a file
+    pub fn from_json_str<W: Into<Password>>(json: &str, password: W) -> Result<Box<Self>> {
+        let (key_file, name) = keystore::parse_labeled(serde_json::from_str(json)?)?;
+        let secret = key_file.to_secret_key(&password.into())?;
 
-        Ok(Box::new(eth_account))
+        let mut eth_account = Self::from_secret(secret, None);
+        eth_account.name = name;
+
+        info!(
+            address = %eth_account.address,
+            event = "loaded",
+            "eth account {} loaded from JSON",
+            eth_account
+        );
+
+        Ok(eth_account)
     }
 
     /// stores keys on disk with changed password
     pub fn change_password<W: Into<Password>>(&self, new_password: W) -> Result<()> {
-        save_key(&self.secret, &self.kestore_path, new_password.into())?;
-        info!("changed password for {}", self);
+        let path = self.keystore_path_or_err()?;
+        save_key(
+            &self.secret,
+            path,
+            new_password.into(),
+            KEY_ITERATIONS,
+            self.name(),
+            self.address_format,
+        )?;
+        info!(
+            address = %self.address,
+            event = "password_changed",
+            "changed password for {}",
+            self
+        );
         Ok(())
     }
+
+    /// re-encrypts keys on disk with the same password, but fresh salt and iv;
+    /// `iterations` defaults to `KEY_ITERATIONS`.
+    /// Fails if `password` does not decrypt the current keystore.
+    pub fn rekey<W: Into<Password>>(&self, password: W, iterations: Option<u32>) -> Result<()> {
+        let path = self.keystore_path_or_err()?;
+        let pwd = password.into();
+        let (key_file, _) = keystore::parse_labeled(serde_json::from_reader(File::open(path)?)?)?;
+        key_file.to_secret_key(&pwd)?;
+
+        let iterations = iterations.unwrap_or(KEY_ITERATIONS);
+        save_key(
+            &self.secret,
+            path,
+            pwd,
+            iterations,
+            self.name(),
+            self.address_format,
+        )?;
+        info!(
+            address = %self.address,
+            event = "rekeyed",
+            iterations,
+            "re-encrypted keystore for {}",
+            self
+        );
+        Ok(())
+    }
+
+    fn keystore_path_or_err(&self) -> Result<&PathBuf> {
+        Ok(self.kestore_path.as_ref().ok_or(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "Account has no keystore path",
+        ))?)
+    }
 }
 
-fn save_key<P, W>(secret: &SecretKey, file_path: &P, password: W) -> Result<()>
+/// writes the keystore; `name` is stored as an extra field, see `keystore::parse_labeled`
+fn save_key<P, W>(
+    secret: &SecretKey,
+    file_path: &P,
+    password: W,
+    iterations: u32,
+    name: Option<&str>,
+    address_format: AddressFormat,
+) -> Result<()>
 where
     P: AsRef<Path>,
     W: Into<Password>,
@@ -157,7 +397,7 @@ where
     let key_file = KeyFile {
         id: format!("{}", uuid::Uuid::new_v4()),
         version: KEYSTORE_VERSION,
-        crypto: secret.to_crypto(&password.into(), KEY_ITERATIONS)?,
+        crypto: secret.to_crypto(&password.into(), iterations)?,
         address: Some(Bytes(secret.public().address().to_vec())),
     };
     let parent_dir = file_path.as_ref().parent().ok_or(std::io::Error::new(
@@ -168,33 +408,201 @@ where
         info!("Creating dir {:?} for key file.", parent_dir);
         std::fs::create_dir_all(parent_dir)?
     }
-    serde_json::to_writer_pretty(&File::create(&file_path)?, &key_file)?;
+    let mut json = serde_json::to_value(&key_file)?;
+    json["address"] = address_format
+        .format(&secret.public().address().as_ref().into())
+        .into();
+    if let Some(name) = name {
+        json["name"] = name.into();
+    }
+    serde_json::to_writer_pretty(&File::create(&file_path)?, &json)?;
     Ok(())
 }
 
+/// Builds a message for signing by hashing given data with keccak256
+pub fn keccak256(data: &[u8]) -> Message {
+    tiny_keccak::keccak256(data)
+}
+
+/// Builds a message for signing personal data:
+/// `keccak256("\x19Ethereum Signed Message:\n" ‖ len(data) ‖ data)`
+pub fn personal_message(data: &[u8]) -> Message {
+    let mut prefixed = format!("\x19Ethereum Signed Message:\n{}", data.len()).into_bytes();
+    prefixed.extend_from_slice(data);
+    keccak256(&prefixed)
+}
+
+/// Builds a message for signing data bound to an application context, so that
+/// a signature made for one application cannot be replayed in another:
+/// `keccak256(len(context) ‖ context ‖ data)`, where `len(context)` is the byte
+/// length of the UTF-8 encoded context as 8 bytes big-endian. The length prefix
+/// keeps e.g. context `"ab"` with data `"c"` apart from context `"a"` with data `"bc"`.
+pub fn context_message(context: &str, data: &[u8]) -> Message {
+    let mut bytes = Vec::with_capacity(8 + context.len() + data.len());
+    bytes.extend_from_slice(&(context.len() as u64).to_be_bytes());
+    bytes.extend_from_slice(context.as_bytes());
+    bytes.extend_from_slice(data);
+    keccak256(&bytes)
+}
+
+/// Recovers address of the signer of given personal message, e.g. signed by a wallet's
+/// `personal_sign`; the EIP-191 prefix is applied to `data` before recovery.
+pub fn recover_personal(sig: &Signature, data: &[u8]) -> Result<Address> {
+    let public = sig
+        .recover(&personal_message(data))
+        .map_err(|e| Error::InvalidSignature(format!("{:?}", e)))?;
+    Ok(public.address().as_ref().into())
+}
+
+/// Verifies signature encoded as 65 bytes `r ‖ s ‖ v` against the signer address;
+/// `v` may be given as 0/1 or in Ethereum notation (27/28).
+/// Returns `Ok(false)` if the message was signed with other key.
+pub fn verify_encoded(addr: &Address, sig_bytes: &[u8; 65], msg: &Message) -> Result<bool> {
+    let v = match sig_bytes[64] {
+        v @ 0..=1 => v,
+        v @ 27..=28 => v - 27,
+        v => return Err(Error::InvalidSignature(format!("invalid v value {}", v))),
+    };
+    let mut r = [0u8; 32];
+    let mut s = [0u8; 32];
+    r.copy_from_slice(&sig_bytes[..32]);
+    s.copy_from_slice(&sig_bytes[32..64]);
+
+    let public = Signature { v, r, s }
+        .recover(msg)
+        .map_err(|e| Error::InvalidSignature(format!("{:?}", e)))?;
+    let signer: Address = public.address().as_ref().into();
+    Ok(signer.ct_eq(addr))
+}
+
+fn to_message(msg: &[u8]) -> Result<Message> {
+    if msg.len() != 32 {
+        return Err(Error::InvalidMessageLength(msg.len()));
+    }
+    let mut message = [0u8; 32];
+    message.copy_from_slice(msg);
+    Ok(message)
+}
+
+/// Fills 32 bytes from the operating system's CSPRNG
 fn random_bytes() -> [u8; 32] {
     let mut secret = [0u8; 32];
-    thread_rng().fill_bytes(&mut secret);
+    OsRng::new()
+        .expect("OS random number generator unavailable")
+        .fill_bytes(&mut secret);
     secret
 }
 
+/// Checks that big-endian `raw` secret is non-zero and below the curve order
+pub fn is_valid_secret(raw: &[u8; 32]) -> bool {
+    // arrays compare lexicographically, i.e. as big-endian numbers
+    raw != &[0u8; 32] && raw < &SECP256K1_ORDER
+}
+
+/// Imports raw 32 bytes secret, rejecting values out of `1..SECP256K1_ORDER`
+pub fn secret_from_raw(raw: &[u8]) -> Result<SecretKey> {
+    if raw.len() != 32 {
+        return Err(Error::InvalidSecretKey(format!(
+            "expected 32 bytes, got {}",
+            raw.len()
+        )));
+    }
+    let mut bytes = [0u8; 32];
+    bytes.copy_from_slice(raw);
+    if !is_valid_secret(&bytes) {
+        return Err(Error::InvalidSecretKey("out of curve order range".into()));
+    }
+    Ok(SecretKey::from_raw(&bytes)?)
+}
+
+/// Uncompressed public key with the `0x04` prefix, as 65 bytes expected by most
+/// secp256k1 libraries; `PublicKey::bytes` gives it without the prefix
+pub fn public_uncompressed_prefixed(public: &PublicKey) -> [u8; 65] {
+    let mut prefixed = [0x04u8; 65];
+    prefixed[1..].copy_from_slice(public.bytes());
+    prefixed
+}
+
+/// Imports uncompressed public key given as 65 bytes with the `0x04` prefix or bare 64 bytes,
+/// rejecting points not on the curve
+pub fn public_from_slice(raw: &[u8]) -> Result<PublicKey> {
+    let bare = match raw.len() {
+        65 if raw[0] == 0x04 => &raw[1..],
+        65 => {
+            return Err(Error::InvalidPublicKey(format!(
+                "invalid prefix 0x{:02x}",
+                raw[0]
+            )))
+        }
+        64 => raw,
+        len => {
+            return Err(Error::InvalidPublicKey(format!(
+                "expected 64 or 65 bytes, got {}",
+                len
+            )))
+        }
+    };
+    let mut prefixed = [0x04u8; 65];
+    prefixed[1..].copy_from_slice(bare);
+    secp256k1::PublicKey::from_slice(&prefixed)
+        .map_err(|e| Error::InvalidPublicKey(e.to_string()))?;
+
+    PublicKey::from_slice(bare).map_err(|e| Error::InvalidPublicKey(format!("{:?}", e)))
+}
+
+/// Generates a new secret key from the operating system's CSPRNG,
+/// without storing it anywhere.
+pub fn generate_secret() -> SecretKey {
+    loop {
+        // out of curve order bytes are astronomically unlikely, yet possible
+        if let Ok(secret) = secret_from_raw(&random_bytes()) {
+            return secret;
+        }
+    }
+}
+
+/// Secret key of 1, stored over dropped keys; zero is not a valid secret key
+const WIPED_SECRET: [u8; 32] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+];
+
+/// Overwrites the key material of `secret` in place
+fn wipe_secret(secret: &mut SecretKey) {
+    let wiped = SecretKey::from_raw(&WIPED_SECRET).expect("1 is a valid secret key");
+    // `SecretKey` keeps the key inline, with nothing to release; the volatile
+    // write is not optimized out, even though the value is never read again
+    unsafe { std::ptr::write_volatile(secret, wiped) };
+    std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+}
+
+impl Drop for EthAccount {
+    fn drop(&mut self) {
+        wipe_secret(&mut self.secret);
+    }
+}
+
 impl fmt::Display for EthAccount {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> std::result::Result<(), fmt::Error> {
-        write!(
-            fmt,
-            "EthAccount address: {}, path: {:?}",
-            self.address(),
-            self.kestore_path
-        )
+        match self.kestore_path {
+            Some(ref path) => write!(
+                fmt,
+                "EthAccount address: {}, path: {:?}",
+                self.address(),
+                path
+            ),
+            None => write!(fmt, "EthAccount address: {}", self.address()),
+        }
     }
 }
 
 impl fmt::Debug for EthAccount {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> std::result::Result<(), fmt::Error> {
-        fmt.debug_struct("EthAccount")
-            .field("public", &self.public)
-            .field("file_path", &self.kestore_path)
-            .finish()
+        let mut debug = fmt.debug_struct("EthAccount");
+        debug.field("public", &self.public);
+        if let Some(ref path) = self.kestore_path {
+            debug.field("file_path", path);
+        }
+        debug.finish()
     }
 }
 
@@ -207,7 +615,7 @@ pub mod prelude {
     //!
     //! The prelude may grow over time.
 
-    pub use super::{Address, EthAccount, Password, PublicKey, SecretKey, Signature};
+    pub use super::{Address, EthAccount, Password, PublicKey, SecretKey, Signature, SignatureExt};
 }
 
 #[cfg(test)]
@@ -215,10 +623,11 @@ mod tests {
     use std::{env, fs::File, path::PathBuf};
 
     use ethsign::keyfile::KeyFile;
-    use rustc_hex::ToHex;
+    use rustc_hex::{FromHex, ToHex};
     use tempfile::tempdir;
 
     use crate::prelude::*;
+    use crate::{AddressFormat, KEY_ITERATIONS};
 
     fn tmp_path() -> PathBuf {
         let mut dir = tempdir().unwrap().into_path();
@@ -304,6 +713,60 @@ mod tests {
         assert_eq!(key_file.version, 3);
     }
 
+    #[test]
+    fn should_round_trip_keystore_name() {
+        // given
+        let path = tmp_path();
+        let mut key = EthAccount::load_or_generate(&path, "pwd").unwrap();
+        assert_eq!(key.name(), None);
+
+        // when
+        key.set_name(Some("work".into()));
+        key.change_password("pwd").unwrap();
+
+        // then
+        let key_file: KeyFile = serde_json::from_reader(File::open(&path).unwrap()).unwrap();
+        assert!(key_file.to_secret_key(&"pwd".into()).is_ok());
+
+        let reloaded = EthAccount::load_or_generate(&path, "pwd").unwrap();
+        assert_eq!(reloaded.name(), Some("work"));
+        assert_eq!(reloaded.address(), key.address());
+
+        let parity = EthAccount::load_or_generate("res/parity-keystore.json", "").unwrap();
+        assert_eq!(parity.name(), Some("Account 1"));
+    }
+
+    #[test]
+    fn should_read_back_raw_and_checksummed_address() {
+        // given
+        let path = tmp_path();
+        let mut key = EthAccount::load_or_generate(&path, "pwd").unwrap();
+        let stored_address = || {
+            let json: serde_json::Value =
+                serde_json::from_reader(File::open(&path).unwrap()).unwrap();
+            json["address"].as_str().unwrap().to_string()
+        };
+        let raw = stored_address();
+        assert_eq!(raw, raw.to_lowercase());
+
+        // when
+        key.set_address_format(AddressFormat::Checksummed);
+        key.change_password("pwd").unwrap();
+
+        // then
+        let checksummed = stored_address();
+        assert_eq!(checksummed, key.address().checksum_hex());
+        assert_eq!(checksummed.to_lowercase(), raw);
+        assert_eq!(
+            format!("0x{}", checksummed).parse::<Address>().unwrap(),
+            *key.address()
+        );
+
+        let reloaded = EthAccount::load_or_generate(&path, "pwd").unwrap();
+        assert_eq!(reloaded.address(), key.address());
+        assert!(EthAccount::check_password(&path, "pwd").unwrap());
+    }
+
     #[test]
     fn should_read_keystore_generated_by_geth() {
         // when
@@ -343,6 +806,54 @@ mod tests {
         assert_eq!(key.public().bytes().to_hex::<String>(), "12e612f62a244e31c45b5bb3a99ec6c40e5a6c94d741352d3ea3aaeab71075b743ca634393f27a56f04a0ff8711227f245dab5dc8049737791b372a94a6524f3");
     }
 
+    #[test]
+    fn should_accept_correct_keystore_password() {
+        assert!(EthAccount::check_password("res/pyethereum-keystore.json", "hekloo").unwrap());
+        assert!(EthAccount::check_password("res/geth-keystore.json", "geth").unwrap());
+    }
+
+    #[test]
+    fn should_reject_incorrect_keystore_password() {
+        assert!(!EthAccount::check_password("res/pyethereum-keystore.json", "hekloo!").unwrap());
+        assert!(!EthAccount::check_password("res/geth-keystore.json", "").unwrap());
+        assert!(EthAccount::check_password("res/no-such-keystore.json", "hekloo").is_err());
+        assert!(EthAccount::check_password("res/eip712-mail.json", "hekloo").is_err());
+    }
+
+    #[test]
+    fn should_read_keystore_from_json_string() {
+        // given
+        let json = include_str!("../res/pyethereum-keystore.json");
+
+        // when
+        let key = EthAccount::from_json_str(json, "hekloo").unwrap();
+
+        // then
+        assert_eq!(
+            format!("{}", key.address()),
+            "0x5240400e8b0aadfd212d9d8c70973b9800fa4b0f"
+        );
+        assert!(key.kestore_path().is_none());
+        assert!(key.change_password("other").is_err());
+    }
+
+    #[test]
+    fn should_build_from_secret() {
+        // given
+        let secret = SecretKey::from_raw(&super::keccak256(b"cow")).unwrap();
+
+        // when
+        let key = EthAccount::from_secret(secret, None);
+
+        // then
+        assert_eq!(
+            format!("{}", key.address()),
+            "0xcd2a3d9f938e13cd947ec05abc7fe734df8dd826"
+        );
+        assert_eq!(key.public().address()[..], key.address().to_vec()[..]);
+        assert!(key.kestore_path().is_none());
+    }
+
     #[test]
     fn should_read_relative_path_as_absolute() {
         let rel_path = "res/pyethereum-keystore.json";
@@ -352,7 +863,7 @@ mod tests {
         let key = EthAccount::load_or_generate(&rel_path, "hekloo").unwrap();
 
         // then
-        assert_eq!(key.kestore_path, abs_path);
+        assert_eq!(key.kestore_path, Some(abs_path));
     }
 
     #[test]
@@ -391,6 +902,89 @@ mod tests {
         assert!(EthAccount::load_or_generate(&path, pwd1).is_ok());
     }
 
+    #[test]
+    fn should_rekey_with_fresh_params_and_reload_with_same_pass() {
+        // given
+        let path = tmp_path();
+        let pwd = "zimko";
+        let key = EthAccount::load_or_generate(&path, pwd).unwrap();
+        let crypto = || {
+            let json: serde_json::Value =
+                serde_json::from_reader(File::open(&path).unwrap()).unwrap();
+            json["crypto"].clone()
+        };
+        let before = crypto();
+
+        // when
+        assert!(key.rekey("wrong", None).is_err());
+        assert_eq!(crypto(), before);
+        key.rekey(pwd, Some(2 * KEY_ITERATIONS)).unwrap();
+
+        // then
+        let after = crypto();
+        assert_ne!(after["kdfparams"]["salt"], before["kdfparams"]["salt"]);
+        assert_ne!(after["cipherparams"]["iv"], before["cipherparams"]["iv"]);
+        assert_eq!(after["kdfparams"]["c"], 2 * KEY_ITERATIONS);
+        let reloaded = EthAccount::load_or_generate(&path, pwd).unwrap();
+        assert_eq!(reloaded.address(), key.address());
+    }
+
+    #[test]
+    fn should_generate_different_secrets() {
+        // when
+        let secret0 = super::generate_secret();
+        let secret1 = super::generate_secret();
+
+        // then
+        assert_ne!(secret0.public().bytes()[..], secret1.public().bytes()[..]);
+    }
+
+    #[test]
+    fn should_reject_out_of_range_secrets() {
+        let mut above_order = super::SECP256K1_ORDER;
+        above_order[31] += 1;
+
+        for raw in &[[0u8; 32], super::SECP256K1_ORDER, above_order, [0xff; 32]] {
+            assert!(!super::is_valid_secret(raw));
+            match super::secret_from_raw(raw) {
+                Err(super::Error::InvalidSecretKey(_)) => (),
+                r => panic!("unexpected result: {:?}", r.map(|_| ())),
+            }
+        }
+
+        let mut below_order = super::SECP256K1_ORDER;
+        below_order[31] -= 1;
+        assert!(super::secret_from_raw(&below_order).is_ok());
+        assert!(super::secret_from_raw(&[1u8; 32]).is_ok());
+    }
+
+    #[test]
+    fn should_round_trip_public_key_through_prefixed_form() {
+        // given
+        let key = EthAccount::load_or_generate(&tmp_path(), "pwd").unwrap();
+        let msg = [3u8; 32];
+        let sig = key.sign(&msg).unwrap();
+
+        // when
+        let prefixed = super::public_uncompressed_prefixed(key.public());
+        let parsed = super::public_from_slice(&prefixed).unwrap();
+
+        // then
+        assert_eq!(prefixed[0], 0x04);
+        assert_eq!(&prefixed[1..], &key.public().bytes()[..]);
+        assert_eq!(parsed.bytes()[..], key.public().bytes()[..]);
+        assert!(parsed.verify(&sig, &msg).unwrap());
+
+        let bare = super::public_from_slice(key.public().bytes()).unwrap();
+        assert_eq!(bare.address(), key.public().address());
+
+        let mut wrong_prefix = prefixed;
+        wrong_prefix[0] = 0x03;
+        assert!(super::public_from_slice(&wrong_prefix).is_err());
+        assert!(super::public_from_slice(&prefixed[..63]).is_err());
+        assert!(super::public_from_slice(&[0u8; 64]).is_err());
+    }
+
     #[test]
     fn should_sign_verify() {
         // given
@@ -407,6 +1001,328 @@ mod tests {
         assert!(result.unwrap());
     }
 
+    #[test]
+    fn should_verify_batch() {
+        // given
+        let key = EthAccount::load_or_generate(&tmp_path(), "pwd").unwrap();
+        let other = EthAccount::load_or_generate(&tmp_path(), "pwd").unwrap();
+        let msg0 = [1u8; 32];
+        let msg1 = [2u8; 32];
+        let items = vec![
+            (key.sign(&msg0).unwrap(), msg0),
+            (key.sign(&msg0).unwrap(), msg1),
+            (other.sign(&msg1).unwrap(), msg1),
+            (key.sign(&msg1).unwrap(), msg1),
+        ];
+
+        // when
+        let results = key.verify_batch(&items).unwrap();
+
+        // then
+        assert_eq!(results, vec![true, false, false, true]);
+        for ((sig, msg), result) in items.iter().zip(results) {
+            assert_eq!(key.verify(sig, msg).unwrap(), result);
+        }
+    }
+
+    #[test]
+    fn should_sign_verify_slice() {
+        // given
+        let msg = rand::random::<[u8; 32]>();
+        let key = EthAccount::load_or_generate(&tmp_path(), "pwd").unwrap();
+
+        // when
+        let sig = key.sign_slice(&msg[..]).unwrap();
+
+        // then
+        assert!(key.verify_slice(&sig, &msg[..]).unwrap());
+        assert!(key.verify(&sig, &msg).unwrap());
+    }
+
+    #[test]
+    fn should_reject_slice_of_wrong_length() {
+        // given
+        let msg = [7u8; 33];
+        let key = EthAccount::load_or_generate(&tmp_path(), "pwd").unwrap();
+        let sig = key.sign(&[7u8; 32]).unwrap();
+
+        // when
+        let verified = key.verify_slice(&sig, &msg[..]);
+        let signed = key.sign_slice(&msg[..31]);
+
+        // then
+        match verified {
+            Err(super::Error::InvalidMessageLength(33)) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+        match signed {
+            Err(super::Error::InvalidMessageLength(31)) => (),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("message of 31 bytes signed"),
+        }
+    }
+
+    #[test]
+    fn should_clone_and_sign_identically() {
+        // given
+        let msg: super::Message = rand::random::<[u8; 32]>().into();
+        let key = EthAccount::load_or_generate(&tmp_path(), "pwd").unwrap();
+
+        // when
+        let cloned = key.clone();
+        let sig = key.sign(&msg).unwrap();
+        drop(key);
+
+        // then
+        let cloned_sig = cloned.sign(&msg).unwrap();
+        assert_eq!(sig.r, cloned_sig.r);
+        assert_eq!(sig.s, cloned_sig.s);
+        assert_eq!(sig.v, cloned_sig.v);
+        assert!(cloned.verify(&sig, &msg).unwrap());
+    }
+
+    #[test]
+    fn should_wipe_secret_in_place() {
+        // given
+        let mut secret = SecretKey::from_raw(&super::keccak256(b"cow")).unwrap();
+        let wiped = SecretKey::from_raw(&super::WIPED_SECRET).unwrap();
+
+        // when
+        super::wipe_secret(&mut secret);
+
+        // then
+        assert_eq!(secret.public().bytes()[..], wiped.public().bytes()[..]);
+    }
+
+    #[test]
+    fn should_sign_typed_data() {
+        // given
+        let secret = SecretKey::from_raw(&super::keccak256(b"cow")).unwrap();
+        let key = EthAccount::from_secret(secret, Some(tmp_path()));
+        let data: super::eip712::TypedData =
+            serde_json::from_str(include_str!("../res/eip712-mail.json")).unwrap();
+
+        // when
+        let sig = key.sign_typed_data(&data).unwrap();
+
+        // then
+        assert_eq!(
+            format!("{}", key.address()),
+            "0xcd2a3d9f938e13cd947ec05abc7fe734df8dd826"
+        );
+        assert!(key.verify(&sig, &data.hash().unwrap()).unwrap());
+    }
+
+    mod capture {
+        use std::{
+            fmt,
+            sync::{Arc, Mutex},
+        };
+
+        use tracing::{
+            field::{Field, Visit},
+            span, Event, Metadata, Subscriber,
+        };
+
+        /// Collects fields of all logged events
+        #[derive(Clone, Default)]
+        pub struct FieldsCapture(pub Arc<Mutex<Vec<(String, String)>>>);
+
+        impl Visit for FieldsCapture {
+            fn record_str(&mut self, field: &Field, value: &str) {
+                let entry = (field.name().to_string(), value.to_string());
+                self.0.lock().unwrap().push(entry);
+            }
+
+            fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+                let entry = (field.name().to_string(), format!("{:?}", value));
+                self.0.lock().unwrap().push(entry);
+            }
+        }
+
+        impl Subscriber for FieldsCapture {
+            fn enabled(&self, _metadata: &Metadata) -> bool {
+                true
+            }
+
+            fn new_span(&self, _span: &span::Attributes) -> span::Id {
+                span::Id::from_u64(1)
+            }
+
+            fn record(&self, _span: &span::Id, _values: &span::Record) {}
+
+            fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+            fn event(&self, event: &Event) {
+                event.record(&mut self.clone())
+            }
+
+            fn enter(&self, _span: &span::Id) {}
+
+            fn exit(&self, _span: &span::Id) {}
+        }
+    }
+
+    #[test]
+    fn should_log_address_and_event_fields() {
+        // given
+        let capture = capture::FieldsCapture::default();
+
+        // when
+        tracing::subscriber::with_default(capture.clone(), || {
+            EthAccount::load_or_generate("res/geth-keystore.json", "geth").unwrap();
+        });
+
+        // then
+        let fields = capture.0.lock().unwrap();
+        let field = |name: &str| {
+            fields
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.clone())
+        };
+        assert_eq!(
+            field("address"),
+            Some("0x8e049da484e853d92d118be16377ff616275d470".to_string())
+        );
+        assert_eq!(field("event"), Some("loaded".to_string()));
+        assert!(field("message").unwrap().starts_with("eth account"));
+    }
+
+    fn encode(sig: &Signature) -> [u8; 65] {
+        let mut bytes = [0u8; 65];
+        bytes[..32].copy_from_slice(&sig.r);
+        bytes[32..64].copy_from_slice(&sig.s);
+        bytes[64] = sig.v + 27;
+        bytes
+    }
+
+    #[test]
+    fn should_verify_encoded_signature() {
+        // given
+        let msg = super::keccak256(b"message");
+        let key = EthAccount::load_or_generate(&tmp_path(), "pwd").unwrap();
+
+        // when
+        let sig = encode(&key.sign(&msg).unwrap());
+
+        // then
+        assert!(super::verify_encoded(key.address(), &sig, &msg).unwrap());
+    }
+
+    #[test]
+    fn should_not_verify_encoded_signature_for_other_address() {
+        // given
+        let msg = super::keccak256(b"message");
+        let key = EthAccount::load_or_generate(&tmp_path(), "pwd").unwrap();
+        let other = Address::from([7u8; 20]);
+
+        // when
+        let sig = encode(&key.sign(&msg).unwrap());
+
+        // then
+        assert!(!super::verify_encoded(&other, &sig, &msg).unwrap());
+    }
+
+    #[test]
+    fn should_reject_malformed_encoded_signature() {
+        // given
+        let msg = super::keccak256(b"message");
+        let key = EthAccount::load_or_generate(&tmp_path(), "pwd").unwrap();
+        let mut sig = encode(&key.sign(&msg).unwrap());
+
+        // when
+        sig[64] = 5;
+
+        // then
+        assert!(super::verify_encoded(key.address(), &sig, &msg).is_err());
+        assert!(super::verify_encoded(key.address(), &[0u8; 65], &msg).is_err());
+    }
+
+    #[test]
+    fn should_sign_login_nonce() {
+        // given
+        let nonce = b"login nonce 8c1f";
+        let key = EthAccount::load_or_generate(&tmp_path(), "pwd").unwrap();
+
+        // when
+        let (address, sig) = key.sign_login(nonce).unwrap();
+
+        // then
+        assert_eq!(&address, key.address());
+        let msg = super::personal_message(nonce);
+        assert!(super::verify_encoded(&address, &sig, &msg).unwrap());
+        assert!(!super::verify_encoded(&address, &sig, &super::keccak256(nonce)).unwrap());
+    }
+
+    #[test]
+    fn should_hash_personal_message() {
+        assert_eq!(
+            super::personal_message(b"Hello World").to_hex::<String>(),
+            "a1de988600a42c4b4ab089b619297c17d53cffae5d5120d82d8a92d0bb3b78f2"
+        );
+    }
+
+    #[test]
+    fn should_recover_personal_signer() {
+        // `personal_sign("Hello World")` by the key derived from keccak256("cow")
+        let rsv: Vec<u8> = "975e01b6f2000b54b780931fe617fac111730c0d65258471ac26fbd324a2c1f9\
+                            1a936ba2ce8354aa87b8380dcc2ecc25f1406a8996e77cb868c140715fbe0ebd1b"
+            .from_hex()
+            .unwrap();
+        let mut r = [0u8; 32];
+        let mut s = [0u8; 32];
+        r.copy_from_slice(&rsv[..32]);
+        s.copy_from_slice(&rsv[32..64]);
+        let sig = Signature {
+            v: rsv[64] - 27,
+            r,
+            s,
+        };
+
+        let signer = super::recover_personal(&sig, b"Hello World").unwrap();
+        assert_eq!(
+            format!("{}", signer),
+            "0xcd2a3d9f938e13cd947ec05abc7fe734df8dd826"
+        );
+        assert_ne!(
+            super::recover_personal(&sig, b"Hello World!").unwrap(),
+            signer
+        );
+    }
+
+    #[test]
+    fn should_bind_signature_to_context() {
+        // given
+        let key = EthAccount::load_or_generate(tmp_path(), "pwd").unwrap();
+        let data = b"same data";
+
+        // when
+        let sig_a = key.sign_with_context("app-a", data).unwrap();
+        let sig_b = key.sign_with_context("app-b", data).unwrap();
+
+        // then
+        assert_ne!(sig_a.to_rsv()[..], sig_b.to_rsv()[..]);
+        assert!(key.verify_with_context(&sig_a, "app-a", data).unwrap());
+        assert!(!key.verify_with_context(&sig_a, "app-b", data).unwrap());
+        assert_ne!(
+            super::context_message("ab", b"c"),
+            super::context_message("a", b"bc")
+        );
+    }
+
+    #[test]
+    fn should_hash_with_keccak256() {
+        assert_eq!(
+            super::keccak256(b"").to_hex::<String>(),
+            "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+        );
+        assert_eq!(
+            super::keccak256(b"hello").to_hex::<String>(),
+            "1c8aff950685c2ed4bc3174f3472287b56d9517b9c948127319a09a7a36deac8"
+        );
+    }
+
     #[test]
     fn should_have_display_impl() {
         let mut abs_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
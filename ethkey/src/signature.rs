@@ -0,0 +1,210 @@
+//! Access to individual signature components, as emitted by JSON-RPC.
+
+use crate::{Error, Result, Signature};
+
+/// Offset of `v` in Ethereum notation (27/28)
+const V_OFFSET: u8 = 27;
+
+/// Convention of encoding the recovery id as `v`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VFormat {
+    /// 0 or 1
+    Raw,
+    /// 27 or 28
+    Ethereum,
+    /// `chain_id * 2 + 35 + recovery id`, see [EIP-155]
+    ///
+    /// [EIP-155]: https://eips.ethereum.org/EIPS/eip-155
+    Eip155 { chain_id: u64 },
+}
+
+/// Recovery id of a signature, 0 or 1, independent of its `v` encoding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecoveryId(u8);
+
+impl RecoveryId {
+    pub fn new(id: u8) -> Result<Self> {
+        match id {
+            0..=1 => Ok(RecoveryId(id)),
+            id => Err(Error::InvalidSignature(format!(
+                "invalid recovery id {}",
+                id
+            ))),
+        }
+    }
+
+    /// Normalizes `v` encoded in given format; fails if `v` does not match the format
+    pub fn from_v(v: u64, format: VFormat) -> Result<Self> {
+        let offset = match format {
+            VFormat::Raw => 0,
+            VFormat::Ethereum => u64::from(V_OFFSET),
+            VFormat::Eip155 { chain_id } => chain_id * 2 + 35,
+        };
+        match v.checked_sub(offset) {
+            Some(id @ 0..=1) => Ok(RecoveryId(id as u8)),
+            _ => Err(Error::InvalidSignature(format!(
+                "invalid v value {} for {:?}",
+                v, format
+            ))),
+        }
+    }
+
+    /// `v` in given format
+    pub fn to_v(self, format: VFormat) -> u64 {
+        let id = u64::from(self.0);
+        match format {
+            VFormat::Raw => id,
+            VFormat::Ethereum => id + u64::from(V_OFFSET),
+            VFormat::Eip155 { chain_id } => chain_id * 2 + 35 + id,
+        }
+    }
+
+    pub fn id(self) -> u8 {
+        self.0
+    }
+}
+
+/// Signature with `v` encoded in chosen format
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormattedSignature {
+    pub r: [u8; 32],
+    pub s: [u8; 32],
+    pub v: u64,
+    pub format: VFormat,
+}
+
+impl FormattedSignature {
+    pub fn new(sig: &Signature, format: VFormat) -> Result<Self> {
+        Ok(FormattedSignature {
+            r: sig.r,
+            s: sig.s,
+            v: RecoveryId::new(sig.v)?.to_v(format),
+            format,
+        })
+    }
+
+    /// Signature with normalized recovery id, e.g. to `recover` the signer
+    pub fn to_signature(&self) -> Result<Signature> {
+        Ok(Signature {
+            v: RecoveryId::from_v(self.v, self.format)?.id(),
+            r: self.r,
+            s: self.s,
+        })
+    }
+}
+
+/// `r`, `s` and `v` components of [`Signature`]
+pub trait SignatureExt {
+    /// `r` component, big-endian
+    fn r(&self) -> [u8; 32];
+
+    /// `s` component, big-endian
+    fn s(&self) -> [u8; 32];
+
+    /// Recovery id in Ethereum notation: 27 or 28
+    fn v(&self) -> u8;
+
+    /// Recovery id with [EIP-155] replay protection: `chain_id * 2 + 35 + recovery id`
+    ///
+    /// [EIP-155]: https://eips.ethereum.org/EIPS/eip-155
+    fn v_with_chain_id(&self, chain_id: u64) -> u64;
+
+    /// 65 bytes `r ‖ s ‖ v`, with `v` in Ethereum notation
+    fn to_rsv(&self) -> [u8; 65] {
+        let mut bytes = [0u8; 65];
+        bytes[..32].copy_from_slice(&self.r());
+        bytes[32..64].copy_from_slice(&self.s());
+        bytes[64] = self.v();
+        bytes
+    }
+}
+
+impl SignatureExt for Signature {
+    fn r(&self) -> [u8; 32] {
+        self.r
+    }
+
+    fn s(&self) -> [u8; 32] {
+        self.s
+    }
+
+    fn v(&self) -> u8 {
+        self.v + V_OFFSET
+    }
+
+    fn v_with_chain_id(&self, chain_id: u64) -> u64 {
+        RecoveryId(self.v).to_v(VFormat::Eip155 { chain_id })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FormattedSignature, RecoveryId, SignatureExt, VFormat};
+    use crate::{keccak256, verify_encoded, Address, EthAccount, SecretKey};
+
+    #[test]
+    fn should_recombine_components() {
+        let secret = SecretKey::from_raw(&keccak256(b"cow")).unwrap();
+        let address: Address = secret.public().address().as_ref().into();
+        let msg = keccak256(b"message");
+        let sig = secret.sign(&msg).unwrap();
+
+        assert!(sig.v() == 27 || sig.v() == 28);
+        assert_eq!(sig.v_with_chain_id(1), u64::from(sig.v()) + 10);
+
+        let mut encoded = [0u8; 65];
+        encoded[..32].copy_from_slice(&sig.r());
+        encoded[32..64].copy_from_slice(&sig.s());
+        encoded[64] = sig.v();
+        assert_eq!(&encoded[..], &sig.to_rsv()[..]);
+        assert!(verify_encoded(&address, &encoded, &msg).unwrap());
+    }
+
+    #[test]
+    fn should_round_trip_each_v_format() {
+        let secret = SecretKey::from_raw(&keccak256(b"cow")).unwrap();
+        let account = EthAccount::from_secret(secret, None);
+        let msg = keccak256(b"message");
+
+        for (format, first_v) in &[
+            (VFormat::Raw, 0),
+            (VFormat::Ethereum, 27),
+            (VFormat::Eip155 { chain_id: 1 }, 37),
+        ] {
+            let sig = account.sign_with_v_format(&msg, *format).unwrap();
+            assert!(sig.v == *first_v || sig.v == first_v + 1);
+
+            let public = sig.to_signature().unwrap().recover(&msg).unwrap();
+            assert_eq!(&Address::from(public.address().as_ref()), account.address());
+
+            // `verify_encoded` takes `v` as 0/1 or 27/28
+            if let VFormat::Raw | VFormat::Ethereum = format {
+                let mut encoded = [0u8; 65];
+                encoded[..32].copy_from_slice(&sig.r);
+                encoded[32..64].copy_from_slice(&sig.s);
+                encoded[64] = sig.v as u8;
+                assert!(verify_encoded(account.address(), &encoded, &msg).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn should_reject_v_of_other_format() {
+        assert_eq!(
+            RecoveryId::from_v(28, VFormat::Ethereum).unwrap(),
+            RecoveryId::new(1).unwrap()
+        );
+        assert!(RecoveryId::from_v(27, VFormat::Raw).is_err());
+        assert!(RecoveryId::from_v(1, VFormat::Ethereum).is_err());
+        assert!(RecoveryId::from_v(37, VFormat::Eip155 { chain_id: 2 }).is_err());
+        assert!(RecoveryId::new(2).is_err());
+
+        let formatted = FormattedSignature {
+            r: [1; 32],
+            s: [1; 32],
+            v: 27,
+            format: VFormat::Raw,
+        };
+        assert!(formatted.to_signature().is_err());
+    }
+}
@@ -0,0 +1,135 @@
+//! Signing behind a trait, so callers don't depend on keys being in a local keystore.
+
+use crate::{Address, EthAccount, Message, PublicKey, Result, Signature};
+
+/// Holder of a secret key, able to sign without exposing it
+pub trait Signer {
+    /// signs given message
+    fn sign(&self, msg: &Message) -> Result<Signature>;
+
+    /// public key
+    fn public(&self) -> &PublicKey;
+
+    /// Ethereum address
+    fn address(&self) -> &Address;
+}
+
+impl Signer for EthAccount {
+    fn sign(&self, msg: &Message) -> Result<Signature> {
+        EthAccount::sign(self, msg)
+    }
+
+    fn public(&self) -> &PublicKey {
+        EthAccount::public(self)
+    }
+
+    fn address(&self) -> &Address {
+        EthAccount::address(self)
+    }
+}
+
+/// Signer delegating to an external device, e.g. Ledger or Trezor.
+///
+/// Placeholder: the device protocol is not implemented yet,
+/// messages are passed to given `transport` instead.
+#[cfg(feature = "external-signer")]
+pub struct ExternalSigner {
+    public: PublicKey,
+    address: Address,
+    transport: Box<dyn Fn(&Message) -> Result<Signature> + Send + Sync>,
+}
+
+#[cfg(feature = "external-signer")]
+impl ExternalSigner {
+    /// `public` is the key of the device account
+    pub fn new<F>(public: PublicKey, transport: F) -> Self
+    where
+        F: Fn(&Message) -> Result<Signature> + Send + Sync + 'static,
+    {
+        ExternalSigner {
+            address: public.address().as_ref().into(),
+            public,
+            transport: Box::new(transport),
+        }
+    }
+}
+
+#[cfg(feature = "external-signer")]
+impl Signer for ExternalSigner {
+    fn sign(&self, msg: &Message) -> Result<Signature> {
+        (self.transport)(msg)
+    }
+
+    fn public(&self) -> &PublicKey {
+        &self.public
+    }
+
+    fn address(&self) -> &Address {
+        &self.address
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethsign::SecretKey;
+
+    use super::Signer;
+    use crate::{
+        keccak256, verify_encoded, Address, EthAccount, Message, PublicKey, Result, Signature,
+        SignatureExt,
+    };
+
+    struct MockSigner {
+        secret: SecretKey,
+        public: PublicKey,
+        address: Address,
+    }
+
+    impl Signer for MockSigner {
+        fn sign(&self, msg: &Message) -> Result<Signature> {
+            Ok(self.secret.sign(msg)?)
+        }
+
+        fn public(&self) -> &PublicKey {
+            &self.public
+        }
+
+        fn address(&self) -> &Address {
+            &self.address
+        }
+    }
+
+    fn round_trip(signer: &dyn Signer) {
+        let msg = keccak256(b"message");
+        let sig = signer.sign(&msg).unwrap();
+
+        assert!(signer.public().verify(&sig, &msg).unwrap());
+        assert!(verify_encoded(signer.address(), &sig.to_rsv(), &msg).unwrap());
+    }
+
+    #[test]
+    fn should_sign_with_account() {
+        let secret = SecretKey::from_raw(&keccak256(b"cow")).unwrap();
+        round_trip(&*EthAccount::from_secret(secret, None));
+    }
+
+    #[test]
+    fn should_sign_with_mock() {
+        let secret = SecretKey::from_raw(&keccak256(b"cow")).unwrap();
+        let signer = MockSigner {
+            public: secret.public(),
+            address: secret.public().address().as_ref().into(),
+            secret,
+        };
+        round_trip(&signer);
+    }
+
+    #[cfg(feature = "external-signer")]
+    #[test]
+    fn should_sign_with_external_transport() {
+        let secret = SecretKey::from_raw(&keccak256(b"cow")).unwrap();
+        let public = secret.public();
+        let signer = super::ExternalSigner::new(public, move |msg| Ok(secret.sign(msg)?));
+        round_trip(&signer);
+    }
+}
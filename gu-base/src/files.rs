@@ -5,9 +5,10 @@ use futures_cpupool::{CpuFuture, CpuPool};
 use sha1::Sha1;
 use std::{
     cmp,
-    fs::File,
-    io::{self, Seek, SeekFrom, Write},
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom, Write},
     path::Path,
+    time::{Duration, Instant},
 };
 use tar::Archive;
 
@@ -47,14 +48,70 @@ impl FilePoolHandler {
         .flatten_stream()
     }
 
-    pub fn untar_archive<P: AsRef<Path> + ToOwned>(
+    pub fn untar_archive<P, F>(
         &self,
         mut archive: Archive<GzDecoder<File>>,
         output_path: P,
-    ) -> impl Future<Item = (), Error = String> {
+        mut progress: F,
+    ) -> impl Future<Item = (), Error = String>
+    where
+        P: AsRef<Path> + ToOwned,
+        F: FnMut(&ExtractProgress) + Send + 'static,
+    {
         let out = output_path.as_ref().to_owned();
-        self.pool.spawn_fn(move || Ok(archive.unpack(out).unwrap()))
+        self.pool.spawn_fn(move || {
+            unpack_with_progress(&mut archive, &out, &mut progress)
+                .map_err(|e| format!("untar error: {}", e))
+        })
+    }
+}
+
+/// Minimal time between two progress reports during extraction
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ExtractProgress {
+    pub extracted_entries: u64,
+    /// `None` when the number of entries is not known upfront, e.g. for compressed archives
+    pub total_entries: Option<u64>,
+    pub written_bytes: u64,
+}
+
+/// Same as `Archive::unpack`, reporting progress at most every `PROGRESS_INTERVAL`
+/// and once more when finished.
+fn unpack_with_progress<R: Read, F: FnMut(&ExtractProgress)>(
+    archive: &mut Archive<R>,
+    dst: &Path,
+    progress: &mut F,
+) -> io::Result<()> {
+    let mut status = ExtractProgress::default();
+    let mut last_report = Instant::now();
+    // directories are unpacked last, so their mtime and permissions are not overwritten
+    let mut directories = Vec::new();
+
+    fs::create_dir_all(dst)?;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.header().entry_type().is_dir() {
+            directories.push(entry);
+            continue;
+        }
+
+        entry.unpack_in(dst)?;
+        status.extracted_entries += 1;
+        status.written_bytes += entry.size();
+        if last_report.elapsed() >= PROGRESS_INTERVAL {
+            progress(&status);
+            last_report = Instant::now();
+        }
     }
+    for mut dir in directories {
+        dir.unpack_in(dst)?;
+        status.extracted_entries += 1;
+    }
+
+    progress(&status);
+    Ok(())
 }
 
 fn write_chunk_on_pool(
@@ -297,22 +354,46 @@ pub fn untgz_async<P: AsRef<Path> + ToOwned>(
     input_path: P,
     output_path: P,
 ) -> impl Future<Item = (), Error = String> {
+    untgz_async_with_progress(input_path, output_path, |_| ())
+}
+
+/// Extracts `.tar.gz` archive, calling `progress` periodically
+pub fn untgz_async_with_progress<P, F>(
+    input_path: P,
+    output_path: P,
+    progress: F,
+) -> impl Future<Item = (), Error = String>
+where
+    P: AsRef<Path> + ToOwned,
+    F: FnMut(&ExtractProgress) + Send + 'static,
+{
     future::result(File::open(input_path))
         .map_err(|e| e.to_string())
         .and_then(|file| {
             let decoder = GzDecoder::new(file);
-            let archive = Archive::new(decoder);
-            FILE_HANDLER.untar_archive(archive, output_path)
+            let mut archive = Archive::new(decoder);
+            // build tools inside the image (e.g. make) rely on the original timestamps
+            archive.set_preserve_mtime(true);
+            FILE_HANDLER.untar_archive(archive, output_path, progress)
         })
 }
 
 #[cfg(test)]
 mod tests {
+    extern crate tempfile;
+
     use actix::{Arbiter, System};
     use bytes::Bytes;
-    use files::write_async_with_sha1;
+    use files::{untgz_async, untgz_async_with_progress, write_async_with_sha1};
+    use flate2::{write::GzEncoder, Compression};
     use futures::{prelude::*, stream};
-    use std::path::PathBuf;
+    use std::{
+        fs,
+        path::PathBuf,
+        sync::{Arc, Mutex},
+        time::UNIX_EPOCH,
+    };
+    use tar::{Builder, Header};
 
     #[test]
     #[ignore]
@@ -328,4 +409,75 @@ mod tests {
             )
         });
     }
+
+    #[test]
+    fn untgz_preserves_mtime() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive_path = dir.path().join("image.tgz");
+        let mtime = 1_500_000_000;
+
+        let content = b"all: ;\n";
+        let mut header = Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(mtime);
+        header.set_cksum();
+
+        let encoder = GzEncoder::new(
+            fs::File::create(&archive_path).unwrap(),
+            Compression::default(),
+        );
+        let mut builder = Builder::new(encoder);
+        builder
+            .append_data(&mut header, "Makefile", &content[..])
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let output_path = dir.path().join("out");
+        untgz_async(archive_path, output_path.clone())
+            .wait()
+            .unwrap();
+
+        let modified = fs::metadata(output_path.join("Makefile"))
+            .unwrap()
+            .modified()
+            .unwrap();
+        assert_eq!(modified.duration_since(UNIX_EPOCH).unwrap().as_secs(), mtime);
+    }
+
+    #[test]
+    fn untgz_reports_progress() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive_path = dir.path().join("image.tgz");
+
+        let encoder = GzEncoder::new(
+            fs::File::create(&archive_path).unwrap(),
+            Compression::default(),
+        );
+        let mut builder = Builder::new(encoder);
+        for (name, content) in &[("a", "a"), ("b", "bb"), ("c", "ccc")] {
+            let mut header = Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, content.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let collected = reports.clone();
+        untgz_async_with_progress(archive_path, dir.path().join("out"), move |progress| {
+            collected.lock().unwrap().push(progress.clone())
+        })
+        .wait()
+        .unwrap();
+
+        let reports = reports.lock().unwrap();
+        let last = reports.last().unwrap();
+        assert_eq!(last.extracted_entries, 3);
+        assert_eq!(last.total_entries, None);
+        assert_eq!(last.written_bytes, 6);
+    }
 }
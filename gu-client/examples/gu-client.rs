@@ -564,7 +564,10 @@ fn run_worker<S: Stream<Item = (Blob, BlenderTaskSpec), Error = Error>>(
                             content: serde_json::to_string(&spec).unwrap(),
                         },
                         Command::Open,
-                        Command::Wait,
+                        Command::Wait {
+                            child_id: None,
+                            timeout: None,
+                        },
                         Command::UploadFile {
                             uri: blob.uri(),
                             file_path: format!("golem/output/outf_{:04}.png", frame),
@@ -582,6 +585,10 @@ fn run_worker<S: Stream<Item = (Blob, BlenderTaskSpec), Error = Error>>(
                             executable: "./gu-render".into(),
                             args: Vec::new(),
                             working_dir: None,
+                            mark_clean: false,
+                            env: Default::default(),
+                            strict_env: false,
+                            output_encoding: Default::default(),
                         },
                         Command::UploadFile {
                             uri: blob.uri(),
@@ -707,11 +714,13 @@ fn blender_deployment_spec(
             env_type: "hd".to_string(),
             image: Image {
                 url: "http://52.31.143.91/images/x86_64/linux/gu-blender.hdi".to_string(),
-                hash: "SHA1:213fad4e020ded42e6a949f61cb660cb69bc9845".to_string(),
+                hash: "SHA1:213fad4e020ded42e6a949f61cb660cb69bc9845".into(),
+                cache_name: None,
             },
             name: "".to_string(),
             tags: vec!["gu:render".into(), "gu:blender".into()],
             note: None,
+            cpu_affinity: Vec::new(),
             options: (),
         }))
     } else {
@@ -723,11 +732,13 @@ fn blender_deployment_spec(
                 image: Image {
                     url: "prekucki/gu-render-blender".to_string(),
                     hash: "sha256:53d11e6866835986b625e9fb07aa73b31dc667da39fe04f56da0ef06a50e0083"
-                        .to_string(),
+                        .into(),
+                    cache_name: None,
                 },
                 name: "".to_string(),
                 tags: vec!["gu:render".into(), "gu:blender".into()],
                 note: None,
+                cpu_affinity: Vec::new(),
                 options: CreateOptions {
                     volumes: vec![
                         VolumeDef::BindRw {
@@ -85,11 +85,13 @@ fn main() {
                                 image: Image {
                                     url: "http://52.31.143.91/images/gu-factor-linux.tar.gz"
                                         .to_string(),
-                                    hash: "not_implemented".to_string(),
+                                    hash: "not_implemented".into(),
+                                    cache_name: None,
                                 },
                                 name: "peer_session".to_string(),
                                 tags: vec![],
                                 note: None,
+                                cpu_affinity: Vec::new(),
                                 options: (),
                             })
                         }),
@@ -103,6 +105,10 @@ fn main() {
                             executable: "gu-factor".to_string(),
                             args: vec!["100".to_string()],
                             working_dir: None,
+                            mark_clean: false,
+                            env: Default::default(),
+                            strict_env: false,
+                            output_encoding: Default::default(),
                         },
                         envman::Command::AddTags(vec!["my_tag_2".to_string()]),
                     ]))
@@ -23,12 +23,13 @@ fn main() {
         env_type: "docker".to_string(),
         image: Image {
             url: "tomcat:6.0.44".to_string(),
-            hash: "sha256:4f00109135274b73a9cd8b3a46f43353a095515088e724a442752a62e9cfa3b3"
-                .to_string(),
+            hash: "sha256:4f00109135274b73a9cd8b3a46f43353a095515088e724a442752a62e9cfa3b3".into(),
+            cache_name: None,
         },
         name: "tomcat".to_string(),
         tags: vec![],
         note: None,
+        cpu_affinity: Vec::new(),
         options: gu_model::dockerman::CreateOptions::default().with_net(NetDef::Host {}),
     });
     eprintln!("{}", s.unwrap());
@@ -46,17 +47,22 @@ fn main() {
                         env_type: "docker".to_string(),
                         image: Image {
                             url: "tomcat:6.0.44".to_string(),
-                            hash: "sha256:4f00109135274b73a9cd8b3a46f43353a095515088e724a442752a62e9cfa3b3".to_string(),
+                            hash: "sha256:4f00109135274b73a9cd8b3a46f43353a095515088e724a442752a62e9cfa3b3".into(),
+                            cache_name: None,
                         },
                         name: "tomcat".to_string(),
                         tags: vec![],
                         note: None,
+                        cpu_affinity: Vec::new(),
                         options:
                         gu_model::dockerman::CreateOptions::default().with_net(NetDef::Host {}),
                     })
                 })
                 .and_then(|tomcat: PeerSession| {
-                    tomcat.update(vec![Command::Open, Command::Wait])
+                    tomcat.update(vec![Command::Open, Command::Wait {
+                        child_id: None,
+                        timeout: None,
+                    }])
                 })
         })
     ).unwrap();
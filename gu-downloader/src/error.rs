@@ -25,6 +25,9 @@ pub enum Error {
     #[fail(display = "Canceled")]
     Canceled,
 
+    #[fail(display = "invalid response status: {}", _0)]
+    HttpStatus(u16),
+
     #[fail(display = "{}", _0)]
     Other(String),
 }
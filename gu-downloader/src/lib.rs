@@ -187,10 +187,7 @@ fn check_url(url: &str) -> impl Future<Item = UrlInfo, Error = Error> {
                         check,
                     }))
                 } else {
-                    Err(Error::Other(format!(
-                        "invalid response status: {}",
-                        resp.status()
-                    )))
+                    Err(Error::HttpStatus(resp.status().as_u16()))
                 }
             })
     })
@@ -1,3 +1,5 @@
+use std::fmt;
+
 use actix::{Actor, ActorResponse, Addr, ArbiterService, Handler, Message, WrapFuture};
 use futures::Future;
 use hostname::get_hostname;
@@ -7,11 +9,14 @@ use serde::{Deserialize, Serialize};
 use gu_actix::flatten::FlattenFuture;
 use gu_net::rpc::{PublicMessage, RemotingContext, RemotingSystemService};
 
+pub use crate::cpu::{CpuInfo, CpuQuery, NumaNode};
 pub use crate::disk::{DiskInfo, DiskQuery};
 use crate::inner_actor::InnerActor;
 pub use crate::ram::{RamInfo, RamQuery};
+pub use crate::sampler::{MemorySampler, MemoryTrend, MemoryTrendQuery};
 use crate::storage::storage_info;
 pub use crate::storage::{StorageInfo, StorageQuery};
+use crate::units::format_bytes;
 
 use super::gpuinfo::{gpu_count, GpuCount};
 
@@ -40,6 +45,22 @@ fn os_type() -> Option<OsType> {
     None
 }
 
+/// CPU architecture the binary was built for, e.g. `x86_64` or `aarch64`
+fn arch() -> String {
+    std::env::consts::ARCH.to_string()
+}
+
+// sysinfo 0.9 does not report OS version; kernel release is used instead
+#[cfg(unix)]
+fn os_version() -> Option<String> {
+    Some(nix::sys::utsname::uname().release().to_string())
+}
+
+#[cfg(not(unix))]
+fn os_version() -> Option<String> {
+    None
+}
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct Hardware {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -51,8 +72,14 @@ pub struct Hardware {
     #[serde(skip_serializing_if = "Option::is_none")]
     os: Option<OsType>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    os_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    arch: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     hostname: Option<String>,
     num_cores: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpu: Option<CpuInfo>,
 }
 
 impl Hardware {
@@ -63,6 +90,41 @@ impl Hardware {
     pub fn os(&self) -> Option<&OsType> {
         self.os.as_ref()
     }
+
+    pub fn os_version(&self) -> Option<&str> {
+        self.os_version.as_ref().map(String::as_str)
+    }
+
+    pub fn arch(&self) -> Option<&str> {
+        self.arch.as_ref().map(String::as_str)
+    }
+
+    pub fn ram(&self) -> Option<&RamInfo> {
+        self.ram.as_ref()
+    }
+
+    pub fn cpu(&self) -> Option<&CpuInfo> {
+        self.cpu.as_ref()
+    }
+}
+
+/// One line summary, e.g. `RAM 8GiB (62% used), 8 cores, 120GiB disk free`
+impl fmt::Display for Hardware {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ram) = self.ram.as_ref() {
+            // memory is reported in KiB
+            write!(f, "RAM {}", format_bytes(ram.total() * 1024))?;
+            if ram.total() > 0 {
+                write!(f, " ({}% used)", ram.used() * 100 / ram.total())?;
+            }
+            write!(f, ", ")?;
+        }
+        write!(f, "{} cores", self.num_cores)?;
+        if let Some(disk) = self.disk.as_ref() {
+            write!(f, ", {} disk free", format_bytes(disk.available()))?;
+        }
+        Ok(())
+    }
 }
 
 impl Message for HardwareQuery {
@@ -112,6 +174,10 @@ fn disk(
         .then(|r| Ok(r.ok()))
 }
 
+fn cpu(inner: &Addr<InnerActor>) -> impl Future<Item = Option<CpuInfo>, Error = String> {
+    inner.send(CpuQuery).then(|r| Ok(r.ok()))
+}
+
 impl Handler<HardwareQuery> for HardwareActor {
     type Result = ActorResponse<Self, Hardware, String>;
 
@@ -126,15 +192,18 @@ impl Handler<HardwareQuery> for HardwareActor {
 
         ActorResponse::r#async(
             ram(RamQuery::default(), &inner)
-                .join(disk(DiskQuery::new(), &inner))
-                .and_then(move |(ram, disk)| {
+                .join3(disk(DiskQuery::new(), &inner), cpu(&inner))
+                .and_then(move |(ram, disk, cpu)| {
                     Ok(Hardware {
                         gpu,
                         ram,
                         disk,
                         os: os_type(),
+                        os_version: os_version(),
+                        arch: Some(arch()),
                         hostname,
                         num_cores: num_cpus::get_physical(),
+                        cpu,
                     })
                 })
                 .into_actor(self),
@@ -153,3 +222,51 @@ impl Handler<StorageQuery> for HardwareActor {
         ActorResponse::reply(storage_info(msg.path()).map_err(|e| e.to_string()))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::{arch, os_version, Hardware};
+
+    #[test]
+    fn test_display_summary() {
+        let hardware: Hardware = serde_json::from_value(json!({
+            "ram": { "free": 3_187_671, "used": 5_200_937, "total": 8_388_608 },
+            "disk": { "available": 120u64 << 30, "total": 500u64 << 30, "disk_type": "SSD" },
+            "num_cores": 8
+        }))
+        .unwrap();
+
+        assert_eq!(
+            hardware.to_string(),
+            "RAM 8GiB (62% used), 8 cores, 120GiB disk free"
+        );
+    }
+
+    #[test]
+    fn test_display_missing_sections() {
+        let hardware: Hardware = serde_json::from_value(json!({ "num_cores": 4 })).unwrap();
+
+        assert_eq!(hardware.to_string(), "4 cores");
+    }
+
+    #[test]
+    fn test_arch_matches_target() {
+        let expected = if cfg!(target_arch = "x86_64") {
+            "x86_64"
+        } else if cfg!(target_arch = "aarch64") {
+            "aarch64"
+        } else {
+            return;
+        };
+
+        assert_eq!(arch(), expected);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_os_version_reported() {
+        assert!(!os_version().unwrap().is_empty());
+    }
+}
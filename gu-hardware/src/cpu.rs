@@ -0,0 +1,222 @@
+use std::path::Path;
+
+use actix::Message;
+use serde::{Deserialize, Serialize};
+use sysinfo::{ComponentExt, SystemExt};
+
+/// Cores belonging to a single NUMA node
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NumaNode {
+    id: u32,
+    cores: Vec<u32>,
+}
+
+impl NumaNode {
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// logical core ids
+    pub fn cores(&self) -> &[u32] {
+        &self.cores
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CpuInfo {
+    num_cores: usize,
+    /// empty on platforms not reporting NUMA topology
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    numa_nodes: Vec<NumaNode>,
+    /// hottest CPU sensor, if any is exposed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    temperature_c: Option<f32>,
+    /// current frequency of each logical core
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    frequency_mhz: Option<Vec<f32>>,
+}
+
+impl CpuInfo {
+    pub fn num_cores(&self) -> usize {
+        self.num_cores
+    }
+
+    pub fn numa_nodes(&self) -> &[NumaNode] {
+        &self.numa_nodes
+    }
+
+    pub fn temperature_c(&self) -> Option<f32> {
+        self.temperature_c
+    }
+
+    pub fn frequency_mhz(&self) -> Option<&[f32]> {
+        self.frequency_mhz.as_ref().map(Vec::as_slice)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CpuQuery;
+
+impl Message for CpuQuery {
+    type Result = CpuInfo;
+}
+
+pub(crate) fn cpu_info(sys: &impl SystemExt) -> CpuInfo {
+    CpuInfo {
+        num_cores: num_cpus::get_physical(),
+        numa_nodes: numa_nodes(Path::new("/sys/devices/system/node")),
+        temperature_c: cpu_temperature(sys),
+        frequency_mhz: proc_cpu_frequencies(),
+    }
+}
+
+fn cpu_temperature(sys: &impl SystemExt) -> Option<f32> {
+    sys.get_components_list()
+        .iter()
+        .filter(|component| {
+            let label = component.get_label().to_lowercase();
+            ["cpu", "core", "package"]
+                .iter()
+                .any(|name| label.contains(name))
+        })
+        .map(|component| component.get_temperature())
+        .fold(None, |max: Option<f32>, t| {
+            Some(max.map_or(t, |max| max.max(t)))
+        })
+}
+
+// sysinfo 0.9 does not report processor frequency
+#[cfg(target_os = "linux")]
+fn proc_cpu_frequencies() -> Option<Vec<f32>> {
+    parse_cpuinfo_mhz(&std::fs::read_to_string("/proc/cpuinfo").ok()?)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn proc_cpu_frequencies() -> Option<Vec<f32>> {
+    None
+}
+
+#[cfg_attr(not(any(target_os = "linux", test)), allow(dead_code))]
+fn parse_cpuinfo_mhz(content: &str) -> Option<Vec<f32>> {
+    let frequencies: Vec<f32> = content
+        .lines()
+        .filter(|line| line.starts_with("cpu MHz"))
+        .filter_map(|line| line.splitn(2, ':').nth(1))
+        .filter_map(|v| v.trim().parse().ok())
+        .collect();
+
+    match frequencies.is_empty() {
+        true => None,
+        false => Some(frequencies),
+    }
+}
+
+/// Parses kernel cpu list format, e.g. `0-3,8,10-11`
+fn parse_cpu_list(list: &str) -> Option<Vec<u32>> {
+    let mut cores = Vec::new();
+
+    for range in list.trim().split(',').filter(|range| !range.is_empty()) {
+        let mut bounds = range.splitn(2, '-');
+        let first: u32 = bounds.next()?.parse().ok()?;
+        let last: u32 = match bounds.next() {
+            Some(last) => last.parse().ok()?,
+            None => first,
+        };
+        cores.extend(first..=last);
+    }
+    Some(cores)
+}
+
+#[cfg(target_os = "linux")]
+fn numa_nodes(sys_node_dir: &Path) -> Vec<NumaNode> {
+    use std::fs;
+
+    let entries = match fs::read_dir(sys_node_dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            debug!("no NUMA topology in {}: {}", sys_node_dir.display(), e);
+            return Vec::new();
+        }
+    };
+
+    let mut nodes: Vec<NumaNode> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with("node") {
+                return None;
+            }
+            let id = name["node".len()..].parse().ok()?;
+            let cpu_list = fs::read_to_string(entry.path().join("cpulist")).ok()?;
+            Some(NumaNode {
+                id,
+                cores: parse_cpu_list(&cpu_list)?,
+            })
+        })
+        .collect();
+    nodes.sort_by_key(|node| node.id);
+    nodes
+}
+
+#[cfg(not(target_os = "linux"))]
+fn numa_nodes(_sys_node_dir: &Path) -> Vec<NumaNode> {
+    Vec::new()
+}
+
+#[cfg(test)]
+mod test {
+    use sysinfo::{System, SystemExt};
+
+    use super::{cpu_info, parse_cpu_list, parse_cpuinfo_mhz};
+
+    #[test]
+    fn test_parse_cpu_list() {
+        assert_eq!(parse_cpu_list("0\n"), Some(vec![0]));
+        assert_eq!(
+            parse_cpu_list("0-3,8,10-11"),
+            Some(vec![0, 1, 2, 3, 8, 10, 11])
+        );
+        assert_eq!(parse_cpu_list(""), Some(vec![]));
+        assert_eq!(parse_cpu_list("0-x"), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_numa_nodes_contain_present_cores() {
+        let present =
+            parse_cpu_list(&std::fs::read_to_string("/sys/devices/system/cpu/present").unwrap())
+                .unwrap();
+        let info = cpu_info(&System::new());
+
+        assert!(!info.numa_nodes().is_empty());
+        let numa_cores: Vec<u32> = info
+            .numa_nodes()
+            .iter()
+            .flat_map(|node| node.cores().iter().cloned())
+            .collect();
+        assert!(present.iter().all(|core| numa_cores.contains(core)));
+    }
+
+    #[test]
+    fn test_parse_cpuinfo_mhz() {
+        let cpuinfo =
+            "processor\t: 0\ncpu MHz\t\t: 2400.000\n\nprocessor\t: 1\ncpu MHz\t\t: 1200.5\n";
+
+        assert_eq!(parse_cpuinfo_mhz(cpuinfo), Some(vec![2400.0, 1200.5]));
+        assert_eq!(parse_cpuinfo_mhz("processor\t: 0\n"), None);
+    }
+
+    #[test]
+    fn test_thermal_and_frequency_optional() {
+        let info = cpu_info(&System::new());
+
+        if let Some(t) = info.temperature_c() {
+            assert!(t.is_finite());
+        }
+        if let Some(frequencies) = info.frequency_mhz() {
+            assert!(!frequencies.is_empty());
+            assert!(frequencies.iter().all(|mhz| *mhz >= 0.0));
+        }
+    }
+}
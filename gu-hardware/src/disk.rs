@@ -1,6 +1,10 @@
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
 use actix::Message;
+#[cfg(unix)]
+use nix::sys::statvfs::statvfs;
 use serde::{Deserialize, Serialize};
 use sysinfo::{DiskExt, DiskType, SystemExt};
 
@@ -14,6 +18,16 @@ pub struct DiskInfo {
     total: u64,
     #[serde(with = "DiskTypeDef")]
     disk_type: DiskType,
+    /// `None` on platforms without inodes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    inodes_total: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    inodes_free: Option<u64>,
+    /// `None` on the first query of a disk and where I/O counters are unavailable
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    read_bytes_per_sec: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    write_bytes_per_sec: Option<f64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -39,6 +53,108 @@ impl DiskInfo {
     pub fn disk_type(&self) -> DiskType {
         self.disk_type
     }
+
+    pub fn inodes_total(&self) -> Option<u64> {
+        self.inodes_total
+    }
+
+    pub fn inodes_free(&self) -> Option<u64> {
+        self.inodes_free
+    }
+
+    /// Bytes read per second since the previous query of this disk
+    pub fn read_bytes_per_sec(&self) -> Option<f64> {
+        self.read_bytes_per_sec
+    }
+
+    /// Bytes written per second since the previous query of this disk
+    pub fn write_bytes_per_sec(&self) -> Option<f64> {
+        self.write_bytes_per_sec
+    }
+}
+
+/// Cumulative bytes transferred by a disk since boot
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct DiskIoCounters {
+    read_bytes: u64,
+    write_bytes: u64,
+}
+
+/// Keeps the last I/O counters of every queried disk, so that the next
+/// query can report transfer rates over the interval between them
+#[derive(Default)]
+pub(crate) struct DiskIoTracker {
+    last: HashMap<String, (Instant, DiskIoCounters)>,
+}
+
+impl DiskIoTracker {
+    /// Rates of (read, write) bytes per second since the previous sample of `device`
+    fn sample(
+        &mut self,
+        device: &str,
+        counters: DiskIoCounters,
+        now: Instant,
+    ) -> (Option<f64>, Option<f64>) {
+        let prev = self.last.insert(device.to_string(), (now, counters));
+        let (then, prev) = match prev {
+            Some(prev) => prev,
+            None => return (None, None),
+        };
+        let elapsed = now.duration_since(then);
+        let secs = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+        if secs <= 0.0 {
+            return (None, None);
+        }
+        // counters are reset when the device is re-attached
+        let rate = |cur: u64, prev: u64| Some(cur.saturating_sub(prev) as f64 / secs);
+        (
+            rate(counters.read_bytes, prev.read_bytes),
+            rate(counters.write_bytes, prev.write_bytes),
+        )
+    }
+
+    fn rates(&mut self, device: &Path) -> (Option<f64>, Option<f64>) {
+        match disk_io_counters(device) {
+            Some((name, counters)) => self.sample(&name, counters, Instant::now()),
+            None => (None, None),
+        }
+    }
+}
+
+/// sysinfo 0.9 does not report disk I/O, so it is read from `/proc/diskstats`
+#[cfg(target_os = "linux")]
+fn disk_io_counters(device: &Path) -> Option<(String, DiskIoCounters)> {
+    // resolves e.g. `/dev/mapper/root` to `dm-0`
+    let name = device
+        .canonicalize()
+        .ok()?
+        .file_name()?
+        .to_str()?
+        .to_string();
+    let counters = parse_diskstats(&std::fs::read_to_string("/proc/diskstats").ok()?, &name)?;
+    Some((name, counters))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn disk_io_counters(_device: &Path) -> Option<(String, DiskIoCounters)> {
+    None
+}
+
+/// Counters of `name` from `/proc/diskstats` content; sectors there are always 512 bytes
+#[cfg_attr(not(any(target_os = "linux", test)), allow(dead_code))]
+fn parse_diskstats(content: &str, name: &str) -> Option<DiskIoCounters> {
+    const SECTOR_SIZE: u64 = 512;
+
+    let fields: Vec<&str> = content
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>())
+        .find(|fields| fields.get(2) == Some(&name))?;
+    let sectors = |idx: usize| fields.get(idx).and_then(|v| v.parse::<u64>().ok());
+
+    Some(DiskIoCounters {
+        read_bytes: sectors(5)? * SECTOR_SIZE,
+        write_bytes: sectors(9)? * SECTOR_SIZE,
+    })
 }
 
 fn disk_for_path(disks: &[impl DiskExt], path: PathBuf) -> Result<&impl DiskExt> {
@@ -61,12 +177,39 @@ fn disk_for_path(disks: &[impl DiskExt], path: PathBuf) -> Result<&impl DiskExt>
     best_match.ok_or_else(|| Error::PathMountpointNotFound(path))
 }
 
-pub(crate) fn disk_info(sys: &impl SystemExt, path: PathBuf) -> Result<DiskInfo> {
+/// Total and free inodes of the filesystem containing `path`
+#[cfg(unix)]
+fn inode_counts(path: &Path) -> (Option<u64>, Option<u64>) {
+    match statvfs(path) {
+        Ok(stat) => (Some(stat.files() as u64), Some(stat.files_free() as u64)),
+        Err(e) => {
+            log::warn!("cannot read inode counts of {}: {}", path.display(), e);
+            (None, None)
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn inode_counts(_path: &Path) -> (Option<u64>, Option<u64>) {
+    (None, None)
+}
+
+pub(crate) fn disk_info(
+    sys: &impl SystemExt,
+    io: &mut DiskIoTracker,
+    path: PathBuf,
+) -> Result<DiskInfo> {
+    let (inodes_total, inodes_free) = inode_counts(&path);
     let disk = disk_for_path(sys.get_disks(), path)?;
+    let (read_bytes_per_sec, write_bytes_per_sec) = io.rates(Path::new(disk.get_name()));
     Ok(DiskInfo {
         available: disk.get_available_space(),
         total: disk.get_total_space(),
         disk_type: disk.get_type(),
+        inodes_total,
+        inodes_free,
+        read_bytes_per_sec,
+        write_bytes_per_sec,
     })
 }
 
@@ -90,3 +233,71 @@ impl DiskQuery {
 impl Message for DiskQuery {
     type Result = Result<DiskInfo>;
 }
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::{parse_diskstats, DiskIoCounters, DiskIoTracker};
+
+    const DISKSTATS: &str = "\
+   8       0 sda 4087 1032 352838 2316 3211 2706 88544 3952 0 3488 6268 0 0 0 0
+   8       1 sda1 3912 1032 344470 2247 3209 2706 88528 3950 0 3432 6197 0 0 0 0
+";
+
+    #[test]
+    fn test_parse_diskstats() {
+        assert_eq!(
+            parse_diskstats(DISKSTATS, "sda1"),
+            Some(DiskIoCounters {
+                read_bytes: 344470 * 512,
+                write_bytes: 88528 * 512,
+            })
+        );
+        assert_eq!(parse_diskstats(DISKSTATS, "sdb"), None);
+    }
+
+    #[test]
+    fn test_two_samples_give_non_negative_rate() {
+        let mut io = DiskIoTracker::default();
+        let start = Instant::now();
+        let counters = DiskIoCounters {
+            read_bytes: 4096,
+            write_bytes: 8192,
+        };
+
+        assert_eq!(io.sample("sda", counters, start), (None, None));
+
+        let (read, write) = io.sample(
+            "sda",
+            DiskIoCounters {
+                read_bytes: 4096 + 2048,
+                ..counters
+            },
+            start + Duration::from_secs(2),
+        );
+        assert_eq!(read, Some(1024.0));
+        assert_eq!(write, Some(0.0));
+
+        // counters reset, e.g. after the device was re-attached
+        let (read, write) = io.sample(
+            "sda",
+            DiskIoCounters {
+                read_bytes: 0,
+                write_bytes: 0,
+            },
+            start + Duration::from_secs(3),
+        );
+        assert!(read.unwrap() >= 0.0);
+        assert!(write.unwrap() >= 0.0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_inode_counts() {
+        let (total, free) = super::inode_counts(&std::env::temp_dir());
+
+        assert!(total.unwrap() > 0);
+        assert!(free.unwrap() > 0);
+    }
+}
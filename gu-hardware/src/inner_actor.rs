@@ -1,17 +1,22 @@
 use actix::{Actor, ArbiterService, Context, Handler, MessageResult, Supervised};
 use sysinfo::{self, System, SystemExt};
 
-use crate::disk::{disk_info, DiskQuery};
-use crate::ram::{ram_info, RamQuery};
+use crate::cpu::{cpu_info, CpuQuery};
+use crate::disk::{disk_info, DiskIoTracker, DiskQuery};
+use crate::ram::{RamCache, RamQuery};
 
 pub struct InnerActor {
     sys: System,
+    ram_cache: RamCache,
+    disk_io: DiskIoTracker,
 }
 
 impl Default for InnerActor {
     fn default() -> InnerActor {
         InnerActor {
             sys: sysinfo::System::new(),
+            ram_cache: RamCache::default(),
+            disk_io: DiskIoTracker::default(),
         }
     }
 }
@@ -21,6 +26,8 @@ impl Actor for InnerActor {
 
     fn started(&mut self, _ctx: &mut Self::Context) {
         self.sys = sysinfo::System::new();
+        self.ram_cache = RamCache::default();
+        self.disk_io = DiskIoTracker::default();
     }
 }
 
@@ -32,11 +39,10 @@ impl Handler<RamQuery> for InnerActor {
 
     fn handle(
         &mut self,
-        _msg: RamQuery,
+        msg: RamQuery,
         _ctx: &mut Context<Self>,
     ) -> <Self as Handler<RamQuery>>::Result {
-        self.sys.refresh_system();
-        MessageResult(Ok(ram_info(&self.sys)))
+        MessageResult(self.ram_cache.ram_info(&mut self.sys, msg.force_refresh))
     }
 }
 
@@ -49,6 +55,19 @@ impl Handler<DiskQuery> for InnerActor {
         _ctx: &mut Context<Self>,
     ) -> <Self as Handler<DiskQuery>>::Result {
         self.sys.refresh_disks();
-        MessageResult(disk_info(&self.sys, msg.path()))
+        MessageResult(disk_info(&self.sys, &mut self.disk_io, msg.path()))
+    }
+}
+
+impl Handler<CpuQuery> for InnerActor {
+    type Result = MessageResult<CpuQuery>;
+
+    fn handle(
+        &mut self,
+        _msg: CpuQuery,
+        _ctx: &mut Context<Self>,
+    ) -> <Self as Handler<CpuQuery>>::Result {
+        self.sys.refresh_system();
+        MessageResult(cpu_info(&self.sys))
     }
 }
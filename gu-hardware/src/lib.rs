@@ -8,10 +8,15 @@ use gu_net::rpc::RemotingSystemService;
 
 pub mod actor;
 pub mod gpuinfo;
+pub mod network;
+pub mod process;
+pub mod units;
 
+mod cpu;
 mod disk;
 mod inner_actor;
 mod ram;
+mod sampler;
 mod storage;
 
 pub mod error {
@@ -36,6 +41,9 @@ pub mod error {
         #[fail(display = "Couldn't find mount point of path: {:?}", _0)]
         PathMountpointNotFound(std::path::PathBuf),
 
+        #[fail(display = "Memory info not available")]
+        MemoryInfoUnavailable,
+
         #[fail(display = "Mailbox error: {}", _0)]
         Mailbox(MailboxError),
 
@@ -0,0 +1,101 @@
+//! Network interfaces inventory
+
+use std::net::IpAddr;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetworkInterface {
+    name: String,
+    addrs: Vec<IpAddr>,
+}
+
+impl NetworkInterface {
+    pub fn new(name: impl Into<String>, addrs: Vec<IpAddr>) -> Self {
+        NetworkInterface {
+            name: name.into(),
+            addrs,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn addrs(&self) -> &[IpAddr] {
+        &self.addrs
+    }
+}
+
+/// Interfaces of this host with their IP addresses
+#[cfg(unix)]
+pub fn network_interfaces() -> crate::error::Result<Vec<NetworkInterface>> {
+    use nix::sys::socket::SockAddr;
+
+    let mut interfaces: Vec<NetworkInterface> = Vec::new();
+    for ifaddr in nix::ifaddrs::getifaddrs().map_err(crate::error::Error::Nix)? {
+        let addr = match ifaddr.address {
+            Some(SockAddr::Inet(addr)) => addr.to_std().ip(),
+            _ => continue,
+        };
+        match interfaces
+            .iter_mut()
+            .find(|interface| interface.name == ifaddr.interface_name)
+        {
+            Some(interface) => interface.addrs.push(addr),
+            None => interfaces.push(NetworkInterface::new(ifaddr.interface_name, vec![addr])),
+        }
+    }
+    Ok(interfaces)
+}
+
+fn is_link_local(addr: &IpAddr) -> bool {
+    match addr {
+        IpAddr::V4(addr) => addr.is_link_local(),
+        IpAddr::V6(addr) => addr.segments()[0] & 0xffc0 == 0xfe80,
+    }
+}
+
+/// Addresses usable for advertising reachability; skips loopback,
+/// link-local and unspecified ones
+pub fn routable_addrs(interfaces: &[NetworkInterface]) -> Vec<IpAddr> {
+    interfaces
+        .iter()
+        .flat_map(|interface| interface.addrs.iter())
+        .filter(|addr| !addr.is_loopback() && !addr.is_unspecified() && !is_link_local(addr))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{routable_addrs, NetworkInterface};
+
+    #[test]
+    fn test_routable_addrs() {
+        let interfaces = vec![
+            NetworkInterface::new(
+                "lo",
+                vec!["127.0.0.1".parse().unwrap(), "::1".parse().unwrap()],
+            ),
+            NetworkInterface::new(
+                "eth0",
+                vec![
+                    "192.168.1.10".parse().unwrap(),
+                    "169.254.3.4".parse().unwrap(),
+                    "fe80::1".parse().unwrap(),
+                    "2001:db8::10".parse().unwrap(),
+                ],
+            ),
+            NetworkInterface::new("tun0", vec!["0.0.0.0".parse().unwrap()]),
+        ];
+
+        assert_eq!(
+            routable_addrs(&interfaces),
+            vec![
+                "192.168.1.10".parse::<std::net::IpAddr>().unwrap(),
+                "2001:db8::10".parse().unwrap()
+            ]
+        );
+    }
+}
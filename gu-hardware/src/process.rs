@@ -0,0 +1,49 @@
+//! Memory usage of single processes, e.g. children started by the provider
+
+use sysinfo::{Pid, ProcessExt, System, SystemExt};
+
+/// Source of per-process memory usage, in KiB
+pub trait ProcessMemorySource {
+    /// Resident set size of process `pid`; `None` if it is not running
+    fn rss(&mut self, pid: u32) -> Option<u64>;
+
+    /// Sum of resident set sizes of given processes; finished ones count as 0
+    fn total_rss(&mut self, pids: &[u32]) -> u64 {
+        pids.iter().filter_map(|&pid| self.rss(pid)).sum()
+    }
+}
+
+/// Reads process memory usage with sysinfo, refreshing only queried processes
+pub struct ProcessMemory {
+    sys: System,
+}
+
+impl Default for ProcessMemory {
+    fn default() -> Self {
+        ProcessMemory { sys: System::new() }
+    }
+}
+
+impl ProcessMemorySource for ProcessMemory {
+    fn rss(&mut self, pid: u32) -> Option<u64> {
+        let pid = pid as Pid;
+        if !self.sys.refresh_process(pid) {
+            return None;
+        }
+        self.sys.get_process(pid).map(|process| process.memory())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ProcessMemory, ProcessMemorySource};
+
+    #[test]
+    fn test_own_rss_is_reported() {
+        let mut source = ProcessMemory::default();
+
+        assert!(source.rss(std::process::id()).unwrap() > 0);
+        assert!(source.total_rss(&[std::process::id()]) > 0);
+        assert_eq!(source.total_rss(&[]), 0);
+    }
+}
@@ -1,17 +1,37 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
 use actix::Message;
 use serde::{Deserialize, Serialize};
 use sysinfo::SystemExt;
 
-use crate::error::Result;
+use crate::error::{Error, Result};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RamInfo {
     free: u64,
     used: u64,
     total: u64,
+    /// `total` is the memory limit of the container, not of the host
+    #[serde(default)]
+    container_limited: bool,
+    /// `free` plus memory the kernel can reclaim, e.g. page cache
+    #[serde(default)]
+    available: u64,
 }
 
 impl RamInfo {
+    /// Builds info from given values (in KiB), e.g. for test fixtures
+    pub fn new(free: u64, used: u64, total: u64) -> Self {
+        RamInfo {
+            free,
+            used,
+            total,
+            container_limited: false,
+            available: free,
+        }
+    }
+
     pub fn free(&self) -> u64 {
         self.free
     }
@@ -23,20 +43,418 @@ impl RamInfo {
     pub fn total(&self) -> u64 {
         self.total
     }
+
+    pub fn container_limited(&self) -> bool {
+        self.container_limited
+    }
+
+    /// Memory available for new processes without swapping; on Linux it counts
+    /// reclaimable caches (`MemAvailable`), elsewhere it equals `free`
+    pub fn available(&self) -> u64 {
+        self.available
+    }
+
+    /// Share of memory left for new work, from 0 (none or unknown total) to 1;
+    /// used by both provider and hub to compare nodes
+    pub fn capacity_score(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        let left = self.available.max(self.free) as f64 / self.total as f64;
+        left.max(0.0).min(1.0)
+    }
+}
+
+/// How long refreshed memory statistics are reused by non-forced queries
+const RAM_CACHE_TTL: Duration = Duration::from_secs(1);
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Source of memory statistics, in KiB
+pub(crate) trait MemorySource {
+    fn refresh(&mut self);
+    fn free_memory(&self) -> u64;
+    fn used_memory(&self) -> u64;
+    fn total_memory(&self) -> u64;
+}
+
+impl<S: SystemExt> MemorySource for S {
+    fn refresh(&mut self) {
+        self.refresh_system()
+    }
+
+    fn free_memory(&self) -> u64 {
+        self.get_free_memory()
+    }
+
+    fn used_memory(&self) -> u64 {
+        self.get_used_memory()
+    }
+
+    fn total_memory(&self) -> u64 {
+        self.get_total_memory()
+    }
+}
+
+pub(crate) fn ram_info(sys: &impl MemorySource) -> Result<RamInfo> {
+    ram_info_or_else(sys, proc_meminfo)
+        .map(|info| with_available(info, proc_mem_available()))
+        .map(|info| limit_to_cgroup(info, cgroup_memory()))
+}
+
+/// Sets available memory, never reporting less than `free`
+fn with_available(info: RamInfo, available: Option<u64>) -> RamInfo {
+    RamInfo {
+        available: available.unwrap_or(info.free).max(info.free),
+        ..info
+    }
 }
 
-pub(crate) fn ram_info(sys: &impl SystemExt) -> RamInfo {
+/// Tracks when memory statistics were last refreshed
+#[derive(Default)]
+pub(crate) struct RamCache {
+    refreshed: Option<Instant>,
+}
+
+impl RamCache {
+    pub(crate) fn ram_info(
+        &mut self,
+        source: &mut impl MemorySource,
+        force_refresh: bool,
+    ) -> Result<RamInfo> {
+        let fresh = match self.refreshed {
+            Some(refreshed) => refreshed.elapsed() < RAM_CACHE_TTL,
+            None => false,
+        };
+        if force_refresh || !fresh {
+            source.refresh();
+            self.refreshed = Some(Instant::now());
+        }
+        ram_info(source)
+    }
+}
+
+/// Uses `fallback` when `source` reports implausible zero total memory,
+/// as sysinfo does in some containers
+fn ram_info_or_else(
+    source: &impl MemorySource,
+    fallback: impl FnOnce() -> Option<RamInfo>,
+) -> Result<RamInfo> {
     // TODO: this is not refreshing and not providing accurate numbers
+    let info = RamInfo::new(
+        source.free_memory(),
+        source.used_memory(),
+        source.total_memory(),
+    );
+    if info.total > 0 {
+        return Ok(info);
+    }
+
+    warn!("zero total memory reported; trying fallback");
+    match fallback() {
+        Some(info) if info.total > 0 => Ok(info),
+        _ => Err(Error::MemoryInfoUnavailable),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn proc_meminfo() -> Option<RamInfo> {
+    parse_meminfo(&std::fs::read_to_string("/proc/meminfo").ok()?)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn proc_meminfo() -> Option<RamInfo> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn proc_mem_available() -> Option<u64> {
+    meminfo_value(
+        &std::fs::read_to_string("/proc/meminfo").ok()?,
+        "MemAvailable",
+    )
+}
+
+#[cfg(not(target_os = "linux"))]
+fn proc_mem_available() -> Option<u64> {
+    None
+}
+
+/// Value of `key` from `/proc/meminfo` content, in KiB
+#[cfg_attr(not(any(target_os = "linux", test)), allow(dead_code))]
+fn meminfo_value(content: &str, key: &str) -> Option<u64> {
+    content
+        .lines()
+        .find(|line| line.starts_with(key) && line[key.len()..].starts_with(':'))
+        .and_then(|line| line[key.len() + 1..].split_whitespace().next())
+        .and_then(|v| v.parse::<u64>().ok())
+}
+
+#[cfg_attr(not(any(target_os = "linux", test)), allow(dead_code))]
+fn parse_meminfo(content: &str) -> Option<RamInfo> {
+    let value = |key: &str| meminfo_value(content, key);
+
+    let total = value("MemTotal")?;
+    let free = value("MemAvailable").or_else(|| value("MemFree"))?;
+    Some(RamInfo::new(free, total.saturating_sub(free), total))
+}
+
+/// Memory limit and usage of the cgroup this process runs in, in bytes
+struct CgroupMemory {
+    limit: u64,
+    usage: Option<u64>,
+}
+
+#[cfg(target_os = "linux")]
+fn cgroup_memory() -> Option<CgroupMemory> {
+    read_cgroup_memory(Path::new(CGROUP_ROOT))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn cgroup_memory() -> Option<CgroupMemory> {
+    None
+}
+
+/// Reads cgroup v2 `memory.max`, falling back to cgroup v1 memory controller
+#[cfg_attr(not(any(target_os = "linux", test)), allow(dead_code))]
+fn read_cgroup_memory(root: &Path) -> Option<CgroupMemory> {
+    let value = |file: &str| {
+        std::fs::read_to_string(root.join(file))
+            .ok()
+            .and_then(|v| v.trim().parse::<u64>().ok())
+    };
+
+    if root.join("memory.max").exists() {
+        // `max` means no limit
+        return value("memory.max").map(|limit| CgroupMemory {
+            limit,
+            usage: value("memory.current"),
+        });
+    }
+    value("memory/memory.limit_in_bytes").map(|limit| CgroupMemory {
+        limit,
+        usage: value("memory/memory.usage_in_bytes"),
+    })
+}
+
+/// Reports cgroup limit as total memory when it is lower than the host one
+fn limit_to_cgroup(info: RamInfo, cgroup: Option<CgroupMemory>) -> RamInfo {
+    let cgroup = match cgroup {
+        Some(cgroup) if cgroup.limit / 1024 < info.total => cgroup,
+        _ => return info,
+    };
+
+    let total = cgroup.limit / 1024;
+    let used = cgroup
+        .usage
+        .map(|usage| usage / 1024)
+        .unwrap_or(info.used)
+        .min(total);
+    let free = total - used;
     RamInfo {
-        free: sys.get_free_memory(),
-        used: sys.get_used_memory(),
-        total: sys.get_total_memory(),
+        free,
+        used,
+        total,
+        container_limited: true,
+        available: info.available.min(total).max(free),
     }
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
-pub struct RamQuery;
+pub struct RamQuery {
+    /// refreshes statistics even if cached ones are recent
+    #[serde(default)]
+    pub force_refresh: bool,
+}
 
 impl Message for RamQuery {
     type Result = Result<RamInfo>;
 }
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::{
+        limit_to_cgroup, parse_meminfo, ram_info_or_else, read_cgroup_memory, with_available,
+        MemorySource, RamCache, RamInfo,
+    };
+    use crate::error::Error;
+
+    struct Fixed(u64, u64, u64);
+
+    impl MemorySource for Fixed {
+        fn refresh(&mut self) {}
+
+        fn free_memory(&self) -> u64 {
+            self.0
+        }
+
+        fn used_memory(&self) -> u64 {
+            self.1
+        }
+
+        fn total_memory(&self) -> u64 {
+            self.2
+        }
+    }
+
+    #[test]
+    fn test_zero_total_without_fallback_is_error() {
+        match ram_info_or_else(&Fixed(0, 0, 0), || None) {
+            Err(Error::MemoryInfoUnavailable) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_zero_total_uses_fallback() {
+        let fallback = || Some(RamInfo::new(1, 2, 3));
+
+        assert_eq!(
+            ram_info_or_else(&Fixed(0, 0, 0), fallback).unwrap().total(),
+            3
+        );
+        assert_eq!(
+            ram_info_or_else(&Fixed(4, 4, 8), fallback).unwrap().total(),
+            8
+        );
+    }
+
+    /// Applies `next` free memory on refresh
+    struct Changing {
+        current: Fixed,
+        next: u64,
+    }
+
+    impl MemorySource for Changing {
+        fn refresh(&mut self) {
+            self.current.0 = self.next;
+        }
+
+        fn free_memory(&self) -> u64 {
+            self.current.free_memory()
+        }
+
+        fn used_memory(&self) -> u64 {
+            self.current.used_memory()
+        }
+
+        fn total_memory(&self) -> u64 {
+            self.current.total_memory()
+        }
+    }
+
+    #[test]
+    fn test_forced_query_refreshes_cache() {
+        let mut cache = RamCache::default();
+        let mut source = Changing {
+            current: Fixed(0, 4, 8),
+            next: 4,
+        };
+
+        assert_eq!(cache.ram_info(&mut source, false).unwrap().free(), 4);
+
+        source.next = 2;
+        assert_eq!(cache.ram_info(&mut source, false).unwrap().free(), 4);
+        assert_eq!(cache.ram_info(&mut source, true).unwrap().free(), 2);
+    }
+
+    #[test]
+    fn test_parse_meminfo() {
+        let info = parse_meminfo(
+            "MemTotal:       16303428 kB\n\
+             MemFree:         1095864 kB\n\
+             MemAvailable:    9101316 kB\n",
+        )
+        .unwrap();
+
+        assert_eq!(info.total(), 16303428);
+        assert_eq!(info.free(), 9101316);
+        assert_eq!(info.used(), 16303428 - 9101316);
+        assert!(parse_meminfo("MemFree: 1 kB\n").is_none());
+    }
+
+    fn host_info() -> RamInfo {
+        RamInfo::new(12 << 20, 4 << 20, 16 << 20)
+    }
+
+    #[test]
+    fn test_available_is_not_below_free() {
+        let info = with_available(RamInfo::new(4, 4, 8), Some(6));
+        assert_eq!(info.available(), 6);
+        assert_eq!(info.free(), 4);
+        assert_eq!(
+            with_available(RamInfo::new(4, 4, 8), Some(2)).available(),
+            4
+        );
+        assert_eq!(with_available(RamInfo::new(4, 4, 8), None).available(), 4);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_linux_available_includes_reclaimable() {
+        use sysinfo::SystemExt;
+
+        let mut sys = sysinfo::System::new();
+        sys.refresh_system();
+        let info = super::ram_info(&sys).unwrap();
+
+        assert!(super::proc_mem_available().is_some());
+        assert!(info.available() >= info.free());
+        assert!(info.available() <= info.total());
+    }
+
+    #[test]
+    fn test_new_from_values() {
+        let info = RamInfo::new(3, 5, 8);
+
+        assert_eq!(info.free(), 3);
+        assert_eq!(info.used(), 5);
+        assert_eq!(info.total(), 8);
+        assert!(!info.container_limited());
+    }
+
+    #[test]
+    fn test_capacity_score() {
+        assert_eq!(RamInfo::new(2, 6, 8).capacity_score(), 0.25);
+        assert_eq!(
+            with_available(RamInfo::new(2, 6, 8), Some(6)).capacity_score(),
+            0.75
+        );
+        assert_eq!(RamInfo::new(10, 0, 8).capacity_score(), 1.0);
+        assert_eq!(RamInfo::new(0, 0, 0).capacity_score(), 0.0);
+    }
+
+    #[test]
+    fn test_cgroup_v2_limit_is_used() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(root.path().join("memory.max"), "2147483648\n").unwrap();
+        fs::write(root.path().join("memory.current"), "536870912\n").unwrap();
+
+        let info = limit_to_cgroup(host_info(), read_cgroup_memory(root.path()));
+        assert!(info.container_limited());
+        assert_eq!(info.total(), 2 << 20);
+        assert_eq!(info.used(), 512 << 10);
+        assert_eq!(info.free(), (2 << 20) - (512 << 10));
+    }
+
+    #[test]
+    fn test_cgroup_v1_unlimited_keeps_host_total() {
+        let root = tempfile::tempdir().unwrap();
+        fs::create_dir(root.path().join("memory")).unwrap();
+        fs::write(
+            root.path().join("memory/memory.limit_in_bytes"),
+            "9223372036854771712\n",
+        )
+        .unwrap();
+
+        let info = limit_to_cgroup(host_info(), read_cgroup_memory(root.path()));
+        assert!(!info.container_limited());
+        assert_eq!(info.total(), 16 << 20);
+
+        fs::write(root.path().join("memory.max"), "max\n").unwrap();
+        assert!(read_cgroup_memory(root.path()).is_none());
+    }
+}
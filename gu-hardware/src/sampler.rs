@@ -0,0 +1,190 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use actix::{
+    Actor, ActorFuture, ArbiterService, AsyncContext, Context, Handler, Message, MessageResult,
+    WrapFuture,
+};
+use serde::{Deserialize, Serialize};
+
+use gu_actix::flatten::FlattenFuture;
+
+use crate::inner_actor::InnerActor;
+use crate::ram::RamQuery;
+
+const DEFAULT_WINDOW: usize = 60;
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Memory usage statistics over the sampling window, in KiB
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemoryTrend {
+    samples: usize,
+    min_used: u64,
+    max_used: u64,
+    avg_used: u64,
+    /// change of used memory, in KiB per second
+    slope: f64,
+}
+
+impl MemoryTrend {
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    pub fn min_used(&self) -> u64 {
+        self.min_used
+    }
+
+    pub fn max_used(&self) -> u64 {
+        self.max_used
+    }
+
+    pub fn avg_used(&self) -> u64 {
+        self.avg_used
+    }
+
+    pub fn slope(&self) -> f64 {
+        self.slope
+    }
+}
+
+/// Returns `None` until the first sample is taken
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MemoryTrendQuery;
+
+impl Message for MemoryTrendQuery {
+    type Result = Option<MemoryTrend>;
+}
+
+/// Periodically samples used memory, retaining the last `window` samples
+pub struct MemorySampler {
+    window: usize,
+    interval: Duration,
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl Default for MemorySampler {
+    fn default() -> Self {
+        MemorySampler::new(DEFAULT_WINDOW, DEFAULT_INTERVAL)
+    }
+}
+
+impl MemorySampler {
+    pub fn new(window: usize, interval: Duration) -> Self {
+        let window = window.max(1);
+
+        MemorySampler {
+            window,
+            interval,
+            samples: VecDeque::with_capacity(window),
+        }
+    }
+
+    fn push(&mut self, at: Instant, used: u64) {
+        if self.samples.len() == self.window {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((at, used));
+    }
+
+    /// Least-squares slope of used memory against time
+    fn trend(&self) -> Option<MemoryTrend> {
+        let (start, _) = *self.samples.front()?;
+        let n = self.samples.len() as f64;
+        let points: Vec<(f64, f64)> = self
+            .samples
+            .iter()
+            .map(|&(at, used)| {
+                let elapsed = at.duration_since(start);
+                let secs = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) * 1e-9;
+                (secs, used as f64)
+            })
+            .collect();
+
+        let mean_x = points.iter().map(|&(x, _)| x).sum::<f64>() / n;
+        let mean_y = points.iter().map(|&(_, y)| y).sum::<f64>() / n;
+        let (cov, var) = points.iter().fold((0.0, 0.0), |(cov, var), &(x, y)| {
+            (
+                cov + (x - mean_x) * (y - mean_y),
+                var + (x - mean_x) * (x - mean_x),
+            )
+        });
+
+        let used = || self.samples.iter().map(|&(_, used)| used);
+        Some(MemoryTrend {
+            samples: self.samples.len(),
+            min_used: used().min()?,
+            max_used: used().max()?,
+            avg_used: mean_y.round() as u64,
+            slope: if var > 0.0 { cov / var } else { 0.0 },
+        })
+    }
+
+    fn sample(&mut self, ctx: &mut Context<Self>) {
+        ctx.spawn(
+            InnerActor::from_registry()
+                .send(RamQuery {
+                    force_refresh: true,
+                })
+                .flatten_fut()
+                .into_actor(self)
+                .map(|info, act, _| act.push(Instant::now(), info.used()))
+                .map_err(|e, _, _| warn!("memory sampling failed: {}", e)),
+        );
+    }
+}
+
+impl Actor for MemorySampler {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.sample(ctx);
+        ctx.run_interval(self.interval, |act, ctx| act.sample(ctx));
+    }
+}
+
+impl Handler<MemoryTrendQuery> for MemorySampler {
+    type Result = MessageResult<MemoryTrendQuery>;
+
+    fn handle(
+        &mut self,
+        _msg: MemoryTrendQuery,
+        _ctx: &mut Context<Self>,
+    ) -> <Self as Handler<MemoryTrendQuery>>::Result {
+        MessageResult(self.trend())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::MemorySampler;
+
+    #[test]
+    fn test_rising_samples_have_positive_slope() {
+        let mut sampler = MemorySampler::new(4, Duration::from_secs(1));
+        let start = Instant::now();
+        assert!(sampler.trend().is_none());
+
+        for (i, used) in [900, 100, 200, 300, 400].iter().enumerate() {
+            sampler.push(start + Duration::from_secs(i as u64), *used);
+        }
+
+        let trend = sampler.trend().unwrap();
+        assert_eq!(trend.samples(), 4);
+        assert_eq!(trend.min_used(), 100);
+        assert_eq!(trend.max_used(), 400);
+        assert_eq!(trend.avg_used(), 250);
+        assert!(trend.slope() > 0.0);
+        assert!((trend.slope() - 100.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_single_sample_is_flat() {
+        let mut sampler = MemorySampler::new(4, Duration::from_secs(1));
+        sampler.push(Instant::now(), 100);
+
+        assert_eq!(sampler.trend().unwrap().slope(), 0.0);
+    }
+}
@@ -1,20 +1,32 @@
-use std::path::PathBuf;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    rc::Rc,
+    thread,
+    time::SystemTime,
+};
 
 use actix::prelude::*;
 use failure::Fail;
-use futures::prelude::*;
-use futures::sync::oneshot::Canceled;
+use futures::sync::oneshot::{self, Canceled};
+use futures::{future, prelude::*};
+use log::warn;
 
 use gu_model::envman::Image;
-use gu_model::hash::{Error as HashParseError, ParsedHash};
+use gu_model::hash::{ContentChecker, Error as HashParseError, ParsedHash};
 
 use super::cache::{resolve, CacheProvider};
-use super::download::DownloadOptionsBuilder;
+use super::download::{DownloadOptionsBuilder, Error as DownloadError, ProgressStatus};
 
 #[derive(Clone, Debug, Fail)]
 pub enum Error {
     #[fail(display = "{}", _0)]
     Other(String),
+    #[fail(display = "invalid response status: {}", _0)]
+    HttpStatus(u16),
 }
 
 impl From<MailboxError> for Error {
@@ -35,49 +47,347 @@ impl From<HashParseError> for Error {
     }
 }
 
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Other(format!("{}", e))
+    }
+}
+
+/// Replaces every character that is not safe in a file name with `_`.
+fn sanitize_file_name(name: &str) -> Result<String, Error> {
+    let name: String = name
+        .chars()
+        .map(|ch| match ch {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' | '_' => ch,
+            _ => '_',
+        })
+        .collect();
+
+    if name.is_empty() || name.chars().all(|ch| ch == '.') {
+        return Err(Error::Other(format!("invalid cache file name: {:?}", name)));
+    }
+    Ok(name)
+}
+
+/// Cache file name for the image.
+///
+/// Explicit `cache_name`s are namespaced by image url, so that the same name
+/// used for different urls does not collide.
+fn cache_file_name(image: &Image) -> Result<String, Error> {
+    match image.cache_name {
+        Some(ref name) => Ok(format!(
+            "{:08x}-{}",
+            crc::crc32::checksum_ieee(image.url.as_bytes()),
+            sanitize_file_name(name)?
+        )),
+        None => {
+            let hash = image.hash.to_hash_str();
+            let h = ParsedHash::from_hash_bytes(hash.as_bytes())?;
+            sanitize_file_name(&h.to_path()?.to_string_lossy())
+        }
+    }
+}
+
+/// Checks if content of the file matches declared hash.
+fn verify_file(path: &Path, hash: &str) -> Result<bool, Error> {
+    let mut checker = ParsedHash::from_hash_bytes(hash.as_bytes())?.checker()?;
+    let mut file = fs::File::open(path)?;
+    let mut buf = [0u8; 8192];
+
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        checker.update(&buf[..n]);
+    }
+    Ok(checker.verify())
+}
+
+/// Same as `verify_file`, but the file is hashed on a separate thread,
+/// so that verification of a large image does not block the event loop
+fn verify_file_async(path: PathBuf, hash: String) -> impl Future<Item = bool, Error = Error> {
+    let (tx, rx) = oneshot::channel();
+    thread::spawn(move || {
+        let _ = tx.send(verify_file(&path, &hash));
+    });
+
+    rx.from_err().and_then(|r| r)
+}
+
+/// Size and modification time of a file, as of its last verification
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    len: u64,
+    modified: SystemTime,
+}
+
+impl FileStamp {
+    fn of(path: &Path) -> io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        Ok(FileStamp {
+            len: metadata.len(),
+            modified: metadata.modified()?,
+        })
+    }
+}
+
+/// Stamps of cached images verified against their hash; an image is hashed
+/// again only if it changed on disk since
 #[derive(Clone, Default)]
-struct ImageCacheProvider;
+struct VerifiedImages(Rc<RefCell<HashMap<PathBuf, FileStamp>>>);
+
+impl VerifiedImages {
+    fn contains(&self, path: &Path) -> bool {
+        match (self.0.borrow().get(path), FileStamp::of(path)) {
+            (Some(stamp), Ok(current)) => *stamp == current,
+            _ => false,
+        }
+    }
+
+    fn insert(&self, path: &Path) {
+        match FileStamp::of(path) {
+            Ok(stamp) => {
+                self.0.borrow_mut().insert(path.to_path_buf(), stamp);
+            }
+            Err(e) => warn!("cannot stamp image {}: {}", path.display(), e),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct ImageKey {
+    file_name: String,
+    hash: String,
+    /// cache directory of the image; the provider's one if not given
+    dir: Option<PathBuf>,
+}
+
+/// Called with the progress of an image download
+pub type DownloadProgress = Box<dyn FnMut(&ProgressStatus) + Send>;
+
+struct ImageHint {
+    image: Image,
+    progress: Option<DownloadProgress>,
+}
+
+#[derive(Clone, Default)]
+struct ImageCacheProvider {
+    cache_dir: Option<PathBuf>,
+    verified: VerifiedImages,
+}
+
+/// Directory downloaded images are cached in
+pub fn cache_dir() -> PathBuf {
+    gu_persist::config::ConfigModule::new()
+        .cache_dir()
+        .to_path_buf()
+}
 
 impl ImageCacheProvider {
-    fn path(&self, hash: &str) -> Result<PathBuf, Error> {
-        let h = ParsedHash::from_hash_bytes(hash.as_bytes())?;
-        Ok(gu_persist::config::ConfigModule::new()
-            .cache_dir()
-            .join(&h.to_path()?))
+    fn path(&self, key: &ImageKey) -> PathBuf {
+        match (&key.dir, &self.cache_dir) {
+            (Some(dir), _) | (None, Some(dir)) => dir.join(&key.file_name),
+            (None, None) => cache_dir().join(&key.file_name),
+        }
     }
 }
 
 impl CacheProvider for ImageCacheProvider {
-    type Key = String;
-    type Hint = Image;
+    type Key = ImageKey;
+    type Hint = ImageHint;
     type Value = PathBuf;
     type Error = Error;
-    type CheckResult = Result<Option<PathBuf>, Error>;
+    type CheckResult = Box<dyn Future<Item = Option<PathBuf>, Error = Error>>;
     type FetchResult = Box<dyn Future<Item = PathBuf, Error = Error>>;
 
+    /// Images are verified once, when downloaded or first found in the cache
     fn try_get(&self, key: &Self::Key) -> Self::CheckResult {
-        let p = self.path(key)?;
+        let p = self.path(key);
 
-        if p.exists() {
-            Ok(Some(p))
-        } else {
-            Ok(None)
+        if !p.exists() {
+            return Box::new(future::ok(None));
         }
+        if self.verified.contains(&p) {
+            return Box::new(future::ok(Some(p)));
+        }
+
+        let verified = self.verified.clone();
+        let hash = key.hash.clone();
+        Box::new(
+            verify_file_async(p.clone(), key.hash.clone()).and_then(move |matches| {
+                if matches {
+                    verified.insert(&p);
+                    return Ok(Some(p));
+                }
+                warn!(
+                    "cached image {} does not match hash {}; downloading again",
+                    p.display(),
+                    hash
+                );
+                fs::remove_file(&p)?;
+                Ok(None)
+            }),
+        )
     }
 
-    fn fetch(&mut self, hash: Self::Key, image: Self::Hint) -> Self::FetchResult {
-        let p = self.path(&hash).unwrap();
+    fn fetch(&mut self, key: Self::Key, hint: Self::Hint) -> Self::FetchResult {
+        let p = self.path(&key);
+        let (download_path, hash) = (p.clone(), key.hash.clone());
+        let verified = self.verified.clone();
+        let mut progress = hint.progress;
 
         Box::new(
             DownloadOptionsBuilder::default()
-                .download(&image.url, p.to_string_lossy().into())
-                .for_each(|progress| Ok(eprintln!("progress={:?}", progress)))
-                .and_then(|_v| Ok(p))
-                .map_err(|e| Error::Other(format!("{}", e))),
+                .download(&hint.image.url, p.to_string_lossy().into())
+                .for_each(move |status| {
+                    if let Some(ref mut progress) = progress {
+                        progress(&status)
+                    }
+                    Ok(())
+                })
+                .map_err(|e| match e {
+                    DownloadError::HttpStatus(code) => Error::HttpStatus(code),
+                    e => Error::Other(format!("{}", e)),
+                })
+                .and_then(move |_| verify_file_async(download_path, hash))
+                .and_then(move |matches| {
+                    if matches {
+                        verified.insert(&p);
+                        return Ok(p);
+                    }
+                    fs::remove_file(&p)?;
+                    Err(Error::Other(format!(
+                        "downloaded image does not match hash {}",
+                        key.hash
+                    )))
+                }),
         )
     }
 }
 
 pub fn image(spec: Image) -> impl Future<Item = PathBuf, Error = Error> {
-    resolve::<ImageCacheProvider>(spec.hash.clone(), spec)
+    cached_image(spec, None, None)
+}
+
+/// Same as [`image`], but caches the image in given directory
+pub fn image_in(spec: Image, dir: PathBuf) -> impl Future<Item = PathBuf, Error = Error> {
+    cached_image(spec, Some(dir), None)
+}
+
+/// Same as [`image_in`], calling `progress` while the image is downloaded.
+/// Concurrent fetches of the same image share one download, reported
+/// only to the `progress` of the first one.
+pub fn image_in_with_progress<F>(
+    spec: Image,
+    dir: PathBuf,
+    progress: F,
+) -> impl Future<Item = PathBuf, Error = Error>
+where
+    F: FnMut(&ProgressStatus) + Send + 'static,
+{
+    cached_image(spec, Some(dir), Some(Box::new(progress)))
+}
+
+fn cached_image(
+    spec: Image,
+    dir: Option<PathBuf>,
+    progress: Option<DownloadProgress>,
+) -> impl Future<Item = PathBuf, Error = Error> {
+    let key = cache_file_name(&spec).map(|file_name| ImageKey {
+        file_name,
+        hash: spec.hash.to_hash_str(),
+        dir,
+    });
+    let hint = ImageHint {
+        image: spec,
+        progress,
+    };
+
+    future::result(key).and_then(|key| resolve::<ImageCacheProvider>(key, hint))
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use super::*;
+
+    const CONTENT_HASH: &str = "SHA1:a9993e364706816aba3e25717850c26c9cd0d89d";
+
+    fn image(cache_name: Option<&str>) -> Image {
+        Image {
+            url: "http://localhost/image.hdi".into(),
+            hash: CONTENT_HASH.into(),
+            cache_name: cache_name.map(Into::into),
+        }
+    }
+
+    #[test]
+    fn test_cache_file_name_is_sanitized() {
+        assert_eq!(
+            cache_file_name(&image(None)).unwrap(),
+            "SHA1---a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+
+        let name = cache_file_name(&image(Some("../etc/passwd"))).unwrap();
+        assert!(name.ends_with("-.._etc_passwd"));
+        assert!(!name.contains('/'));
+        assert!(cache_file_name(&image(Some(".."))).is_err());
+    }
+
+    #[test]
+    fn test_stale_cache_entry_is_downloaded_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let provider = ImageCacheProvider {
+            cache_dir: Some(dir.path().into()),
+        };
+        let key = ImageKey {
+            file_name: cache_file_name(&image(None)).unwrap(),
+            hash: CONTENT_HASH.into(),
+            dir: None,
+        };
+
+        fs::write(provider.path(&key), b"stale").unwrap();
+        assert!(provider.try_get(&key).wait().unwrap().is_none());
+        assert!(!provider.path(&key).exists());
+
+        fs::write(provider.path(&key), b"abc").unwrap();
+        assert_eq!(
+            provider.try_get(&key).wait().unwrap(),
+            Some(provider.path(&key))
+        );
+        assert!(provider.verified.contains(&provider.path(&key)));
+
+        // a verified image changed on disk is verified again
+        fs::write(provider.path(&key), b"abcd").unwrap();
+        assert!(!provider.verified.contains(&provider.path(&key)));
+        assert!(provider.try_get(&key).wait().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_verify_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("abc");
+        fs::write(&path, b"abc").unwrap();
+
+        let hashes = [
+            "SHA1:a9993e364706816aba3e25717850c26c9cd0d89d",
+            "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            "blake2b:ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d1\
+             7d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923",
+            "SHA3:3a985da74fe225b2045c172d6bd390bd855f086e3e9d525b46bfe24511431532",
+        ];
+        for hash in hashes.iter() {
+            assert!(verify_file(&path, hash).unwrap());
+            assert!(verify_file_async(path.clone(), hash.to_string())
+                .wait()
+                .unwrap());
+        }
+
+        fs::write(&path, b"abd").unwrap();
+        for hash in hashes.iter() {
+            assert!(!verify_file(&path, hash).unwrap());
+        }
+    }
 }
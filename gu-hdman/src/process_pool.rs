@@ -119,11 +119,14 @@ impl ProcessPool {
         Ok(())
     }
 
-    fn kill_all(&mut self) {
+    /// Returns number of processes signalled to terminate
+    fn kill_all(&mut self) -> usize {
         // TODO: log error
-        self.exec_processes.drain().for_each(|(_pid, tx)| {
-            let _ = tx.send(());
-        });
+        self.exec_processes
+            .drain()
+            .map(|(_pid, tx)| tx.send(()))
+            .filter(Result::is_ok)
+            .count()
     }
 
     fn spawn_child(&mut self, ctx: &mut <Self as Actor>::Context, child: Child) -> Pid {
@@ -153,7 +156,7 @@ impl ProcessPool {
                 .then(move |_r, act, _ctx| {
                     act.exec_processes.remove(&pid);
                     if Some(pid) == act.main_process {
-                        act.kill_all()
+                        act.kill_all();
                     }
                     fut::ok(())
                 }),
@@ -211,11 +214,11 @@ impl Handler<Stop> for ProcessPool {
 pub struct KillAll;
 
 impl Message for KillAll {
-    type Result = Result<(), envman::Error>;
+    type Result = Result<usize, envman::Error>;
 }
 
 impl Handler<KillAll> for ProcessPool {
-    type Result = Result<(), envman::Error>;
+    type Result = Result<usize, envman::Error>;
 
     fn handle(&mut self, msg: KillAll, ctx: &mut Self::Context) -> Self::Result {
         Ok(self.kill_all())
@@ -98,6 +98,7 @@ pub fn scope<S: 'static>(scope: Scope<S>) -> Scope<S> {
                         .send(SessionUpdate {
                             session_id: path.into_inner().deployment_id,
                             commands: commands.into_inner(),
+                            on_error: Default::default(),
                         })
                         .map_err(|e| match e {
                             SendError::NoDestination => {
@@ -8,6 +8,7 @@ use std::{
 
 use actix::{Actor, Context, Handler, Message, MessageResult, Supervised, SystemService};
 use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use log::{error, info, warn};
 use semver::Version;
 
@@ -17,7 +18,8 @@ use gu_persist::config::ConfigModule;
 use super::{
     parser::{BytesPluginParser, PluginParser, ZipParser},
     plugin::{
-        DirectoryHandler, Plugin, PluginEvent, PluginHandler, PluginInfo, PluginStatus, ZipHandler,
+        DirectoryHandler, Plugin, PluginDirEntry, PluginEvent, PluginHandler, PluginInfo,
+        PluginMetadata, PluginSource, PluginStatus, ZipHandler,
     },
     rest_result::InstallQueryResult,
 };
@@ -46,6 +48,14 @@ impl Default for PluginManager {
 }
 
 impl PluginManager {
+    /// Manager keeping plugin files in given directory instead of the work dir
+    pub fn with_directory(directory: PathBuf) -> Self {
+        Self {
+            directory: Some(directory),
+            ..Self::default()
+        }
+    }
+
     fn directory(&mut self) -> &PathBuf {
         if self.directory.is_none() {
             self.directory = Some(ConfigModule::new().work_dir().join("plugins"));
@@ -57,10 +67,15 @@ impl PluginManager {
         }
     }
 
-    fn install_plugin<T: 'static + PluginHandler>(&mut self, handler: T) -> InstallQueryResult {
+    fn install_plugin<T: 'static + PluginHandler>(
+        &mut self,
+        handler: T,
+        source: PluginSource,
+        installed_at: DateTime<Utc>,
+    ) -> InstallQueryResult {
         use super::rest_result::InstallQueryResult::*;
 
-        let mut plugin = Plugin::new(handler);
+        let mut plugin = Plugin::new(handler, source, installed_at);
         plugin.activate();
 
         plugin
@@ -91,12 +106,33 @@ impl PluginManager {
 
     fn load_zip(&mut self, name: &str) -> InstallQueryResult {
         let path = self.directory().join(name.to_string());
+        // plugin file is written on upload, so it keeps the install time across restarts
+        let installed_at = fs::metadata(&path)
+            .and_then(|meta| meta.modified())
+            .map(DateTime::<Utc>::from)
+            .unwrap_or_else(|_| Utc::now());
+
         ZipHandler::new(&path, self.gu_version.clone())
             .map_err(|e| InstallQueryResult::InvalidFile(e))
-            .map(|handler| self.install_plugin(handler))
+            .map(|handler| self.install_plugin(handler, PluginSource::Upload, installed_at))
             .unwrap_or_else(|e| e)
     }
 
+    /// Info of the plugin, if `result` of its installation is successful
+    fn installed_info(
+        &self,
+        name: &str,
+        result: InstallQueryResult,
+    ) -> Result<PluginInfo, InstallQueryResult> {
+        match result {
+            InstallQueryResult::Installed | InstallQueryResult::Overwritten => self
+                .plugin(name)
+                .and_then(|plugin| plugin.info())
+                .map_err(|e| InstallQueryResult::InvalidMetadata(e)),
+            e => Err(e),
+        }
+    }
+
     fn save_plugin_file(&mut self, name: &str, bytes: &[u8]) -> Result<(), InstallQueryResult> {
         use self::InstallQueryResult::*;
         use std::path::Path;
@@ -216,6 +252,87 @@ impl Handler<PluginFile> for PluginManager {
     }
 }
 
+/// GET PLUGIN MANIFEST
+#[derive(Debug)]
+pub struct GetPluginManifest {
+    pub plugin: String,
+}
+
+impl Message for GetPluginManifest {
+    type Result = Result<PluginMetadata, String>;
+}
+
+impl Handler<GetPluginManifest> for PluginManager {
+    type Result = MessageResult<GetPluginManifest>;
+
+    fn handle(
+        &mut self,
+        msg: GetPluginManifest,
+        _ctx: &mut Context<Self>,
+    ) -> <Self as Handler<GetPluginManifest>>::Result {
+        MessageResult(self.plugin(&msg.plugin).and_then(|plug| plug.metadata()))
+    }
+}
+
+/// LIST PLUGIN DIRECTORY
+#[derive(Debug)]
+pub struct ListPluginDir {
+    pub plugin: String,
+    /// directory within the plugin, empty for its root
+    pub path: PathBuf,
+}
+
+impl Message for ListPluginDir {
+    type Result = Result<Vec<PluginDirEntry>, String>;
+}
+
+impl Handler<ListPluginDir> for PluginManager {
+    type Result = MessageResult<ListPluginDir>;
+
+    fn handle(
+        &mut self,
+        msg: ListPluginDir,
+        _ctx: &mut Context<Self>,
+    ) -> <Self as Handler<ListPluginDir>>::Result {
+        MessageResult(
+            self.plugin(&msg.plugin)
+                .and_then(|plug| plug.list_dir(&msg.path)),
+        )
+    }
+}
+
+/// GET PLUGIN DEFAULT FILE
+#[derive(Debug)]
+pub struct PluginIndexFile {
+    pub plugin: String,
+    /// directory within the plugin, empty for its root
+    pub dir: PathBuf,
+}
+
+impl Message for PluginIndexFile {
+    /// path and content of the index file, `None` if the plugin has none there
+    type Result = Result<Option<(PathBuf, Vec<u8>)>, String>;
+}
+
+impl Handler<PluginIndexFile> for PluginManager {
+    type Result = MessageResult<PluginIndexFile>;
+
+    fn handle(
+        &mut self,
+        msg: PluginIndexFile,
+        _ctx: &mut Context<Self>,
+    ) -> <Self as Handler<PluginIndexFile>>::Result {
+        MessageResult(self.plugin(&msg.plugin).and_then(|plug| {
+            let path = msg.dir.join(plug.metadata()?.index());
+            match plug.file(&path) {
+                Ok(data) => Ok(Some((path, data))),
+                Err(_) if plug.status() == PluginStatus::Active => Ok(None),
+                Err(e) => Err(e),
+            }
+        }))
+    }
+}
+
 /// INSTALL PLUGIN
 #[derive(Debug)]
 pub struct InstallPlugin {
@@ -223,7 +340,8 @@ pub struct InstallPlugin {
 }
 
 impl Message for InstallPlugin {
-    type Result = InstallQueryResult;
+    /// info of the installed plugin
+    type Result = Result<PluginInfo, InstallQueryResult>;
 }
 
 impl Handler<InstallPlugin> for PluginManager {
@@ -246,10 +364,67 @@ impl Handler<InstallPlugin> for PluginManager {
                 })
                 .and_then(|metadata| {
                     let name = metadata.name();
-                    self.save_plugin_file(name, msg.bytes.into_inner().as_ref())
-                        .map(|_| self.load_zip(&name.to_string()))
+                    self.save_plugin_file(name, msg.bytes.into_inner().as_ref())?;
+                    let result = self.load_zip(name);
+                    self.installed_info(name, result)
+                }),
+        )
+    }
+}
+
+/// UPDATE PLUGIN
+#[derive(Debug)]
+pub struct UpdatePlugin {
+    pub plugin: String,
+    pub bytes: Cursor<Bytes>,
+}
+
+impl Message for UpdatePlugin {
+    /// info of the updated plugin
+    type Result = Result<PluginInfo, InstallQueryResult>;
+}
+
+impl Handler<UpdatePlugin> for PluginManager {
+    type Result = MessageResult<UpdatePlugin>;
+
+    fn handle(
+        &mut self,
+        msg: UpdatePlugin,
+        _ctx: &mut Context<Self>,
+    ) -> <Self as Handler<UpdatePlugin>>::Result {
+        use self::InstallQueryResult::*;
+
+        let was_active = match self.plugin(&msg.plugin) {
+            Ok(plugin) => plugin.status() == PluginStatus::Active,
+            Err(_) => return MessageResult(Err(PluginNotFound)),
+        };
+
+        MessageResult(
+            ZipParser::<BufReader<Cursor<Bytes>>>::from_bytes(msg.bytes.clone())
+                .map_err(|a| InvalidFile(a))
+                .and_then(|mut parser| {
+                    parser
+                        .validate_and_load_metadata(self.gu_version.clone())
+                        .map_err(|e| InvalidMetadata(e))
                 })
-                .unwrap_or_else(|a| a),
+                .and_then(|metadata| {
+                    if metadata.name() != msg.plugin {
+                        return Err(InvalidMetadata(format!(
+                            "archive contains plugin {}, expected {}",
+                            metadata.name(),
+                            msg.plugin
+                        )));
+                    }
+
+                    let path = self.directory().join(&msg.plugin);
+                    fs::write(path, msg.bytes.into_inner().as_ref())
+                        .map_err(|e| InvalidFile(e.to_string()))?;
+                    let result = self.load_zip(&msg.plugin);
+                    if !was_active {
+                        let _ = self.plugin_mut(&msg.plugin).map(|plug| plug.inactivate());
+                    }
+                    self.installed_info(&msg.plugin, result)
+                }),
         )
     }
 }
@@ -326,9 +501,10 @@ impl Handler<InstallDevPlugin> for PluginManager {
     ) -> <Self as Handler<InstallDevPlugin>>::Result {
         use self::InstallQueryResult::*;
 
+        let source = PluginSource::Dev(msg.path.clone());
         let res = DirectoryHandler::new(msg.path)
             .map_err(|_| InvalidPath)
-            .map(|handler| self.install_plugin(handler))
+            .map(|handler| self.install_plugin(handler, source, Utc::now()))
             .unwrap_or_else(|e| e);
 
         MessageResult(res)
@@ -1,11 +1,12 @@
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     fmt::{self, Debug},
-    fs::File,
+    fs::{self, File},
     io::Read,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
 };
 
+use chrono::{DateTime, Utc};
 use prettytable::{cell, row};
 use semver::{Version, VersionReq};
 use serde::de::DeserializeOwned;
@@ -43,6 +44,9 @@ pub struct PluginMetadata {
     /// scripts to load on startup
     #[serde(default)]
     load: Vec<String>,
+    /// file served for requests of plugin root or its directories
+    #[serde(default = "PluginMetadata::default_index")]
+    index: String,
 
     #[serde(default)]
     required_services: Vec<JsonValue>,
@@ -57,10 +61,18 @@ impl PluginMetadata {
         self.name.as_ref()
     }
 
+    pub fn version(&self) -> &Version {
+        &self.version
+    }
+
     pub fn load(&self) -> &Vec<String> {
         self.load.as_ref()
     }
 
+    pub fn index(&self) -> &str {
+        self.index.as_ref()
+    }
+
     fn default_name() -> String {
         "plugin".to_string()
     }
@@ -69,6 +81,10 @@ impl PluginMetadata {
         Version::new(0, 0, 1)
     }
 
+    fn default_index() -> String {
+        "index.html".to_string()
+    }
+
     pub fn service<T: DeserializeOwned>(&self, key: &str) -> Vec<T> {
         self.required_services
             .iter()
@@ -82,10 +98,13 @@ impl PluginMetadata {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
 pub struct PluginInfo {
     #[serde(flatten)]
     metadata: PluginMetadata,
     status: PluginStatus,
+    installed_at: DateTime<Utc>,
+    source: PluginSource,
 }
 
 impl PluginInfo {
@@ -98,6 +117,35 @@ impl PluginInfo {
     pub fn metadata(&self) -> &PluginMetadata {
         &self.metadata
     }
+
+    #[inline]
+    pub fn installed_at(&self) -> DateTime<Utc> {
+        self.installed_at
+    }
+
+    #[inline]
+    pub fn source(&self) -> &PluginSource {
+        &self.source
+    }
+}
+
+/// Where the plugin was installed from
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum PluginSource {
+    /// archive uploaded to the hub
+    Upload,
+    /// development directory
+    Dev(PathBuf),
+}
+
+impl fmt::Display for PluginSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PluginSource::Upload => write!(f, "upload"),
+            PluginSource::Dev(path) => write!(f, "dev {}", path.display()),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -115,23 +163,49 @@ impl fmt::Display for PluginStatus {
 
 pub fn format_plugins_table(plugins: Vec<PluginInfo>) {
     cli::format_table(
-        row!["Name", "Version", "Status"],
+        row!["Name", "Version", "Status", "Installed", "Source"],
         || "No plugins installed",
         plugins.iter().map(|plugin| {
             row![
                 plugin.metadata.name,
                 plugin.metadata.version.to_string(),
                 plugin.status.to_string(),
+                plugin.installed_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+                plugin.source.to_string(),
             ]
         }),
     )
 }
 
+/// Entry of a directory within a plugin
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PluginDirEntry {
+    pub name: String,
+    pub is_dir: bool,
+    /// file size in bytes, 0 for directories
+    pub size: u64,
+}
+
 /// Trait for providing plugin files
 pub trait PluginHandler: Debug {
     fn metadata(&self) -> Result<PluginMetadata, String>;
 
     fn file(&self, path: &Path) -> Result<Vec<u8>, String>;
+
+    /// Entries of a directory relative to the plugin root, sorted by name
+    fn list_dir(&self, path: &Path) -> Result<Vec<PluginDirEntry>, String>;
+}
+
+/// Rejects absolute paths and paths escaping the plugin root
+pub(crate) fn check_relative(path: &Path) -> Result<(), String> {
+    for component in path.components() {
+        match component {
+            Component::Normal(_) | Component::CurDir => (),
+            _ => return Err(format!("Invalid path {:?}", path)),
+        }
+    }
+    Ok(())
 }
 
 #[derive(Debug)]
@@ -171,6 +245,27 @@ impl PluginHandler for DirectoryHandler {
             .map_err(|e| format!("Reading file failed: {:?}", e))?;
         Ok(buf)
     }
+
+    fn list_dir(&self, path: &Path) -> Result<Vec<PluginDirEntry>, String> {
+        let path = self.directory.join(self.metadata()?.name).join(path);
+        let dir =
+            fs::read_dir(&path).map_err(|e| format!("Cannot read dir: {:?}, {:?}", e, path))?;
+
+        let mut entries = Vec::new();
+        for entry in dir {
+            let entry = entry.map_err(|e| format!("Reading dir failed: {:?}", e))?;
+            let meta = entry
+                .metadata()
+                .map_err(|e| format!("Reading dir failed: {:?}", e))?;
+            entries.push(PluginDirEntry {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                is_dir: meta.is_dir(),
+                size: if meta.is_dir() { 0 } else { meta.len() },
+            });
+        }
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(entries)
+    }
 }
 
 #[derive(Debug)]
@@ -201,19 +296,63 @@ impl PluginHandler for ZipHandler {
             .map(|data| data.clone())
             .ok_or(format!("File {:?} not found", path))
     }
+
+    fn list_dir(&self, path: &Path) -> Result<Vec<PluginDirEntry>, String> {
+        let mut entries = BTreeMap::new();
+
+        for (file, data) in self.files.iter() {
+            let mut rest = match file.strip_prefix(path) {
+                Ok(rest) => rest.components(),
+                Err(_) => continue,
+            };
+            let name = match rest.next() {
+                Some(name) => name.as_os_str().to_string_lossy().into_owned(),
+                None => continue,
+            };
+            let is_dir = rest.next().is_some();
+
+            // zip directory records are loaded as empty files
+            entries
+                .entry(name.clone())
+                .and_modify(|entry: &mut PluginDirEntry| {
+                    if is_dir {
+                        entry.is_dir = true;
+                        entry.size = 0;
+                    }
+                })
+                .or_insert(PluginDirEntry {
+                    name,
+                    is_dir,
+                    size: if is_dir { 0 } else { data.len() as u64 },
+                });
+        }
+
+        if entries.is_empty() {
+            return Err(format!("Directory {:?} not found", path));
+        }
+        Ok(entries.into_iter().map(|(_, entry)| entry).collect())
+    }
 }
 
 #[derive(Debug)]
 pub struct Plugin {
     handler: Box<dyn PluginHandler>,
     status: PluginStatus,
+    installed_at: DateTime<Utc>,
+    source: PluginSource,
 }
 
 impl Plugin {
-    pub fn new<T: 'static + PluginHandler>(handler: T) -> Self {
+    pub fn new<T: 'static + PluginHandler>(
+        handler: T,
+        source: PluginSource,
+        installed_at: DateTime<Utc>,
+    ) -> Self {
         Self {
             handler: Box::new(handler),
             status: PluginStatus::Installed,
+            installed_at,
+            source,
         }
     }
 
@@ -235,6 +374,8 @@ impl Plugin {
         Ok(PluginInfo {
             metadata: meta.clone(),
             status: self.status(),
+            installed_at: self.installed_at,
+            source: self.source.clone(),
         })
     }
 
@@ -248,4 +389,12 @@ impl Plugin {
     pub fn metadata(&self) -> Result<PluginMetadata, String> {
         self.handler.metadata()
     }
+
+    pub fn list_dir(&self, path: &Path) -> Result<Vec<PluginDirEntry>, String> {
+        check_relative(path)?;
+        match self.status() {
+            PluginStatus::Active => self.handler.list_dir(path),
+            a => Err(format!("Plugin is not active (State - {})", a)),
+        }
+    }
 }
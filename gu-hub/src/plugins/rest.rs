@@ -8,26 +8,33 @@ use std::{
 use actix::{Arbiter, System, SystemService};
 use actix_web::{
     client,
-    error::{ErrorBadRequest, ErrorInternalServerError},
-    http, AsyncResponder, HttpMessage, HttpRequest, HttpResponse, Responder, Scope,
+    http::{self, header},
+    AsyncResponder, HttpMessage, HttpRequest, HttpResponse, Responder, ResponseError, Scope,
 };
-use bytes::{buf::IntoBuf, Bytes};
+use bytes::{buf::IntoBuf, Bytes, BytesMut};
 use futures::{
     future::{self, Future},
     prelude::*,
     stream::Stream,
 };
-use log::{debug, error};
+use log::{debug, error, warn};
+use serde::{Deserialize, Serialize};
 
-use crate::server::HubClient as ServerClient;
+use gu_actix::prelude::*;
+use gu_persist::config::{ConfigManager, GetConfig};
+
+use crate::server::{HubClient as ServerClient, HubConfig};
 
 use super::{
     manager::{
-        ChangePluginState, InstallDevPlugin, InstallPlugin, ListPlugins, PluginFile, PluginManager,
-        QueriedStatus,
+        ChangePluginState, GetPluginManifest, InstallDevPlugin, InstallPlugin, ListPluginDir,
+        ListPlugins, PluginFile, PluginIndexFile, PluginManager, QueriedStatus, UpdatePlugin,
+    },
+    plugin::{check_relative, format_plugins_table, PluginDirEntry, PluginInfo, PluginMetadata},
+    rest_result::{
+        bad_request, internal_error, not_found, payload_too_large, InstallQueryResult, RestError,
+        RestResponse, ToHttpResponse,
     },
-    plugin::{format_plugins_table, PluginInfo},
-    rest_result::{InstallQueryResult, RestResponse, ToHttpResponse},
 };
 use std::ffi::OsStr;
 
@@ -59,7 +66,13 @@ pub fn read_file(path: &Path) -> Result<Vec<u8>, ()> {
 
 pub fn install_query_inner(buf: Vec<u8>) -> impl Future<Item = (), Error = ()> {
     ServerClient::post("/plug", buf)
-        .and_then(|r: RestResponse<InstallQueryResult>| Ok(debug!("{}", r.message.message())))
+        .and_then(|info: PluginInfo| {
+            Ok(println!(
+                "Installed {} {}.",
+                info.metadata().name(),
+                info.metadata().version()
+            ))
+        })
         .map_err(|e| {
             error!("Error on server connection {:?}", e);
             debug!("Error details: {:?}", e)
@@ -215,17 +228,19 @@ pub fn status_query(plugin: String, status: QueriedStatus) {
     });
 }
 
+/// Body of dev plugin install request
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DevInstall {
+    /// absolute path of the plugin directory
+    pub path: PathBuf,
+}
+
 pub fn dev_query(path: PathBuf) {
-    let path = path
-        .canonicalize()
-        .expect("Cannot canonicalize dir path")
-        .to_str()
-        .expect("Cannot parse filepath to str")
-        .to_string();
+    let path = path.canonicalize().expect("Cannot canonicalize dir path");
 
     System::run(move || {
         Arbiter::spawn(
-            ServerClient::empty_post(format!("/plug/dev{}", path))
+            ServerClient::post_json("/plug/dev", DevInstall { path })
                 .and_then(|r: RestResponse<InstallQueryResult>| {
                     Ok(debug!("{}", r.message.message()))
                 })
@@ -240,7 +255,9 @@ pub fn scope<S: 'static>(scope: Scope<S>) -> Scope<S> {
         .route("", http::Method::GET, list_scope)
         .route("", http::Method::POST, install_scope)
         .route("/install-github", http::Method::POST, install_github_scope)
-        .route("/dev/{pluginPath:.*}", http::Method::POST, dev_scope)
+        .route("/dev", http::Method::POST, dev_scope)
+        .route("/{pluginName}", http::Method::GET, dir_scope)
+        .route("/{pluginName}", http::Method::PUT, update_scope)
         .route("/{pluginName}", http::Method::DELETE, |r| {
             state_scope(QueriedStatus::Uninstall, r)
         })
@@ -252,6 +269,7 @@ pub fn scope<S: 'static>(scope: Scope<S>) -> Scope<S> {
             http::Method::PATCH,
             |r| state_scope(QueriedStatus::Inactivate, r),
         )
+        .route("/{pluginName}/manifest", http::Method::GET, manifest_scope)
         .route("/{pluginName}/{fileName:.*}", http::Method::GET, file_scope)
 }
 
@@ -260,7 +278,7 @@ fn list_scope<S>(_r: HttpRequest<S>) -> impl Responder {
 
     manager
         .send(ListPlugins)
-        .map_err(|e| ErrorInternalServerError(format!("err: {}", e)))
+        .map_err(|e| internal_error(e.to_string()))
         .and_then(|res| Ok(HttpResponse::Ok().json(res)))
         .responder()
 }
@@ -297,69 +315,223 @@ impl ToString for ContentType {
     }
 }
 
-fn file_scope<S>(r: HttpRequest<S>) -> impl Responder {
+/// Lists entries of plugin directory given by `path` query parameter
+fn dir_scope<S>(r: HttpRequest<S>) -> impl Responder {
     let manager = PluginManager::from_registry();
-    let match_info = r.match_info();
+    let plugin = r
+        .match_info()
+        .get("pluginName")
+        .expect("Can't get plugin name from query")
+        .to_string();
+    let path = PathBuf::from(r.query().get("path").cloned().unwrap_or_default());
+    if let Err(e) = check_relative(&path) {
+        return future::err(bad_request(e)).responder();
+    }
 
-    let path = PathBuf::from(
-        match_info
-            .get("fileName")
-            .expect("Can't get filename from query"),
-    );
+    manager
+        .send(ListPluginDir { plugin, path })
+        .map_err(|e| internal_error(e.to_string()))
+        .and_then(dir_response)
+        .responder()
+}
 
-    let plugin = match_info
+/// Unknown or inactive plugins, as well as missing directories, are reported as 404
+fn dir_response(
+    result: Result<Vec<PluginDirEntry>, String>,
+) -> Result<HttpResponse, actix_web::Error> {
+    result
+        .map_err(not_found)
+        .map(|entries| HttpResponse::Ok().json(entries))
+}
+
+/// Metadata declared by the plugin, without reading its files
+fn manifest_scope<S>(r: HttpRequest<S>) -> impl Responder {
+    let manager = PluginManager::from_registry();
+    let plugin = r
+        .match_info()
         .get("pluginName")
         .expect("Can't get plugin name from query")
         .to_string();
 
-    let b = path
+    manager
+        .send(GetPluginManifest { plugin })
+        .map_err(|e| internal_error(e.to_string()))
+        .and_then(manifest_response)
+        .responder()
+}
+
+fn manifest_response(
+    result: Result<PluginMetadata, String>,
+) -> Result<HttpResponse, actix_web::Error> {
+    result
+        .map_err(not_found)
+        .map(|manifest| HttpResponse::Ok().json(manifest))
+}
+
+fn content_type(path: &Path) -> Result<ContentType, actix_web::Error> {
+    let content = path
         .extension()
         .and_then(|e| e.to_str())
         .map(|a| ContentType::from(a));
 
-    match b {
-        None => future::err(ErrorBadRequest("Cannot parse file extension")).responder(),
-        Some(ContentType::NotSupported) => {
-            future::err(ErrorBadRequest(ContentType::NotSupported.to_string())).responder()
-        }
-        Some(content) => manager
+    match content {
+        None => Err(bad_request("Cannot parse file extension")),
+        Some(ContentType::NotSupported) => Err(bad_request(ContentType::NotSupported.to_string())),
+        Some(content) => Ok(content),
+    }
+}
+
+fn file_scope<S>(r: HttpRequest<S>) -> impl Responder {
+    let manager = PluginManager::from_registry();
+    let match_info = r.match_info();
+
+    let file_name = match_info
+        .get("fileName")
+        .expect("Can't get filename from query");
+    let path = PathBuf::from(file_name);
+
+    let plugin = match_info
+        .get("pluginName")
+        .expect("Can't get plugin name from query")
+        .to_string();
+
+    // plugin root or its directory
+    if file_name.is_empty() || file_name.ends_with('/') {
+        return manager
+            .send(PluginIndexFile { plugin, dir: path })
+            .map_err(|e| internal_error(e.to_string()))
+            .and_then(index_response)
+            .responder();
+    }
+
+    match content_type(&path) {
+        Err(e) => future::err(e).responder(),
+        Ok(content) => manager
             .send(PluginFile { plugin, path })
-            .map_err(|e| ErrorInternalServerError(format!("err: {}", e)))
-            .and_then(|res| res.map_err(|e| ErrorInternalServerError(format!("err: {}", e))))
-            .and_then(move |res| {
-                Ok(HttpResponse::Ok()
-                    .content_type(content.to_string())
-                    .body(res))
-            })
+            .map_err(|e| internal_error(e.to_string()))
+            .and_then(move |res| file_response(content, res))
             .responder(),
     }
 }
 
-fn install_scope<S>(r: HttpRequest<S>) -> impl Responder {
+/// Missing index, as well as index of unknown or inactive plugins, is reported as 404
+fn index_response(
+    result: Result<Option<(PathBuf, Vec<u8>)>, String>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (path, res) = result
+        .map_err(not_found)?
+        .ok_or_else(|| not_found("Plugin has no index file"))?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(content_type(&path)?.to_string())
+        .body(res))
+}
+
+/// Missing files, as well as files of unknown or inactive plugins, are reported as 404
+fn file_response(
+    content: ContentType,
+    result: Result<Vec<u8>, String>,
+) -> Result<HttpResponse, actix_web::Error> {
+    result.map_err(not_found).map(|res| {
+        HttpResponse::Ok()
+            .content_type(content.to_string())
+            .body(res)
+    })
+}
+
+/// Reads request body, failing with 413 as soon as it exceeds `limit` bytes
+fn read_body_limited<S>(
+    r: &HttpRequest<S>,
+    limit: usize,
+) -> impl Future<Item = Bytes, Error = actix_web::Error> {
+    let too_large = move || {
+        warn!("Rejected plugin upload exceeding {} bytes", limit);
+        payload_too_large(format!("Plugin upload exceeds {} bytes", limit))
+    };
+
+    let declared_len = r
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|len| len.to_str().ok())
+        .and_then(|len| len.parse::<usize>().ok());
+    if declared_len.map_or(false, |len| len > limit) {
+        return future::Either::A(future::err(too_large()));
+    }
+
+    future::Either::B(
+        r.payload()
+            .map_err(|e| bad_request(format!("Couldn't get request body: {:?}", e)))
+            .fold(BytesMut::new(), move |mut body, chunk| {
+                if body.len() + chunk.len() > limit {
+                    return Err(too_large());
+                }
+                body.extend_from_slice(&chunk);
+                Ok(body)
+            })
+            .map(BytesMut::freeze),
+    )
+}
+
+/// Reads request body, limited to the configured maximum plugin upload size
+fn read_upload<S: 'static>(
+    r: HttpRequest<S>,
+) -> impl Future<Item = Bytes, Error = actix_web::Error> {
+    ConfigManager::from_registry()
+        .send(GetConfig::<HubConfig>::new())
+        .flatten_fut()
+        .map_err(|e| internal_error(e.to_string()))
+        .and_then(move |config| read_body_limited(&r, config.max_plugin_upload_size))
+}
+
+fn install_scope<S: 'static>(r: HttpRequest<S>) -> impl Responder {
     let manager = PluginManager::from_registry();
 
-    r.payload()
-        .map_err(|e| ErrorBadRequest(format!("Couldn't get request body: {:?}", e)))
-        .concat2()
+    read_upload(r)
         .and_then(|a| Ok(a.into_buf()))
         .and_then(move |a: Cursor<Bytes>| {
             manager
                 .send(InstallPlugin { bytes: a })
-                .map_err(|e| ErrorInternalServerError(format!("{:?}", e)))
+                .map_err(|e| internal_error(format!("{:?}", e)))
         })
-        .and_then(|result| Ok(result.to_http_response()))
+        .and_then(|result| Ok(install_response(result)))
         .responder()
 }
 
-fn install_github_scope<S>(r: HttpRequest<S>) -> impl Responder {
-    r.payload()
-        .map_err(|e| ErrorBadRequest(format!("Couldn't get request body: {:?}", e)))
-        .concat2()
+fn update_scope<S: 'static>(r: HttpRequest<S>) -> impl Responder {
+    let manager = PluginManager::from_registry();
+    let plugin = r
+        .match_info()
+        .get("pluginName")
+        .expect("Can't get plugin name from query")
+        .to_string();
+
+    read_upload(r)
+        .and_then(move |a| {
+            manager
+                .send(UpdatePlugin {
+                    plugin,
+                    bytes: a.into_buf(),
+                })
+                .map_err(|e| internal_error(format!("{:?}", e)))
+        })
+        .and_then(|result| Ok(install_response(result)))
+        .responder()
+}
+
+/// Installed plugin info, or the failure with matching status code
+fn install_response(result: Result<PluginInfo, InstallQueryResult>) -> HttpResponse {
+    match result {
+        Ok(info) => HttpResponse::Ok().json(info),
+        Err(e) => RestError::new(e.status_code(), e.message()).error_response(),
+    }
+}
+
+fn install_github_scope<S: 'static>(r: HttpRequest<S>) -> impl Responder {
+    read_upload(r)
         .and_then(|a| {
             let plugins: Vec<(String, String)> = serde_json::from_slice(&a).unwrap_or_default();
             Ok(plugins)
         })
-        .map_err(|e| error!("{}", e))
         .and_then(move |plugins| {
             let download_and_save = move |(file_name, url): (String, String)| {
                 let tmp_file_name = format!("{}.tmp", file_name);
@@ -379,6 +551,7 @@ fn install_github_scope<S>(r: HttpRequest<S>) -> impl Responder {
                                     bytes: Bytes::from(buf).into_buf(),
                                 })
                                 .map_err(|e| error!("{:?}", e))
+                                .and_then(|result| result.map_err(|e| error!("{}", e.message())))
                         })
                     })
                     .and_then(move |_| {
@@ -395,8 +568,8 @@ fn install_github_scope<S>(r: HttpRequest<S>) -> impl Responder {
                     Box::new(prev.and_then(move |_| download_and_save(cur)))
                 })
                 .map(|_| ())
+                .map_err(|e| bad_request(format!("Plugin installation error: {:?}", e)))
         })
-        .map_err(|e| ErrorBadRequest(format!("Plugin installation error: {:?}", e)))
         .and_then(|_| Ok(InstallQueryResult::Installed.to_http_response()))
         .responder()
 }
@@ -412,6 +585,8 @@ fn state_scope<S>(state: QueriedStatus, r: HttpRequest<S>) -> impl Responder {
 
     manager
         .send(ChangePluginState { plugin, state })
+        .map_err(|e| internal_error(e.to_string()))
+        .and_then(|res| res.map_err(|e| not_found(e)))
         .and_then(move |_res| {
             Ok(HttpResponse::Ok()
                 .content_type("application/json")
@@ -420,19 +595,499 @@ fn state_scope<S>(state: QueriedStatus, r: HttpRequest<S>) -> impl Responder {
         .responder()
 }
 
-fn dev_scope<S>(r: HttpRequest<S>) -> impl Responder {
-    let manager = PluginManager::from_registry();
-    let match_info = r.match_info();
+/// Normalized path of existing plugin directory
+fn dev_plugin_dir(path: &Path) -> Result<PathBuf, actix_web::Error> {
+    if !path.is_absolute() {
+        return Err(bad_request(format!("Path {:?} is not absolute", path)));
+    }
 
-    let path = PathBuf::from(format!(
-        "/{}",
-        match_info
-            .get("pluginPath")
-            .expect("Can't get plugin name from query")
-    ));
+    match path.canonicalize() {
+        Ok(dir) if dir.is_dir() => Ok(dir),
+        Ok(_) => Err(bad_request(format!("Path {:?} is not a directory", path))),
+        Err(e) => Err(bad_request(format!("Cannot access {:?}: {}", path, e))),
+    }
+}
 
-    manager
-        .send(InstallDevPlugin { path })
-        .and_then(|result| Ok(result.to_http_response()))
+fn dev_scope<S: 'static>(r: HttpRequest<S>) -> impl Responder {
+    let manager = PluginManager::from_registry();
+
+    read_upload(r)
+        .and_then(|body| {
+            serde_json::from_slice::<DevInstall>(&body)
+                .map_err(|e| bad_request(format!("Invalid request body: {}", e)))
+        })
+        .and_then(|request| dev_plugin_dir(&request.path))
+        .and_then(move |path| {
+            manager
+                .send(InstallDevPlugin { path })
+                .map_err(|e| internal_error(e.to_string()))
+        })
+        .and_then(|result| match result.status_code().is_success() {
+            true => Ok(result.to_http_response()),
+            false => Err(RestError::new(result.status_code(), result.message()).into()),
+        })
         .responder()
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Cursor, Write},
+        path::Path,
+    };
+
+    use actix::{Actor, System};
+    use actix_web::{http::header, http::StatusCode, test::TestRequest, Body};
+    use bytes::{buf::IntoBuf, Bytes};
+    use zip::{write::FileOptions, ZipWriter};
+
+    use super::{
+        content_type, dev_plugin_dir, dir_response, file_response, index_response,
+        install_response, manifest_response, read_body_limited,
+    };
+    use crate::plugins::manager::{
+        ChangePluginState, GetPluginManifest, InstallDevPlugin, InstallPlugin, ListPluginDir,
+        ListPlugins, PluginFile, PluginIndexFile, PluginManager, QueriedStatus, UpdatePlugin,
+    };
+    use crate::plugins::plugin::{check_relative, PluginDirEntry, PluginSource, PluginStatus};
+    use crate::plugins::rest_result::ErrorBody;
+    use crate::plugins::rest_result::InstallQueryResult;
+
+    fn plugin_zip(metadata: &str) -> Vec<u8> {
+        plugin_zip_with_files(metadata, &[])
+    }
+
+    fn plugin_zip_with_files(metadata: &str, files: &[(&str, &str)]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("gu-plugin.json", FileOptions::default())
+            .unwrap();
+        zip.write_all(metadata.as_bytes()).unwrap();
+        for (name, content) in files {
+            zip.start_file(*name, FileOptions::default()).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn install_message(bytes: Vec<u8>) -> InstallPlugin {
+        InstallPlugin {
+            bytes: Bytes::from(bytes).into_buf(),
+        }
+    }
+
+    fn install(directory: &tempfile::TempDir, bytes: Vec<u8>) -> (StatusCode, String) {
+        let mut sys = System::new("test");
+        let manager = PluginManager::with_directory(directory.path().into()).start();
+        let result = sys.block_on(manager.send(install_message(bytes))).unwrap();
+
+        let response = install_response(result);
+        let body = match response.body() {
+            Body::Binary(binary) => String::from_utf8(binary.as_ref().to_vec()).unwrap(),
+            body => panic!("unexpected body: {:?}", body),
+        };
+        (response.status(), body)
+    }
+
+    #[test]
+    fn install_responds_with_plugin_info() {
+        let directory = tempfile::tempdir().unwrap();
+        let (status, body) = install(
+            &directory,
+            plugin_zip(r#"{"name": "test-plugin", "version": "1.2.3"}"#),
+        );
+
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains(r#""name":"test-plugin""#));
+        assert!(body.contains(r#""version":"1.2.3""#));
+    }
+
+    #[test]
+    fn install_of_invalid_file_is_bad_request() {
+        let directory = tempfile::tempdir().unwrap();
+        let (status, body) = install(&directory, b"not a zip".to_vec());
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let body: ErrorBody = serde_json::from_str(&body).unwrap();
+        assert_eq!(body.error.code, 400);
+        assert!(body.error.message.starts_with("Received data is invalid"));
+    }
+
+    #[test]
+    fn unsupported_file_type_is_json_error() {
+        let response = content_type(Path::new("plugin.exe"))
+            .err()
+            .unwrap()
+            .as_response_error()
+            .error_response();
+        let body = match response.body() {
+            Body::Binary(binary) => binary.as_ref().to_vec(),
+            body => panic!("unexpected body: {:?}", body),
+        };
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["code"], 400);
+        assert_eq!(body["error"]["message"], "Content type not supported");
+    }
+
+    #[test]
+    fn plugin_root_serves_index() {
+        let directory = tempfile::tempdir().unwrap();
+        let zip = plugin_zip_with_files(
+            r#"{"name": "indexed"}"#,
+            &[("indexed/index.html", "<h1>indexed</h1>")],
+        );
+        let mut sys = System::new("test");
+        let manager = PluginManager::with_directory(directory.path().into()).start();
+        sys.block_on(manager.send(install_message(zip)))
+            .unwrap()
+            .unwrap();
+
+        let index = |dir: &str| PluginIndexFile {
+            plugin: "indexed".into(),
+            dir: dir.into(),
+        };
+        let (path, content) = sys
+            .block_on(manager.send(index("")))
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(path.to_str(), Some("index.html"));
+        assert_eq!(content, b"<h1>indexed</h1>");
+
+        assert!(sys
+            .block_on(manager.send(index("missing/")))
+            .unwrap()
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn index_of_unknown_or_inactive_plugin_is_not_found() {
+        let directory = tempfile::tempdir().unwrap();
+        let archive = |name: &str| {
+            let metadata = format!(r#"{{"name": "{}"}}"#, name);
+            let file = format!("{}/index.html", name);
+            plugin_zip_with_files(&metadata, &[(file.as_str(), name)])
+        };
+
+        let mut sys = System::new("test");
+        let manager = PluginManager::with_directory(directory.path().into()).start();
+        for name in &["active", "inactive"] {
+            sys.block_on(manager.send(install_message(archive(name))))
+                .unwrap()
+                .unwrap();
+        }
+        sys.block_on(manager.send(ChangePluginState {
+            plugin: "inactive".into(),
+            state: QueriedStatus::Inactivate,
+        }))
+        .unwrap()
+        .unwrap();
+
+        let mut serve = |plugin: &str, dir: &str| {
+            let res = sys
+                .block_on(manager.send(PluginIndexFile {
+                    plugin: plugin.into(),
+                    dir: dir.into(),
+                }))
+                .unwrap();
+            match index_response(res) {
+                Ok(response) => response.status(),
+                Err(e) => e.as_response_error().error_response().status(),
+            }
+        };
+
+        assert_eq!(serve("active", ""), StatusCode::OK);
+        assert_eq!(serve("active", "missing/"), StatusCode::NOT_FOUND);
+        assert_eq!(serve("inactive", ""), StatusCode::NOT_FOUND);
+        assert_eq!(serve("unknown", ""), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn list_reports_install_sources() {
+        let directory = tempfile::tempdir().unwrap();
+        let dev_dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dev_dir.path().join("gu-plugin.json"),
+            r#"{"name": "dev-plugin"}"#,
+        )
+        .unwrap();
+
+        let mut sys = System::new("test");
+        let manager = PluginManager::with_directory(directory.path().into()).start();
+        sys.block_on(manager.send(install_message(plugin_zip(r#"{"name": "uploaded"}"#))))
+            .unwrap()
+            .unwrap();
+        sys.block_on(manager.send(InstallDevPlugin {
+            path: dev_dir.path().into(),
+        }))
+        .unwrap();
+
+        let plugins = sys.block_on(manager.send(ListPlugins)).unwrap();
+        let source = |name: &str| {
+            plugins
+                .iter()
+                .find(|info| info.metadata().name() == name)
+                .map(|info| info.source().clone())
+        };
+        assert_eq!(source("uploaded"), Some(PluginSource::Upload));
+        assert_eq!(
+            source("dev-plugin"),
+            Some(PluginSource::Dev(dev_dir.path().into()))
+        );
+    }
+
+    #[test]
+    fn manifest_is_returned() {
+        let directory = tempfile::tempdir().unwrap();
+        let metadata = r#"{"name": "described", "version": "1.2.3", "author": "golem",
+            "load": ["app.js"]}"#;
+
+        let mut sys = System::new("test");
+        let manager = PluginManager::with_directory(directory.path().into()).start();
+        let archive = plugin_zip_with_files(metadata, &[("described/app.js", "")]);
+        sys.block_on(manager.send(install_message(archive)))
+            .unwrap()
+            .unwrap();
+
+        let mut manifest = |plugin: &str| {
+            let res = sys
+                .block_on(manager.send(GetPluginManifest {
+                    plugin: plugin.into(),
+                }))
+                .unwrap();
+            match manifest_response(res) {
+                Ok(response) => response,
+                Err(e) => e.as_response_error().error_response(),
+            }
+        };
+
+        let response = manifest("described");
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = match response.body() {
+            Body::Binary(binary) => serde_json::from_slice(binary.as_ref()).unwrap(),
+            body => panic!("unexpected body: {:?}", body),
+        };
+        assert_eq!(body["name"], "described");
+        assert_eq!(body["version"], "1.2.3");
+        assert_eq!(body["author"], "golem");
+        assert_eq!(body["load"], serde_json::json!(["app.js"]));
+        assert!(body.get("gu-version-req").is_some());
+
+        assert_eq!(manifest("unknown").status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn inactive_plugin_files_are_not_found() {
+        let directory = tempfile::tempdir().unwrap();
+        let archive = |name: &str| {
+            let metadata = format!(r#"{{"name": "{}", "version": "0.1.0"}}"#, name);
+            let file = format!("{}/app.js", name);
+            plugin_zip_with_files(&metadata, &[(file.as_str(), name)])
+        };
+
+        let mut sys = System::new("test");
+        let manager = PluginManager::with_directory(directory.path().into()).start();
+        for name in &["active", "inactive"] {
+            sys.block_on(manager.send(install_message(archive(name))))
+                .unwrap()
+                .unwrap();
+        }
+        sys.block_on(manager.send(ChangePluginState {
+            plugin: "inactive".into(),
+            state: QueriedStatus::Inactivate,
+        }))
+        .unwrap()
+        .unwrap();
+
+        let mut serve = |plugin: &str| {
+            let res = sys
+                .block_on(manager.send(PluginFile {
+                    plugin: plugin.into(),
+                    path: "app.js".into(),
+                }))
+                .unwrap();
+            let content = content_type(Path::new("app.js")).unwrap();
+            match file_response(content, res) {
+                Ok(response) => response.status(),
+                Err(e) => e.as_response_error().error_response().status(),
+            }
+        };
+
+        assert_eq!(serve("active"), StatusCode::OK);
+        assert_eq!(serve("inactive"), StatusCode::NOT_FOUND);
+        assert_eq!(serve("unknown"), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn update_keeps_plugin_active() {
+        let directory = tempfile::tempdir().unwrap();
+        let archive = |version: &str, script: &str| {
+            let metadata = format!(r#"{{"name": "updated", "version": "{}"}}"#, version);
+            plugin_zip_with_files(&metadata, &[("updated/app.js", script)])
+        };
+        let update = |plugin: &str, bytes: Vec<u8>| UpdatePlugin {
+            plugin: plugin.into(),
+            bytes: Bytes::from(bytes).into_buf(),
+        };
+
+        let mut sys = System::new("test");
+        let manager = PluginManager::with_directory(directory.path().into()).start();
+        sys.block_on(manager.send(install_message(archive("0.1.0", "v1"))))
+            .unwrap()
+            .unwrap();
+
+        let info = sys
+            .block_on(manager.send(update("updated", archive("0.2.0", "v2"))))
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.status(), PluginStatus::Active);
+        assert_eq!(info.metadata().version().to_string(), "0.2.0");
+
+        let script = sys
+            .block_on(manager.send(PluginFile {
+                plugin: "updated".into(),
+                path: "app.js".into(),
+            }))
+            .unwrap()
+            .unwrap();
+        assert_eq!(script, b"v2");
+
+        sys.block_on(manager.send(ChangePluginState {
+            plugin: "updated".into(),
+            state: QueriedStatus::Inactivate,
+        }))
+        .unwrap()
+        .unwrap();
+        let info = sys
+            .block_on(manager.send(update("updated", archive("0.2.1", "v3"))))
+            .unwrap()
+            .unwrap();
+        assert_eq!(info.status(), PluginStatus::Installed);
+
+        match sys
+            .block_on(manager.send(update("other", archive("0.3.0", "v4"))))
+            .unwrap()
+        {
+            Err(InstallQueryResult::PluginNotFound) => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn list_plugin_subdirectory() {
+        let directory = tempfile::tempdir().unwrap();
+        let zip = plugin_zip_with_files(
+            r#"{"name": "browse"}"#,
+            &[
+                ("browse/app.js", "app"),
+                ("browse/img/a.png", "png"),
+                ("browse/img/icons/b.svg", "svg"),
+            ],
+        );
+        let mut sys = System::new("test");
+        let manager = PluginManager::with_directory(directory.path().into()).start();
+        sys.block_on(manager.send(install_message(zip)))
+            .unwrap()
+            .unwrap();
+
+        let list = |path: &str| ListPluginDir {
+            plugin: "browse".into(),
+            path: path.into(),
+        };
+        let entries = sys.block_on(manager.send(list("img"))).unwrap().unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                PluginDirEntry {
+                    name: "a.png".into(),
+                    is_dir: false,
+                    size: 3,
+                },
+                PluginDirEntry {
+                    name: "icons".into(),
+                    is_dir: true,
+                    size: 0,
+                },
+            ]
+        );
+
+        assert!(sys
+            .block_on(manager.send(list("img/../..")))
+            .unwrap()
+            .is_err());
+        assert!(sys.block_on(manager.send(list("/img"))).unwrap().is_err());
+        assert!(check_relative(Path::new("img/../..")).is_err());
+        assert!(check_relative(Path::new("/img")).is_err());
+
+        let mut status = |plugin: &str, path: &str| {
+            let res = sys
+                .block_on(manager.send(ListPluginDir {
+                    plugin: plugin.into(),
+                    path: path.into(),
+                }))
+                .unwrap();
+            match dir_response(res) {
+                Ok(response) => response.status(),
+                Err(e) => e.as_response_error().error_response().status(),
+            }
+        };
+        assert_eq!(status("browse", "img"), StatusCode::OK);
+        assert_eq!(status("browse", "missing"), StatusCode::NOT_FOUND);
+        assert_eq!(status("unknown", "img"), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn dev_install_from_nested_path() {
+        let root = tempfile::tempdir().unwrap();
+        let nested = root.path().join("workspace").join("plugins").join("nested");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(nested.join("gu-plugin.json"), r#"{"name": "nested"}"#).unwrap();
+
+        assert!(dev_plugin_dir(Path::new("workspace/plugins/nested")).is_err());
+        assert!(dev_plugin_dir(&nested.join("gu-plugin.json")).is_err());
+        let path = dev_plugin_dir(&nested.join("..").join("nested")).unwrap();
+        assert_eq!(path, nested.canonicalize().unwrap());
+
+        let mut sys = System::new("test");
+        let manager = PluginManager::with_directory(root.path().join("installed")).start();
+        match sys
+            .block_on(manager.send(InstallDevPlugin { path: path.clone() }))
+            .unwrap()
+        {
+            InstallQueryResult::Installed => (),
+            r => panic!("unexpected result: {:?}", r),
+        }
+
+        let plugins = sys.block_on(manager.send(ListPlugins)).unwrap();
+        assert_eq!(plugins.len(), 1);
+        assert_eq!(plugins[0].source(), &PluginSource::Dev(path));
+    }
+
+    #[test]
+    fn oversized_upload_is_rejected() {
+        let mut sys = System::new("test");
+        let status = |r: Result<Bytes, actix_web::Error>| {
+            r.unwrap_err().as_response_error().error_response().status()
+        };
+
+        let req = TestRequest::default().set_payload(vec![0u8; 16]).finish();
+        assert_eq!(
+            status(sys.block_on(read_body_limited(&req, 8))),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+
+        let req = TestRequest::with_header(header::CONTENT_LENGTH, "1048576").finish();
+        assert_eq!(
+            status(sys.block_on(read_body_limited(&req, 8))),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+
+        let req = TestRequest::default().set_payload(vec![1u8; 8]).finish();
+        assert_eq!(
+            sys.block_on(read_body_limited(&req, 8)).unwrap(),
+            Bytes::from(vec![1u8; 8])
+        );
+    }
+}
@@ -1,4 +1,5 @@
-use actix_web::{http::StatusCode, HttpResponse};
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use failure::Fail;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json;
 use std::fmt::Debug;
@@ -10,6 +11,63 @@ pub struct RestResponse<T> {
     // TODO: anything more here?
 }
 
+/// JSON error envelope, `{ "error": { "code": 404, "message": "..." } }`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorBody {
+    pub error: ErrorDetails,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorDetails {
+    /// HTTP status code
+    pub code: u16,
+    pub message: String,
+}
+
+/// Error of plugin endpoints, responded with `ErrorBody`
+#[derive(Debug, Fail)]
+#[fail(display = "{}", message)]
+pub struct RestError {
+    status: StatusCode,
+    message: String,
+}
+
+impl RestError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        RestError {
+            status,
+            message: message.into(),
+        }
+    }
+}
+
+impl ResponseError for RestError {
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status).json(ErrorBody {
+            error: ErrorDetails {
+                code: self.status.as_u16(),
+                message: self.message.clone(),
+            },
+        })
+    }
+}
+
+pub fn bad_request(message: impl Into<String>) -> actix_web::Error {
+    RestError::new(StatusCode::BAD_REQUEST, message).into()
+}
+
+pub fn not_found(message: impl Into<String>) -> actix_web::Error {
+    RestError::new(StatusCode::NOT_FOUND, message).into()
+}
+
+pub fn payload_too_large(message: impl Into<String>) -> actix_web::Error {
+    RestError::new(StatusCode::PAYLOAD_TOO_LARGE, message).into()
+}
+
+pub fn internal_error(message: impl Into<String>) -> actix_web::Error {
+    RestError::new(StatusCode::INTERNAL_SERVER_ERROR, message).into()
+}
+
 pub trait ToHttpResponse: Serialize + DeserializeOwned + Debug {
     fn to_http_response(&self) -> HttpResponse {
         let code = self.status_code();
@@ -35,6 +93,7 @@ pub enum InstallQueryResult {
     Overwritten,
     FileAlreadyExists,
     PluginAlreadyExists,
+    PluginNotFound,
     InvalidPath,
     InvalidMetadata(String),
     InvalidFile(String),
@@ -49,6 +108,7 @@ impl ToHttpResponse for InstallQueryResult {
             Overwritten => "Previous plugin has been replaced".to_string(),
             FileAlreadyExists => "Plugin file for the plugin already exists".to_string(),
             PluginAlreadyExists => "Plugin already exists".to_string(),
+            PluginNotFound => "Plugin is not installed".to_string(),
             InvalidPath => "Path to resource is invalid".to_string(),
             InvalidMetadata(m) => format!("Metadata file is invalid - {}", m),
             InvalidFile(m) => format!("Received data is invalid - {}", m),
@@ -63,6 +123,7 @@ impl ToHttpResponse for InstallQueryResult {
             Overwritten => StatusCode::OK,
             FileAlreadyExists => StatusCode::CONFLICT,
             PluginAlreadyExists => StatusCode::CONFLICT,
+            PluginNotFound => StatusCode::NOT_FOUND,
             InvalidPath => StatusCode::BAD_REQUEST,
             InvalidMetadata(_) => StatusCode::BAD_REQUEST,
             InvalidFile(_) => StatusCode::BAD_REQUEST,
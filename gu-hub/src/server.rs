@@ -35,6 +35,9 @@ pub(crate) struct HubConfig {
     control_socket: Option<String>,
     #[serde(default = "HubConfig::publish_service")]
     pub(crate) publish_service: bool,
+    /// limit of uploaded plugin archive size, in bytes
+    #[serde(default = "HubConfig::default_max_plugin_upload_size")]
+    pub(crate) max_plugin_upload_size: usize,
 }
 
 pub(crate) type HubClient = ServerClient<HubConfig>;
@@ -45,6 +48,7 @@ impl Default for HubConfig {
             p2p_port: Self::default_p2p_port(),
             control_socket: None,
             publish_service: Self::publish_service(),
+            max_plugin_upload_size: Self::default_max_plugin_upload_size(),
         }
     }
 }
@@ -53,6 +57,10 @@ impl ServerConfig for HubConfig {
     fn port(&self) -> u16 {
         self.p2p_port
     }
+
+    fn service_name() -> &'static str {
+        "gu-hub"
+    }
 }
 
 impl HubConfig {
@@ -67,6 +75,10 @@ impl HubConfig {
     fn publish_service() -> bool {
         true
     }
+
+    fn default_max_plugin_upload_size() -> usize {
+        64 * 1024 * 1024
+    }
 }
 
 impl config::HasSectionId for HubConfig {
@@ -385,6 +385,7 @@ impl Session {
                 .send(gu_model::envman::SessionUpdate {
                     session_id: deployment_id,
                     commands: commands,
+                    on_error: Default::default(),
                 })
                 .map_err(|_| SessionErr::CannotUpdatePeerDeployment),
         )
@@ -23,13 +23,16 @@ use tokio::{
 use codec::MdnsCodec;
 use codec::ParsedPacket;
 use continuous::{
-    ContinuousInstancesList, ForeignMdnsQueryInfo, NewInstance, ReceivedMdnsInstance, Subscribe,
-    Subscription,
+    ContinuousInstancesList, ForeignMdnsQueryInfo, NewInstance, ReceivedMdnsInstance,
+    RemovedMdnsInstance, Subscribe, Subscription,
 };
 use errors::{Error, ErrorKind, Result};
 use gu_actix::FlattenFuture;
-use service::{ServiceDescription, Services};
-use service::{ServiceInstance, ServicesDescription};
+use service::{service_of, ServiceDescription, Services};
+use service::{
+    InstanceNamesQuery, RankedInstance, RankedQuery, ServiceInstance, ServicesDescription,
+    Transport,
+};
 
 /// Actor resolving mDNS services names into list of IPs
 #[derive(Debug, Default)]
@@ -37,10 +40,12 @@ pub struct MdnsActor<T: MdnsConnection> {
     /// Interior, indirect responder sink
     sender: Option<mpsc::Sender<((ServicesDescription, u16), SocketAddr)>>,
     data: Box<T>,
+    group: MdnsGroup,
 }
 
 pub trait MdnsConnection: 'static + Default + Sized {
-    fn port() -> u16;
+    /// Port to bind for given multicast group
+    fn port(group: &MdnsGroup) -> u16;
 
     fn unicast_query() -> bool;
 
@@ -50,12 +55,85 @@ pub trait MdnsConnection: 'static + Default + Sized {
 pub type OneShotResponse<T> = ActorResponse<MdnsActor<T>, HashSet<ServiceInstance>, Error>;
 pub type ContinuousResponse<T> = ActorResponse<MdnsActor<T>, Subscription, Error>;
 
+/// Multicast group used for mDNS queries and announcements
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MdnsGroup {
+    pub addr: Ipv4Addr,
+    pub port: u16,
+    /// TTL of sent multicast packets; 1 keeps them on the local link
+    ttl: u32,
+}
+
+impl Default for MdnsGroup {
+    fn default() -> Self {
+        MdnsGroup {
+            addr: Ipv4Addr::new(224, 0, 0, 251),
+            port: 5353,
+            ttl: 1,
+        }
+    }
+}
+
+impl MdnsGroup {
+    /// Group on non-standard `port`; lets tests run without privileges
+    /// and without colliding with the system responder
+    pub fn with_port(port: u16) -> Self {
+        MdnsGroup {
+            port,
+            ..MdnsGroup::default()
+        }
+    }
+
+    /// Group with given multicast TTL, letting packets be routed across subnets;
+    /// fails with `InvalidTtl` unless it is within 1..=255
+    pub fn with_ttl(self, ttl: u32) -> Result<Self> {
+        match ttl {
+            1..=255 => Ok(MdnsGroup { ttl, ..self }),
+            _ => Err(ErrorKind::InvalidTtl(ttl).into()),
+        }
+    }
+
+    pub fn ttl(&self) -> u32 {
+        self.ttl
+    }
+
+    pub fn socket_addr(&self) -> SocketAddr {
+        SocketAddrV4::new(self.addr, self.port).into()
+    }
+}
+
+/// Re-sending of mDNS queries which got no response
+#[derive(Debug, Clone, Copy)]
+pub struct QueryRetries {
+    /// Number of additional queries sent when nothing was found
+    pub count: u32,
+    /// Time to wait for responses after each query
+    pub interval: Duration,
+}
+
+impl Default for QueryRetries {
+    fn default() -> Self {
+        QueryRetries {
+            count: 2,
+            interval: Duration::from_millis(100),
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct OneShot {
     /// Next id for mDNS query
     next_id: u16,
     /// Services for given id
     map: HashMap<u16, Services>,
+    retries: QueryRetries,
+}
+
+impl OneShot {
+    /// Checks if query with given id got no response yet and can be sent again
+    fn needs_retry(&self, id: u16, attempt: u32) -> bool {
+        attempt < self.retries.count && self.map.get(&id).map_or(false, Services::is_empty)
+    }
 }
 
 #[derive(Default)]
@@ -65,7 +143,7 @@ pub struct Continuous {
 }
 
 impl MdnsConnection for OneShot {
-    fn port() -> u16 {
+    fn port(_group: &MdnsGroup) -> u16 {
         0
     }
 
@@ -75,39 +153,55 @@ impl MdnsConnection for OneShot {
 
     fn handle_packet(&mut self, packet: ParsedPacket, src: SocketAddr) {
         if let Some(services) = self.map.get_mut(&packet.id) {
+            for name in packet.names {
+                services.add_name(name);
+            }
             for mut service in packet.instances {
                 match src {
                     V4(sock) => service.addrs_v4 = biggest_mask_ipv4(&service.addrs_v4, sock.ip()),
                     _ => (),
                 }
+                service.source = Some(src);
+                service.transport = Some(Transport::of(&src));
 
                 services.add_instance(service);
             }
+            for name in packet.goodbyes {
+                services.remove_instance(&name);
+            }
         }
     }
 }
 
 impl MdnsConnection for Continuous {
-    fn port() -> u16 {
-        5353
+    fn port(group: &MdnsGroup) -> u16 {
+        group.port
     }
 
     fn unicast_query() -> bool {
         false
     }
 
-    fn handle_packet(&mut self, packet: ParsedPacket, _src: SocketAddr) {
+    fn handle_packet(&mut self, packet: ParsedPacket, src: SocketAddr) {
         for name in packet.questions {
             self.map
                 .get(&name)
                 .map(|list| list.do_send(ForeignMdnsQueryInfo));
         }
 
-        for instance in packet.instances {
+        for mut instance in packet.instances {
+            instance.source = Some(src);
+            instance.transport = Some(Transport::of(&src));
             self.map
                 .get(&instance.service())
                 .map(|list| list.do_send(ReceivedMdnsInstance::new(instance)));
         }
+
+        for name in packet.goodbyes {
+            self.map
+                .get(&service_of(&name))
+                .map(|list| list.do_send(RemovedMdnsInstance::new(name)));
+        }
     }
 }
 
@@ -116,34 +210,51 @@ impl<T: MdnsConnection> MdnsActor<T> {
         MdnsActor::default()
     }
 
-    fn create_mdns_socket() -> Result<UdpSocket> {
-        let socket = Socket::new(Domain::ipv4(), Type::dgram(), Some(Protocol::udp()))?;
-
-        let multicast_ip = Ipv4Addr::new(224, 0, 0, 251);
-        let any_ip = Ipv4Addr::new(0, 0, 0, 0);
-
-        let socket_address = SocketAddrV4::new(any_ip, T::port());
+    /// Actor using given multicast group instead of the standard one;
+    /// has to be started directly, not taken from the registry
+    pub fn with_group(group: MdnsGroup) -> Self {
+        MdnsActor {
+            group,
+            ..MdnsActor::default()
+        }
+    }
 
-        #[cfg(not(windows))]
-        socket.set_reuse_port(true)?;
-        socket.set_reuse_address(true)?;
-        socket.set_multicast_loop_v4(true)?;
-        socket.join_multicast_v4(&multicast_ip, &any_ip)?;
-        socket.bind(&socket_address.into())?;
+    fn create_mdns_socket(group: &MdnsGroup) -> Result<UdpSocket> {
+        let socket = mdns_socket(group.addr, T::port(group), group.ttl)?;
 
         UdpSocket::from_std(socket.into_udp_socket(), &Handle::default()).map_err(Error::from)
     }
 }
 
+/// Creates socket bound to given port, joined to given multicast group,
+/// sending multicast packets with given TTL
+pub(crate) fn mdns_socket(multicast_ip: Ipv4Addr, port: u16, ttl: u32) -> Result<Socket> {
+    let socket = Socket::new(Domain::ipv4(), Type::dgram(), Some(Protocol::udp()))?;
+
+    let any_ip = Ipv4Addr::new(0, 0, 0, 0);
+
+    let socket_address = SocketAddrV4::new(any_ip, port);
+
+    #[cfg(not(windows))]
+    socket.set_reuse_port(true)?;
+    socket.set_reuse_address(true)?;
+    socket.set_multicast_loop_v4(true)?;
+    socket.set_multicast_ttl_v4(ttl)?;
+    socket.join_multicast_v4(&multicast_ip, &any_ip)?;
+    socket
+        .bind(&socket_address.into())
+        .map_err(|e| ErrorKind::BindFailed(port, e))?;
+
+    Ok(socket)
+}
+
 pub fn send_mdns_query(
     sender: Option<mpsc::Sender<((ServicesDescription, u16), SocketAddr)>>,
     services: ServicesDescription,
     id: u16,
+    group: SocketAddr,
 ) -> impl Future<Item = (), Error = Error> {
-    let multicast_ip = Ipv4Addr::new(224, 0, 0, 251);
-    let addr = SocketAddrV4::new(multicast_ip, 5353).into();
-
-    let message = ((services, id), addr);
+    let message = ((services, id), group);
 
     match sender {
         Some(a) => future::Either::A(
@@ -160,34 +271,94 @@ pub fn send_mdns_query(
 }
 
 impl MdnsActor<OneShot> {
-    fn retrieve_services(&mut self, id: u16) -> Result<HashSet<ServiceInstance>> {
+    fn retrieve_services(&mut self, id: u16) -> Result<Services> {
         self.data
             .map
             .remove(&id)
             .ok_or(ErrorKind::MissingKey.into())
-            .and_then(|a| Ok(a.collect()))
     }
 
-    fn build_response<F>(
+    /// Waits for responses, re-sending the query while nothing was found
+    fn await_response<R, G>(
         &mut self,
-        fut: F,
-        _ctx: &mut Context<Self>,
+        ctx: &mut Context<Self>,
+        query: ServicesDescription,
         id: u16,
-    ) -> OneShotResponse<OneShot>
+        attempt: u32,
+        tx: oneshot::Sender<Result<R>>,
+        collect: G,
+    ) where
+        G: FnOnce(Services) -> R + 'static,
+        R: 'static,
+    {
+        ctx.run_later(self.data.retries.interval, move |act, ctx| {
+            if tx.is_canceled() || !act.data.map.contains_key(&id) {
+                debug!("mDNS query {} cancelled", id);
+                act.data.map.remove(&id);
+                let _ = tx.send(Err(ErrorKind::Cancelled.into()));
+            } else if act.data.needs_retry(id, attempt) {
+                debug!("no response for mDNS query {}; retrying", id);
+                ctx.spawn(
+                    send_mdns_query(
+                        act.sender.clone(),
+                        query.clone(),
+                        id,
+                        act.group.socket_addr(),
+                    )
+                    .map_err(|e| error!("{}", e))
+                    .into_actor(act),
+                );
+                act.await_response(ctx, query, id, attempt + 1, tx, collect);
+            } else {
+                let _ = tx
+                    .send(act.retrieve_services(id).map(collect))
+                    .map_err(|_e| error!("cannot send mDNS query result"));
+            }
+        });
+    }
+
+    /// Sends the query; resolves to its id and the receiver of its result
+    fn start_query<R, G>(
+        &mut self,
+        msg: ServicesDescription,
+        collect: G,
+    ) -> impl ActorFuture<Actor = Self, Item = (u16, oneshot::Receiver<Result<R>>), Error = Error>
     where
-        F: Future<Item = (), Error = Error> + 'static,
+        G: FnOnce(Services) -> R + 'static,
+        R: 'static,
     {
+        let id = self.data.next_id;
+        self.data.next_id = id.wrapping_add(1);
+
+        self.data.map.insert(id, Services::from(&msg));
+        let future = send_mdns_query(
+            self.sender.clone(),
+            msg.clone(),
+            id,
+            self.group.socket_addr(),
+        );
         let (tx, rx) = oneshot::channel();
 
-        ActorResponse::r#async(fut.into_actor(self).and_then(move |_r, act, ctx| {
-            ctx.run_later(Duration::from_millis(100), move |act, _ctx| {
-                let _ = tx
-                    .send(act.retrieve_services(id))
-                    .and_then(|_a| Ok(()))
-                    .map_err(|e| error!("{:?}", e));
-            });
-            rx.flatten_fut().into_actor(act)
-        }))
+        future.into_actor(self).map(move |_r, act, ctx| {
+            act.await_response(ctx, msg, id, 0, tx, collect);
+            (id, rx)
+        })
+    }
+
+    fn query<R, G>(
+        &mut self,
+        msg: ServicesDescription,
+        _ctx: &mut Context<Self>,
+        collect: G,
+    ) -> ActorResponse<Self, R, Error>
+    where
+        G: FnOnce(Services) -> R + 'static,
+        R: 'static,
+    {
+        ActorResponse::r#async(
+            self.start_query(msg, collect)
+                .and_then(|(_id, rx), act, _ctx| rx.flatten_fut().into_actor(act)),
+        )
     }
 }
 
@@ -247,7 +418,7 @@ impl<T: MdnsConnection> Actor for MdnsActor<T> {
 
     /// Creates stream handler for incoming mDNS packets
     fn started(&mut self, ctx: &mut Self::Context) {
-        let socket = Self::create_mdns_socket().expect("Creation of mDNS socket failed");
+        let socket = Self::create_mdns_socket(&self.group).expect("Creation of mDNS socket failed");
         let (sink, stream) = UdpFramed::new(socket, MdnsCodec(T::unicast_query())).split();
 
         ctx.add_message_stream(
@@ -281,13 +452,98 @@ impl Handler<ServicesDescription> for MdnsActor<OneShot> {
         msg: ServicesDescription,
         ctx: &mut Self::Context,
     ) -> OneShotResponse<OneShot> {
-        let id = self.data.next_id;
-        self.data.next_id = id.wrapping_add(1);
+        self.query(msg, ctx, Services::collect)
+    }
+}
 
-        self.data.map.insert(id, Services::from(&msg));
-        let future = send_mdns_query(self.sender.clone(), msg, id);
+/// Configures retries of one-shot queries
+pub struct SetQueryRetries(pub QueryRetries);
+
+impl Message for SetQueryRetries {
+    type Result = ();
+}
+
+impl Handler<SetQueryRetries> for MdnsActor<OneShot> {
+    type Result = ();
 
-        self.build_response(future, ctx, id)
+    fn handle(&mut self, msg: SetQueryRetries, _ctx: &mut Self::Context) {
+        self.data.retries = msg.0;
+    }
+}
+
+impl Handler<InstanceNamesQuery> for MdnsActor<OneShot> {
+    type Result = ActorResponse<Self, HashSet<String>, Error>;
+
+    fn handle(&mut self, msg: InstanceNamesQuery, ctx: &mut Self::Context) -> Self::Result {
+        self.query(msg.0, ctx, Services::collect_names)
+    }
+}
+
+impl Handler<RankedQuery> for MdnsActor<OneShot> {
+    type Result = ActorResponse<Self, Vec<RankedInstance>, Error>;
+
+    fn handle(&mut self, msg: RankedQuery, ctx: &mut Self::Context) -> Self::Result {
+        self.query(msg.0, ctx, Services::collect_ranked)
+    }
+}
+
+/// Starts a one-shot query without waiting for its result.
+///
+/// Dropping the returned `PendingQuery`, or sending `CancelQuery` with its id,
+/// stops re-sending the query within one retry interval.
+pub struct StartQuery(pub ServicesDescription);
+
+impl Message for StartQuery {
+    type Result = Result<PendingQuery>;
+}
+
+impl Handler<StartQuery> for MdnsActor<OneShot> {
+    type Result = ActorResponse<Self, PendingQuery, Error>;
+
+    fn handle(&mut self, msg: StartQuery, _ctx: &mut Self::Context) -> Self::Result {
+        ActorResponse::r#async(
+            self.start_query(msg.0, Services::collect)
+                .map(|(id, rx), _act, _ctx| PendingQuery { id, rx }),
+        )
+    }
+}
+
+/// Result of a query started with `StartQuery`; fails with `Cancelled` if the query was cancelled
+pub struct PendingQuery {
+    id: u16,
+    rx: oneshot::Receiver<Result<HashSet<ServiceInstance>>>,
+}
+
+impl PendingQuery {
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+}
+
+impl Future for PendingQuery {
+    type Item = HashSet<ServiceInstance>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.rx.poll()? {
+            Async::Ready(result) => result.map(Async::Ready),
+            Async::NotReady => Ok(Async::NotReady),
+        }
+    }
+}
+
+/// Cancels the query with given id, started with `StartQuery`
+pub struct CancelQuery(pub u16);
+
+impl Message for CancelQuery {
+    type Result = ();
+}
+
+impl Handler<CancelQuery> for MdnsActor<OneShot> {
+    type Result = ();
+
+    fn handle(&mut self, msg: CancelQuery, _ctx: &mut Self::Context) {
+        self.data.map.remove(&msg.0);
     }
 }
 
@@ -309,7 +565,11 @@ impl Handler<SubscribeInstance> for MdnsActor<Continuous> {
         _ctx: &mut Self::Context,
     ) -> ContinuousResponse<Continuous> {
         use std::collections::hash_map::Entry;
-        let list = ContinuousInstancesList::new(msg.service.clone(), self.sender.clone().unwrap());
+        let list = ContinuousInstancesList::new(
+            msg.service.clone(),
+            self.sender.clone().unwrap(),
+            self.group.socket_addr(),
+        );
 
         let res = match self.data.map.entry(msg.service.to_string()) {
             Entry::Vacant(a) => {
@@ -334,12 +594,325 @@ impl Handler<SubscribeInstance> for MdnsActor<Continuous> {
 
 #[cfg(test)]
 mod tests {
-    use actor::{MdnsActor, OneShot};
+    use actix::prelude::*;
+    use actor::{
+        mdns_socket, CancelQuery, MdnsActor, MdnsConnection, MdnsGroup, OneShot, QueryRetries,
+        SetQueryRetries, StartQuery,
+    };
+    use codec::ParsedPacket;
+    use errors::{Error, ErrorKind};
+    use futures::prelude::*;
+    use gu_actix::FlattenFuture;
+    use service::{ServiceInstance, Services, ServicesDescription, Transport};
+    use std::net::{Ipv4Addr, UdpSocket};
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+    use tokio::timer::Delay;
 
     #[test]
     fn create_mdns_socket() {
-        let socket = MdnsActor::<OneShot>::create_mdns_socket();
+        let socket = MdnsActor::<OneShot>::create_mdns_socket(&MdnsGroup::default());
 
         assert!(socket.is_ok());
     }
+
+    #[test]
+    fn bind_failure_is_reported() {
+        // held without SO_REUSEPORT, so the mDNS socket cannot share the port
+        let taken = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let port = taken.local_addr().unwrap().port();
+
+        match mdns_socket(MdnsGroup::default().addr, port, 1) {
+            Err(Error(ErrorKind::BindFailed(p, _), _)) => assert_eq!(p, port),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("bound to taken port {}", port),
+        }
+    }
+
+    #[test]
+    fn configured_ttl_is_applied() {
+        let group = MdnsGroup::default().with_ttl(16).unwrap();
+        let socket = mdns_socket(group.addr, 0, group.ttl()).unwrap();
+        assert_eq!(socket.multicast_ttl_v4().unwrap(), 16);
+
+        for ttl in &[0, 256] {
+            match MdnsGroup::default().with_ttl(*ttl) {
+                Err(Error(ErrorKind::InvalidTtl(t), _)) => assert_eq!(t, *ttl),
+                _ => panic!("TTL {} accepted", ttl),
+            }
+        }
+    }
+
+    fn hub_packet(id: u16) -> ParsedPacket {
+        ParsedPacket {
+            id,
+            instances: vec![ServiceInstance {
+                name: "host._gu_hub._tcp.local".into(),
+                host: "host.local".into(),
+                txt: Vec::new(),
+                addrs_v4: vec!["10.0.0.2".parse().unwrap()],
+                ports: vec![61622],
+                priority: 0,
+                weight: 0,
+                source: None,
+                transport: None,
+            }],
+            names: Vec::new(),
+            questions: Vec::new(),
+            goodbyes: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn retry_recovers_dropped_response() {
+        let mut one_shot = OneShot::default();
+        let query = ServicesDescription::new(vec!["hub".into()]);
+        one_shot.map.insert(5, Services::from(&query));
+
+        // first response was dropped
+        assert!(one_shot.needs_retry(5, 0));
+
+        one_shot.handle_packet(hub_packet(5), "10.0.0.2:5353".parse().unwrap());
+        assert!(!one_shot.needs_retry(5, 1));
+
+        let instances = one_shot.map.remove(&5).unwrap().collect();
+        assert_eq!(instances.len(), 1);
+    }
+
+    #[test]
+    fn response_source_is_recorded() {
+        let mut one_shot = OneShot::default();
+        let query = ServicesDescription::new(vec!["hub".into()]);
+        one_shot.map.insert(6, Services::from(&query));
+
+        let src = "192.168.1.7:5353".parse().unwrap();
+        one_shot.handle_packet(hub_packet(6), src);
+
+        let instances = one_shot.map.remove(&6).unwrap().collect();
+        let instance = instances.iter().next().unwrap();
+        assert_eq!(instance.source, Some(src));
+        assert_eq!(
+            instance.addrs_v4,
+            vec!["10.0.0.2".parse::<Ipv4Addr>().unwrap()]
+        );
+    }
+
+    #[test]
+    fn response_transport_is_recorded() {
+        let mut one_shot = OneShot::default();
+        let query = ServicesDescription::new(vec!["hub".into()]);
+        one_shot.map.insert(8, Services::from(&query));
+        one_shot.map.insert(9, Services::from(&query));
+
+        one_shot.handle_packet(hub_packet(8), "192.168.1.7:5353".parse().unwrap());
+        one_shot.handle_packet(hub_packet(9), "[fe80::1]:5353".parse().unwrap());
+
+        let transport = |one_shot: &mut OneShot, id| {
+            let instances = one_shot.map.remove(&id).unwrap().collect();
+            instances.iter().next().unwrap().transport
+        };
+        assert_eq!(transport(&mut one_shot, 8), Some(Transport::V4));
+        assert_eq!(transport(&mut one_shot, 9), Some(Transport::V6));
+    }
+
+    #[test]
+    fn instances_without_required_txt_are_skipped() {
+        let mut one_shot = OneShot::default();
+        let query = ServicesDescription::new(vec!["hub".into()]).require_txt("gpu", "true");
+        one_shot.map.insert(7, Services::from(&query));
+
+        let mut packet = hub_packet(7);
+        let mut instance = packet.instances[0].clone();
+        packet.instances[0].txt = vec!["gpu=true".into(), "node_id=0x01".into()];
+        instance.name = "other._gu_hub._tcp.local".into();
+        instance.addrs_v4 = vec!["10.0.0.3".parse().unwrap()];
+        for txt in &["gpu=false", "gpu=truely", "gpu"] {
+            let mut instance = instance.clone();
+            instance.txt = vec![txt.to_string()];
+            packet.instances.push(instance);
+        }
+        one_shot.handle_packet(packet, "10.0.0.2:5353".parse().unwrap());
+
+        let instances = one_shot.map.remove(&7).unwrap().collect();
+        assert_eq!(instances.len(), 1);
+        assert_eq!(
+            instances.iter().next().unwrap().name,
+            "host._gu_hub._tcp.local"
+        );
+    }
+
+    #[test]
+    fn faster_responder_ranks_first() {
+        let mut one_shot = OneShot::default();
+        let query = ServicesDescription::new(vec!["hub".into()]);
+        one_shot.map.insert(10, Services::from(&query));
+
+        let responder = |name: &str, ip: &str| {
+            let mut packet = hub_packet(10);
+            packet.instances[0].name = format!("{}._gu_hub._tcp.local", name);
+            packet.instances[0].addrs_v4 = vec![ip.parse().unwrap()];
+            packet
+        };
+        thread::sleep(Duration::from_millis(10));
+        one_shot.handle_packet(
+            responder("fast", "10.0.0.2"),
+            "10.0.0.2:5353".parse().unwrap(),
+        );
+        thread::sleep(Duration::from_millis(40));
+        one_shot.handle_packet(
+            responder("slow", "10.0.0.3"),
+            "10.0.0.3:5353".parse().unwrap(),
+        );
+        // repeated responses do not change the time of the first one
+        one_shot.handle_packet(
+            responder("fast", "10.0.0.2"),
+            "10.0.0.2:5353".parse().unwrap(),
+        );
+
+        let ranked = one_shot.map.remove(&10).unwrap().collect_ranked();
+        let names: Vec<_> = ranked.iter().map(|r| r.instance.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["fast._gu_hub._tcp.local", "slow._gu_hub._tcp.local"]
+        );
+        assert!(ranked[0].response_time >= Duration::from_millis(10));
+        assert!(ranked[1].response_time >= ranked[0].response_time + Duration::from_millis(40));
+    }
+
+    #[test]
+    fn retries_are_limited() {
+        let mut one_shot = OneShot::default();
+        let query = ServicesDescription::new(vec!["hub".into()]);
+        one_shot.map.insert(5, Services::from(&query));
+
+        assert!(one_shot.needs_retry(5, one_shot.retries.count - 1));
+        assert!(!one_shot.needs_retry(5, one_shot.retries.count));
+    }
+
+    #[test]
+    fn goodbye_removes_discovered_instance() {
+        let mut one_shot = OneShot::default();
+        let query = ServicesDescription::new(vec!["hub".into()]);
+        one_shot.map.insert(4, Services::from(&query));
+        let src = "10.0.0.2:5353".parse().unwrap();
+
+        one_shot.handle_packet(hub_packet(4), src);
+        assert!(!one_shot.needs_retry(4, 0));
+
+        let mut goodbye = hub_packet(4);
+        goodbye.instances.clear();
+        goodbye.goodbyes = vec!["host._gu_hub._tcp.local".into()];
+        one_shot.handle_packet(goodbye, src);
+
+        assert!(one_shot.map.remove(&4).unwrap().collect().is_empty());
+    }
+
+    #[test]
+    fn name_only_query_skips_addresses() {
+        let mut one_shot = OneShot::default();
+        let query = ServicesDescription::new(vec!["hub".into()]);
+        one_shot.map.insert(3, Services::from(&query));
+
+        one_shot.handle_packet(
+            ParsedPacket {
+                id: 3,
+                instances: Vec::new(),
+                names: vec![
+                    "host._gu_hub._tcp.local".into(),
+                    "other._gu_provider._tcp.local".into(),
+                ],
+                questions: Vec::new(),
+                goodbyes: Vec::new(),
+            },
+            "127.0.0.1:5353".parse().unwrap(),
+        );
+
+        let services = one_shot.map.remove(&3).unwrap();
+        assert_eq!(
+            services.collect_names().into_iter().collect::<Vec<_>>(),
+            vec!["host._gu_hub._tcp.local".to_string()]
+        );
+    }
+
+    /// Number of queries still awaiting responses
+    struct PendingCount;
+
+    impl Message for PendingCount {
+        type Result = usize;
+    }
+
+    impl Handler<PendingCount> for MdnsActor<OneShot> {
+        type Result = usize;
+
+        fn handle(&mut self, _msg: PendingCount, _ctx: &mut Self::Context) -> usize {
+            self.data.map.len()
+        }
+    }
+
+    fn slow_retrying_actor(port: u16) -> Addr<MdnsActor<OneShot>> {
+        let actor = MdnsActor::<OneShot>::with_group(MdnsGroup::with_port(port)).start();
+        actor.do_send(SetQueryRetries(QueryRetries {
+            count: 1000,
+            interval: Duration::from_millis(10),
+        }));
+        actor
+    }
+
+    #[test]
+    fn cancelled_query_stops_promptly() {
+        System::run(|| {
+            let actor = slow_retrying_actor(15354);
+            let query = ServicesDescription::new(vec!["cancel-test".into()]);
+            let started = Instant::now();
+
+            Arbiter::spawn(
+                actor
+                    .send(StartQuery(query))
+                    .flatten_fut()
+                    .and_then(move |pending| {
+                        actor
+                            .send(CancelQuery(pending.id()))
+                            .map_err(Error::from)
+                            .and_then(|_| pending)
+                    })
+                    .then(move |r| {
+                        match r {
+                            Err(Error(ErrorKind::Cancelled, _)) => (),
+                            Err(e) => panic!("unexpected error: {}", e),
+                            Ok(_) => panic!("query was not cancelled"),
+                        }
+                        // without cancelling, retries would take 10 s
+                        assert!(started.elapsed() < Duration::from_secs(1));
+                        Ok(System::current().stop())
+                    }),
+            );
+        });
+    }
+
+    #[test]
+    fn dropped_query_is_released() {
+        System::run(|| {
+            let actor = slow_retrying_actor(15355);
+            let query = ServicesDescription::new(vec!["drop-test".into()]);
+
+            Arbiter::spawn(
+                actor
+                    .send(StartQuery(query))
+                    .flatten_fut()
+                    .map_err(|e| panic!("cannot start query: {}", e))
+                    .and_then(|pending| {
+                        drop(pending);
+                        Delay::new(Instant::now() + Duration::from_millis(100))
+                            .map_err(|e| panic!("{}", e))
+                    })
+                    .and_then(move |_| actor.send(PendingCount).map_err(|e| panic!("{}", e)))
+                    .then(|r| {
+                        assert_eq!(r, Ok(0));
+                        Ok(System::current().stop())
+                    }),
+            );
+        });
+    }
 }
@@ -0,0 +1,355 @@
+//! Publishing of own service instance over mDNS
+
+use std::net::{self, SocketAddr};
+
+use actix::prelude::*;
+use bytes::BytesMut;
+use dns_parser::{Packet, QueryType};
+use futures::prelude::*;
+use tokio::{
+    net::{UdpFramed, UdpSocket},
+    reactor::Handle,
+};
+use tokio_codec::BytesCodec;
+
+use actor::{mdns_socket, MdnsGroup};
+use errors::{Error, Result};
+use service::ServiceInstance;
+
+/// TTL of announced records, in seconds
+const RECORD_TTL: u32 = 120;
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+
+fn put_name(buf: &mut Vec<u8>, name: &str) {
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        buf.push(label.len() as u8);
+        buf.extend_from_slice(label.as_bytes());
+    }
+    buf.push(0);
+}
+
+fn put_u16(buf: &mut Vec<u8>, v: u16) {
+    buf.extend_from_slice(&[(v >> 8) as u8, v as u8]);
+}
+
+fn put_u32(buf: &mut Vec<u8>, v: u32) {
+    put_u16(buf, (v >> 16) as u16);
+    put_u16(buf, v as u16);
+}
+
+fn put_record(buf: &mut Vec<u8>, name: &str, rtype: u16, ttl: u32, rdata: &[u8]) {
+    put_name(buf, name);
+    put_u16(buf, rtype);
+    put_u16(buf, CLASS_IN);
+    put_u32(buf, ttl);
+    put_u16(buf, rdata.len() as u16);
+    buf.extend_from_slice(rdata);
+}
+
+/// Announces given service instance in the local network and responds
+/// to mDNS queries for its service type.
+///
+/// Records are withdrawn (sent with TTL 0) when the actor stops.
+pub struct ServiceAnnouncement {
+    instance: ServiceInstance,
+    socket: Option<net::UdpSocket>,
+    group: MdnsGroup,
+}
+
+impl ServiceAnnouncement {
+    /// `instance.name` is full instance name, e.g. `host._gu_provider._tcp.local`;
+    /// the first of `instance.ports` is announced.
+    pub fn new(instance: ServiceInstance) -> Self {
+        ServiceAnnouncement {
+            instance,
+            socket: None,
+            group: MdnsGroup::default(),
+        }
+    }
+
+    /// Announces in given multicast group instead of the standard one
+    pub fn with_group(mut self, group: MdnsGroup) -> Self {
+        self.group = group;
+        self
+    }
+
+    /// Announced instance; can be excluded from own queries
+    pub fn instance(&self) -> &ServiceInstance {
+        &self.instance
+    }
+
+    fn matches(&self, packet: &Packet) -> bool {
+        let service = self.instance.service();
+
+        packet.questions.iter().any(|q| match q.qtype {
+            QueryType::PTR | QueryType::All => q.qname.to_string() == service,
+            _ => false,
+        })
+    }
+
+    /// Response packet with PTR answer and SRV, TXT and A additional records
+    fn response(&self, id: u16, ttl: u32) -> Vec<u8> {
+        let instance = &self.instance;
+        let mut buf = Vec::new();
+
+        put_u16(&mut buf, id);
+        // response, authoritative answer
+        put_u16(&mut buf, 0x8400);
+        put_u16(&mut buf, 0);
+        put_u16(&mut buf, 1);
+        put_u16(&mut buf, 0);
+        put_u16(&mut buf, 2 + instance.addrs_v4.len() as u16);
+
+        let mut ptr = Vec::new();
+        put_name(&mut ptr, &instance.name);
+        put_record(&mut buf, &instance.service(), TYPE_PTR, ttl, &ptr);
+
+        let mut srv = Vec::new();
+        put_u16(&mut srv, instance.priority);
+        put_u16(&mut srv, instance.weight);
+        put_u16(
+            &mut srv,
+            instance.ports.first().cloned().unwrap_or_default(),
+        );
+        put_name(&mut srv, &instance.host);
+        put_record(&mut buf, &instance.name, TYPE_SRV, ttl, &srv);
+
+        let mut txt = Vec::new();
+        for entry in instance.txt.iter() {
+            txt.push(entry.len() as u8);
+            txt.extend_from_slice(entry.as_bytes());
+        }
+        if txt.is_empty() {
+            txt.push(0);
+        }
+        put_record(&mut buf, &instance.name, TYPE_TXT, ttl, &txt);
+
+        for addr in instance.addrs_v4.iter() {
+            put_record(&mut buf, &instance.host, TYPE_A, ttl, &addr.octets());
+        }
+
+        buf
+    }
+
+    fn send(&self, packet: &[u8], dst: SocketAddr) {
+        if let Some(ref socket) = self.socket {
+            if let Err(e) = socket.send_to(packet, dst) {
+                error!("cannot send mDNS announcement: {}", e)
+            }
+        }
+    }
+
+    fn create_socket(&self) -> Result<(net::UdpSocket, UdpSocket)> {
+        let socket =
+            mdns_socket(self.group.addr, self.group.port, self.group.ttl())?.into_udp_socket();
+        let sender = socket.try_clone()?;
+
+        Ok((
+            sender,
+            UdpSocket::from_std(socket, &Handle::default()).map_err(Error::from)?,
+        ))
+    }
+}
+
+struct IncomingPacket(BytesMut, SocketAddr);
+
+impl Message for IncomingPacket {
+    type Result = ();
+}
+
+impl Actor for ServiceAnnouncement {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        let (sender, socket) = match self.create_socket() {
+            Ok(sockets) => sockets,
+            Err(e) => {
+                error!("cannot create mDNS announcement socket: {}", e);
+                return ctx.stop();
+            }
+        };
+
+        ctx.add_message_stream(
+            UdpFramed::new(socket, BytesCodec::new())
+                .map(|(packet, src)| IncomingPacket(packet, src))
+                .map_err(|e| error!("mDNS announcement socket error: {}", e)),
+        );
+        self.socket = Some(sender);
+
+        self.send(&self.response(0, RECORD_TTL), self.group.socket_addr());
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        debug!("withdrawing mDNS announcement of {}", self.instance.name);
+        self.send(&self.response(0, 0), self.group.socket_addr());
+    }
+}
+
+impl Handler<IncomingPacket> for ServiceAnnouncement {
+    type Result = ();
+
+    fn handle(&mut self, msg: IncomingPacket, _ctx: &mut Self::Context) {
+        let IncomingPacket(packet, src) = msg;
+        let packet = match Packet::parse(packet.as_ref()) {
+            Ok(packet) => packet,
+            Err(_) => return,
+        };
+
+        if !packet.header.query || !self.matches(&packet) {
+            return;
+        }
+
+        // legacy unicast queries (not from mDNS port) and QU questions get direct answer
+        let unicast =
+            src.port() != self.group.port || packet.questions.iter().any(|q| q.prefer_unicast);
+        if unicast {
+            self.send(&self.response(packet.header.id, RECORD_TTL), src);
+        } else {
+            self.send(&self.response(0, RECORD_TTL), self.group.socket_addr());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix::prelude::*;
+    use bytes::BytesMut;
+    use dns_parser::Packet;
+    use futures::prelude::*;
+    use tokio_codec::Decoder;
+
+    use super::ServiceAnnouncement;
+    use actor::{MdnsActor, MdnsGroup, OneShot};
+    use codec::MdnsCodec;
+    use gu_actix::prelude::*;
+    use service::{ServiceInstance, ServicesDescription, Transport};
+
+    fn instance() -> ServiceInstance {
+        ServiceInstance {
+            name: "announced._gu_announce-test._tcp.local".into(),
+            host: "announced.local".into(),
+            txt: vec!["node_id=0x01".into()],
+            addrs_v4: vec!["127.0.0.1".parse().unwrap()],
+            ports: vec![61621],
+            priority: 1,
+            weight: 5,
+            source: None,
+            transport: None,
+        }
+    }
+
+    #[test]
+    fn response_decodes_to_instance() {
+        let announcement = ServiceAnnouncement::new(instance());
+        let mut src = BytesMut::from(announcement.response(9, 120));
+
+        let packet = MdnsCodec(true).decode(&mut src).unwrap().unwrap();
+        assert_eq!(packet.id, 9);
+        assert_eq!(packet.instances, vec![instance()]);
+    }
+
+    #[test]
+    fn goodbye_has_zero_ttl() {
+        let announcement = ServiceAnnouncement::new(instance());
+        let goodbye = announcement.response(0, 0);
+        let packet = Packet::parse(&goodbye).unwrap();
+
+        assert!(!packet.answers.is_empty());
+        assert!(packet
+            .answers
+            .iter()
+            .chain(packet.additional.iter())
+            .all(|record| record.ttl == 0));
+    }
+
+    #[test]
+    fn goodbye_is_decoded_as_removal() {
+        let announcement = ServiceAnnouncement::new(instance());
+
+        let mut src = BytesMut::from(announcement.response(0, 120));
+        let packet = MdnsCodec(true).decode(&mut src).unwrap().unwrap();
+        assert_eq!(packet.instances, vec![instance()]);
+        assert!(packet.goodbyes.is_empty());
+
+        let mut src = BytesMut::from(announcement.response(0, 0));
+        let packet = MdnsCodec(true).decode(&mut src).unwrap().unwrap();
+        assert!(packet.instances.is_empty());
+        assert_eq!(packet.goodbyes, vec![instance().name]);
+    }
+
+    #[test]
+    #[ignore] // uses the standard mDNS group, so it needs a multicast-capable network
+    fn announced_service_is_discovered() {
+        System::run(|| {
+            let _announcement = ServiceAnnouncement::new(instance()).start();
+
+            Arbiter::spawn(
+                MdnsActor::<OneShot>::from_registry()
+                    .send(ServicesDescription::new(vec!["announce-test".into()]))
+                    .flatten_fut()
+                    .then(|r| {
+                        let instances = r.unwrap();
+                        assert!(instances.iter().any(|i| i.name == instance().name
+                            && i.ports == instance().ports
+                            && i.source.is_some()
+                            && i.transport == Some(Transport::V4)));
+                        Ok(System::current().stop())
+                    }),
+            );
+        });
+    }
+
+    #[test]
+    #[ignore] // uses the standard mDNS group, so it needs a multicast-capable network
+    fn own_instance_is_excluded() {
+        System::run(|| {
+            let announcement = ServiceAnnouncement::new(instance());
+            let query = ServicesDescription::new(vec!["announce-test".into()])
+                .exclude(announcement.instance().clone());
+            let _announcement = announcement.start();
+
+            Arbiter::spawn(
+                MdnsActor::<OneShot>::from_registry()
+                    .send(query)
+                    .flatten_fut()
+                    .then(|r| {
+                        let instances = r.unwrap();
+                        assert!(instances.iter().all(|i| i.name != instance().name));
+                        Ok(System::current().stop())
+                    }),
+            );
+        });
+    }
+
+    #[test]
+    fn announced_service_is_discovered_on_isolated_port() {
+        // off the standard port, so neither the system responder nor other
+        // hosts take part
+        let group = MdnsGroup::with_port(15353);
+
+        System::run(move || {
+            let _announcement = ServiceAnnouncement::new(instance())
+                .with_group(group)
+                .start();
+
+            Arbiter::spawn(
+                MdnsActor::<OneShot>::with_group(group)
+                    .start()
+                    .send(ServicesDescription::new(vec!["announce-test".into()]))
+                    .flatten_fut()
+                    .then(|r| {
+                        let instances = r.unwrap();
+                        assert!(instances.iter().any(|i| i == &instance()
+                            && i.source.is_some()
+                            && i.transport == Some(Transport::V4)));
+                        Ok(System::current().stop())
+                    }),
+            );
+        });
+    }
+}
@@ -5,7 +5,8 @@ use tokio_codec::{Decoder, Encoder};
 use dns_parser::{
     rdata::{
         a::Record,
-        RData::{A, SRV, TXT},
+        ptr,
+        RData::{A, PTR, SRV, TXT},
     },
     Builder, Packet, QueryClass, QueryType, Question, ResourceRecord,
 };
@@ -20,7 +21,11 @@ use std::{
 pub struct ParsedPacket {
     pub id: u16,
     pub instances: Vec<ServiceInstance>,
+    /// instance names from PTR records
+    pub names: Vec<String>,
     pub questions: Vec<String>,
+    /// names of instances withdrawn with TTL 0 records (mDNS goodbye)
+    pub goodbyes: Vec<String>,
 }
 
 #[derive(Debug)]
@@ -52,10 +57,28 @@ fn combine_questions(mut parse_sets: QuestionParseSets, questions: &mut Vec<Stri
 }
 
 fn parse_answer(answer: ResourceRecord, parse_maps: &mut ResponseParseMaps) {
+    if answer.ttl == 0 {
+        match answer.data {
+            SRV(_) | TXT(_) => {
+                parse_maps.goodbyes.insert(answer.name.to_string());
+            }
+            PTR(ptr::Record(name)) => {
+                parse_maps.goodbyes.insert(name.to_string());
+            }
+            _ => (),
+        }
+        return;
+    }
+
     match answer.data {
         SRV(data) => {
             let key = (answer.name.to_string(), data.target.clone().to_string());
-            parse_maps.srv.entry(key).or_default().push(data.port);
+            parse_maps
+                .srv
+                .entry(key)
+                .or_insert_with(|| (data.priority, data.weight, Vec::new()))
+                .2
+                .push(data.port);
         }
         TXT(data) => {
             parse_maps.txt.insert(
@@ -65,6 +88,9 @@ fn parse_answer(answer: ResourceRecord, parse_maps: &mut ResponseParseMaps) {
                     .collect::<Vec<_>>(),
             );
         }
+        PTR(ptr::Record(name)) => {
+            parse_maps.ptr.push(name.to_string());
+        }
         A(data) => {
             let Record(arr) = data;
             parse_maps
@@ -85,7 +111,7 @@ fn combine_answers(parse_maps: ResponseParseMaps, services: &mut Vec<ServiceInst
         let pair = e.0;
         let name = pair.0;
         let host = pair.1;
-        let ports = e.1;
+        let (priority, weight, ports) = e.1;
 
         let addrs_v4 = a.get(&host).map(|a| a.clone()).unwrap_or(Vec::new());
         let txt = txt.get(&name).map(|a| a.clone()).unwrap_or(Vec::new());
@@ -96,18 +122,26 @@ fn combine_answers(parse_maps: ResponseParseMaps, services: &mut Vec<ServiceInst
             txt,
             addrs_v4,
             ports,
+            priority,
+            weight,
+            source: None,
+            transport: None,
         })
     });
 }
 
 #[derive(Default)]
 struct ResponseParseMaps {
-    // (service, host) -> ports
-    pub srv: HashMap<(String, String), Vec<u16>>,
+    // (service, host) -> (priority, weight, ports)
+    pub srv: HashMap<(String, String), (u16, u16, Vec<u16>)>,
     // service -> description
     pub txt: HashMap<String, Vec<String>>,
     // host -> IPv4
     pub a: HashMap<String, Vec<Ipv4Addr>>,
+    // instance names
+    pub ptr: Vec<String>,
+    // names of withdrawn instances
+    pub goodbyes: HashSet<String>,
 }
 
 #[derive(Default, Debug)]
@@ -141,6 +175,8 @@ impl Decoder for MdnsCodec {
 
         let mut services: Vec<ServiceInstance> = Vec::new();
         let mut questions: Vec<String> = Vec::new();
+        let names = parse_maps.ptr.drain(..).collect();
+        let goodbyes = parse_maps.goodbyes.drain().collect();
 
         combine_answers(parse_maps, &mut services);
         combine_questions(parse_sets, &mut questions);
@@ -150,7 +186,9 @@ impl Decoder for MdnsCodec {
         Ok(Some(ParsedPacket {
             id,
             instances: services,
+            names,
             questions,
+            goodbyes,
         }))
     }
 }
@@ -176,3 +214,66 @@ impl Encoder for MdnsCodec {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use dns_parser::Packet;
+    use tokio_codec::{Decoder, Encoder};
+
+    use super::MdnsCodec;
+    use service::{ServiceDescription, ServicesDescription};
+
+    fn push_name(buf: &mut Vec<u8>, name: &str) {
+        for label in name.split('.') {
+            buf.push(label.len() as u8);
+            buf.extend_from_slice(label.as_bytes());
+        }
+        buf.push(0);
+    }
+
+    /// Response with a single PTR answer, without SRV/A records
+    fn ptr_response(id: u16, service: &str, instance: &str) -> BytesMut {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&id.to_be_bytes());
+        buf.extend_from_slice(&[0x84, 0x00, 0, 0, 0, 1, 0, 0, 0, 0]);
+        push_name(&mut buf, service);
+        // type PTR, class IN, ttl 120
+        buf.extend_from_slice(&[0, 12, 0, 1, 0, 0, 0, 120]);
+
+        let mut rdata = Vec::new();
+        push_name(&mut rdata, instance);
+        buf.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        buf.extend_from_slice(&rdata);
+
+        buf.as_slice().into()
+    }
+
+    #[test]
+    fn decode_ptr_names() {
+        let mut src = ptr_response(7, "_gu_hub._tcp.local", "host._gu_hub._tcp.local");
+        let packet = MdnsCodec(true).decode(&mut src).unwrap().unwrap();
+
+        assert_eq!(packet.id, 7);
+        assert_eq!(packet.names, vec!["host._gu_hub._tcp.local".to_string()]);
+        assert!(packet.instances.is_empty());
+    }
+
+    #[test]
+    fn encode_query_in_custom_domain() {
+        let service = ServiceDescription::from("hub").with_domain("example.com");
+        assert_eq!(service.domain(), "example.com");
+
+        let mut dst = BytesMut::new();
+        MdnsCodec(false)
+            .encode((ServicesDescription::new(vec![service]), 3), &mut dst)
+            .unwrap();
+        let packet = Packet::parse(dst.as_ref()).unwrap();
+
+        assert_eq!(packet.questions.len(), 1);
+        assert_eq!(
+            packet.questions[0].qname.to_string(),
+            "_gu_hub._tcp.example.com"
+        );
+    }
+}
@@ -3,7 +3,7 @@ use actor::send_mdns_query;
 use errors::ErrorKind;
 use futures::{sync::mpsc, Future};
 use rand::{thread_rng, Rng, ThreadRng};
-use service::{ServiceDescription, ServiceInstance, ServicesDescription};
+use service::{service_of, ServiceDescription, ServiceInstance, ServicesDescription};
 use std::{
     cmp::Ordering,
     collections::{BinaryHeap, HashMap, HashSet},
@@ -145,6 +145,19 @@ impl MemoryManager {
         result
     }
 
+    /// Forgets instance by name right away, e.g. on mDNS goodbye; returns it if it was known
+    pub fn remove(&mut self, name: &str) -> Option<ServiceInstance> {
+        let id = self
+            .data_map
+            .iter()
+            .find(|(_, data)| data.name == name)
+            .map(|(id, _)| id.clone())?;
+
+        // entries left in the queue are skipped, as they have no time in `time_map`
+        self.time_map.remove(&id);
+        self.data_map.remove(&id)
+    }
+
     fn conditionally_destroy_instance(&mut self, time: Instant) -> bool {
         use std::{
             collections::{binary_heap::PeekMut, hash_map::Entry},
@@ -204,6 +217,8 @@ pub struct ContinuousInstancesList {
     memory: MemoryManager,
     notifier: ExponentialNotify,
     sender: mpsc::Sender<((ServicesDescription, u16), SocketAddr)>,
+    /// multicast group queries are sent to
+    group: SocketAddr,
     subscribers: HashSet<Recipient<NewInstance>>,
 }
 
@@ -211,12 +226,14 @@ impl ContinuousInstancesList {
     pub fn new(
         name: ServiceDescription,
         sender: mpsc::Sender<((ServicesDescription, u16), SocketAddr)>,
+        group: SocketAddr,
     ) -> Self {
         ContinuousInstancesList {
             name,
             memory: MemoryManager::new(Duration::from_secs(SERVICE_TTL)),
             notifier: ExponentialNotify::new(),
             sender,
+            group,
             subscribers: HashSet::new(),
         }
     }
@@ -254,7 +271,7 @@ impl Actor for ContinuousInstancesList {
             match time.0 {
                 Some(_) => {
                     ctx.spawn(
-                        send_mdns_query(Some(act.sender.clone()), vec.clone(), 0)
+                        send_mdns_query(Some(act.sender.clone()), vec.clone(), 0, act.group)
                             .map_err(|e| error!("mDNS query error: {:?}", e))
                             .into_actor(act),
                     );
@@ -277,6 +294,29 @@ impl ReceivedMdnsInstance {
     }
 }
 
+/// Instance withdrawn by an mDNS goodbye, given by its name
+pub struct RemovedMdnsInstance(String);
+
+impl RemovedMdnsInstance {
+    pub fn new(name: String) -> Self {
+        RemovedMdnsInstance(name)
+    }
+}
+
+impl Message for RemovedMdnsInstance {
+    type Result = ();
+}
+
+impl Handler<RemovedMdnsInstance> for ContinuousInstancesList {
+    type Result = ();
+
+    fn handle(&mut self, msg: RemovedMdnsInstance, _ctx: &mut Context<Self>) -> () {
+        if self.memory.remove(&msg.0).is_some() {
+            debug!("instance {} said goodbye", msg.0);
+        }
+    }
+}
+
 pub struct NewInstance {
     pub data: ServiceInstance,
 }
@@ -358,3 +398,40 @@ impl Drop for Subscription {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::MemoryManager;
+    use service::ServiceInstance;
+
+    fn instance() -> ServiceInstance {
+        ServiceInstance {
+            name: "host._gu_hub._tcp.local".into(),
+            host: "host.local".into(),
+            txt: vec!["node_id=0x01".into()],
+            addrs_v4: vec!["10.0.0.2".parse().unwrap()],
+            ports: vec![61622],
+            priority: 0,
+            weight: 0,
+            source: None,
+            transport: None,
+        }
+    }
+
+    #[test]
+    fn goodbye_removes_instance_right_away() {
+        let mut memory = MemoryManager::new(Duration::from_secs(60));
+        assert!(memory.update(instance()).is_some());
+
+        assert_eq!(memory.remove(&instance().name), Some(instance()));
+        assert!(memory.memory().is_empty());
+        assert_eq!(memory.remove(&instance().name), None);
+
+        // announced again after the goodbye, so reported to subscribers as new
+        assert!(memory.update(instance()).is_some());
+        memory.clear_memory();
+        assert_eq!(memory.memory(), vec![instance()]);
+    }
+}
@@ -31,12 +31,37 @@ error_chain! {
             display("there is no such key")
         }
 
+        InvalidTxtValue(key: String, value: String) {
+            description("invalid TXT record value")
+            display("invalid TXT record value {}='{}'", key, value)
+        }
+
         DoSendError {
             description("cannot send message by do_send")
             display("cannot send message by do_send")
         }
 
         Mailbox
+
+        Timeout {
+            description("mDNS query timed out")
+            display("mDNS query timed out")
+        }
+
+        Cancelled {
+            description("mDNS query cancelled")
+            display("mDNS query cancelled")
+        }
+
+        InvalidTtl(ttl: u32) {
+            description("invalid multicast TTL")
+            display("invalid multicast TTL {}, expected 1 to 255", ttl)
+        }
+
+        BindFailed(port: u16, e: ::std::io::Error) {
+            description("cannot bind mDNS socket")
+            display("cannot bind mDNS socket to port {}: {}", port, e)
+        }
     }
 }
 
@@ -25,21 +25,33 @@ extern crate socket2;
 extern crate tokio;
 extern crate tokio_codec;
 
-use std::net::SocketAddr;
+use std::{collections::HashSet, net::SocketAddr, time::Duration};
 
 use mdns::{Responder, Service};
 use serde::{Deserialize, Serialize};
 
+pub use actor::MdnsGroup;
+pub use announce::ServiceAnnouncement;
 pub use continuous::{NewInstance, Subscription};
+/// Kinds of discovery errors, e.g. `BindFailed` or `Timeout`
+pub use errors::ErrorKind as LanError;
 use gu_net::NodeId;
-pub use service::ServiceDescription;
+pub use meta::{ProviderCapabilities, ProviderMeta};
+pub use selector::Selector;
+pub use service::{
+    diff, InstanceNamesQuery, RankedInstance, RankedQuery, ServiceDescription, ServiceDiff,
+    ServiceInstance, ServicesDescription, Transport, DEFAULT_DOMAIN,
+};
 
 pub mod actor;
+mod announce;
 mod codec;
 mod continuous;
 
 pub mod errors;
+mod meta;
 pub mod module;
+mod selector;
 mod service;
 
 pub const ID_LAN: u32 = 576411;
@@ -126,6 +138,45 @@ pub fn list_hubs() -> impl futures::Future<Item = Vec<HubDesc>, Error = ()> {
         .map_err(|_e| ())
 }
 
+/// Resolves given services, blocking until the query completes or `timeout` elapses.
+///
+/// Runs its own short-lived actix system, so it must not be called from within
+/// a running one; intended for CLI tools and tests.
+pub fn discover(
+    desc: ServicesDescription,
+    timeout: Duration,
+) -> errors::Result<HashSet<ServiceInstance>> {
+    use actix::prelude::*;
+
+    let mut sys = System::new("gu-lan-discover");
+    sys.block_on(discover_async(desc, timeout))
+}
+
+/// Resolves given services within the running actix system, failing with
+/// `Timeout` if the query does not complete in `timeout`.
+///
+/// Can be chained in actor handlers, e.g. via `into_actor(self)`.
+/// Dropping the returned future cancels the query.
+pub fn discover_async(
+    desc: ServicesDescription,
+    timeout: Duration,
+) -> impl futures::Future<Item = HashSet<ServiceInstance>, Error = errors::Error> {
+    use self::actor::{MdnsActor, OneShot, StartQuery};
+    use actix::prelude::*;
+    use gu_actix::prelude::*;
+    use tokio::timer::Timeout;
+
+    let query = MdnsActor::<OneShot>::from_registry()
+        .send(StartQuery(desc))
+        .flatten_fut()
+        .and_then(|pending| pending);
+
+    Timeout::new(query, timeout).map_err(|e| {
+        e.into_inner()
+            .unwrap_or_else(|| errors::ErrorKind::Timeout.into())
+    })
+}
+
 pub struct MdnsPublisher {
     is_hub: bool,
     port: Option<u16>,
@@ -205,4 +256,78 @@ impl MdnsPublisher {
 
         mdns
     }
+
+    /// Provider publisher announcing all of `meta` in its TXT records
+    pub fn init_provider_publisher(port: u16, meta: &ProviderMeta) -> Self {
+        let mut mdns = MdnsPublisher::default();
+        mdns.is_hub = false;
+        mdns.init(port, meta.to_txt());
+
+        mdns
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use actix::prelude::*;
+
+    use super::{discover, discover_async, ServicesDescription};
+
+    #[test]
+    fn discover_without_system() {
+        let instances = discover(
+            ServicesDescription::new(vec!["discover-test".into()]),
+            Duration::from_secs(5),
+        )
+        .unwrap();
+
+        assert!(instances.is_empty());
+    }
+
+    struct Starter {
+        found: Option<usize>,
+    }
+
+    impl Actor for Starter {
+        type Context = Context<Self>;
+
+        fn started(&mut self, ctx: &mut Self::Context) {
+            let desc = ServicesDescription::new(vec!["discover-async-test".into()]);
+            ctx.wait(
+                discover_async(desc, Duration::from_secs(5))
+                    .into_actor(self)
+                    .then(|r, act, _ctx| {
+                        act.found = Some(r.unwrap().len());
+                        fut::ok(())
+                    }),
+            );
+        }
+    }
+
+    struct Found;
+
+    impl Message for Found {
+        type Result = Option<usize>;
+    }
+
+    impl Handler<Found> for Starter {
+        type Result = Option<usize>;
+
+        fn handle(&mut self, _msg: Found, _ctx: &mut Self::Context) -> Self::Result {
+            self.found
+        }
+    }
+
+    #[test]
+    fn discover_async_within_system() {
+        let mut sys = System::new("test");
+        let starter = Starter { found: None }.start();
+
+        // messages are processed only after discovery, waited on in `started`, completes
+        let found = sys.block_on(starter.send(Found)).unwrap();
+
+        assert_eq!(found, Some(0));
+    }
 }
@@ -0,0 +1,206 @@
+//! Typed provider metadata carried in TXT records
+
+use std::str::FromStr;
+
+use errors::{ErrorKind, Result};
+use gu_net::NodeId;
+
+const NODE_ID: &str = "node_id";
+const RAM: &str = "ram";
+const CORES: &str = "cores";
+const GPUS: &str = "gpus";
+const ENVS: &str = "envs";
+
+/// Provider capabilities, announced as `key=value` TXT entries;
+/// lets the hub filter and rank discovered providers
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProviderCapabilities {
+    /// total RAM, in bytes
+    pub ram: Option<u64>,
+    pub cores: Option<usize>,
+    pub gpus: Option<u32>,
+    /// supported execution environments, e.g. `hd`; announced comma separated
+    pub envs: Vec<String>,
+}
+
+/// Provider identity with its capabilities
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProviderMeta {
+    pub node_id: NodeId,
+    pub capabilities: ProviderCapabilities,
+}
+
+fn parse<T: FromStr>(key: &str, value: &str) -> Result<T> {
+    value
+        .parse()
+        .map_err(|_| ErrorKind::InvalidTxtValue(key.into(), value.into()).into())
+}
+
+/// Splits TXT entries into keys and values, skipping entries without `=`
+fn entries<'a>(txt: &'a [String]) -> impl Iterator<Item = (&'a str, &'a str)> {
+    txt.iter().filter_map(|entry| {
+        entry
+            .find('=')
+            .map(|pos| (&entry[..pos], &entry[pos + 1..]))
+    })
+}
+
+impl ProviderCapabilities {
+    /// Parses TXT entries; unknown keys and entries without `=` are ignored,
+    /// while known keys must have valid values.
+    pub fn from_txt(txt: &[String]) -> Result<ProviderCapabilities> {
+        let mut capabilities = ProviderCapabilities::default();
+
+        for (key, value) in entries(txt) {
+            match key {
+                RAM => capabilities.ram = Some(parse(key, value)?),
+                CORES => capabilities.cores = Some(parse(key, value)?),
+                GPUS => capabilities.gpus = Some(parse(key, value)?),
+                ENVS => {
+                    capabilities.envs = value
+                        .split(',')
+                        .filter(|env| !env.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                }
+                NODE_ID => (),
+                _ => debug!("ignoring unknown TXT key {}", key),
+            }
+        }
+        Ok(capabilities)
+    }
+
+    /// TXT entries to be announced; absent values are omitted
+    pub fn to_txt(&self) -> Vec<String> {
+        let mut txt = Vec::new();
+
+        if let Some(ram) = self.ram {
+            txt.push(format!("{}={}", RAM, ram));
+        }
+        if let Some(cores) = self.cores {
+            txt.push(format!("{}={}", CORES, cores));
+        }
+        if let Some(gpus) = self.gpus {
+            txt.push(format!("{}={}", GPUS, gpus));
+        }
+        if !self.envs.is_empty() {
+            txt.push(format!("{}={}", ENVS, self.envs.join(",")));
+        }
+        txt
+    }
+
+    pub fn supports_env(&self, env: &str) -> bool {
+        self.envs.iter().any(|e| e == env)
+    }
+}
+
+impl ProviderMeta {
+    pub fn new(node_id: NodeId) -> Self {
+        ProviderMeta {
+            node_id,
+            capabilities: ProviderCapabilities::default(),
+        }
+    }
+
+    /// Parses TXT entries as `ProviderCapabilities::from_txt` does; `node_id` is required
+    pub fn from_txt(txt: &[String]) -> Result<ProviderMeta> {
+        let node_id = match entries(txt).find(|(key, _)| *key == NODE_ID) {
+            Some((key, value)) => parse(key, value)?,
+            None => return Err(ErrorKind::MissingKey.into()),
+        };
+
+        Ok(ProviderMeta {
+            node_id,
+            capabilities: ProviderCapabilities::from_txt(txt)?,
+        })
+    }
+
+    /// TXT entries to be announced; absent values are omitted
+    pub fn to_txt(&self) -> Vec<String> {
+        let mut txt = vec![format!("{}={}", NODE_ID, self.node_id.to_string())];
+        txt.extend(self.capabilities.to_txt());
+        txt
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ProviderCapabilities, ProviderMeta};
+    use gu_net::NodeId;
+    use service::ServiceInstance;
+
+    fn node_id() -> NodeId {
+        "0x2b9bc1b2cd0c4a2f79ea1fbd2b29ae0e4a3c3f64"
+            .parse()
+            .unwrap()
+    }
+
+    #[test]
+    fn round_trips_through_txt() {
+        let meta = ProviderMeta {
+            capabilities: ProviderCapabilities {
+                ram: Some(8 << 30),
+                cores: Some(4),
+                gpus: Some(1),
+                envs: vec!["hd".into(), "docker".into()],
+            },
+            ..ProviderMeta::new(node_id())
+        };
+
+        assert_eq!(ProviderMeta::from_txt(&meta.to_txt()).unwrap(), meta);
+
+        let minimal = ProviderMeta::new(node_id());
+        assert_eq!(minimal.to_txt().len(), 1);
+        assert_eq!(ProviderMeta::from_txt(&minimal.to_txt()).unwrap(), minimal);
+    }
+
+    #[test]
+    fn tolerates_unknown_keys() {
+        let mut txt = ProviderMeta::new(node_id()).to_txt();
+        txt.push("color=blue".into());
+        txt.push("flag".into());
+        txt.push("cores=2".into());
+
+        let meta = ProviderMeta::from_txt(&txt).unwrap();
+        assert_eq!(meta.capabilities.cores, Some(2));
+    }
+
+    #[test]
+    fn discovered_instance_has_typed_capabilities() {
+        let instance = ServiceInstance {
+            name: "provider-1._gu_provider._tcp.local".into(),
+            host: "provider-1.local".into(),
+            txt: vec![
+                "node_id=0x2b9bc1b2cd0c4a2f79ea1fbd2b29ae0e4a3c3f64".into(),
+                "ram=17179869184".into(),
+                "cores=8".into(),
+                "gpus=0".into(),
+                "envs=hd,docker".into(),
+                "version=0.3.0".into(),
+            ],
+            addrs_v4: vec!["192.168.1.20".parse().unwrap()],
+            ports: vec![61621],
+            priority: 0,
+            weight: 0,
+            source: None,
+            transport: None,
+        };
+
+        let capabilities = instance.capabilities().unwrap();
+        assert_eq!(capabilities.ram, Some(16 << 30));
+        assert_eq!(capabilities.cores, Some(8));
+        assert_eq!(capabilities.gpus, Some(0));
+        assert_eq!(capabilities.envs, vec!["hd", "docker"]);
+        assert!(capabilities.supports_env("docker"));
+        assert!(!capabilities.supports_env("wasm"));
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        let mut txt = ProviderMeta::new(node_id()).to_txt();
+        assert!(ProviderMeta::from_txt(&txt[1..]).is_err());
+
+        txt.push("ram=lots".into());
+        assert!(ProviderMeta::from_txt(&txt).is_err());
+    }
+}
@@ -7,7 +7,9 @@ use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use futures::Future;
 use gu_base::{cli, Decorator, Module};
 use serde::Serialize;
-use service::{ServiceInstance, ServicesDescription};
+use service::{
+    InstanceNamesQuery, ServiceDescription, ServiceInstance, ServicesDescription, DEFAULT_DOMAIN,
+};
 use std::{collections::HashSet, net::Ipv4Addr};
 
 fn format_addresses(addrs_v4: &Vec<Ipv4Addr>, ports: &Vec<u16>) -> String {
@@ -41,31 +43,52 @@ pub fn format_instances_table(instances: &HashSet<ServiceInstance>) {
     )
 }
 
-fn run_client(instances: &String) {
+pub fn format_names_table(names: &HashSet<String>) {
+    cli::format_table(
+        row!["Instance name"],
+        || "No instances found",
+        names.iter().map(|name| row![name]),
+    )
+}
+
+fn run_client(instances: &String, domain: &String, names_only: bool) {
     use actix::SystemService;
 
     let sys = actix::System::new("gu-lan");
-    let instances = instances.split(',').map(|s| s.to_string().into()).collect();
+    let instances = instances
+        .split(',')
+        .map(|s| ServiceDescription::from(s).with_domain(domain.clone()))
+        .collect();
 
     let mdns_actor = MdnsActor::<OneShot>::from_registry();
-    let query = ServicesDescription::new(instances);
-
-    Arbiter::spawn(
-        mdns_actor
-            .send(query)
-            .map_err(|e| error!("error! {}", e))
-            .and_then(|r| r.map_err(|e| error!("error! {}", e)))
-            .and_then(|r| Ok(format_instances_table(&r)))
-            .map_err(|e| error!("error! {:?}", e))
-            .then(|_| Ok(System::current().stop())),
-    );
+
+    if names_only {
+        Arbiter::spawn(
+            mdns_actor
+                .send(InstanceNamesQuery::new(instances))
+                .map_err(|e| error!("error! {}", e))
+                .and_then(|r| r.map_err(|e| error!("error! {}", e)))
+                .and_then(|r| Ok(format_names_table(&r)))
+                .then(|_| Ok(System::current().stop())),
+        );
+    } else {
+        Arbiter::spawn(
+            mdns_actor
+                .send(ServicesDescription::new(instances))
+                .map_err(|e| error!("error! {}", e))
+                .and_then(|r| r.map_err(|e| error!("error! {}", e)))
+                .and_then(|r| Ok(format_instances_table(&r)))
+                .map_err(|e| error!("error! {:?}", e))
+                .then(|_| Ok(System::current().stop())),
+        );
+    }
 
     let _ = sys.run();
 }
 
 enum LanCommand {
     None,
-    List(String),
+    List(String, String, bool),
 }
 
 pub struct LanModule {
@@ -93,7 +116,20 @@ impl Module for LanModule {
                 .subcommand(
                     SubCommand::with_name("list")
                         .about("Lists available instances (use -I to filter results)")
-                        .arg(instance),
+                        .arg(instance)
+                        .arg(
+                            Arg::with_name("domain")
+                                .short("d")
+                                .long("domain")
+                                .takes_value(true)
+                                .help("Domain to query instances in (default: local)"),
+                        )
+                        .arg(
+                            Arg::with_name("names")
+                                .short("n")
+                                .long("names")
+                                .help("Lists instance names only, without resolving addresses"),
+                        ),
                 )
                 .about("Shows information about all hubs and providers in the local area network"),
         )
@@ -106,6 +142,8 @@ impl Module for LanModule {
                     m.value_of("instance_types")
                         .unwrap_or("hub,provider")
                         .to_string(),
+                    m.value_of("domain").unwrap_or(DEFAULT_DOMAIN).to_string(),
+                    m.is_present("names"),
                 ),
                 _ => return false,
             };
@@ -117,7 +155,7 @@ impl Module for LanModule {
 
     fn run<D: Decorator + Clone + 'static>(&self, _decorator: D) {
         match self.command {
-            LanCommand::List(ref s) => run_client(s),
+            LanCommand::List(ref s, ref domain, names_only) => run_client(s, domain, names_only),
             _ => (),
         }
     }
@@ -0,0 +1,193 @@
+//! Choice among discovered instances according to their SRV priority and weight ([RFC 2782])
+//!
+//! [RFC 2782]: https://tools.ietf.org/html/rfc2782
+
+use std::collections::HashSet;
+
+use rand::{rngs::StdRng, thread_rng, Rng, SeedableRng, ThreadRng};
+
+use service::ServiceInstance;
+
+/// Selects instances of the lowest priority tier, at random proportionally to their weights.
+///
+/// Zero weight instances are selected only if the whole tier has zero weights.
+pub struct Selector<R = ThreadRng> {
+    /// sorted by priority
+    instances: Vec<ServiceInstance>,
+    rng: R,
+}
+
+impl Selector<ThreadRng> {
+    pub fn new(instances: HashSet<ServiceInstance>) -> Self {
+        Selector::with_rng(instances, thread_rng())
+    }
+}
+
+impl Selector<StdRng> {
+    /// Selector with deterministic choices, e.g. for tests
+    pub fn with_seed(instances: HashSet<ServiceInstance>, seed: u64) -> Self {
+        let mut bytes = [0u8; 32];
+        for (i, byte) in bytes.iter_mut().take(8).enumerate() {
+            *byte = (seed >> (8 * i)) as u8;
+        }
+        Selector::with_rng(instances, StdRng::from_seed(bytes))
+    }
+}
+
+impl<R: Rng> Selector<R> {
+    pub fn with_rng(instances: HashSet<ServiceInstance>, rng: R) -> Self {
+        let mut instances: Vec<ServiceInstance> = instances.into_iter().collect();
+        // name makes the order independent of the set iteration order
+        instances.sort_by(|a, b| (a.priority, &a.name).cmp(&(b.priority, &b.name)));
+
+        Selector { instances, rng }
+    }
+
+    /// `None` if there are no instances
+    pub fn select(&mut self) -> Option<&ServiceInstance> {
+        let priority = match self.instances.first() {
+            Some(instance) => instance.priority,
+            None => return None,
+        };
+        let tier: Vec<&ServiceInstance> = self
+            .instances
+            .iter()
+            .take_while(|instance| instance.priority == priority)
+            .collect();
+
+        let idx = pick(&mut self.rng, &tier);
+        Some(tier[idx])
+    }
+
+    /// All instances in order in which they should be tried: by priority tiers,
+    /// each tier shuffled according to weights
+    pub fn ordered(&mut self) -> Vec<&ServiceInstance> {
+        let mut ordered = Vec::with_capacity(self.instances.len());
+        let mut rest: &[ServiceInstance] = &self.instances;
+
+        while let Some(first) = rest.first() {
+            let len = rest
+                .iter()
+                .take_while(|instance| instance.priority == first.priority)
+                .count();
+            let mut tier: Vec<&ServiceInstance> = rest[..len].iter().collect();
+            while !tier.is_empty() {
+                let idx = pick(&mut self.rng, &tier);
+                ordered.push(tier.remove(idx));
+            }
+            rest = &rest[len..];
+        }
+        ordered
+    }
+}
+
+/// Index of weighted random choice from non-empty `tier`
+fn pick<R: Rng>(rng: &mut R, tier: &[&ServiceInstance]) -> usize {
+    let total: u32 = tier.iter().map(|instance| u32::from(instance.weight)).sum();
+    if total == 0 {
+        return rng.gen_range(0, tier.len());
+    }
+
+    let target = rng.gen_range(0, total);
+    let mut running = 0;
+    for (idx, instance) in tier.iter().enumerate() {
+        running += u32::from(instance.weight);
+        if running > target {
+            return idx;
+        }
+    }
+    unreachable!("target {} below total weight {}", target, total)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use super::Selector;
+    use service::ServiceInstance;
+
+    fn instance(name: &str, priority: u16, weight: u16) -> ServiceInstance {
+        ServiceInstance {
+            name: name.into(),
+            host: format!("{}.local", name),
+            txt: Vec::new(),
+            addrs_v4: Vec::new(),
+            ports: vec![61621],
+            priority,
+            weight,
+            source: None,
+            transport: None,
+        }
+    }
+
+    fn instances() -> HashSet<ServiceInstance> {
+        vec![
+            instance("a", 10, 10),
+            instance("b", 10, 20),
+            instance("c", 10, 70),
+            instance("backup", 20, 100),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    #[test]
+    fn selection_follows_weights() {
+        let mut selector = Selector::with_seed(instances(), 7);
+        let mut counts: HashMap<String, usize> = HashMap::new();
+
+        for _ in 0..10_000 {
+            let name = selector.select().unwrap().name.clone();
+            *counts.entry(name).or_insert(0) += 1;
+        }
+
+        assert_eq!(counts.get("backup"), None);
+        for (name, expected) in &[("a", 1_000), ("b", 2_000), ("c", 7_000)] {
+            let count = counts[*name] as i64;
+            assert!(
+                (count - expected).abs() < 300,
+                "{} selected {} times, expected about {}",
+                name,
+                count,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_same_choices() {
+        let mut first = Selector::with_seed(instances(), 42);
+        let mut second = Selector::with_seed(instances(), 42);
+
+        for _ in 0..100 {
+            assert_eq!(first.select(), second.select());
+        }
+    }
+
+    #[test]
+    fn ordered_tries_tiers_by_priority() {
+        let mut selector = Selector::with_seed(instances(), 1);
+        let ordered = selector.ordered();
+
+        assert_eq!(ordered.len(), 4);
+        assert_eq!(ordered[3].name, "backup");
+    }
+
+    #[test]
+    fn zero_weights_are_chosen_uniformly() {
+        let set = vec![instance("a", 0, 0), instance("b", 0, 0)]
+            .into_iter()
+            .collect();
+        let mut selector = Selector::with_seed(set, 3);
+
+        let a_count = (0..1_000)
+            .filter(|_| selector.select().unwrap().name == "a")
+            .count();
+        assert!(a_count > 400 && a_count < 600);
+    }
+
+    #[test]
+    fn empty_selector_selects_none() {
+        assert_eq!(Selector::with_seed(HashSet::new(), 0).select(), None);
+    }
+}
@@ -2,24 +2,30 @@ use std::{
     borrow::Cow,
     collections::{HashMap, HashSet},
     fmt::Display,
-    net::Ipv4Addr,
+    hash::{Hash, Hasher},
+    net::{Ipv4Addr, SocketAddr},
     result::Result as StdResult,
     str::FromStr,
+    time::{Duration, Instant},
 };
 
 use actix::prelude::*;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use errors::Result;
+use meta::ProviderCapabilities;
 
-/// Struct describing single service in .local domain's network
+/// Domain used by mDNS, queried when no other domain is given
+pub const DEFAULT_DOMAIN: &str = "local";
+
+/// Struct describing single service in the network
 ///
 /// Service Instance Name = <Instance> . <Service> . <Domain>
 #[derive(Debug, Clone)]
 pub struct ServiceDescription {
-    /// Instance name; eg. "_http._tcp"
+    /// Service type; eg. "_http._tcp"
     service: Cow<'static, str>,
-    /// Service type; eg. "local"
+    /// Domain; eg. "local"
     domain: Cow<'static, str>,
 }
 
@@ -35,6 +41,16 @@ impl ServiceDescription {
         }
     }
 
+    /// Replaces the domain the service is queried in (`local` by default)
+    pub fn with_domain<D: Into<Cow<'static, str>>>(mut self, domain: D) -> Self {
+        self.domain = domain.into();
+        self
+    }
+
+    pub fn domain(&self) -> &str {
+        self.domain.as_ref()
+    }
+
     pub(crate) fn to_string(&self) -> String {
         format!("{}.{}", self.service, self.domain)
     }
@@ -47,7 +63,7 @@ where
     fn from(s: T) -> Self {
         ServiceDescription {
             service: format!("_gu_{}._tcp", s).into(),
-            domain: "local".into(),
+            domain: DEFAULT_DOMAIN.into(),
         }
     }
 }
@@ -59,11 +75,33 @@ impl Message for ServiceDescription {
 #[derive(Debug, Clone)]
 pub struct ServicesDescription {
     services: Vec<ServiceDescription>,
+    /// Instances omitted from results, e.g. the ones announced by this host
+    excluded: Vec<ServiceInstance>,
+    /// `key=value` TXT entries results must have
+    required_txt: Vec<(String, String)>,
 }
 
 impl ServicesDescription {
     pub fn new(services: Vec<ServiceDescription>) -> Self {
-        ServicesDescription { services }
+        ServicesDescription {
+            services,
+            excluded: Vec::new(),
+            required_txt: Vec::new(),
+        }
+    }
+
+    /// Omits instances with the same name or address and port as given one;
+    /// allows a node to skip its own announcement
+    pub fn exclude(mut self, instance: ServiceInstance) -> Self {
+        self.excluded.push(instance);
+        self
+    }
+
+    /// Returns only instances with `key=value` TXT entry, e.g. `gpu=true`;
+    /// not applied to name-only queries, as names come without TXT records
+    pub fn require_txt<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.required_txt.push((key.into(), value.into()));
+        self
     }
 
     pub fn single<A, B>(instance: A, service: B) -> Self
@@ -83,14 +121,96 @@ impl Message for ServicesDescription {
     type Result = Result<HashSet<ServiceInstance>>;
 }
 
+/// Query returning only names of discovered instances (from PTR records),
+/// without resolving their addresses and ports
+#[derive(Debug, Clone)]
+pub struct InstanceNamesQuery(pub ServicesDescription);
+
+impl InstanceNamesQuery {
+    pub fn new(services: Vec<ServiceDescription>) -> Self {
+        InstanceNamesQuery(ServicesDescription::new(services))
+    }
+}
+
+impl Message for InstanceNamesQuery {
+    type Result = Result<HashSet<String>>;
+}
+
+/// Query returning discovered instances ordered by response time, fastest first
+#[derive(Debug, Clone)]
+pub struct RankedQuery(pub ServicesDescription);
+
+impl Message for RankedQuery {
+    type Result = Result<Vec<RankedInstance>>;
+}
+
+/// Instance with the time its first response arrived after the query was sent
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RankedInstance {
+    pub instance: ServiceInstance,
+    pub response_time: Duration,
+}
+
+/// IP stack on which an instance was discovered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    V4,
+    V6,
+}
+
+impl Transport {
+    pub fn of(addr: &SocketAddr) -> Self {
+        match addr {
+            SocketAddr::V4(_) => Transport::V4,
+            SocketAddr::V6(_) => Transport::V6,
+        }
+    }
+}
+
 /// Contains information about single service in a network
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+///
+/// Equality and hash cover the announced records only, so the same instance
+/// seen from another source or over another transport is not duplicated.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServiceInstance {
     pub name: String,
     pub host: String,
     pub txt: Vec<String>,
     pub addrs_v4: Vec<Ipv4Addr>,
     pub ports: Vec<u16>,
+    /// SRV priority; instances with lower value are preferred
+    pub priority: u16,
+    /// SRV weight; relative chance of selection among instances of the same priority
+    pub weight: u16,
+    /// Address the response packet came from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<SocketAddr>,
+    /// Transport (IPv4 or IPv6 multicast group) the response came on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transport: Option<Transport>,
+}
+
+impl PartialEq for ServiceInstance {
+    fn eq(&self, other: &ServiceInstance) -> bool {
+        self.name == other.name
+            && self.host == other.host
+            && self.addrs_v4 == other.addrs_v4
+            && self.ports == other.ports
+            && self.txt == other.txt
+    }
+}
+
+impl Eq for ServiceInstance {}
+
+impl Hash for ServiceInstance {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+        self.host.hash(state);
+        self.addrs_v4.hash(state);
+        self.ports.hash(state);
+        self.txt.hash(state);
+    }
 }
 
 impl ServiceInstance {
@@ -108,20 +228,58 @@ impl ServiceInstance {
             .next()
     }
 
+    /// Typed capabilities announced by a provider in TXT entries
+    pub fn capabilities(&self) -> Result<ProviderCapabilities> {
+        ProviderCapabilities::from_txt(&self.txt)
+    }
+
     pub(crate) fn service(&self) -> String {
-        let mut res = String::new();
-        self.name.split('.').skip(1).for_each(|x| {
-            res.push_str(x);
-            res.push('.')
-        });
-        res.pop();
-        res
+        service_of(&self.name)
+    }
+
+    /// Checks if instance has TXT entry `key=value`
+    pub fn has_txt(&self, key: &str, value: &str) -> bool {
+        self.txt.iter().any(|txt| {
+            txt.len() == key.len() + value.len() + 1
+                && txt.starts_with(key)
+                && txt[key.len()..].starts_with('=')
+                && txt.ends_with(value)
+        })
+    }
+
+    /// Checks if both describe the same instance: by name or by common address and port
+    pub fn same_as(&self, other: &ServiceInstance) -> bool {
+        self.name == other.name
+            || (self.addrs_v4.iter().any(|a| other.addrs_v4.contains(a))
+                && self.ports.iter().any(|p| other.ports.contains(p)))
     }
 }
 
+/// Service part of the instance name: <Service> . <Domain>
+pub(crate) fn service_of(name: &str) -> String {
+    let mut res = String::new();
+    name.split('.').skip(1).for_each(|x| {
+        res.push_str(x);
+        res.push('.')
+    });
+    res.pop();
+    res
+}
+
 #[derive(Debug, Serialize, Default)]
 pub struct Services {
     map: HashMap<String, HashSet<ServiceInstance>>,
+    names: HashSet<String>,
+    #[serde(skip)]
+    excluded: Vec<ServiceInstance>,
+    #[serde(skip)]
+    required_txt: Vec<(String, String)>,
+    /// When the query was sent
+    #[serde(skip)]
+    started: Option<Instant>,
+    /// Time of the first response, by instance name
+    #[serde(skip)]
+    response_times: HashMap<String, Duration>,
 }
 
 impl<'a> From<&'a ServicesDescription> for Services {
@@ -130,6 +288,9 @@ impl<'a> From<&'a ServicesDescription> for Services {
         for service in s.services() {
             res.add_service(service.to_string());
         }
+        res.excluded = s.excluded.clone();
+        res.required_txt = s.required_txt.clone();
+        res.started = Some(Instant::now());
 
         res
     }
@@ -141,9 +302,50 @@ impl Services {
     }
 
     pub(crate) fn add_instance(&mut self, instance: ServiceInstance) {
-        self.map
-            .get_mut::<str>(instance.service().as_ref())
-            .and_then(|map| Some(map.insert(instance)));
+        if self.excluded.iter().any(|e| e.same_as(&instance)) {
+            return;
+        }
+        if !self
+            .required_txt
+            .iter()
+            .all(|(key, value)| instance.has_txt(key, value))
+        {
+            return;
+        }
+        if let Some(map) = self.map.get_mut::<str>(instance.service().as_ref()) {
+            if let Some(started) = self.started {
+                self.response_times
+                    .entry(instance.name.clone())
+                    .or_insert_with(|| started.elapsed());
+            }
+            map.insert(instance);
+        }
+    }
+
+    pub(crate) fn add_name(&mut self, name: String) {
+        if self.excluded.iter().any(|e| e.name == name) {
+            return;
+        }
+        if self.map.contains_key(&service_of(&name)) {
+            self.names.insert(name);
+        }
+    }
+
+    /// Forgets instance withdrawn by an mDNS goodbye
+    pub(crate) fn remove_instance(&mut self, name: &str) {
+        if let Some(map) = self.map.get_mut(&service_of(name)) {
+            map.retain(|instance| instance.name != name);
+        }
+        self.names.remove(name);
+        self.response_times.remove(name);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.names.is_empty() && self.map.values().all(HashSet::is_empty)
+    }
+
+    pub(crate) fn collect_names(self) -> HashSet<String> {
+        self.names
     }
 
     pub(crate) fn collect(self) -> HashSet<ServiceInstance> {
@@ -153,4 +355,162 @@ impl Services {
         }
         set
     }
+
+    /// Instances sorted by response time; ties are ordered by name
+    pub(crate) fn collect_ranked(self) -> Vec<RankedInstance> {
+        let response_times = self.response_times.clone();
+        let mut ranked: Vec<RankedInstance> = self
+            .collect()
+            .into_iter()
+            .map(|instance| RankedInstance {
+                response_time: response_times
+                    .get(&instance.name)
+                    .cloned()
+                    .unwrap_or_default(),
+                instance,
+            })
+            .collect();
+        ranked.sort_by(|a, b| {
+            (a.response_time, &a.instance.name).cmp(&(b.response_time, &b.instance.name))
+        });
+        ranked
+    }
+}
+
+/// Change between two discovery runs
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServiceDiff {
+    pub added: Vec<ServiceInstance>,
+    pub removed: Vec<ServiceInstance>,
+}
+
+impl ServiceDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Instances present only in `new` are added, only in `old` removed;
+/// an instance that changed e.g. its addresses is both removed and added.
+/// Both lists are sorted by instance name.
+pub fn diff(old: &HashSet<ServiceInstance>, new: &HashSet<ServiceInstance>) -> ServiceDiff {
+    let sorted = |instances: &mut Vec<ServiceInstance>| {
+        instances.sort_by(|a, b| a.name.cmp(&b.name));
+    };
+    let mut added: Vec<_> = new.difference(old).cloned().collect();
+    let mut removed: Vec<_> = old.difference(new).cloned().collect();
+    sorted(&mut added);
+    sorted(&mut removed);
+
+    ServiceDiff { added, removed }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+    use std::collections::HashSet;
+
+    use super::{diff, ServiceInstance, Transport};
+
+    #[test]
+    fn instance_round_trips_through_json() {
+        let instance = ServiceInstance {
+            name: "provider._gu_provider._tcp.local".into(),
+            host: "provider.local".into(),
+            txt: vec!["node_id=0x01".into()],
+            addrs_v4: vec!["192.168.1.10".parse().unwrap()],
+            ports: vec![61621],
+            priority: 0,
+            weight: 10,
+            source: Some("192.168.1.10:5353".parse().unwrap()),
+            transport: Some(Transport::V4),
+        };
+        let json = serde_json::to_string(&instance).unwrap();
+        let decoded = serde_json::from_str::<ServiceInstance>(&json).unwrap();
+        assert_eq!(decoded, instance);
+        assert_eq!(decoded.source, instance.source);
+        assert_eq!(decoded.transport, instance.transport);
+
+        let anonymous = ServiceInstance {
+            source: None,
+            transport: None,
+            ..instance
+        };
+        let json = serde_json::to_string(&anonymous).unwrap();
+        assert!(!json.contains("transport"));
+        assert_eq!(
+            serde_json::from_str::<ServiceInstance>(&json).unwrap(),
+            anonymous
+        );
+    }
+
+    fn instance(name: &str, addr: &str) -> ServiceInstance {
+        ServiceInstance {
+            name: format!("{}._gu_provider._tcp.local", name),
+            host: format!("{}.local", name),
+            txt: Vec::new(),
+            addrs_v4: vec![addr.parse().unwrap()],
+            ports: vec![61621],
+            priority: 0,
+            weight: 0,
+            source: None,
+            transport: None,
+        }
+    }
+
+    #[test]
+    fn source_and_transport_do_not_distinguish_instances() {
+        let v4 = ServiceInstance {
+            source: Some("10.0.0.1:5353".parse().unwrap()),
+            transport: Some(Transport::V4),
+            ..instance("seen", "10.0.0.1")
+        };
+        let v6 = ServiceInstance {
+            source: Some("[fe80::1]:5353".parse().unwrap()),
+            transport: Some(Transport::V6),
+            ..instance("seen", "10.0.0.1")
+        };
+        assert_eq!(v4, v6);
+
+        let instances: HashSet<_> = vec![v4, v6].into_iter().collect();
+        assert_eq!(instances.len(), 1);
+
+        let other_port = ServiceInstance {
+            ports: vec![61622],
+            ..instance("seen", "10.0.0.1")
+        };
+        assert_ne!(other_port, instance("seen", "10.0.0.1"));
+    }
+
+    #[test]
+    fn diff_reports_added_and_removed() {
+        let before: HashSet<_> = vec![
+            instance("stays", "10.0.0.1"),
+            instance("leaves", "10.0.0.2"),
+            instance("moves", "10.0.0.3"),
+        ]
+        .into_iter()
+        .collect();
+        let after: HashSet<_> = vec![
+            instance("stays", "10.0.0.1"),
+            instance("moves", "10.0.0.4"),
+            instance("joins", "10.0.0.5"),
+        ]
+        .into_iter()
+        .collect();
+
+        let changes = diff(&before, &after);
+        assert_eq!(
+            changes.added,
+            vec![instance("joins", "10.0.0.5"), instance("moves", "10.0.0.4")]
+        );
+        assert_eq!(
+            changes.removed,
+            vec![
+                instance("leaves", "10.0.0.2"),
+                instance("moves", "10.0.0.3")
+            ]
+        );
+        assert!(diff(&after, &after).is_empty());
+    }
 }
@@ -1,8 +1,13 @@
-use std::{fmt, io};
+use std::{
+    collections::BTreeMap,
+    fmt, io,
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
 
 #[cfg(feature = "with-actix")]
 use actix::prelude::*;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 #[cfg(feature = "with-actix")]
 use gu_net::rpc::peer::PeerSessionInfo;
@@ -19,6 +24,15 @@ pub enum Error {
     NoSuchSession(String),
     NoSuchChild(String),
     UnknownEnv(String),
+    DownloadHttpStatus(u16),
+    /// upload not finished within given number of seconds
+    UploadTimeout(u64),
+    /// `ResourceFormat` not handled by the environment
+    UnsupportedFormat(String),
+    /// children of all sessions use up the node memory budget, given in KiB
+    MemoryBudgetExceeded(u64),
+    /// session is being drained by `DrainSession`, so it takes no new processes
+    SessionDraining(String),
 }
 
 impl From<io::Error> for Error {
@@ -45,6 +59,17 @@ impl fmt::Display for Error {
             Error::NoSuchSession(msg) => write!(f, "session not found: {}", msg)?,
             Error::NoSuchChild(msg) => write!(f, "child not found: {}", msg)?,
             Error::UnknownEnv(env_id) => write!(f, "unknown exec environment: {}", env_id)?,
+            Error::DownloadHttpStatus(code) => {
+                write!(f, "download failed with HTTP status: {}", code)?
+            }
+            Error::UploadTimeout(secs) => write!(f, "upload timed out after {}s", secs)?,
+            Error::UnsupportedFormat(format) => {
+                write!(f, "unsupported resource format: {}", format)?
+            }
+            Error::MemoryBudgetExceeded(budget) => {
+                write!(f, "memory budget of {} KiB exceeded", budget)?
+            }
+            Error::SessionDraining(session_id) => write!(f, "session is draining: {}", session_id)?,
         }
         Ok(())
     }
@@ -60,8 +85,92 @@ impl From<String> for Error {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Image {
+    /// `file://` urls point to a local directory or archive (host direct only); `hash` is not
+    /// checked for them
     pub url: String,
-    pub hash: String,
+    pub hash: ImageHash,
+    /// file name used for the local image cache; defaults to one derived from `hash`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_name: Option<String>,
+}
+
+/// Hash of the image content.
+///
+/// Accepted either as an opaque `algo:value` string or as an explicit
+/// `{ "algo": "sha256", "value": "..." }` object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageHash {
+    /// algorithm that produced `value`; when missing, `value` is used as is
+    pub algo: Option<String>,
+    pub value: String,
+}
+
+impl ImageHash {
+    pub fn new(algo: impl Into<String>, value: impl Into<String>) -> Self {
+        ImageHash {
+            algo: Some(algo.into()),
+            value: value.into(),
+        }
+    }
+
+    /// Hash in the `algo:value` form used for verification and cache file names.
+    pub fn to_hash_str(&self) -> String {
+        match self.algo {
+            Some(ref algo) => format!("{}:{}", algo, self.value),
+            None => self.value.clone(),
+        }
+    }
+}
+
+impl fmt::Display for ImageHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.to_hash_str())
+    }
+}
+
+impl From<String> for ImageHash {
+    fn from(value: String) -> Self {
+        ImageHash { algo: None, value }
+    }
+}
+
+impl<'a> From<&'a str> for ImageHash {
+    fn from(value: &'a str) -> Self {
+        value.to_string().into()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum ImageHashRepr {
+    Plain(String),
+    Explicit {
+        #[serde(default)]
+        algo: Option<String>,
+        value: String,
+    },
+}
+
+impl Serialize for ImageHash {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.algo {
+            Some(ref algo) => ImageHashRepr::Explicit {
+                algo: Some(algo.clone()),
+                value: self.value.clone(),
+            }
+            .serialize(serializer),
+            None => serializer.serialize_str(&self.value),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ImageHash {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match ImageHashRepr::deserialize(deserializer)? {
+            ImageHashRepr::Plain(value) => value.into(),
+            ImageHashRepr::Explicit { algo, value } => ImageHash { algo, value },
+        })
+    }
 }
 
 /// Message for session creation: local provisioning: downloads and unpacks the binaries
@@ -73,6 +182,9 @@ pub struct CreateSession<Options = ()> {
     pub name: String,
     pub tags: Vec<String>,
     pub note: Option<String>,
+    /// cores the session processes are pinned to; empty means no pinning
+    #[serde(default)]
+    pub cpu_affinity: Vec<usize>,
     #[serde(default)]
     pub options: Options,
 }
@@ -95,6 +207,25 @@ impl<Options> Message for CreateSession<Options> {
 pub struct SessionUpdate {
     pub session_id: String,
     pub commands: Vec<Command>,
+    /// what happens with remaining commands when one fails
+    #[serde(default)]
+    pub on_error: OnError,
+}
+
+/// Handling of a failed command in `SessionUpdate`
+#[derive(Clone, Copy, Serialize, Deserialize, Hash, Eq, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum OnError {
+    /// remaining commands are skipped
+    StopChain,
+    /// remaining commands are run; the update fails if any of the commands failed
+    Continue,
+}
+
+impl Default for OnError {
+    fn default() -> Self {
+        OnError::StopChain
+    }
 }
 
 #[cfg(feature = "with-actix")]
@@ -102,11 +233,81 @@ impl PublicMessage for SessionUpdate {
     const ID: u32 = 38;
 }
 
+/// `SessionUpdate` reporting the wall-clock duration of every command besides its output
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TimedSessionUpdate(pub SessionUpdate);
+
+/// Outputs of `SessionUpdate` commands, as in its plain result,
+/// with the duration of each at the same index of `durations`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TimedOutputs {
+    pub outputs: Vec<String>,
+    pub durations: Vec<Duration>,
+}
+
+impl TimedOutputs {
+    pub fn push<S: Into<String>>(&mut self, output: S, duration: Duration) {
+        self.outputs.push(output.into());
+        self.durations.push(duration);
+    }
+}
+
+#[cfg(feature = "with-actix")]
+impl PublicMessage for TimedSessionUpdate {
+    const ID: u32 = 52;
+}
+
+/// as `SessionUpdate`, with outputs timed
+#[cfg(feature = "with-actix")]
+impl Message for TimedSessionUpdate {
+    type Result = Result<TimedOutputs, TimedOutputs>;
+}
+
+/// Applies the same commands to many sessions in one round-trip
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BulkSessionUpdate {
+    pub session_ids: Vec<String>,
+    pub commands: Vec<Command>,
+    /// what happens with remaining commands of a session when one fails;
+    /// other sessions are updated regardless
+    #[serde(default)]
+    pub on_error: OnError,
+}
+
+#[cfg(feature = "with-actix")]
+impl PublicMessage for BulkSessionUpdate {
+    const ID: u32 = 42;
+}
+
+/// outcome of `SessionUpdate` for each session id
+#[cfg(feature = "with-actix")]
+impl Message for BulkSessionUpdate {
+    type Result = Result<BTreeMap<String, Result<Vec<String>, Vec<String>>>, ()>;
+}
+
+/// Format of a file transferred by `DownloadFile` and `UploadFile`
 #[derive(Copy, Clone, Serialize, Deserialize, Debug, Eq, Ord, PartialOrd, PartialEq, Hash)]
 #[serde(rename_all = "camelCase")]
 pub enum ResourceFormat {
+    /// single file, stored as is
     Raw,
+    /// directory, extracted on download and packed on upload
     Tar,
+    /// as `Tar`, gzip compressed
+    TarGz,
+}
+
+impl ResourceFormat {
+    /// `Content-Type` of the resource when transferred over HTTP
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ResourceFormat::Raw => "application/octet-stream",
+            ResourceFormat::Tar => "application/x-tar",
+            ResourceFormat::TarGz => "application/gzip",
+        }
+    }
 }
 
 impl Default for ResourceFormat {
@@ -115,8 +316,59 @@ impl Default for ResourceFormat {
     }
 }
 
+/// Archive format of an exported session workspace
+#[derive(Copy, Clone, Serialize, Deserialize, Debug, Eq, PartialEq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum ArchiveFormat {
+    Tar,
+    TarGz,
+}
+
+impl ArchiveFormat {
+    /// `Content-Type` of the archive when transferred over HTTP
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ArchiveFormat::Tar => "application/x-tar",
+            ArchiveFormat::TarGz => "application/gzip",
+        }
+    }
+}
+
+impl Default for ArchiveFormat {
+    fn default() -> Self {
+        ArchiveFormat::TarGz
+    }
+}
+
+/// Encoding of command output returned as the command result
+#[derive(Clone, Copy, Serialize, Deserialize, Hash, Eq, PartialEq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum OutputEncoding {
+    /// invalid UTF-8 sequences are replaced with `U+FFFD`
+    Utf8Lossy,
+    /// exact bytes, base64 encoded
+    Base64,
+}
+
+impl Default for OutputEncoding {
+    fn default() -> Self {
+        OutputEncoding::Utf8Lossy
+    }
+}
+
+/// Condition signalling that a started process is ready
 #[derive(Clone, Serialize, Deserialize, Hash, Eq, PartialEq, Debug)]
 #[serde(rename_all = "camelCase")]
+pub enum ReadySignal {
+    /// path, relative to the session directory, created by the process when ready
+    File(String),
+    /// regex matched against lines the process writes to stdout
+    StdoutLine(String),
+}
+
+// `remote` derives inherent (de)serialize fns, wrapped by the impls below
+#[derive(Clone, Serialize, Deserialize, Hash, Eq, PartialEq, Debug)]
+#[serde(remote = "Command", rename_all = "camelCase")]
 pub enum Command {
     Exec {
         // return cmd output
@@ -125,20 +377,49 @@ pub enum Command {
         #[serde(default)]
         #[serde(skip_serializing_if = "Option::is_none")]
         working_dir: Option<String>,
+        /// marks session as clean (ready) if the command succeeds
+        #[serde(default, rename = "markClean")]
+        mark_clean: bool,
+        /// values of `${NAME}` placeholders in `args`,
+        /// besides built-in `SESSION_DIR` and `CACHE_DIR`
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        env: BTreeMap<String, String>,
+        /// fails on placeholders without value instead of leaving them as is
+        #[serde(default, rename = "strictEnv")]
+        strict_env: bool,
+        /// how stdout of the command is returned
+        #[serde(default, rename = "outputEncoding")]
+        output_encoding: OutputEncoding,
     },
     Open,
     Close,
+    /// returns child process id; with `ready_signal`, only after the signal
+    /// appears, failing if it does not within `ready_timeout`
+    #[serde(rename_all = "camelCase")]
     Start {
-        // return child process id
         executable: String,
         args: Vec<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ready_signal: Option<ReadySignal>,
+        /// in seconds; provider default if not given
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ready_timeout: Option<u64>,
     },
 
     #[serde(rename_all = "camelCase")]
     Stop {
         child_id: String,
     },
-    Wait,
+    /// waits for `child_id` to exit, returning its exit code;
+    /// without `child_id`, waits for the whole session (e.g. docker container)
+    #[serde(rename_all = "camelCase")]
+    Wait {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        child_id: Option<String>,
+        /// in seconds; no limit if not given
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timeout: Option<u64>,
+    },
     AddTags(Vec<String>),
     DelTags(Vec<String>),
     #[serde(rename_all = "camelCase")]
@@ -160,15 +441,166 @@ pub enum Command {
         content: String,
         file_path: String,
     },
+    /// writes file readable by the session owner only; contents are never logged
+    #[serde(rename_all = "camelCase")]
+    WriteSecret {
+        contents: Secret,
+        file_path: String,
+    },
+    /// uploads whole session directory as a single archive
+    ExportWorkspace {
+        uri: String,
+        #[serde(default)]
+        format: ArchiveFormat,
+    },
+    /// forces status of a session with no running processes, e.g. after a task crashed
+    ResetStatus(IdleStatus),
+}
+
+/// `wait` used to be a unit variant; its plain form waits for the whole session
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum CommandRepr {
+    LegacyWait(LegacyWait),
+    Current(#[serde(deserialize_with = "Command::deserialize")] Command),
+}
+
+#[derive(Deserialize)]
+enum LegacyWait {
+    #[serde(rename = "wait")]
+    Wait,
+}
+
+impl Serialize for Command {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Command::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Command {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match CommandRepr::deserialize(deserializer)? {
+            CommandRepr::LegacyWait(LegacyWait::Wait) => Command::Wait {
+                child_id: None,
+                timeout: None,
+            },
+            CommandRepr::Current(command) => command,
+        })
+    }
 }
 
+/// Sensitive string, redacted in `Debug` output
+#[derive(Clone, Serialize, Deserialize, Hash, Eq, PartialEq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Secret(value.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Secret(***)")
+    }
+}
+
+/// Status of a session with no running processes
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum IdleStatus {
+    /// clean, as just after creation
+    Created,
+    /// some commands were already executed
+    Configured,
+}
+
+/// ok: output of every command;
+/// err: outputs of commands run, with error message in place of each failed one
 #[cfg(feature = "with-actix")]
 impl Message for SessionUpdate {
     type Result = Result<Vec<String>, Vec<String>>;
 }
 
-#[derive(Serialize, Deserialize, Default, Debug)]
-pub struct GetSessions {}
+/// Glob pattern for session tags; supports `*` (any sequence) and `?` (any character).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TagPattern(String);
+
+impl TagPattern {
+    pub fn matches(&self, tag: &str) -> bool {
+        fn glob(pattern: &[char], tag: &[char]) -> bool {
+            match pattern.split_first() {
+                None => tag.is_empty(),
+                Some(('*', rest)) => (0..=tag.len()).any(|skip| glob(rest, &tag[skip..])),
+                Some((&p, rest)) => match tag.split_first() {
+                    Some((&t, tag_rest)) if p == '?' || p == t => glob(rest, tag_rest),
+                    _ => false,
+                },
+            }
+        }
+
+        let pattern: Vec<char> = self.0.chars().collect();
+        let tag: Vec<char> = tag.chars().collect();
+        glob(&pattern, &tag)
+    }
+}
+
+impl FromStr for TagPattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err("empty tag pattern".into());
+        }
+        match s.chars().find(|ch| "[]{}\\".contains(*ch)) {
+            Some(ch) => Err(format!(
+                "unsupported character {:?} in tag pattern {}",
+                ch, s
+            )),
+            None => Ok(TagPattern(s.into())),
+        }
+    }
+}
+
+impl fmt::Display for TagPattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Serialize for TagPattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for TagPattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct GetSessions {
+    /// only sessions with at least one tag matching the pattern are returned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<TagPattern>,
+}
+
+impl GetSessions {
+    pub fn matches<'a>(&self, tags: impl IntoIterator<Item = &'a String>) -> bool {
+        match self.tag {
+            Some(ref pattern) => tags.into_iter().any(|tag| pattern.matches(tag)),
+            None => true,
+        }
+    }
+}
 
 #[cfg(feature = "with-actix")]
 impl PublicMessage for GetSessions {
@@ -180,6 +612,23 @@ impl Message for GetSessions {
     type Result = Result<Vec<PeerSessionInfo>, ()>;
 }
 
+/// Info of a single session, without listing all of them
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GetSession {
+    pub session_id: String,
+}
+
+#[cfg(feature = "with-actix")]
+impl PublicMessage for GetSession {
+    const ID: u32 = 41;
+}
+
+#[cfg(feature = "with-actix")]
+impl Message for GetSession {
+    type Result = Result<PeerSessionInfo, Error>;
+}
+
 /// Message for session destruction: clean local resources and kill all child processes
 #[derive(Serialize, Deserialize)]
 pub struct DestroySession {
@@ -193,15 +642,247 @@ impl PublicMessage for DestroySession {
 
 #[cfg(feature = "with-actix")]
 impl Message for DestroySession {
+    type Result = Result<SessionClosed, Error>;
+}
+
+/// Result of `DestroySession`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionClosed {
+    pub message: String,
+    /// Number of session processes terminated on destruction
+    pub processes_killed: usize,
+}
+
+/// Asks session processes to finish before the session is destroyed: the session stops
+/// accepting `Start` and `Exec`, its processes are sent SIGTERM, then awaited for up to
+/// `timeout` seconds. Processes still running afterwards are not
+/// killed; `DestroySession` does it. Returns the number of such processes.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DrainSession {
+    pub session_id: String,
+    pub timeout: u64,
+}
+
+#[cfg(feature = "with-actix")]
+impl PublicMessage for DrainSession {
+    const ID: u32 = 43;
+}
+
+#[cfg(feature = "with-actix")]
+impl Message for DrainSession {
+    type Result = Result<usize, Error>;
+}
+
+/// Reference counts of cached images used by live sessions of the environment,
+/// by image file name
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GetImageRefs {
+    pub env_type: String,
+}
+
+#[cfg(feature = "with-actix")]
+impl PublicMessage for GetImageRefs {
+    const ID: u32 = 45;
+}
+
+#[cfg(feature = "with-actix")]
+impl Message for GetImageRefs {
+    type Result = Result<BTreeMap<String, usize>, Error>;
+}
+
+/// Removes given images, by file name, from the image cache of the environment;
+/// images used by live sessions are kept. Returns names of evicted images.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EvictImages {
+    pub env_type: String,
+    pub images: Vec<String>,
+}
+
+#[cfg(feature = "with-actix")]
+impl PublicMessage for EvictImages {
+    const ID: u32 = 46;
+}
+
+#[cfg(feature = "with-actix")]
+impl Message for EvictImages {
+    type Result = Result<Vec<String>, Error>;
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ProcessStatus {
+    Running,
+    /// exit code, if the process was not terminated by a signal
+    Exited(Option<i32>),
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProcessInfo {
+    pub child_id: String,
+    pub pid: u32,
+    pub started_at: SystemTime,
+    pub status: ProcessStatus,
+}
+
+/// Returns child processes of the given session.
+///
+/// Processes which exited, but were not yet collected by the periodic scan,
+/// are reported with `Exited` status.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GetSessionProcesses {
+    pub session_id: String,
+}
+
+#[cfg(feature = "with-actix")]
+impl PublicMessage for GetSessionProcesses {
+    const ID: u32 = 47;
+}
+
+#[cfg(feature = "with-actix")]
+impl Message for GetSessionProcesses {
+    type Result = Result<Vec<ProcessInfo>, Error>;
+}
+
+/// Disk usage of a session workspace.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionSize {
+    pub bytes: u64,
+    pub files: u64,
+}
+
+/// Returns the current disk usage of the session workspace.
+///
+/// The result may be reused by the environment for a while, so repeated queries
+/// do not walk the workspace every time.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GetSessionSize {
+    pub session_id: String,
+}
+
+#[cfg(feature = "with-actix")]
+impl PublicMessage for GetSessionSize {
+    const ID: u32 = 50;
+}
+
+#[cfg(feature = "with-actix")]
+impl Message for GetSessionSize {
+    type Result = Result<SessionSize, Error>;
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedImage {
+    pub file_name: String,
+    pub size: u64,
+    /// not available on some platforms and filesystems
+    pub last_access: Option<SystemTime>,
+    /// whether a live session was created from the image
+    pub in_use: bool,
+}
+
+/// Lists images in the download cache of the environment.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GetCachedImages {
+    pub env_type: String,
+}
+
+#[cfg(feature = "with-actix")]
+impl PublicMessage for GetCachedImages {
+    const ID: u32 = 49;
+}
+
+#[cfg(feature = "with-actix")]
+impl Message for GetCachedImages {
+    type Result = Result<Vec<CachedImage>, Error>;
+}
+
+/// Downloads and verifies the image into the cache of the environment without
+/// creating a session, so that the first session created from it does not wait
+/// for the download. A no-op if the image is cached already; resolves to the file
+/// name of the cached image, as used by `GetCachedImages` and `EvictImages`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PrefetchImage {
+    pub env_type: String,
+    pub image: Image,
+}
+
+#[cfg(feature = "with-actix")]
+impl PublicMessage for PrefetchImage {
+    const ID: u32 = 51;
+}
+
+#[cfg(feature = "with-actix")]
+impl Message for PrefetchImage {
     type Result = Result<String, Error>;
 }
 
+/// Resources of the node, as reported by gu-hardware
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeCapacity {
+    pub total_memory: u64,
+    pub free_memory: u64,
+    pub num_cores: usize,
+}
+
+/// Returns capacity of the node, for capacity-aware placement of sessions.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GetNodeCapacity;
+
+#[cfg(feature = "with-actix")]
+impl PublicMessage for GetNodeCapacity {
+    const ID: u32 = 48;
+}
+
+#[cfg(feature = "with-actix")]
+impl Message for GetNodeCapacity {
+    type Result = Result<NodeCapacity, Error>;
+}
+
 #[cfg(test)]
 mod test {
     use serde_json;
 
     use super::*;
 
+    #[test]
+    fn test_get_sessions_tag_pattern() {
+        // given
+        let msg: GetSessions = serde_json::from_str(r#"{"tag": "build-*"}"#).unwrap();
+        let sessions = vec![
+            vec!["build-1".to_string()],
+            vec!["build-2".to_string(), "x".to_string()],
+            vec!["deploy-1".to_string()],
+        ];
+
+        // when
+        let matching: Vec<_> = sessions.iter().filter(|tags| msg.matches(*tags)).collect();
+
+        // then
+        assert_eq!(matching, vec![&sessions[0], &sessions[1]]);
+        assert!(GetSessions::default().matches(&sessions[2]));
+        assert!("build-?".parse::<TagPattern>().unwrap().matches("build-1"));
+        assert!(!"build-?".parse::<TagPattern>().unwrap().matches("build-10"));
+    }
+
+    #[test]
+    fn test_get_sessions_invalid_tag_pattern() {
+        assert!("build-[".parse::<TagPattern>().is_err());
+        assert!("".parse::<TagPattern>().is_err());
+        assert!(serde_json::from_str::<GetSessions>(r#"{"tag": "build-[0-9]"}"#).is_err());
+    }
+
     #[test]
     fn test_create_session_deserialization() {
         // given
@@ -222,11 +903,31 @@ mod test {
         // then
         assert_eq!(c.env_type, "hd");
         assert_eq!(c.image.url, "http://some.url/file.tgz");
-        assert_eq!(c.image.hash, "12345");
+        assert_eq!(c.image.hash, "12345".into());
         assert_eq!(c.tags.len(), 1);
         assert_eq!(c.tags[0], "lato");
     }
 
+    #[test]
+    fn test_image_hash_with_explicit_algo() {
+        let json = r#"{
+            "url": "http://some.url/file.tgz",
+            "hash": { "algo": "sha256", "value": "abcd" }
+        }"#;
+
+        let image: Image = serde_json::from_str(json).unwrap();
+        assert_eq!(image.hash, ImageHash::new("sha256", "abcd"));
+        assert_eq!(image.hash.to_hash_str(), "sha256:abcd");
+
+        let value = serde_json::to_value(&image.hash).unwrap();
+        assert_eq!(value["algo"], "sha256");
+        assert_eq!(value["value"], "abcd");
+        assert_eq!(
+            serde_json::to_value(&ImageHash::from("SHA1:abcd")).unwrap(),
+            "SHA1:abcd"
+        );
+    }
+
     #[test]
     fn test_session_update_single_comm_deserialization() {
         // given
@@ -289,4 +990,47 @@ mod test {
             panic!("DelTags command expected");
         }
     }
+
+    #[test]
+    fn test_legacy_wait_deserialization() {
+        let commands: Vec<Command> = serde_json::from_str(
+            r#"["wait", {"wait":{"childId":"145ccba6","timeout":5}}, "open"]"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            commands,
+            vec![
+                Command::Wait {
+                    child_id: None,
+                    timeout: None,
+                },
+                Command::Wait {
+                    child_id: Some("145ccba6".into()),
+                    timeout: Some(5),
+                },
+                Command::Open,
+            ]
+        );
+        assert_eq!(
+            serde_json::to_value(&commands[1]).unwrap(),
+            serde_json::json!({"wait": {"childId": "145ccba6", "timeout": 5}})
+        );
+    }
+
+    #[test]
+    fn test_session_closed_serialization() {
+        let closed = SessionClosed {
+            message: "Session closed, 2 processes killed".into(),
+            processes_killed: 2,
+        };
+
+        assert_eq!(
+            serde_json::to_value(&closed).unwrap(),
+            serde_json::json!({
+                "message": "Session closed, 2 processes killed",
+                "processesKilled": 2
+            })
+        );
+    }
 }
@@ -144,13 +144,17 @@ impl<'a> ParsedHash<'a> {
         digest(self.hash_name, self.hash_value.len() * 4)
     }
 
+    /// Algorithm names are matched case-insensitively
     pub fn checker(&self) -> Result<Box<dyn DynContentChecker>, Error> {
-        Ok(match (self.hash_name, self.hash_value.len() * 4) {
+        let hash_name = self.hash_name.to_ascii_uppercase();
+        Ok(match (hash_name.as_slice(), self.hash_value.len() * 4) {
+            (b"SHA256", 256) => Box::new(self.value_checker::<sha2::Sha256>()?),
             (b"SHA3", 224) => Box::new(self.value_checker::<sha3::Sha3_224>()?),
             (b"SHA3", 256) => Box::new(self.value_checker::<sha3::Sha3_256>()?),
             (b"SHA3", 384) => Box::new(self.value_checker::<sha3::Sha3_384>()?),
             (b"SHA3", 512) => Box::new(self.value_checker::<sha3::Sha3_512>()?),
             (b"SHA1", 160) => Box::new(self.value_checker::<sha1::Sha1>()?),
+            (b"BLAKE2B", 512) => Box::new(self.value_checker::<blake2::Blake2b>()?),
             _ => return Err(Error::InvalidHashFormat),
         })
     }
@@ -170,13 +174,17 @@ impl<'a> ParsedHash<'a> {
     }
 }
 
+/// Algorithm names are matched case-insensitively
 pub fn digest<R: AsRef<[u8]>>(hash_name: R, bits: usize) -> Result<Box<dyn DynDigest>, Error> {
-    Ok(match (hash_name.as_ref(), bits) {
+    let upper_name = hash_name.as_ref().to_ascii_uppercase();
+    Ok(match (upper_name.as_slice(), bits) {
+        (b"SHA256", 256) => Box::new(sha2::Sha256::default()),
         (b"SHA3", 224) => Box::new(sha3::Sha3_224::default()),
         (b"SHA3", 256) => Box::new(sha3::Sha3_256::default()),
         (b"SHA3", 384) => Box::new(sha3::Sha3_384::default()),
         (b"SHA3", 512) => Box::new(sha3::Sha3_512::default()),
         (b"SHA1", 160) => Box::new(sha1::Sha1::default()),
+        (b"BLAKE2B", 512) => Box::new(blake2::Blake2b::default()),
         _ => {
             return Err(Error::UnknownHashFunc(
                 String::from_utf8(hash_name.as_ref().to_vec()).unwrap_or_else(|_| "invalid".into()),
@@ -235,7 +243,11 @@ mod test {
             "SHA3:7ba62e92095980b4fd8a743d608d8a5b0b0224105ddab845845b7c622c60f248",
         );
         test_value(b"golem2", "SHA3:9fa5c15b117a49c638aa438e2b6e33601360732e8d1f776535d93e21f733dd501c9756fa2feb508d3daf180253ecc1ef");
-        test_value(b"golem1", "SHA3:e43d55ac264ee607918a78561e1f45779b192c747f5844d08a63697314ccf2445edb823cd6bbe14782a40a932176bcda9f35c097cbf49872095205ad102a7960")
+        test_value(b"golem1", "SHA3:e43d55ac264ee607918a78561e1f45779b192c747f5844d08a63697314ccf2445edb823cd6bbe14782a40a932176bcda9f35c097cbf49872095205ad102a7960");
+        test_value(
+            b"golem1",
+            "sha3:dd1a350cfe1d851f36a40d2b0f9f705a0bc076ab31dd81a662ebdf40",
+        );
     }
 
     #[test]
@@ -25,7 +25,7 @@ pub enum State {
     DESTROYING,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub enum PeerSessionStatus {
     /// during session creation
     PENDING,
@@ -48,6 +48,13 @@ pub struct PeerSessionInfo {
     pub tags: Vec<String>,
     pub note: Option<String>,
     pub processes: HashSet<String>,
+    /// `false` if the session directory was removed from disk, e.g. manually
+    #[serde(default = "workspace_exists_default")]
+    pub workspace_exists: bool,
+}
+
+fn workspace_exists_default() -> bool {
+    true
 }
 
 #[derive(Serialize, Deserialize)]
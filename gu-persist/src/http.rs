@@ -3,7 +3,7 @@ use actix::SystemService;
 use actix::{
     Actor, ActorResponse, ArbiterService, Context, Handler, Message, Supervised, WrapFuture,
 };
-use actix_web::client::Connection;
+use actix_web::client::{Connection, SendRequestError};
 use actix_web::{self, client::ClientRequest, http, Body, HttpMessage};
 use bytes::Bytes;
 #[cfg(all(unix, feature = "uds_server"))]
@@ -21,6 +21,8 @@ use serde_json;
 use std::marker::PhantomData;
 #[cfg(not(all(unix, feature = "uds_server")))]
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio_timer::Delay;
 
 mod error {
     use actix::MailboxError;
@@ -36,6 +38,10 @@ mod error {
             SendRequestError(e: actix_web::client::SendRequestError) {}
             ConfigError {}
             IOError(e: std::io::Error) {}
+            ServerUnreachable(url: String, service: &'static str) {
+                description("server unreachable")
+                display("cannot connect to {}; is {} running?", url, service)
+            }
         }
 
     }
@@ -83,6 +89,118 @@ pub trait ServerConfig:
     'static + Default + Serialize + DeserializeOwned + HasSectionId + Send + Sync
 {
     fn port(&self) -> u16;
+
+    /// Name of the server process, shown when it cannot be reached.
+    fn service_name() -> &'static str {
+        "the server"
+    }
+}
+
+/// Bounded retry of requests refused by the server, with exponential backoff.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// total number of connection attempts
+    pub attempts: u32,
+    /// delay before the first retry, doubled on every next one
+    pub initial_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 4,
+            initial_delay: Duration::from_millis(250),
+        }
+    }
+}
+
+impl RetryPolicy {
+    fn delay(&self, attempt: u32) -> Duration {
+        self.initial_delay * 2u32.pow(attempt)
+    }
+}
+
+/// Sends the request to `url`, retrying according to `policy` as long as
+/// the connection cannot be established.
+pub fn send_with_retry<T, M>(
+    url: String,
+    msg: M,
+    policy: RetryPolicy,
+    service: &'static str,
+) -> impl Future<Item = T, Error = ClientError>
+where
+    T: de::DeserializeOwned + 'static,
+    M: IntoRequest + Clone + 'static,
+{
+    // every attempt consumes its own clone; clones share the body
+    retry_unreachable(url.clone(), policy, service, move || {
+        match msg.clone().into_request(&url, None) {
+            Ok(client) => future::Either::A(send_request(client)),
+            Err(err) => future::Either::B(future::err(AttemptError::Failed(err.into()))),
+        }
+    })
+}
+
+/// Failure of a single request attempt; only `Unreachable` ones are retried
+enum AttemptError {
+    Unreachable(String),
+    Failed(ClientError),
+}
+
+fn send_request<T: de::DeserializeOwned + 'static>(
+    client: ClientRequest,
+) -> impl Future<Item = T, Error = AttemptError> {
+    client
+        .send()
+        .map_err(|e| match e {
+            SendRequestError::Connector(e) => AttemptError::Unreachable(e.to_string()),
+            e => AttemptError::Failed(error::ErrorKind::SendRequestError(e).into()),
+        })
+        .and_then(|r| {
+            r.json::<T>()
+                .map_err(|e| AttemptError::Failed(error::ErrorKind::Json(e).into()))
+        })
+}
+
+/// Runs `attempt` until it reaches `target`, at most `policy.attempts` times
+fn retry_unreachable<T, F, R>(
+    target: String,
+    policy: RetryPolicy,
+    service: &'static str,
+    mut attempt: F,
+) -> impl Future<Item = T, Error = ClientError>
+where
+    T: 'static,
+    F: FnMut() -> R,
+    R: Future<Item = T, Error = AttemptError> + 'static,
+{
+    future::loop_fn(0u32, move |attempt_no| {
+        let target = target.clone();
+        attempt().then(
+            move |r| -> Box<dyn Future<Item = future::Loop<T, u32>, Error = ClientError>> {
+                match r {
+                    Ok(v) => Box::new(future::ok(future::Loop::Break(v))),
+                    Err(AttemptError::Unreachable(e)) => {
+                        if attempt_no + 1 >= policy.attempts {
+                            debug!("connection to {} failed: {}", target, e);
+                            return Box::new(future::err(
+                                error::ErrorKind::ServerUnreachable(target, service).into(),
+                            ));
+                        }
+                        let delay = policy.delay(attempt_no);
+                        warn!(
+                            "cannot connect to {} ({}), retrying in {:?}",
+                            target, e, delay
+                        );
+                        Box::new(Delay::new(Instant::now() + delay).then(move |_| {
+                            Ok::<_, ClientError>(future::Loop::Continue(attempt_no + 1))
+                        }))
+                    }
+                    Err(AttemptError::Failed(e)) => Box::new(future::err(e)),
+                }
+            },
+        )
+    })
 }
 
 #[derive(Default)]
@@ -195,6 +313,12 @@ pub trait IntoRequest {
 
 struct ResourceGet<T>(String, PhantomData<T>);
 
+impl<T> Clone for ResourceGet<T> {
+    fn clone(&self) -> Self {
+        ResourceGet(self.0.clone(), PhantomData)
+    }
+}
+
 impl<T> ResourceGet<T> {
     fn new(path: String) -> Self {
         ResourceGet::<T>(path, PhantomData)
@@ -222,6 +346,12 @@ impl<T> IntoRequest for ResourceGet<T> {
 
 struct ResourceDelete<T>(String, PhantomData<T>);
 
+impl<T> Clone for ResourceDelete<T> {
+    fn clone(&self) -> Self {
+        ResourceDelete(self.0.clone(), PhantomData)
+    }
+}
+
 impl<T> ResourceDelete<T> {
     fn new(path: String) -> Self {
         ResourceDelete(path, PhantomData)
@@ -249,6 +379,12 @@ impl<T> IntoRequest for ResourceDelete<T> {
 
 struct ResourcePatch<T>(String, PhantomData<T>);
 
+impl<T> Clone for ResourcePatch<T> {
+    fn clone(&self) -> Self {
+        ResourcePatch(self.0.clone(), PhantomData)
+    }
+}
+
 impl<T> ResourcePatch<T> {
     fn new(path: String) -> Self {
         ResourcePatch(path, PhantomData)
@@ -276,6 +412,12 @@ impl<T> IntoRequest for ResourcePatch<T> {
 
 struct ResourcePost<T>(String, Bytes, PhantomData<T>);
 
+impl<T> Clone for ResourcePost<T> {
+    fn clone(&self) -> Self {
+        ResourcePost(self.0.clone(), self.1.clone(), PhantomData)
+    }
+}
+
 impl<T> ResourcePost<T> {
     fn new(path: String, body: Bytes) -> Self {
         ResourcePost(path, body, PhantomData)
@@ -305,6 +447,12 @@ impl<T> IntoRequest for ResourcePost<T> {
 
 struct ResourcePut<T>(String, Bytes, PhantomData<T>);
 
+impl<T> Clone for ResourcePut<T> {
+    fn clone(&self) -> Self {
+        ResourcePut(self.0.clone(), self.1.clone(), PhantomData)
+    }
+}
+
 impl<T> ResourcePut<T> {
     fn new(path: String, body: Bytes) -> Self {
         ResourcePut(path, body, PhantomData)
@@ -350,8 +498,8 @@ impl<T: de::DeserializeOwned + 'static> Message for ResourcePatch<T> {
     type Result = Result<T, ClientError>;
 }
 
-impl<C: ServerConfig, T: de::DeserializeOwned + 'static, M: IntoRequest + Message> Handler<M>
-    for ServerClient<C>
+impl<C: ServerConfig, T: de::DeserializeOwned + 'static, M: IntoRequest + Clone + Message>
+    Handler<M> for ServerClient<C>
 where
     M: Message<Result = Result<T, ClientError>> + 'static,
 {
@@ -360,39 +508,40 @@ where
     /* Using Unix domain sockets on macOS and Linux, TCP sockets on Windows. */
     #[cfg(all(unix, feature = "uds_server"))]
     fn handle(&mut self, msg: M, _ctx: &mut Self::Context) -> Self::Result {
-        let path = msg.path().to_string();
+        use tokio_uds::UnixStream;
+
+        /* ip and port are not important - using unix domain sockets */
+        let url = format!("http://127.0.0.1:{}{}", 61621, msg.path());
+        let uds_path = ConfigModule::new().runtime_dir().join("gu-provider.socket");
+        info!("Connecting to unix domain socket at {:?}", &uds_path);
         ActorResponse::r#async(
-            future::lazy(move || {
-                /* ip and port are not important - using unix domain sockets */
-                let url = format!("http://127.0.0.1:{}{}", 61621, &path);
-                use tokio_uds::UnixStream;
-                let uds_path = ConfigModule::new().runtime_dir().join("gu-provider.socket");
-                info!("Connecting to unix domain socket at {:?}", &uds_path);
-                UnixStream::connect(uds_path)
-                    .map_err(|e| {
-                        error!(
-                            "Cannot connect to unix domain socket. Is the server running? \
-                             Use --user to connect to local user domain socket."
-                        );
-                        error::ErrorKind::IOError(e).into()
-                    })
-                    .join(future::ok(url))
-            })
-            .and_then(move |(stream, url)| {
-                let connection = actix_web::client::Connection::from_stream(stream);
-                let client = match msg.into_request(&url, Some(connection)) {
-                    Ok(cli) => cli,
-                    Err(err) => return future::Either::B(future::err(err.into())),
-                };
-                future::Either::A(
-                    client
-                        .send()
-                        .map_err(|e| error::ErrorKind::SendRequestError(e).into())
-                        .and_then(|r| {
-                            r.json::<T>()
-                                .map_err(move |e| error::ErrorKind::Json(e).into())
-                        }),
-                )
+            retry_unreachable(
+                uds_path.display().to_string(),
+                RetryPolicy::default(),
+                C::service_name(),
+                move || {
+                    let (msg, url) = (msg.clone(), url.clone());
+                    UnixStream::connect(&uds_path)
+                        .map_err(|e| AttemptError::Unreachable(e.to_string()))
+                        .and_then(move |stream| {
+                            let connection = Connection::from_stream(stream);
+                            match msg.into_request(&url, Some(connection)) {
+                                Ok(client) => future::Either::A(send_request(client)),
+                                Err(err) => {
+                                    future::Either::B(future::err(AttemptError::Failed(err.into())))
+                                }
+                            }
+                        })
+                },
+            )
+            .map_err(|e| {
+                if let error::ErrorKind::ServerUnreachable(..) = e.kind() {
+                    error!(
+                        "Cannot connect to unix domain socket. Is the server running? \
+                         Use --user to connect to local user domain socket."
+                    );
+                }
+                e
             })
             .into_actor(self),
         )
@@ -411,21 +560,87 @@ where
                 })
                 .and_then(move |config: Arc<C>| {
                     let url = format!("http://127.0.0.1:{}{}", config.port(), &path);
-                    let client = match msg.into_request(&url, None) {
-                        Ok(cli) => cli,
-                        Err(err) => return future::Either::B(future::err(err.into())),
-                    };
-                    future::Either::A(
-                        client
-                            .send()
-                            .map_err(|e| error::ErrorKind::SendRequestError(e).into())
-                            .and_then(|r| {
-                                r.json::<T>()
-                                    .map_err(move |e| error::ErrorKind::Json(e).into())
-                            }),
-                    )
+                    send_with_retry(url, msg, RetryPolicy::default(), C::service_name())
                 })
                 .into_actor(self),
         )
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{send_with_retry, ResourceGet, RetryPolicy};
+    use actix::System;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_retries_until_server_accepts() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        // nothing listens on the port at first, so the first attempts are refused
+        let server = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            let listener = TcpListener::bind(("127.0.0.1", port)).unwrap();
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = stream.read(&mut buf).unwrap();
+            stream
+                .write_all(
+                    b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\
+                      Content-Length: 5\r\nConnection: close\r\n\r\n[1,2]",
+                )
+                .unwrap();
+        });
+
+        let policy = RetryPolicy {
+            attempts: 8,
+            initial_delay: Duration::from_millis(50),
+        };
+        let url = format!("http://127.0.0.1:{}/plug", port);
+        let result: Vec<u32> = System::new("test")
+            .block_on(send_with_retry(
+                url,
+                ResourceGet::<Vec<u32>>::new("/plug".into()),
+                policy,
+                "gu-hub",
+            ))
+            .unwrap();
+
+        assert_eq!(result, vec![1, 2]);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn test_unreachable_server_error() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let policy = RetryPolicy {
+            attempts: 2,
+            initial_delay: Duration::from_millis(10),
+        };
+        let url = format!("http://127.0.0.1:{}/plug", port);
+        let err = System::new("test")
+            .block_on(send_with_retry::<Vec<u32>, _>(
+                url.clone(),
+                ResourceGet::<Vec<u32>>::new("/plug".into()),
+                policy,
+                "gu-hub",
+            ))
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            format!("cannot connect to {}; is gu-hub running?", url)
+        );
+    }
+}
@@ -23,6 +23,7 @@ extern crate gu_actix;
 extern crate gu_base;
 extern crate tokio_fs;
 extern crate tokio_io;
+extern crate tokio_timer;
 extern crate tokio_uds;
 
 pub mod error {
@@ -4,7 +4,7 @@ use std::collections::HashMap;
 use futures::future::{self, Future, IntoFuture};
 use log::debug;
 
-use gu_model::envman::Error;
+use gu_model::envman::{Error, GetSessions, SessionClosed};
 use gu_net::rpc::peer::PeerSessionInfo;
 
 use crate::id::generate_new_id;
@@ -15,8 +15,17 @@ pub trait IntoDeployInfo {
 }
 
 pub trait Destroy {
-    fn destroy(&mut self) -> Box<dyn Future<Item = (), Error = Error>> {
-        Box::new(future::ok(()))
+    /// Tears down the deployment; resolves to the number of processes terminated
+    fn destroy(&mut self) -> Box<dyn Future<Item = usize, Error = Error>> {
+        Box::new(future::ok(0))
+    }
+}
+
+/// `DestroySession` response reporting how many processes were terminated
+pub fn session_closed(killed: usize) -> SessionClosed {
+    SessionClosed {
+        message: format!("Session closed, {} processes killed", killed),
+        processes_killed: killed,
     }
 }
 
@@ -61,7 +70,6 @@ impl<T: IntoDeployInfo + Destroy + GetStatus> DeployManager<T> {
         self.deploys.contains_key(key)
     }
 
-    #[allow(unused)]
     pub fn deploy(&self, deploy_id: &String) -> Result<&T, Error> {
         match self.deploys.get(deploy_id) {
             Some(deploy) => Ok(deploy),
@@ -81,7 +89,10 @@ impl<T: IntoDeployInfo + Destroy + GetStatus> DeployManager<T> {
         self.deploys.entry(deploy_id)
     }
 
-    pub fn destroy_deploy(&mut self, session_id: &String) -> impl Future<Item = (), Error = Error> {
+    pub fn destroy_deploy(
+        &mut self,
+        session_id: &String,
+    ) -> impl Future<Item = usize, Error = Error> {
         self.deploys
             .remove(session_id)
             .ok_or(Error::NoSuchSession(session_id.clone()))
@@ -96,6 +107,28 @@ impl<T: IntoDeployInfo + Destroy + GetStatus> DeployManager<T> {
             .collect()
     }
 
+    pub fn deploy_info(&self, deploy_id: &String) -> Result<PeerSessionInfo, Error> {
+        self.deploy(deploy_id)
+            .map(|deploy| deploy.convert(deploy_id))
+    }
+
+    /// Info of deploys matching the tag filter of given `GetSessions` message
+    pub fn filtered_deploys_info(&self, msg: &GetSessions) -> Vec<PeerSessionInfo> {
+        self.deploys_info()
+            .into_iter()
+            .filter(|info| msg.matches(&info.tags))
+            .collect()
+    }
+
+    /// Removes all deploys, leaving them to the caller to destroy
+    pub fn drain(&mut self) -> Vec<(String, T)> {
+        self.deploys.drain().collect()
+    }
+
+    pub fn values<'a>(&'a self) -> impl Iterator<Item = &T> + 'a {
+        self.deploys.values()
+    }
+
     pub fn values_mut<'a>(&'a mut self) -> impl Iterator<Item = &mut T> + 'a {
         self.deploys.values_mut().into_iter()
     }
@@ -110,6 +143,22 @@ impl<T: IntoDeployInfo + Destroy + GetStatus> DeployManager<T> {
             false => status::EnvStatus::Working,
         }
     }
+
+    pub fn session_counts(&self) -> status::SessionCounts {
+        let mut counts = status::SessionCounts::default();
+        for (id, deploy) in self.deploys.iter() {
+            counts.add(&deploy.convert(id).status);
+        }
+        counts
+    }
+
+    /// Overall status with the session breakdown
+    pub fn env_status(&self) -> status::EnvStatusInfo {
+        status::EnvStatusInfo {
+            status: self.status(),
+            sessions: self.session_counts(),
+        }
+    }
 }
 
 impl<T: IntoDeployInfo + Destroy> Drop for DeployManager<T> {
@@ -27,7 +27,7 @@ use gu_persist::config::ConfigModule;
 use crate::provision;
 use crate::workspace::{Workspace, WorkspacesManager};
 
-use super::deployment::{DeployManager, Destroy, IntoDeployInfo};
+use super::deployment::{session_closed, DeployManager, Destroy, IntoDeployInfo};
 use super::envman;
 
 // Actor.
@@ -133,6 +133,7 @@ impl DockerSession {
         &mut self,
         content: bytes::Bytes,
         file_path: String,
+        mode: u32,
     ) -> impl Future<Item = String, Error = String> {
         let mut outf = Vec::new();
 
@@ -157,7 +158,7 @@ impl DockerSession {
             let mut header = tar::Header::new_ustar();
             header.set_size(content.len() as u64);
             header.set_path(&rel_path)?;
-            header.set_mode(0o644);
+            header.set_mode(mode);
             header.set_uid(0);
             header.set_gid(0);
             header.set_cksum();
@@ -224,6 +225,11 @@ impl DockerSession {
                 )
             }
             ResourceFormat::Tar => Box::new(provision::download_stream(url.as_str())),
+            ResourceFormat::TarGz => {
+                return future::Either::A(future::err(
+                    Error::UnsupportedFormat(format!("{:?}", format)).to_string(),
+                ));
+            }
         };
 
         let untar_path = match untar_path.to_str() {
@@ -289,6 +295,9 @@ impl DockerSession {
                             .and_then(|r| r.send().map_err(|e| e.to_string())),
                     )
                 }
+                ResourceFormat::TarGz => Box::new(future::err(
+                    Error::UnsupportedFormat(format!("{:?}", format)).to_string(),
+                )),
                 ResourceFormat::Tar => Box::new(
                     client::put(&url)
                         .streaming(data.map_err(|e| actix_web::error::ErrorInternalServerError(e)))
@@ -317,12 +326,13 @@ impl IntoDeployInfo for DockerSession {
             tags: self.workspace.tags(),
             note: None,
             processes: HashSet::new(),
+            workspace_exists: true,
         }
     }
 }
 
 impl Destroy for DockerSession {
-    fn destroy(&mut self) -> Box<dyn Future<Item = (), Error = Error>> {
+    fn destroy(&mut self) -> Box<dyn Future<Item = usize, Error = Error>> {
         let workspace = self.workspace.clone();
         let container_copy = self.container.clone();
         Box::new(
@@ -356,6 +366,7 @@ impl Destroy for DockerSession {
                 .and_then(move |_| {
                     workspace
                         .clear_dir()
+                        .map(|_| 0)
                         .map_err(|e| Error::IoError(e.to_string()))
                 }),
         )
@@ -414,8 +425,19 @@ impl Actor for DockerMan {
     fn started(&mut self, ctx: &mut <Self as Actor>::Context) {
         match new_docker(None) {
             Ok(docker_api) => {
+                // containers outlive the provider, so sessions of them are restored
+                for (session_id, workspace) in self.workspaces_man.saved_workspaces() {
+                    info!("restoring session {}", session_id);
+                    let session = DockerSession {
+                        workspace,
+                        container: docker_api.container(Cow::from(session_id.clone())),
+                        status: PeerSessionStatus::CREATED,
+                    };
+                    self.deploys.insert_deploy(session_id, session);
+                }
                 self.docker_api = Some(docker_api);
-                envman::register("docker", ctx.address())
+                envman::register("docker", ctx.address());
+                envman::register_shutdown("docker", ctx.address().recipient())
             }
             Err(e) => {
                 error!("docker start failed: {}", e);
@@ -445,9 +467,9 @@ impl Handler<CreateSession<CreateOptions>> for DockerMan {
 
                 let (binds, workspace) = self.binds_and_workspace(&msg);
 
-                workspace
-                    .create_dirs()
-                    .expect("Creating session dirs failed");
+                if let Err(e) = workspace.create_dirs().and_then(|_| workspace.save()) {
+                    return ActorResponse::reply(Err(e.into()));
+                }
                 let host_config = async_docker::models::HostConfig::new()
                     .with_binds(binds)
                     .with_cap_add(msg.options.cap_add.clone());
@@ -471,6 +493,12 @@ impl Handler<CreateSession<CreateOptions>> for DockerMan {
 
                 ActorResponse::r#async(fut::wrap_future(pull_and_create).and_then(
                     move |id, act: &mut DockerMan, _| {
+                        let mut workspace = workspace;
+                        // container id is the session id
+                        workspace.set_session_id(id.clone());
+                        if let Err(e) = workspace.save() {
+                            warn!("cannot save session {}: {}", id, e);
+                        }
                         if let Some(ref api) = act.docker_api {
                             let mut deploy = DockerSession {
                                 workspace,
@@ -533,17 +561,16 @@ fn run_command(
             executable,
             args,
             working_dir,
+            ..
         } => docker_man.run_for_deployment(session_id, |deployment| {
             deployment.do_exec(executable, args, working_dir)
         }),
         // TODO: FIXME @destruktiv: same as Exec but async
-        Command::Start {
-            executable: _,
-            args: _,
-        } => docker_man.run_for_deployment(session_id, DockerSession::do_start),
+        Command::Start { .. } => docker_man.run_for_deployment(session_id, DockerSession::do_start),
         // TODO: FIXME @destruktiv: same as Exec but async
         Command::Stop { child_id: _ } => Box::new(fut::ok("Stop mock".to_string())),
-        Command::Wait => docker_man.run_for_deployment(session_id, DockerSession::do_wait),
+        Command::ResetStatus(_) => Box::new(fut::err("status reset not supported".to_string())),
+        Command::Wait { .. } => docker_man.run_for_deployment(session_id, DockerSession::do_wait),
         Command::DownloadFile {
             uri,
             file_path,
@@ -558,34 +585,46 @@ fn run_command(
         } => docker_man.run_for_deployment(session_id, |deployment| {
             deployment.do_upload(uri, file_path, format)
         }),
-        Command::WriteFile { content, file_path } => {
-            docker_man.run_for_deployment(session_id, |d| d.write_file(content.into(), file_path))
-        }
+        Command::WriteFile { content, file_path } => docker_man
+            .run_for_deployment(session_id, |d| {
+                d.write_file(content.into(), file_path, 0o644)
+            }),
+        Command::WriteSecret {
+            contents,
+            file_path,
+        } => docker_man.run_for_deployment(session_id, |d| {
+            d.write_file(contents.expose().to_owned().into(), file_path, 0o600)
+        }),
+        Command::ExportWorkspace { .. } => Box::new(fut::err(
+            "workspace export is not supported by docker env".to_string(),
+        )),
         Command::AddTags(tags) => Box::new(fut::result(
             docker_man
                 .deploys
                 .deploy_mut(&session_id)
-                .map(|session| {
+                .map_err(|e| e.to_string())
+                .and_then(|session| {
                     session.workspace.add_tags(tags);
-                    format!(
+                    session.workspace.save().map_err(|e| e.to_string())?;
+                    Ok(format!(
                         "tags inserted. Current tags are: {:?}",
                         &session.workspace.tags()
-                    )
-                })
-                .map_err(|e| e.to_string()),
+                    ))
+                }),
         )),
         Command::DelTags(tags) => Box::new(fut::result(
             docker_man
                 .deploys
                 .deploy_mut(&session_id)
-                .map(|session| {
+                .map_err(|e| e.to_string())
+                .and_then(|session| {
                     session.workspace.remove_tags(tags);
-                    format!(
+                    session.workspace.save().map_err(|e| e.to_string())?;
+                    Ok(format!(
                         "tags removed. Current tags are: {:?}",
                         &session.workspace.tags()
-                    )
-                })
-                .map_err(|e| e.to_string()),
+                    ))
+                }),
         )),
     }
 }
@@ -594,23 +633,29 @@ fn run_commands(
     hd_man: &mut DockerMan,
     session_id: String,
     commands: Vec<Command>,
+    on_error: OnError,
 ) -> impl ActorFuture<Actor = DockerMan, Item = Vec<String>, Error = Vec<String>> {
-    let f: Box<dyn ActorFuture<Actor = DockerMan, Item = Vec<String>, Error = Vec<String>>> =
-        Box::new(future::ok(Vec::new()).into_actor(hd_man));
+    type Chain = Box<dyn ActorFuture<Actor = DockerMan, Item = Vec<String>, Error = Vec<String>>>;
+    let f: Chain = Box::new(future::ok(Vec::new()).into_actor(hd_man));
 
     commands.into_iter().fold(f, |acc, command| {
         let session_id = session_id.clone();
-        Box::new(acc.and_then(|mut vec, act, _ctx| {
-            run_command(act, session_id, command).then(move |i, _, _| match i {
-                Ok(a) => {
-                    vec.push(a);
-                    fut::ok(vec)
-                }
-                Err(a) => {
-                    vec.push(a);
-                    fut::err(vec)
+        Box::new(acc.then(move |r, act, _ctx| -> Chain {
+            let (mut vec, failed) = match r {
+                Ok(vec) => (vec, false),
+                Err(vec) => (vec, true),
+            };
+            if failed && on_error == OnError::StopChain {
+                return Box::new(fut::err(vec));
+            }
+            Box::new(run_command(act, session_id, command).then(move |i, _, _| {
+                let succeeded = i.is_ok();
+                vec.push(i.unwrap_or_else(|e| e));
+                match succeeded && !failed {
+                    true => fut::ok(vec),
+                    false => fut::err(vec),
                 }
-            })
+            }))
         }))
     })
 }
@@ -626,7 +671,7 @@ impl Handler<SessionUpdate> for DockerMan {
         }
         let session_id = msg.session_id.clone();
 
-        ActorResponse::r#async(run_commands(self, session_id, msg.commands))
+        ActorResponse::r#async(run_commands(self, session_id, msg.commands, msg.on_error))
     }
 }
 
@@ -635,15 +680,27 @@ impl Handler<GetSessions> for DockerMan {
 
     fn handle(
         &mut self,
-        _msg: GetSessions,
+        msg: GetSessions,
         _ctx: &mut Self::Context,
     ) -> <Self as Handler<GetSessions>>::Result {
-        ActorResponse::reply(Ok(self.deploys.deploys_info()))
+        ActorResponse::reply(Ok(self.deploys.filtered_deploys_info(&msg)))
+    }
+}
+
+impl Handler<GetSession> for DockerMan {
+    type Result = ActorResponse<DockerMan, PeerSessionInfo, Error>;
+
+    fn handle(
+        &mut self,
+        msg: GetSession,
+        _ctx: &mut Self::Context,
+    ) -> <Self as Handler<GetSession>>::Result {
+        ActorResponse::reply(self.deploys.deploy_info(&msg.session_id))
     }
 }
 
 impl Handler<DestroySession> for DockerMan {
-    type Result = ActorResponse<DockerMan, String, Error>;
+    type Result = ActorResponse<DockerMan, SessionClosed, Error>;
 
     fn handle(
         &mut self,
@@ -658,12 +715,50 @@ impl Handler<DestroySession> for DockerMan {
         ActorResponse::r#async(
             self.deploys
                 .destroy_deploy(&msg.session_id)
-                .and_then(|_| Ok("done".into()))
+                .and_then(|killed| Ok(session_closed(killed)))
+                .into_actor(self),
+        )
+    }
+}
+
+/// Stops and deletes containers of all sessions
+impl Handler<envman::Shutdown> for DockerMan {
+    type Result = ActorResponse<DockerMan, usize, ()>;
+
+    fn handle(&mut self, _msg: envman::Shutdown, _ctx: &mut Self::Context) -> Self::Result {
+        let sessions = self.deploys.drain();
+        let destroyed = sessions.len();
+        let destroys: Vec<_> = sessions
+            .into_iter()
+            .map(|(session_id, mut session)| {
+                session.destroy().then(move |r| {
+                    if let Err(e) = r {
+                        warn!("cannot destroy session {}: {}", session_id, e)
+                    }
+                    Ok::<_, ()>(())
+                })
+            })
+            .collect();
+
+        ActorResponse::r#async(
+            future::join_all(destroys)
+                .map(move |_| destroyed)
                 .into_actor(self),
         )
     }
 }
 
+impl Handler<DrainSession> for DockerMan {
+    type Result = Result<usize, Error>;
+
+    fn handle(&mut self, msg: DrainSession, _ctx: &mut Self::Context) -> Self::Result {
+        self.deploys.deploy(&msg.session_id)?;
+        Err(Error::Error(
+            "session drain is not supported by docker env".into(),
+        ))
+    }
+}
+
 struct Init {
     should_run: bool,
 }
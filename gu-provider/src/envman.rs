@@ -2,15 +2,18 @@
 //!
 
 use actix::prelude::*;
-use futures::{future, prelude::*};
+use futures::{future, prelude::*, stream};
 use gu_actix::prelude::*;
+use gu_hardware::actor::{HardwareActor, HardwareQuery};
 use gu_model::envman::*;
 use gu_net::rpc::peer::PeerSessionInfo;
 use gu_net::rpc::{PublicMessage, RemotingContext, RemotingSystemService};
+use log::warn;
 use serde::{de::DeserializeOwned, Serialize};
 use serde_json::Value as JsonValue;
 use std::borrow::Cow;
 use std::collections::BTreeMap;
+use std::time::Duration;
 
 /// Actor
 #[derive(Default)]
@@ -18,7 +21,17 @@ struct EnvMan {
     create_map: BTreeMap<String, Box<dyn CreateSender>>,
     session_update_map: BTreeMap<String, Recipient<SessionUpdate>>,
     get_sessions_map: BTreeMap<String, Recipient<GetSessions>>,
+    get_session_map: BTreeMap<String, Recipient<GetSession>>,
     destroy_session_map: BTreeMap<String, Recipient<DestroySession>>,
+    drain_session_map: BTreeMap<String, Recipient<DrainSession>>,
+    image_refs_map: BTreeMap<String, Recipient<GetImageRefs>>,
+    evict_images_map: BTreeMap<String, Recipient<EvictImages>>,
+    session_processes_map: BTreeMap<String, Recipient<GetSessionProcesses>>,
+    cached_images_map: BTreeMap<String, Recipient<GetCachedImages>>,
+    session_size_map: BTreeMap<String, Recipient<GetSessionSize>>,
+    prefetch_image_map: BTreeMap<String, Recipient<PrefetchImage>>,
+    shutdown_map: BTreeMap<String, Recipient<Shutdown>>,
+    timed_update_map: BTreeMap<String, Recipient<TimedSessionUpdate>>,
 }
 
 impl Actor for EnvMan {
@@ -27,8 +40,19 @@ impl Actor for EnvMan {
     fn started(&mut self, ctx: &mut Self::Context) {
         ctx.bind::<CreateSession<JsonValue>>(CreateSession::<JsonValue>::ID);
         ctx.bind::<SessionUpdate>(SessionUpdate::ID);
+        ctx.bind::<TimedSessionUpdate>(TimedSessionUpdate::ID);
+        ctx.bind::<BulkSessionUpdate>(BulkSessionUpdate::ID);
         ctx.bind::<GetSessions>(GetSessions::ID);
+        ctx.bind::<GetSession>(GetSession::ID);
         ctx.bind::<DestroySession>(DestroySession::ID);
+        ctx.bind::<DrainSession>(DrainSession::ID);
+        ctx.bind::<GetImageRefs>(GetImageRefs::ID);
+        ctx.bind::<EvictImages>(EvictImages::ID);
+        ctx.bind::<GetSessionProcesses>(GetSessionProcesses::ID);
+        ctx.bind::<GetNodeCapacity>(GetNodeCapacity::ID);
+        ctx.bind::<GetCachedImages>(GetCachedImages::ID);
+        ctx.bind::<GetSessionSize>(GetSessionSize::ID);
+        ctx.bind::<PrefetchImage>(PrefetchImage::ID);
     }
 }
 
@@ -55,6 +79,7 @@ impl<T: EnvManService + 'static> CreateSender for CreateRecipient<T> {
                         name: msg.name,
                         tags: msg.tags,
                         note: msg.note,
+                        cpu_affinity: msg.cpu_affinity,
                         options,
                     })
                     .flatten_fut(),
@@ -86,11 +111,15 @@ where
     T: Handler<CreateSession<Options>>
         + Handler<SessionUpdate>
         + Handler<GetSessions>
-        + Handler<DestroySession>,
+        + Handler<GetSession>
+        + Handler<DestroySession>
+        + Handler<DrainSession>,
     T::Context: actix::dev::ToEnvelope<T, CreateSession<T::CreateOptions>>,
     T::Context: actix::dev::ToEnvelope<T, SessionUpdate>,
     T::Context: actix::dev::ToEnvelope<T, GetSessions>,
+    T::Context: actix::dev::ToEnvelope<T, GetSession>,
     T::Context: actix::dev::ToEnvelope<T, DestroySession>,
+    T::Context: actix::dev::ToEnvelope<T, DrainSession>,
 {
     type Result = ();
 
@@ -108,11 +137,87 @@ where
             .insert(env_type.clone(), msg.address.clone().recipient());
         self.get_sessions_map
             .insert(env_type.clone(), msg.address.clone().recipient());
+        self.get_session_map
+            .insert(env_type.clone(), msg.address.clone().recipient());
         self.destroy_session_map
+            .insert(env_type.clone(), msg.address.clone().recipient());
+        self.drain_session_map
+            .insert(env_type, msg.address.recipient());
+    }
+}
+
+/// Registers handlers of messages only some environments support;
+/// `EnvMan` answers them with `UnknownEnv` for other environments
+struct RegisterExtensions<T>
+where
+    T: Actor,
+{
+    env_type: Cow<'static, str>,
+    address: Addr<T>,
+}
+
+impl<T> Message for RegisterExtensions<T>
+where
+    T: Actor,
+{
+    type Result = ();
+}
+
+impl<T> Handler<RegisterExtensions<T>> for EnvMan
+where
+    T: Actor
+        + Handler<GetImageRefs>
+        + Handler<EvictImages>
+        + Handler<GetSessionProcesses>
+        + Handler<GetCachedImages>
+        + Handler<GetSessionSize>
+        + Handler<PrefetchImage>
+        + Handler<TimedSessionUpdate>,
+    T::Context: actix::dev::ToEnvelope<T, GetImageRefs>,
+    T::Context: actix::dev::ToEnvelope<T, EvictImages>,
+    T::Context: actix::dev::ToEnvelope<T, GetSessionProcesses>,
+    T::Context: actix::dev::ToEnvelope<T, GetCachedImages>,
+    T::Context: actix::dev::ToEnvelope<T, GetSessionSize>,
+    T::Context: actix::dev::ToEnvelope<T, PrefetchImage>,
+    T::Context: actix::dev::ToEnvelope<T, TimedSessionUpdate>,
+{
+    type Result = ();
+
+    fn handle(&mut self, msg: RegisterExtensions<T>, _ctx: &mut Self::Context) {
+        let env_type: String = msg.env_type.into();
+        self.image_refs_map
+            .insert(env_type.clone(), msg.address.clone().recipient());
+        self.evict_images_map
+            .insert(env_type.clone(), msg.address.clone().recipient());
+        self.session_processes_map
+            .insert(env_type.clone(), msg.address.clone().recipient());
+        self.cached_images_map
+            .insert(env_type.clone(), msg.address.clone().recipient());
+        self.session_size_map
+            .insert(env_type.clone(), msg.address.clone().recipient());
+        self.prefetch_image_map
+            .insert(env_type.clone(), msg.address.clone().recipient());
+        self.timed_update_map
             .insert(env_type, msg.address.recipient());
     }
 }
 
+/// Passes `msg` to the recipient registered for `env_type` in `map`
+fn forward_to_env<M, T>(
+    map: &BTreeMap<String, Recipient<M>>,
+    env_type: &str,
+    msg: M,
+) -> Box<dyn Future<Item = T, Error = Error>>
+where
+    M: Message<Result = Result<T, Error>> + Send + 'static,
+    T: Send + 'static,
+{
+    match map.get(env_type) {
+        Some(recipient) => Box::new(recipient.send(msg).flatten_fut()),
+        None => Box::new(future::err(Error::UnknownEnv(env_type.into()))),
+    }
+}
+
 fn extract_prefix(s: &str) -> Result<(&str, &str), Error> {
     if let Some(break_pos) = s.find("::") {
         return Ok((&s[..break_pos], &s[break_pos + 2..]));
@@ -137,36 +242,103 @@ impl Handler<CreateSession<JsonValue>> for EnvMan {
     }
 }
 
+impl EnvMan {
+    /// Passes the update to the environment given by the session id prefix
+    fn forward_update(
+        &self,
+        msg: SessionUpdate,
+    ) -> Box<dyn Future<Item = Vec<String>, Error = Vec<String>>> {
+        let (prefix, session_id) = match extract_prefix(&msg.session_id) {
+            Ok(v) => v,
+            Err(_e) => {
+                return Box::new(future::err(vec!["Invalid environment prefix".to_string()]));
+            }
+        };
+
+        match self.session_update_map.get(prefix) {
+            Some(r) => Box::new(
+                r.send(SessionUpdate {
+                    session_id: session_id.into(),
+                    commands: msg.commands,
+                    on_error: msg.on_error,
+                })
+                .map_err(|_e| Vec::new())
+                .flatten_fut(),
+            ),
+            None => Box::new(future::err(Vec::new())),
+        }
+    }
+}
+
 impl Handler<SessionUpdate> for EnvMan {
     type Result = ActorResponse<EnvMan, Vec<String>, Vec<String>>;
 
     fn handle(&mut self, msg: SessionUpdate, _ctx: &mut Self::Context) -> Self::Result {
+        ActorResponse::r#async(self.forward_update(msg).into_actor(self))
+    }
+}
+
+impl Handler<TimedSessionUpdate> for EnvMan {
+    type Result = ActorResponse<EnvMan, TimedOutputs, TimedOutputs>;
+
+    fn handle(&mut self, msg: TimedSessionUpdate, _ctx: &mut Self::Context) -> Self::Result {
+        let msg = msg.0;
         let (prefix, session_id) = match extract_prefix(&msg.session_id) {
             Ok(v) => v,
             Err(_e) => {
-                return ActorResponse::reply(Err(vec!["Invalid environment prefix".to_string()]));
+                let mut outputs = TimedOutputs::default();
+                outputs.push("Invalid environment prefix", Duration::default());
+                return ActorResponse::reply(Err(outputs));
             }
         };
 
-        match self.session_update_map.get(prefix) {
+        match self.timed_update_map.get(prefix) {
             Some(r) => ActorResponse::r#async(
-                r.send(SessionUpdate {
+                r.send(TimedSessionUpdate(SessionUpdate {
                     session_id: session_id.into(),
                     commands: msg.commands,
-                })
-                .map_err(|_e| Vec::new())
+                    on_error: msg.on_error,
+                }))
+                .map_err(|_e| TimedOutputs::default())
                 .flatten_fut()
                 .into_actor(self),
             ),
-            None => ActorResponse::reply(Err(Vec::new())),
+            None => ActorResponse::reply(Err(TimedOutputs::default())),
         }
     }
 }
 
-impl Handler<GetSessions> for EnvMan {
-    type Result = ActorResponse<EnvMan, Vec<PeerSessionInfo>, ()>;
+impl Handler<BulkSessionUpdate> for EnvMan {
+    type Result = ActorResponse<EnvMan, BTreeMap<String, Result<Vec<String>, Vec<String>>>, ()>;
+
+    fn handle(&mut self, msg: BulkSessionUpdate, _ctx: &mut Self::Context) -> Self::Result {
+        let updates = msg
+            .session_ids
+            .into_iter()
+            .map(|session_id| {
+                self.forward_update(SessionUpdate {
+                    session_id: session_id.clone(),
+                    commands: msg.commands.clone(),
+                    on_error: msg.on_error,
+                })
+                .then(move |outcome| Ok::<_, ()>((session_id, outcome)))
+            })
+            .collect::<Vec<_>>();
+
+        ActorResponse::r#async(
+            future::join_all(updates)
+                .map(|outcomes| outcomes.into_iter().collect())
+                .into_actor(self),
+        )
+    }
+}
 
-    fn handle(&mut self, _msg: GetSessions, _ctx: &mut Self::Context) -> Self::Result {
+impl EnvMan {
+    /// Sessions of all environments, with ids prefixed by the environment
+    fn all_sessions(
+        &self,
+        msg: GetSessions,
+    ) -> impl Future<Item = Vec<PeerSessionInfo>, Error = ()> {
         fn add_sessions_prefix(
             prefix: String,
             sessions: Vec<PeerSessionInfo>,
@@ -180,29 +352,112 @@ impl Handler<GetSessions> for EnvMan {
                 .collect()
         }
 
-        let j = future::join_all(
+        future::join_all(
             self.get_sessions_map
                 .iter()
                 .map(|(k, v)| {
                     let prefix = k.to_owned();
 
-                    v.send(GetSessions {})
+                    v.send(msg.clone())
                         .map_err(|_| ())
                         .flatten_fut()
                         .and_then(|sessions| Ok(add_sessions_prefix(prefix, sessions)))
                 })
                 .collect::<Vec<_>>(),
-        );
-
-        ActorResponse::r#async(
-            j.and_then(|v: Vec<Vec<PeerSessionInfo>>| Ok(v.into_iter().flatten().collect()))
-                .into_actor(self),
         )
+        .and_then(|v: Vec<Vec<PeerSessionInfo>>| Ok(v.into_iter().flatten().collect()))
+    }
+}
+
+impl Handler<GetSessions> for EnvMan {
+    type Result = ActorResponse<EnvMan, Vec<PeerSessionInfo>, ()>;
+
+    fn handle(&mut self, msg: GetSessions, _ctx: &mut Self::Context) -> Self::Result {
+        ActorResponse::r#async(self.all_sessions(msg).into_actor(self))
+    }
+}
+
+/// `GetSessions` answered with NDJSON: one `PeerSessionInfo` per line, with the
+/// sessions of each environment sent as soon as it answers
+struct StreamSessions(GetSessions);
+
+pub type SessionLines = Box<dyn Stream<Item = String, Error = ()> + Send>;
+
+impl Message for StreamSessions {
+    type Result = SessionLines;
+}
+
+impl Handler<StreamSessions> for EnvMan {
+    type Result = MessageResult<StreamSessions>;
+
+    fn handle(&mut self, msg: StreamSessions, _ctx: &mut Self::Context) -> Self::Result {
+        let envs = self
+            .get_sessions_map
+            .iter()
+            .map(|(k, v)| {
+                let prefix = k.to_owned();
+
+                v.send(msg.0.clone())
+                    .map_err(|_| ())
+                    .flatten_fut()
+                    .or_else({
+                        let prefix = prefix.clone();
+                        move |()| {
+                            warn!("cannot list sessions of {}", prefix);
+                            Ok(Vec::new())
+                        }
+                    })
+                    .map(move |sessions| {
+                        stream::iter_ok(sessions.into_iter().filter_map(move |session| {
+                            let session = PeerSessionInfo {
+                                id: format!("{}::{}", prefix, session.id),
+                                ..session
+                            };
+                            match serde_json::to_string(&session) {
+                                Ok(line) => Some(line + "\n"),
+                                Err(e) => {
+                                    warn!("cannot serialize session {}: {}", session.id, e);
+                                    None
+                                }
+                            }
+                        }))
+                    })
+            })
+            .collect::<Vec<_>>();
+
+        MessageResult(Box::new(stream::futures_unordered(envs).flatten()))
+    }
+}
+
+impl Handler<GetSession> for EnvMan {
+    type Result = ActorResponse<EnvMan, PeerSessionInfo, Error>;
+
+    fn handle(&mut self, msg: GetSession, _ctx: &mut Self::Context) -> Self::Result {
+        let (prefix, session_id) = match extract_prefix(&msg.session_id) {
+            Ok(v) => v,
+            Err(e) => return ActorResponse::reply(Err(e)),
+        };
+
+        match self.get_session_map.get(prefix) {
+            Some(address) => {
+                let id = msg.session_id.clone();
+                ActorResponse::r#async(
+                    address
+                        .send(GetSession {
+                            session_id: session_id.into(),
+                        })
+                        .flatten_fut()
+                        .map(|session| PeerSessionInfo { id, ..session })
+                        .into_actor(self),
+                )
+            }
+            None => ActorResponse::reply(Err(Error::UnknownEnv(prefix.into()))),
+        }
     }
 }
 
 impl Handler<DestroySession> for EnvMan {
-    type Result = ActorResponse<EnvMan, String, Error>;
+    type Result = ActorResponse<EnvMan, SessionClosed, Error>;
 
     fn handle(
         &mut self,
@@ -229,6 +484,133 @@ impl Handler<DestroySession> for EnvMan {
     }
 }
 
+impl Handler<DrainSession> for EnvMan {
+    type Result = ActorResponse<EnvMan, usize, Error>;
+
+    fn handle(&mut self, msg: DrainSession, _ctx: &mut Self::Context) -> Self::Result {
+        let (prefix, session_id) = match extract_prefix(&msg.session_id) {
+            Ok(v) => v,
+            Err(e) => return ActorResponse::reply(Err(e)),
+        };
+
+        match self.drain_session_map.get(prefix) {
+            Some(address) => ActorResponse::r#async(
+                address
+                    .send(DrainSession {
+                        session_id: session_id.into(),
+                        ..msg
+                    })
+                    .flatten_fut()
+                    .into_actor(self),
+            ),
+            None => ActorResponse::reply(Err(Error::UnknownEnv(prefix.into()))),
+        }
+    }
+}
+
+impl Handler<GetImageRefs> for EnvMan {
+    type Result = ActorResponse<EnvMan, BTreeMap<String, usize>, Error>;
+
+    fn handle(&mut self, msg: GetImageRefs, _ctx: &mut Self::Context) -> Self::Result {
+        let env_type = msg.env_type.clone();
+        ActorResponse::r#async(
+            forward_to_env(&self.image_refs_map, &env_type, msg).into_actor(self),
+        )
+    }
+}
+
+impl Handler<GetCachedImages> for EnvMan {
+    type Result = ActorResponse<EnvMan, Vec<CachedImage>, Error>;
+
+    fn handle(&mut self, msg: GetCachedImages, _ctx: &mut Self::Context) -> Self::Result {
+        let env_type = msg.env_type.clone();
+        ActorResponse::r#async(
+            forward_to_env(&self.cached_images_map, &env_type, msg).into_actor(self),
+        )
+    }
+}
+
+impl Handler<PrefetchImage> for EnvMan {
+    type Result = ActorResponse<EnvMan, String, Error>;
+
+    fn handle(&mut self, msg: PrefetchImage, _ctx: &mut Self::Context) -> Self::Result {
+        let env_type = msg.env_type.clone();
+        ActorResponse::r#async(
+            forward_to_env(&self.prefetch_image_map, &env_type, msg).into_actor(self),
+        )
+    }
+}
+
+impl Handler<EvictImages> for EnvMan {
+    type Result = ActorResponse<EnvMan, Vec<String>, Error>;
+
+    fn handle(&mut self, msg: EvictImages, _ctx: &mut Self::Context) -> Self::Result {
+        let env_type = msg.env_type.clone();
+        ActorResponse::r#async(
+            forward_to_env(&self.evict_images_map, &env_type, msg).into_actor(self),
+        )
+    }
+}
+
+impl Handler<GetSessionProcesses> for EnvMan {
+    type Result = ActorResponse<EnvMan, Vec<ProcessInfo>, Error>;
+
+    fn handle(&mut self, msg: GetSessionProcesses, _ctx: &mut Self::Context) -> Self::Result {
+        let (prefix, session_id) = match extract_prefix(&msg.session_id) {
+            Ok(v) => v,
+            Err(e) => return ActorResponse::reply(Err(e)),
+        };
+        let msg = GetSessionProcesses {
+            session_id: session_id.into(),
+        };
+
+        ActorResponse::r#async(
+            forward_to_env(&self.session_processes_map, prefix, msg).into_actor(self),
+        )
+    }
+}
+
+impl Handler<GetSessionSize> for EnvMan {
+    type Result = ActorResponse<EnvMan, SessionSize, Error>;
+
+    fn handle(&mut self, msg: GetSessionSize, _ctx: &mut Self::Context) -> Self::Result {
+        let (prefix, session_id) = match extract_prefix(&msg.session_id) {
+            Ok(v) => v,
+            Err(e) => return ActorResponse::reply(Err(e)),
+        };
+        let msg = GetSessionSize {
+            session_id: session_id.into(),
+        };
+
+        ActorResponse::r#async(forward_to_env(&self.session_size_map, prefix, msg).into_actor(self))
+    }
+}
+
+fn node_capacity() -> impl Future<Item = NodeCapacity, Error = Error> {
+    HardwareActor::from_registry()
+        .send(HardwareQuery)
+        .map_err(|e| e.to_string())
+        .flatten_fut()
+        .map_err(Error::Error)
+        .and_then(|hardware| match hardware.ram() {
+            Some(ram) => Ok(NodeCapacity {
+                total_memory: ram.total(),
+                free_memory: ram.free(),
+                num_cores: hardware.num_cores(),
+            }),
+            None => Err(Error::Error("memory info not available".into())),
+        })
+}
+
+/// Capacity is a property of the node, so it is answered here for all environments.
+impl Handler<GetNodeCapacity> for EnvMan {
+    type Result = ActorResponse<EnvMan, NodeCapacity, Error>;
+
+    fn handle(&mut self, _msg: GetNodeCapacity, _ctx: &mut Self::Context) -> Self::Result {
+        ActorResponse::r#async(node_capacity().into_actor(self))
+    }
+}
+
 pub fn register<A, IntoCowStr, Options>(env_type: IntoCowStr, address: Addr<A>)
 where
     IntoCowStr: Into<Cow<'static, str>>,
@@ -237,11 +619,15 @@ where
     A: Handler<CreateSession<Options>>
         + Handler<SessionUpdate>
         + Handler<GetSessions>
-        + Handler<DestroySession>,
+        + Handler<GetSession>
+        + Handler<DestroySession>
+        + Handler<DrainSession>,
     A::Context: actix::dev::ToEnvelope<A, CreateSession<A::CreateOptions>>,
     A::Context: actix::dev::ToEnvelope<A, SessionUpdate>,
     A::Context: actix::dev::ToEnvelope<A, GetSessions>,
+    A::Context: actix::dev::ToEnvelope<A, GetSession>,
     A::Context: actix::dev::ToEnvelope<A, DestroySession>,
+    A::Context: actix::dev::ToEnvelope<A, DrainSession>,
 {
     EnvMan::from_registry().do_send(Register {
         env_type: env_type.into(),
@@ -249,10 +635,221 @@ where
     })
 }
 
+/// Registers handlers of optional messages of the environment, next to `register`
+pub fn register_extensions<A, IntoCowStr>(env_type: IntoCowStr, address: Addr<A>)
+where
+    IntoCowStr: Into<Cow<'static, str>>,
+    A: Actor
+        + Handler<GetImageRefs>
+        + Handler<EvictImages>
+        + Handler<GetSessionProcesses>
+        + Handler<GetCachedImages>
+        + Handler<GetSessionSize>
+        + Handler<PrefetchImage>
+        + Handler<TimedSessionUpdate>,
+    A::Context: actix::dev::ToEnvelope<A, GetImageRefs>,
+    A::Context: actix::dev::ToEnvelope<A, EvictImages>,
+    A::Context: actix::dev::ToEnvelope<A, GetSessionProcesses>,
+    A::Context: actix::dev::ToEnvelope<A, GetCachedImages>,
+    A::Context: actix::dev::ToEnvelope<A, GetSessionSize>,
+    A::Context: actix::dev::ToEnvelope<A, PrefetchImage>,
+    A::Context: actix::dev::ToEnvelope<A, TimedSessionUpdate>,
+{
+    EnvMan::from_registry().do_send(RegisterExtensions {
+        env_type: env_type.into(),
+        address,
+    })
+}
+
+/// Destroys all sessions on provider shutdown; resolves to the number of destroyed
+/// sessions. Sent to `EnvMan`, it is passed to every environment registered with
+/// `register_shutdown`, and resolves once all of them are done.
+pub struct Shutdown;
+
+impl Message for Shutdown {
+    type Result = Result<usize, ()>;
+}
+
+struct RegisterShutdown {
+    env_type: Cow<'static, str>,
+    recipient: Recipient<Shutdown>,
+}
+
+impl Message for RegisterShutdown {
+    type Result = ();
+}
+
+impl Handler<RegisterShutdown> for EnvMan {
+    type Result = ();
+
+    fn handle(&mut self, msg: RegisterShutdown, _ctx: &mut Self::Context) {
+        self.shutdown_map.insert(msg.env_type.into(), msg.recipient);
+    }
+}
+
+impl Handler<Shutdown> for EnvMan {
+    type Result = ActorResponse<EnvMan, usize, ()>;
+
+    fn handle(&mut self, _msg: Shutdown, _ctx: &mut Self::Context) -> Self::Result {
+        let shutdowns: Vec<_> = self
+            .shutdown_map
+            .iter()
+            .map(|(env_type, recipient)| {
+                let env_type = env_type.clone();
+                recipient.send(Shutdown).then(move |r| match r {
+                    Ok(Ok(destroyed)) => Ok(destroyed),
+                    _ => {
+                        warn!("{} environment not shut down cleanly", env_type);
+                        Ok(0)
+                    }
+                })
+            })
+            .collect();
+
+        ActorResponse::r#async(
+            future::join_all(shutdowns)
+                .map(|destroyed| destroyed.into_iter().sum())
+                .into_actor(self),
+        )
+    }
+}
+
+/// Makes the environment take part in provider shutdown; see [`Shutdown`]
+pub fn register_shutdown<IntoCowStr>(env_type: IntoCowStr, recipient: Recipient<Shutdown>)
+where
+    IntoCowStr: Into<Cow<'static, str>>,
+{
+    EnvMan::from_registry().do_send(RegisterShutdown {
+        env_type: env_type.into(),
+        recipient,
+    })
+}
+
+/// Shuts all registered environments down; see [`Shutdown`]
+pub fn shutdown() -> impl Future<Item = usize, Error = ()> {
+    EnvMan::from_registry()
+        .send(Shutdown)
+        .then(|r| r.unwrap_or(Err(())))
+}
+
+/// Sessions of all environments as NDJSON lines; see [`StreamSessions`]
+pub fn stream_sessions(msg: GetSessions) -> impl Future<Item = SessionLines, Error = MailboxError> {
+    EnvMan::from_registry().send(StreamSessions(msg))
+}
+
 #[cfg(test)]
 mod test {
+    use gu_net::rpc::peer::PeerSessionStatus;
+
     use super::*;
 
+    /// Environment with fixed sessions, succeeding every update of them
+    struct FixedEnv(Vec<String>);
+
+    impl Actor for FixedEnv {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<SessionUpdate> for FixedEnv {
+        type Result = Result<Vec<String>, Vec<String>>;
+
+        fn handle(&mut self, msg: SessionUpdate, _ctx: &mut Self::Context) -> Self::Result {
+            if !self.0.contains(&msg.session_id) {
+                return Err(vec![Error::NoSuchSession(msg.session_id).to_string()]);
+            }
+            Ok(msg.commands.iter().map(|_| "ok".to_string()).collect())
+        }
+    }
+
+    #[test]
+    fn test_bulk_update_reports_every_session() {
+        let sessions = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+
+        System::run(move || {
+            let mut env_man = EnvMan::default();
+            env_man
+                .session_update_map
+                .insert("fixed".into(), FixedEnv(sessions).start().recipient());
+
+            Arbiter::spawn(
+                gu_net::rpc::start_actor(env_man)
+                    .send(BulkSessionUpdate {
+                        session_ids: vec![
+                            "fixed::a".into(),
+                            "fixed::missing".into(),
+                            "fixed::b".into(),
+                            "fixed::c".into(),
+                        ],
+                        commands: vec![Command::AddTags(vec!["bulk".into()])],
+                        on_error: OnError::StopChain,
+                    })
+                    .then(|r| {
+                        let outcomes = r.unwrap().unwrap();
+                        assert_eq!(outcomes.len(), 4);
+                        for id in &["fixed::a", "fixed::b", "fixed::c"] {
+                            assert_eq!(outcomes[*id], Ok(vec!["ok".to_string()]));
+                        }
+                        assert!(outcomes["fixed::missing"].is_err());
+                        Ok(System::current().stop())
+                    }),
+            )
+        });
+    }
+
+    impl Handler<GetSessions> for FixedEnv {
+        type Result = Result<Vec<PeerSessionInfo>, ()>;
+
+        fn handle(&mut self, msg: GetSessions, _ctx: &mut Self::Context) -> Self::Result {
+            Ok(self
+                .0
+                .iter()
+                .map(|id| PeerSessionInfo {
+                    id: id.clone(),
+                    name: format!("session {}", id),
+                    status: PeerSessionStatus::CREATED,
+                    tags: vec![format!("build-{}", id)],
+                    note: None,
+                    processes: Default::default(),
+                    workspace_exists: true,
+                })
+                .filter(|info| msg.matches(&info.tags))
+                .collect())
+        }
+    }
+
+    #[test]
+    fn test_ndjson_lines_parse_as_session_info() {
+        let sessions = vec!["a".to_string(), "b".to_string(), "other".to_string()];
+
+        System::run(move || {
+            let mut env_man = EnvMan::default();
+            env_man
+                .get_sessions_map
+                .insert("fixed".into(), FixedEnv(sessions).start().recipient());
+            let msg: GetSessions = serde_json::from_str(r#"{"tag": "build-?"}"#).unwrap();
+
+            Arbiter::spawn(
+                gu_net::rpc::start_actor(env_man)
+                    .send(StreamSessions(msg))
+                    .then(|lines| lines.unwrap().collect())
+                    .then(|r| {
+                        let mut ids: Vec<String> = r
+                            .unwrap()
+                            .iter()
+                            .map(|line| {
+                                assert!(line.ends_with('\n'));
+                                serde_json::from_str::<PeerSessionInfo>(line).unwrap().id
+                            })
+                            .collect();
+                        ids.sort();
+
+                        assert_eq!(ids, vec!["fixed::a", "fixed::b"]);
+                        Ok(System::current().stop())
+                    }),
+            )
+        });
+    }
+
     #[test]
     fn test_split() {
         let (p, s) = extract_prefix("hd::12345").unwrap();
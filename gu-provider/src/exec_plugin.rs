@@ -1,9 +1,12 @@
-use crate::deployment::{DeployManager, Destroy, IntoDeployInfo};
+use crate::deployment::{session_closed, DeployManager, Destroy, IntoDeployInfo};
 use crate::workspace::{Workspace, WorkspacesManager};
 use crate::{envman, status};
 use actix::prelude::*;
 use gu_hdman::process_pool::{self as pp, KillAll, ProcessPool};
-use gu_model::envman::{Command, CreateSession, DestroySession, GetSessions, SessionUpdate};
+use gu_model::envman::{
+    Command, CreateSession, DestroySession, DrainSession, GetSession, GetSessions, SessionClosed,
+    SessionUpdate,
+};
 use gu_model::plugin::{PluginManifest, ResolveResult, SimpleExecEnvSpec};
 use std::path::{Path, PathBuf};
 use std::process;
@@ -84,12 +87,13 @@ impl IntoDeployInfo for PlugSession {
             tags,
             note,
             processes,
+            workspace_exists: true,
         }
     }
 }
 
 impl Destroy for PlugSession {
-    fn destroy(&mut self) -> Box<dyn Future<Item = (), Error = EnvError>> {
+    fn destroy(&mut self) -> Box<dyn Future<Item = usize, Error = EnvError>> {
         /// TODO Add self.workspace.clear_dir().map_err(From::from).into_future()
         Box::new(
             self.pool
@@ -144,7 +148,7 @@ impl Handler<CreateSession<<Self as EnvManService>::CreateOptions>> for PluginMa
                     // Image is valid
                     let mut workspace = act.workspaces_man.workspace();
                     workspace.add_tags(tags);
-                    if let Err(e) = workspace.create_dirs() {
+                    if let Err(e) = workspace.create_dirs().and_then(|_| workspace.save()) {
                         return fut::Either::B(fut::err(EnvError::IoError(e.to_string())));
                     }
                     let exec = act.exec.clone();
@@ -227,11 +231,15 @@ impl Handler<SessionUpdate> for PluginMan {
             self,
             ctx,
             msg.commands,
+            msg.on_error,
             move |command, act, ctx| {
                 match command {
                     Command::AddTags(new_tags) => {
                         if let Ok(session) = act.deploys.deploy_mut(&session_id) {
                             session.workspace.add_tags(new_tags);
+                            if let Err(e) = session.workspace.save() {
+                                return Box::new(futures::future::err(e.to_string()));
+                            }
                             Box::new(futures::future::ok(format!(
                                 "tags added. Current tags are: {:?}",
                                 &session.workspace.tags()
@@ -243,6 +251,9 @@ impl Handler<SessionUpdate> for PluginMan {
                     Command::DelTags(tags) => {
                         if let Ok(session) = act.deploys.deploy_mut(&session_id) {
                             session.workspace.remove_tags(tags);
+                            if let Err(e) = session.workspace.save() {
+                                return Box::new(futures::future::err(e.to_string()));
+                            }
                             Box::new(futures::future::ok(format!(
                                 "tags deleted. Current tags are: {:?}",
                                 &session.workspace.tags()
@@ -271,6 +282,7 @@ impl Handler<SessionUpdate> for PluginMan {
                         executable,
                         mut args,
                         /*TODO */ working_dir,
+                        ..
                     } => {
                         let mut driver_args: Vec<String> = vec![
                             "exec".into(),
@@ -343,7 +355,12 @@ impl Handler<SessionUpdate> for PluginMan {
                         )
                         .and_then(move |resp: ResolveResult| match resp {
                             ResolveResult::ResolvedPath(input_path) => {
-                                crate::provision::upload_step(&uri, input_path.into(), format)
+                                crate::provision::upload_step(
+                                    &uri,
+                                    input_path.into(),
+                                    format,
+                                    crate::provision::DEFAULT_UPLOAD_TIMEOUT,
+                                )
                             }
                         })
                         .and_then(|_| Ok("uploaded".into())),
@@ -357,6 +374,15 @@ impl Handler<SessionUpdate> for PluginMan {
                     Command::Wait { .. } => {
                         Box::new(futures::future::err("wait not implemented".into()))
                     }
+                    Command::ResetStatus(_) => {
+                        Box::new(futures::future::err("status reset not implemented".into()))
+                    }
+                    Command::WriteSecret { .. } => {
+                        Box::new(futures::future::err("secrets not implemented".into()))
+                    }
+                    Command::ExportWorkspace { .. } => {
+                        Box::new(futures::future::err("export not implemented".into()))
+                    }
                     Command::Stop { child_id } => {
                         let pid: pp::Pid = match child_id.parse() {
                             Ok(pid) => pid,
@@ -414,13 +440,21 @@ fn resolve_path(
 impl Handler<GetSessions> for PluginMan {
     type Result = Result<Vec<PeerSessionInfo>, ()>;
 
-    fn handle(&mut self, _: GetSessions, _ctx: &mut Self::Context) -> Self::Result {
-        Ok(self.deploys.deploys_info())
+    fn handle(&mut self, msg: GetSessions, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.deploys.filtered_deploys_info(&msg))
+    }
+}
+
+impl Handler<GetSession> for PluginMan {
+    type Result = Result<PeerSessionInfo, EnvError>;
+
+    fn handle(&mut self, msg: GetSession, _ctx: &mut Self::Context) -> Self::Result {
+        self.deploys.deploy_info(&msg.session_id)
     }
 }
 
 impl Handler<DestroySession> for PluginMan {
-    type Result = ActorResponse<Self, String, EnvError>;
+    type Result = ActorResponse<Self, SessionClosed, EnvError>;
 
     fn handle(&mut self, msg: DestroySession, _ctx: &mut Self::Context) -> Self::Result {
         ActorResponse::r#async(
@@ -428,18 +462,27 @@ impl Handler<DestroySession> for PluginMan {
                 .destroy_deploy(&msg.session_id)
                 .into_actor(self)
                 .then(|r, _act, _ctx| match r {
-                    Ok(_) => fut::ok("Session closed".into()),
+                    Ok(killed) => fut::ok(session_closed(killed)),
                     Err(e) => fut::err(e),
                 }),
         )
     }
 }
 
+impl Handler<DrainSession> for PluginMan {
+    type Result = Result<usize, EnvError>;
+
+    fn handle(&mut self, msg: DrainSession, _ctx: &mut Self::Context) -> Self::Result {
+        self.deploys.deploy(&msg.session_id)?;
+        Err(EnvError::Error("drain not implemented".into()))
+    }
+}
+
 impl Handler<status::GetEnvStatus> for PluginMan {
     type Result = MessageResult<status::GetEnvStatus>;
 
     fn handle(&mut self, _: GetEnvStatus, _ctx: &mut Self::Context) -> Self::Result {
-        MessageResult(self.deploys.status())
+        MessageResult(self.deploys.env_status())
     }
 }
 
@@ -0,0 +1,493 @@
+//! Fake execution environment.
+//!
+//! Accepts the same session messages as real environments, but never downloads
+//! images nor spawns processes. Commands are echoed back as their results.
+
+use std::collections::{BTreeSet, HashSet};
+
+use actix::prelude::*;
+use futures::{future, prelude::*};
+use log::debug;
+
+use gu_model::envman::*;
+use gu_net::rpc::peer::{PeerSessionInfo, PeerSessionStatus};
+
+use crate::deployment::{session_closed, DeployManager, Destroy, IntoDeployInfo};
+use crate::id::new_id;
+use crate::{envman, status};
+
+struct FakeSessionInfo {
+    name: String,
+    image: Image,
+    status: PeerSessionStatus,
+    tags: BTreeSet<String>,
+    note: Option<String>,
+    processes: HashSet<String>,
+    draining: bool,
+}
+
+impl IntoDeployInfo for FakeSessionInfo {
+    fn convert(&self, id: &String) -> PeerSessionInfo {
+        PeerSessionInfo {
+            id: id.clone(),
+            name: self.name.clone(),
+            status: self.status.clone(),
+            tags: self.tags.iter().cloned().collect(),
+            note: self.note.clone(),
+            processes: self.processes.clone(),
+            workspace_exists: true,
+        }
+    }
+}
+
+impl Destroy for FakeSessionInfo {
+    fn destroy(&mut self) -> Box<dyn Future<Item = usize, Error = Error>> {
+        let killed = self.processes.len();
+        self.processes.clear();
+        Box::new(future::ok(killed))
+    }
+}
+
+impl FakeSessionInfo {
+    fn run_command(&mut self, command: Command) -> Result<String, String> {
+        Ok(match command {
+            Command::Open => "Open mock".into(),
+            Command::Close => "Close mock".into(),
+            Command::Exec {
+                executable,
+                args,
+                mark_clean,
+                ..
+            } => {
+                if self.processes.is_empty() {
+                    self.status = match mark_clean {
+                        true => PeerSessionStatus::CREATED,
+                        false => PeerSessionStatus::CONFIGURED,
+                    };
+                }
+                format!("{} {}", executable, args.join(" "))
+            }
+            Command::Start { .. } => {
+                let child_id = new_id();
+                self.processes.insert(child_id.clone());
+                self.status = PeerSessionStatus::RUNNING;
+                child_id
+            }
+            Command::Stop { child_id } => {
+                if !self.processes.remove(&child_id) {
+                    return Err(format!("child {} not found", child_id));
+                }
+                if self.processes.is_empty() {
+                    self.status = PeerSessionStatus::CONFIGURED;
+                }
+                child_id
+            }
+            Command::Wait { .. } => {
+                if !self.processes.is_empty() {
+                    self.processes.clear();
+                    self.status = PeerSessionStatus::CONFIGURED;
+                }
+                "Wait mock".into()
+            }
+            Command::ResetStatus(status) => {
+                if !self.processes.is_empty() {
+                    return Err("session has running processes".into());
+                }
+                self.status = match status {
+                    IdleStatus::Created => PeerSessionStatus::CREATED,
+                    IdleStatus::Configured => PeerSessionStatus::CONFIGURED,
+                };
+                format!("status reset to {:?}", self.status)
+            }
+            Command::AddTags(tags) => {
+                self.tags.extend(tags);
+                format!("tags inserted. Current tags are: {:?}", &self.tags)
+            }
+            Command::DelTags(tags) => {
+                for tag in tags {
+                    self.tags.remove(&tag);
+                }
+                format!("tags removed. Current tags are: {:?}", &self.tags)
+            }
+            Command::DownloadFile { uri, .. } => format!("{:?} file downloaded", uri),
+            Command::UploadFile { file_path, .. } => format!("{:?} file uploaded", file_path),
+            Command::WriteFile { file_path, .. } => format!("{:?} file written", file_path),
+            Command::WriteSecret { file_path, .. } => format!("{:?} secret written", file_path),
+            Command::ExportWorkspace { uri, .. } => format!("workspace exported to {:?}", uri),
+        })
+    }
+}
+
+/// Environment faking image download and process execution, for tests.
+#[derive(Default)]
+pub struct FakeMan {
+    deploys: DeployManager<FakeSessionInfo>,
+}
+
+impl FakeMan {
+    pub fn start() -> Addr<Self> {
+        Actor::start(FakeMan::default())
+    }
+}
+
+impl envman::EnvManService for FakeMan {
+    type CreateOptions = ();
+}
+
+impl Actor for FakeMan {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        envman::register("fake", ctx.address());
+
+        status::StatusManager::from_registry()
+            .do_send(status::AddProvider::new("fake", ctx.address().recipient()));
+    }
+}
+
+impl Handler<CreateSession> for FakeMan {
+    type Result = Result<String, Error>;
+
+    fn handle(&mut self, msg: CreateSession, _ctx: &mut Self::Context) -> Self::Result {
+        let session_id = self.deploys.generate_session_id();
+        debug!("fake download of: {:?}", msg.image);
+
+        self.deploys.insert_deploy(
+            session_id.clone(),
+            FakeSessionInfo {
+                name: msg.name,
+                image: msg.image,
+                status: PeerSessionStatus::CREATED,
+                tags: msg.tags.into_iter().collect(),
+                note: msg.note,
+                processes: HashSet::new(),
+                draining: false,
+            },
+        );
+        Ok(session_id)
+    }
+}
+
+impl Handler<SessionUpdate> for FakeMan {
+    type Result = Result<Vec<String>, Vec<String>>;
+
+    fn handle(&mut self, msg: SessionUpdate, _ctx: &mut Self::Context) -> Self::Result {
+        let session = match self.deploys.deploy_mut(&msg.session_id) {
+            Ok(session) => session,
+            Err(_) => return Err(vec![Error::NoSuchSession(msg.session_id).to_string()]),
+        };
+        debug!("fake update of session from: {}", session.image.url);
+
+        let mut results = Vec::new();
+        let mut failed = false;
+        for command in msg.commands {
+            let result = match command {
+                Command::Exec { .. } | Command::Start { .. } if session.draining => {
+                    Err(Error::SessionDraining(msg.session_id.clone()).to_string())
+                }
+                command => session.run_command(command),
+            };
+            match result {
+                Ok(result) => results.push(result),
+                Err(e) => {
+                    results.push(e);
+                    failed = true;
+                    if msg.on_error == OnError::StopChain {
+                        break;
+                    }
+                }
+            }
+        }
+        match failed {
+            false => Ok(results),
+            true => Err(results),
+        }
+    }
+}
+
+impl Handler<GetSessions> for FakeMan {
+    type Result = Result<Vec<PeerSessionInfo>, ()>;
+
+    fn handle(&mut self, msg: GetSessions, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.deploys.filtered_deploys_info(&msg))
+    }
+}
+
+impl Handler<GetSession> for FakeMan {
+    type Result = Result<PeerSessionInfo, Error>;
+
+    fn handle(&mut self, msg: GetSession, _ctx: &mut Self::Context) -> Self::Result {
+        self.deploys.deploy_info(&msg.session_id)
+    }
+}
+
+impl Handler<DestroySession> for FakeMan {
+    type Result = ActorResponse<FakeMan, SessionClosed, Error>;
+
+    fn handle(&mut self, msg: DestroySession, _ctx: &mut Self::Context) -> Self::Result {
+        ActorResponse::r#async(
+            self.deploys
+                .destroy_deploy(&msg.session_id)
+                .map(session_closed)
+                .into_actor(self),
+        )
+    }
+}
+
+/// Fake processes exit at once when asked to
+impl Handler<DrainSession> for FakeMan {
+    type Result = Result<usize, Error>;
+
+    fn handle(&mut self, msg: DrainSession, _ctx: &mut Self::Context) -> Self::Result {
+        let session = self.deploys.deploy_mut(&msg.session_id)?;
+        session.draining = true;
+        if !session.processes.is_empty() {
+            session.processes.clear();
+            session.status = PeerSessionStatus::CONFIGURED;
+        }
+        Ok(0)
+    }
+}
+
+impl Handler<status::GetEnvStatus> for FakeMan {
+    type Result = MessageResult<status::GetEnvStatus>;
+
+    fn handle(&mut self, _msg: status::GetEnvStatus, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.deploys.env_status())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{BTreeSet, HashSet};
+
+    use actix::prelude::*;
+    use futures::prelude::*;
+
+    use gu_actix::prelude::*;
+    use gu_model::envman::*;
+    use gu_net::rpc::peer::PeerSessionStatus;
+
+    use super::{FakeMan, FakeSessionInfo};
+    use crate::deployment::{session_closed, DeployManager};
+    use crate::status::{EnvStatus, SessionCounts};
+
+    fn image() -> Image {
+        Image {
+            url: "http://localhost/image.hdi".into(),
+            hash: "SHA1:a9993e364706816aba3e25717850c26c9cd0d89d".into(),
+            cache_name: None,
+        }
+    }
+
+    #[test]
+    fn test_env_status_counts_sessions() {
+        let mut deploys = DeployManager::default();
+        let sessions = vec![
+            ("created", vec![]),
+            (
+                "configured",
+                vec![Command::Exec {
+                    executable: "echo".into(),
+                    args: Vec::new(),
+                    working_dir: None,
+                    mark_clean: false,
+                    env: Default::default(),
+                    strict_env: false,
+                    output_encoding: Default::default(),
+                }],
+            ),
+            (
+                "running",
+                vec![Command::Start {
+                    executable: "sleep".into(),
+                    args: vec!["100".into()],
+                    ready_signal: None,
+                    ready_timeout: None,
+                }],
+            ),
+        ];
+
+        for (name, commands) in sessions {
+            let mut session = FakeSessionInfo {
+                name: name.into(),
+                image: image(),
+                status: PeerSessionStatus::CREATED,
+                tags: BTreeSet::new(),
+                note: None,
+                processes: HashSet::new(),
+                draining: false,
+            };
+            for command in commands {
+                session.run_command(command).unwrap();
+            }
+            deploys.insert_deploy(name.into(), session);
+        }
+
+        let status = deploys.env_status();
+        assert_eq!(status.status, EnvStatus::Working);
+        assert_eq!(
+            status.sessions,
+            SessionCounts {
+                pending: 0,
+                created: 1,
+                running: 1,
+                configured: 1,
+                destroying: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_destroy_reports_killed_processes() {
+        let mut deploys = DeployManager::default();
+        let mut session = FakeSessionInfo {
+            name: "busy".into(),
+            image: image(),
+            status: PeerSessionStatus::CREATED,
+            tags: BTreeSet::new(),
+            note: None,
+            processes: HashSet::new(),
+            draining: false,
+        };
+        for _ in 0..2 {
+            session
+                .run_command(Command::Start {
+                    executable: "sleep".into(),
+                    args: vec!["100".into()],
+                    ready_signal: None,
+                    ready_timeout: None,
+                })
+                .unwrap();
+        }
+        deploys.insert_deploy("busy".into(), session);
+
+        let killed = deploys.destroy_deploy(&"busy".to_string()).wait().unwrap();
+        assert_eq!(killed, 2);
+        let closed = session_closed(killed);
+        assert_eq!(closed.processes_killed, 2);
+        assert_eq!(closed.message, "Session closed, 2 processes killed");
+    }
+
+    #[test]
+    fn test_get_single_session() {
+        let mut sys = System::new("test");
+        let fake = FakeMan::start();
+
+        let session_id = sys
+            .block_on(
+                fake.send(CreateSession {
+                    env_type: "fake".into(),
+                    image: image(),
+                    name: "single".into(),
+                    tags: vec!["test".into()],
+                    note: Some("note".into()),
+                    cpu_affinity: Vec::new(),
+                    options: (),
+                })
+                .flatten_fut(),
+            )
+            .unwrap();
+        let sessions = sys
+            .block_on(fake.send(GetSessions::default()))
+            .unwrap()
+            .unwrap();
+        let session = sys
+            .block_on(fake.send(GetSession {
+                session_id: session_id.clone(),
+            }))
+            .unwrap()
+            .unwrap();
+
+        let listed = sessions.iter().find(|s| s.id == session_id).unwrap();
+        assert_eq!(session.id, listed.id);
+        assert_eq!(session.name, listed.name);
+        assert_eq!(session.status, listed.status);
+        assert_eq!(session.tags, listed.tags);
+        assert_eq!(session.note, listed.note);
+
+        match sys.block_on(fake.send(GetSession {
+            session_id: "missing".into(),
+        })) {
+            Ok(Err(Error::NoSuchSession(id))) => assert_eq!(id, "missing"),
+            r => panic!("unexpected result: {:?}", r.map(|r| r.map(|s| s.id))),
+        }
+    }
+
+    #[test]
+    fn test_session_lifecycle() {
+        System::run(|| {
+            let fake = FakeMan::start();
+            let (update, sessions, destroy) = (fake.clone(), fake.clone(), fake.clone());
+
+            Arbiter::spawn(
+                fake.send(CreateSession {
+                    env_type: "fake".into(),
+                    image: image(),
+                    name: "lifecycle".into(),
+                    tags: vec!["test".into()],
+                    note: None,
+                    cpu_affinity: Vec::new(),
+                    options: (),
+                })
+                .flatten_fut()
+                .map_err(|e| panic!("create failed: {}", e))
+                .and_then(move |session_id| {
+                    update
+                        .send(SessionUpdate {
+                            session_id: session_id.clone(),
+                            commands: vec![
+                                Command::Exec {
+                                    executable: "echo".into(),
+                                    args: vec!["hello".into()],
+                                    working_dir: None,
+                                    mark_clean: false,
+                                    env: Default::default(),
+                                    strict_env: false,
+                                    output_encoding: Default::default(),
+                                },
+                                Command::Start {
+                                    executable: "sleep".into(),
+                                    args: vec!["100".into()],
+                                    ready_signal: None,
+                                    ready_timeout: None,
+                                },
+                            ],
+                            on_error: OnError::StopChain,
+                        })
+                        .flatten_fut()
+                        .map_err(|e| panic!("update failed: {:?}", e))
+                        .and_then(move |results| {
+                            assert_eq!(results[0], "echo hello");
+                            sessions
+                                .send(GetSessions::default())
+                                .flatten_fut()
+                                .map_err(|e| panic!("get sessions failed: {:?}", e))
+                                .map(move |sessions| (session_id, sessions))
+                        })
+                })
+                .and_then(move |(session_id, sessions)| {
+                    assert_eq!(sessions.len(), 1);
+                    assert_eq!(sessions[0].status, PeerSessionStatus::RUNNING);
+                    assert_eq!(sessions[0].processes.len(), 1);
+
+                    destroy
+                        .send(DestroySession {
+                            session_id: session_id.clone(),
+                        })
+                        .flatten_fut()
+                        .map_err(|e| panic!("destroy failed: {}", e))
+                        .and_then(move |_| {
+                            destroy
+                                .send(DestroySession { session_id })
+                                .flatten_fut()
+                                .then(|r| {
+                                    assert!(r.is_err());
+                                    Ok(System::current().stop())
+                                })
+                        })
+                }),
+            )
+        });
+    }
+}
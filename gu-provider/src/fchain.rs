@@ -1,5 +1,6 @@
 use actix::ActorFuture;
 use futures::{Async, Future};
+use gu_model::envman::OnError;
 use serde::export::PhantomData;
 use std::iter::IntoIterator;
 use std::mem;
@@ -71,6 +72,8 @@ struct FuChain<Actor, Input, Output, F> {
     result: Vec<Output>,
     process: F,
     actor: PhantomData<Actor>,
+    on_error: OnError,
+    failed: bool,
 }
 
 impl<Input, Output, Actor: actix::Actor, F> ActorFuture for FuChain<Actor, Input, Output, F>
@@ -93,6 +96,10 @@ where
         while let Some(mut current) = self.current.take() {
             let r = match current.poll() {
                 Ok(v) => v,
+                Err(e) if self.on_error == OnError::Continue => {
+                    self.failed = true;
+                    Async::Ready(e)
+                }
                 Err(e) => {
                     let mut result = mem::replace(&mut self.result, Default::default());
                     result.push(e);
@@ -113,10 +120,11 @@ where
                 }
             }
         }
-        Ok(Async::Ready(mem::replace(
-            &mut self.result,
-            Default::default(),
-        )))
+        let result = mem::replace(&mut self.result, Default::default());
+        match self.failed {
+            false => Ok(Async::Ready(result)),
+            true => Err(result),
+        }
     }
 }
 
@@ -129,6 +137,7 @@ pub fn process_chain_act<
     act: &mut Actor,
     ctx: &mut Actor::Context,
     input: Vec<Input>,
+    on_error: OnError,
     mut process: F,
 ) -> impl ActorFuture<Actor = Actor, Item = Vec<Output>, Error = Vec<Output>> {
     let mut chain = input.into_iter();
@@ -141,5 +150,7 @@ pub fn process_chain_act<
         result,
         process,
         actor,
+        on_error,
+        failed: false,
     }
 }
@@ -1,29 +1,34 @@
 use std::{
     collections::{
         hash_map::{Entry, OccupiedEntry},
-        HashMap, HashSet,
+        BTreeMap, HashMap, HashSet,
     },
     fs,
     fs::OpenOptions,
-    path::{Path, PathBuf},
-    process, result, time,
+    io,
+    path::{Component, Path, PathBuf},
+    process, result, thread,
+    time::{self, SystemTime},
 };
 
 use actix::{fut, prelude::*};
-use futures::{future, prelude::*};
-use log::{debug, error, info};
+use futures::{future, prelude::*, sync::oneshot};
+use log::{debug, error, info, trace, warn};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 
 use gu_actix::prelude::*;
+use gu_hardware::process::{ProcessMemory, ProcessMemorySource};
 use gu_hdman::image_manager;
 use gu_model::envman::*;
 use gu_net::rpc::{
     peer::{PeerSessionInfo, PeerSessionStatus},
     *,
 };
-use gu_persist::config::ConfigModule;
+use gu_persist::config::{ConfigManager, ConfigModule, GetConfig};
 
-use crate::deployment::{DeployManager, Destroy, IntoDeployInfo};
+use crate::deployment::{session_closed, DeployManager, Destroy, IntoDeployInfo};
+use crate::server::ProviderConfig;
 
 /**
 
@@ -31,11 +36,17 @@ Host direct manager.
 
 */
 use super::id::generate_new_id;
-use super::provision::{download_step, untgz, upload_step};
-use super::workspace::{Workspace, WorkspacesManager};
+use super::provision::{
+    dir_size, download_step, export_step, image_checksum, untgz, upload_step, DirSize, LocalImage,
+    DEFAULT_UPLOAD_TIMEOUT,
+};
+use super::workspace::{Workspace, WorkspacesManager, DEFAULT_LOG_RETENTION};
 use super::{
     envman, status,
-    sync_exec::{Exec, ExecResult, SyncExecManager},
+    sync_exec::{
+        check_cpu_affinity, kill_all, read_lines, set_cpu_affinity, terminate, Exec, ExecResult,
+        SyncExecManager, DEFAULT_KILL_TIMEOUT,
+    },
 };
 
 impl IntoDeployInfo for HdSessionInfo {
@@ -47,34 +58,158 @@ impl IntoDeployInfo for HdSessionInfo {
             tags: self.workspace.tags(),
             note: self.note.clone(),
             processes: self.processes.keys().cloned().collect(),
+            workspace_exists: self.workspace_exists,
         }
     }
 }
 
 impl Destroy for HdSessionInfo {
-    fn destroy(&mut self) -> Box<dyn Future<Item = (), Error = Error>> {
+    fn destroy(&mut self) -> Box<dyn Future<Item = usize, Error = Error>> {
         debug!("killing all running child processes");
-        let _ = self
+        let killed = self
             .processes
             .values_mut()
-            .map(|child| child.kill())
-            .collect::<Vec<_>>();
+            .map(|process| process.child.kill())
+            .filter(Result::is_ok)
+            .count();
         let _ = self
             .processes
             .values_mut()
-            .map(|child| child.wait())
+            .map(|process| process.child.wait())
             .collect::<Vec<_>>();
-        Box::new(self.workspace.clear_dir().map_err(From::from).into_future())
+        Box::new(
+            self.workspace
+                .clear_dir()
+                .map(move |_| killed)
+                .map_err(From::from)
+                .into_future(),
+        )
+    }
+}
+
+/// How often finished children are collected while some `Wait` is pending
+const WAIT_POLL_INTERVAL: time::Duration = time::Duration::from_millis(100);
+
+/// How long `Start` waits for the ready signal, unless given
+const DEFAULT_READY_TIMEOUT: time::Duration = time::Duration::from_secs(30);
+
+/// How often a ready-file is checked for
+const READY_FILE_POLL_INTERVAL: time::Duration = time::Duration::from_millis(50);
+
+/// How long a computed session size is reported without walking the workspace again
+const SESSION_SIZE_TTL: time::Duration = time::Duration::from_secs(5);
+
+/// Command output as returned to the caller
+fn encode_output(output: &[u8], encoding: OutputEncoding) -> String {
+    match encoding {
+        OutputEncoding::Utf8Lossy => String::from_utf8_lossy(output).into_owned(),
+        OutputEncoding::Base64 => base64::encode(output),
     }
 }
 
+/// Resolves to `true` once `path` exists, or to `false` after `timeout`
+fn wait_for_file(path: PathBuf, timeout: time::Duration) -> oneshot::Receiver<bool> {
+    let (tx, rx) = oneshot::channel();
+    thread::spawn(move || {
+        let deadline = time::Instant::now() + timeout;
+        while !path.exists() {
+            if time::Instant::now() >= deadline {
+                let _ = tx.send(false);
+                return;
+            }
+            thread::sleep(READY_FILE_POLL_INTERVAL);
+        }
+        let _ = tx.send(true);
+    });
+    rx
+}
+
+/// Resolves to `true` on the first line of `stdout` matching `pattern`,
+/// or to `false` if `stdout` is closed before. Reads `stdout` until it is closed,
+/// so that the child never blocks on a full pipe, copying it to `log`.
+fn wait_for_line(
+    stdout: process::ChildStdout,
+    pattern: Regex,
+    mut log: Option<fs::File>,
+) -> oneshot::Receiver<bool> {
+    use std::io::Write;
+
+    let (tx, rx) = oneshot::channel();
+    thread::spawn(move || {
+        let mut tx = Some(tx);
+        let read = read_lines(stdout, |line| {
+            trace!("child output: {}", line);
+            if let Some(Err(e)) = log.as_mut().map(|log| writeln!(log, "{}", line)) {
+                warn!("cannot write child log: {}", e);
+                log = None;
+            }
+            if tx.is_some() && pattern.is_match(line) {
+                let _ = tx.take().map(|tx| tx.send(true));
+            }
+        });
+        if let Err(e) = read {
+            debug!("cannot read child output: {}", e);
+        }
+        if let Some(tx) = tx {
+            let _ = tx.send(false);
+        }
+    });
+    rx
+}
+
 /// Host direct manager
 pub struct HdMan {
     deploys: DeployManager<HdSessionInfo>,
-    //TODO: implement using this configured property
-    #[allow(unused)]
     cache_dir: PathBuf,
     workspaces_man: WorkspacesManager,
+    upload_timeout: time::Duration,
+    /// how long logs of finished children are kept
+    log_retention: time::Duration,
+    memory_budget: MemoryBudget,
+    /// directory local session images must be in; `None` disables them
+    local_image_root: Option<PathBuf>,
+}
+
+/// Memory, in KiB, a new child is expected to need at least, unless configured
+pub(crate) const DEFAULT_CHILD_MEMORY_RESERVE: u64 = 16 * 1024;
+
+/// Node-wide memory budget of children of all sessions
+struct MemoryBudget {
+    /// in KiB; `None` means no limit
+    limit: Option<u64>,
+    /// in KiB, reserved for the child about to be started
+    child_reserve: u64,
+    source: Box<dyn ProcessMemorySource>,
+}
+
+impl Default for MemoryBudget {
+    fn default() -> Self {
+        MemoryBudget {
+            limit: None,
+            child_reserve: DEFAULT_CHILD_MEMORY_RESERVE,
+            source: Box::new(ProcessMemory::default()),
+        }
+    }
+}
+
+impl MemoryBudget {
+    /// Fails unless one more child fits in the budget next to given processes
+    fn check(&mut self, pids: &[u32]) -> Result<(), Error> {
+        let limit = match self.limit {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        let used = self.source.total_rss(pids);
+        debug!(
+            "children use {} of {} KiB budget, {} KiB reserved for a new one",
+            used, limit, self.child_reserve
+        );
+
+        match used.saturating_add(self.child_reserve) <= limit {
+            true => Ok(()),
+            false => Err(Error::MemoryBudgetExceeded(limit)),
+        }
+    }
 }
 
 impl envman::EnvManService for HdMan {
@@ -86,15 +221,55 @@ impl Actor for HdMan {
 
     fn started(&mut self, ctx: &mut Self::Context) {
         envman::register("hd", ctx.address());
+        envman::register_extensions("hd", ctx.address());
 
         status::StatusManager::from_registry().do_send(status::AddProvider::new(
             "hostDirect",
             ctx.address().recipient(),
         ));
+        envman::register_shutdown("hd", ctx.address().recipient());
+
+        // children did not survive the restart, but workspaces and tags did
+        for (session_id, workspace) in self.workspaces_man.saved_workspaces() {
+            if !self.deploys.contains_deploy(&session_id) {
+                info!("restoring session {}", session_id);
+                let session = HdSessionInfo {
+                    status: PeerSessionStatus::CREATED,
+                    ..HdSessionInfo::new(workspace)
+                };
+                self.deploys.insert_deploy(session_id, session);
+            }
+        }
 
         ctx.run_interval(time::Duration::from_secs(10), |act, _| {
             act.scan_for_processes()
         });
+        ctx.run_interval(WAIT_POLL_INTERVAL, |act, _| {
+            if act
+                .deploys
+                .values()
+                .any(|session| !session.waiters.is_empty())
+            {
+                act.scan_for_processes()
+            }
+        });
+
+        ctx.spawn(
+            ConfigManager::from_registry()
+                .send(GetConfig::<ProviderConfig>::new())
+                .flatten_fut()
+                .into_actor(self)
+                .map(|config, act, _ctx| {
+                    act.upload_timeout = time::Duration::from_secs(config.upload_timeout);
+                    act.log_retention = time::Duration::from_secs(config.log_retention);
+                    if let Some(limit) = config.memory_budget {
+                        act.memory_budget.limit = Some(limit);
+                    }
+                    act.memory_budget.child_reserve = config.child_memory_reserve;
+                    act.local_image_root = config.local_image_root;
+                })
+                .map_err(|e, _act, _ctx| warn!("cannot read provider config: {}", e)),
+        );
     }
 }
 
@@ -112,9 +287,23 @@ impl HdMan {
             deploys: Default::default(),
             cache_dir,
             workspaces_man,
+            upload_timeout: DEFAULT_UPLOAD_TIMEOUT,
+            log_retention: DEFAULT_LOG_RETENTION,
+            memory_budget: MemoryBudget::default(),
+            local_image_root: None,
         })
     }
 
+    /// Rejects starting more children once the running ones use up the memory budget
+    fn check_memory_budget(&mut self) -> Result<(), Error> {
+        let pids: Vec<u32> = self
+            .deploys
+            .values()
+            .flat_map(|session| session.processes.values().map(|process| process.child.id()))
+            .collect();
+        self.memory_budget.check(&pids)
+    }
+
     #[allow(unused)]
     fn get_cache_path(&self, file_name: &Path) -> PathBuf {
         self.cache_dir.join(file_name)
@@ -155,25 +344,47 @@ impl HdMan {
         Ok(self.get_session_mut(&session_id)?.insert_process(child))
     }
 
-    fn scan_for_processes(&mut self) {
-        for sess_info in self.deploys.values_mut() {
-            let finished: Vec<String> = sess_info
-                .processes
-                .iter_mut()
-                .filter_map(|(id, child)| match child.try_wait() {
-                    Ok(Some(_exit_st)) => Some(id.clone()),
-                    _ => None,
-                })
-                .collect();
+    fn image_refs(&self) -> ImageRefs {
+        ImageRefs::from_images(
+            self.deploys
+                .values()
+                .filter_map(|session| session.image.as_ref().map(PathBuf::as_path)),
+        )
+    }
+
+    /// Destroys all sessions; see [`envman::Shutdown`]
+    fn shutdown(&mut self) -> impl Future<Item = usize, Error = ()> {
+        let mut sessions = self.deploys.drain();
+        let destroyed = sessions.len();
 
-            let some_finished = !finished.is_empty();
-            for f in finished {
-                sess_info.processes.remove(&f);
-                info!("finished {:?}; removing", f)
+        // children of all sessions are killed together, so that shutdown takes
+        // one kill timeout at most
+        let children = sessions
+            .iter_mut()
+            .flat_map(|(_, session)| {
+                session.waiters.clear();
+                session.processes.drain()
+            })
+            .map(|(child_id, process)| (child_id, process.child))
+            .collect();
+
+        kill_all(children, DEFAULT_KILL_TIMEOUT).then(move |_| {
+            for (session_id, session) in sessions {
+                if let Err(e) = session.workspace.clear_dir() {
+                    warn!("cannot clear dir of session {}: {}", session_id, e)
+                }
             }
+            Ok(destroyed)
+        })
+    }
 
-            if some_finished & sess_info.processes.is_empty() {
-                sess_info.status = PeerSessionStatus::CONFIGURED;
+    fn scan_for_processes(&mut self) {
+        for sess_info in self.deploys.values_mut() {
+            sess_info.collect_finished();
+            sess_info.check_workspace();
+            // logs of a removed session dir are already gone with it
+            if sess_info.workspace_exists {
+                sess_info.prune_logs(self.log_retention);
             }
         }
     }
@@ -187,16 +398,176 @@ struct HdSessionInfo {
     dirty: bool,
     note: Option<String>,
     config_files: HashSet<PathBuf>,
-    processes: HashMap<String, process::Child>,
+    processes: HashMap<String, ChildProcess>,
+    /// pending `Wait` commands, by child id
+    waiters: HashMap<String, Vec<oneshot::Sender<Option<i32>>>>,
+    /// whether the session directory was still on disk at the last scan
+    workspace_exists: bool,
+    /// cached image the session was created from
+    image: Option<PathBuf>,
+    /// cores the session processes are pinned to
+    cpu_affinity: Vec<usize>,
+    /// last computed disk usage of the workspace, with the time it was computed
+    size: Option<(time::Instant, SessionSize)>,
+    /// set by `DrainSession`; `Start` and `Exec` are rejected from then on
+    draining: bool,
 }
 
 impl HdSessionInfo {
+    fn new(workspace: Workspace) -> Self {
+        HdSessionInfo {
+            workspace,
+            status: PeerSessionStatus::PENDING,
+            dirty: false,
+            note: None,
+            processes: HashMap::new(),
+            waiters: HashMap::new(),
+            workspace_exists: true,
+            config_files: HashSet::new(),
+            image: None,
+            cpu_affinity: Vec::new(),
+            size: None,
+            draining: false,
+        }
+    }
+
     fn insert_process(&mut self, child: process::Child) -> String {
         let id = generate_new_id(&self.processes);
-        self.processes.insert(id.clone(), child);
+        self.insert_process_as(id.clone(), child);
+        id
+    }
+
+    fn insert_process_as(&mut self, id: String, child: process::Child) {
+        self.processes.insert(id, ChildProcess::new(child));
         self.dirty = true;
         self.status = PeerSessionStatus::RUNNING;
-        id
+    }
+
+    /// Opens for appending the log file of given child, in the workspace logs dir
+    fn open_log(&self, child_id: &str) -> io::Result<fs::File> {
+        let logs_dir = self.workspace.logs_dir();
+        fs::create_dir_all(&logs_dir)?;
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(logs_dir.join(format!("{}.log", child_id)))
+    }
+
+    /// Removes finished children, passing their exit codes to pending `Wait`s
+    fn collect_finished(&mut self) {
+        let finished: Vec<(String, Option<i32>)> = self
+            .processes
+            .iter_mut()
+            .filter_map(|(id, process)| match process.child.try_wait() {
+                Ok(Some(exit_st)) => Some((id.clone(), exit_st.code())),
+                _ => None,
+            })
+            .collect();
+
+        let some_finished = !finished.is_empty();
+        for (id, code) in finished {
+            self.processes.remove(&id);
+            for waiter in self.waiters.remove(&id).unwrap_or_default() {
+                let _ = waiter.send(code);
+            }
+            info!("finished {:?}; removing", id)
+        }
+
+        if some_finished & self.processes.is_empty() {
+            self.status = self.idle_status();
+        }
+    }
+
+    /// Removes logs of finished children older than `retention`
+    fn prune_logs(&self, retention: time::Duration) {
+        let processes = &self.processes;
+        if let Err(e) = self
+            .workspace
+            .prune_logs(retention, |child_id| processes.contains_key(child_id))
+        {
+            warn!(
+                "cannot prune logs of {}: {}",
+                self.workspace.path().display(),
+                e
+            );
+        }
+    }
+
+    fn check_workspace(&mut self) {
+        let exists = self.workspace.path().is_dir();
+        if self.workspace_exists && !exists {
+            warn!(
+                "session dir {} disappeared",
+                self.workspace.path().display()
+            );
+        }
+        self.workspace_exists = exists;
+    }
+
+    /// Resolves to the exit code of the child, once `collect_finished` notices its exit
+    fn wait_process(
+        &mut self,
+        child_id: &str,
+    ) -> Result<impl Future<Item = Option<i32>, Error = String>, Error> {
+        let exit_status = match self.processes.get_mut(child_id) {
+            Some(process) => process
+                .child
+                .try_wait()
+                .map_err(|e| Error::IoError(e.to_string()))?,
+            None => return Err(Error::NoSuchChild(child_id.into())),
+        };
+
+        Ok(match exit_status {
+            Some(exit_status) => future::Either::A(future::ok(exit_status.code())),
+            None => {
+                let (tx, rx) = oneshot::channel();
+                self.waiters
+                    .entry(child_id.into())
+                    .or_insert_with(Vec::new)
+                    .push(tx);
+                let child_id = child_id.to_owned();
+                future::Either::B(rx.map_err(move |_| format!("child {} was stopped", child_id)))
+            }
+        })
+    }
+
+    /// Status of the session with no running children
+    fn idle_status(&self) -> PeerSessionStatus {
+        match self.dirty {
+            true => PeerSessionStatus::CONFIGURED,
+            false => PeerSessionStatus::CREATED,
+        }
+    }
+
+    /// Records successful `Exec`; the session stays dirty unless `mark_clean` was set
+    fn exec_finished(&mut self, mark_clean: bool) {
+        self.dirty = !mark_clean;
+        if self.processes.is_empty() {
+            self.status = self.idle_status();
+        }
+    }
+
+    /// Forces idle status; fails if any child is still running.
+    /// Finished children not yet collected by the periodic scan are removed.
+    fn reset_status(&mut self, status: IdleStatus) -> result::Result<String, String> {
+        match self.status {
+            PeerSessionStatus::PENDING | PeerSessionStatus::DESTROYING => {
+                return Err(format!("cannot reset status of {:?} session", self.status));
+            }
+            _ => (),
+        }
+
+        self.collect_finished();
+        if !self.processes.is_empty() {
+            return Err(format!(
+                "session has {} running process(es)",
+                self.processes.len()
+            ));
+        }
+
+        self.dirty = status == IdleStatus::Configured;
+        self.status = self.idle_status();
+        Ok(format!("status reset to {:?}", self.status))
     }
 
     fn get_session_exec_path(&self, executable: &String) -> String {
@@ -218,64 +589,81 @@ impl Handler<CreateSession> for HdMan {
         _ctx: &mut Self::Context,
     ) -> <Self as Handler<CreateSession>>::Result {
         let session_id = self.deploys.generate_session_id();
-        let image_hash =
-            match gu_model::hash::ParsedHash::from_hash_bytes(msg.image.hash.as_bytes()) {
-                Ok(v) => v,
-                Err(e) => {
-                    return ActorResponse::reply(Err(Error::IncorrectOptions(format!(
-                        "invalid hash format for {}: {}",
-                        msg.image.hash, e
-                    ))));
-                }
-            };
+        let local_image_root = self.local_image_root.as_ref().map(PathBuf::as_path);
+        let image_source = match LocalImage::from_url(&msg.image.url, local_image_root) {
+            Some(Ok(image)) => ImageSource::Local(image),
+            Some(Err(e)) => return ActorResponse::reply(Err(Error::IncorrectOptions(e))),
+            None => match check_remote_image(&msg.image) {
+                Ok(()) => ImageSource::Remote(msg.image),
+                Err(e) => return ActorResponse::reply(Err(e)),
+            },
+        };
 
-        if let Err(e) = image_hash.to_path() {
-            return ActorResponse::reply(Err(Error::IncorrectOptions(format!(
-                "invalid hash {}: {}",
-                msg.image.hash, e
-            ))));
+        if let Err(e) = check_cpu_affinity(&msg.cpu_affinity) {
+            return ActorResponse::reply(Err(Error::IncorrectOptions(e)));
         }
 
         let mut workspace = self.workspaces_man.workspace();
+        workspace.set_session_id(session_id.clone());
         workspace.add_tags(msg.tags);
-        match workspace.create_dirs() {
+        match workspace.create_dirs().and_then(|_| workspace.save()) {
             Ok(_) => (),
             Err(e) => return ActorResponse::reply(Err(e.into())),
         }
         let workspace_path = workspace.path().clone();
 
         let session = HdSessionInfo {
-            workspace,
-            status: PeerSessionStatus::PENDING,
-            dirty: false,
             note: msg.note,
-            processes: HashMap::new(),
-            config_files: HashSet::new(),
+            cpu_affinity: msg.cpu_affinity,
+            ..HdSessionInfo::new(workspace)
         };
 
         self.deploys.insert_deploy(session_id.clone(), session);
 
-        debug!("hey! I'm downloading from: {:?}", msg.image);
         let sess_id = session_id.clone();
+        let provision: Box<dyn Future<Item = Option<PathBuf>, Error = Error>> = match image_source {
+            ImageSource::Local(image) => {
+                debug!("using local image: {:?}", image);
+                Box::new(
+                    image
+                        .install(workspace_path)
+                        .map(|_| None)
+                        .map_err(|e| Error::IoError(e)),
+                )
+            }
+            ImageSource::Remote(image) => {
+                debug!("hey! I'm downloading from: {:?}", image);
+                Box::new(
+                    fetch_image(image, self.cache_dir.clone()).and_then(|cache_path| {
+                        untgz(cache_path.clone(), workspace_path)
+                            .map(|_| Some(cache_path))
+                            .map_err(|e| Error::IoError(e))
+                    }),
+                )
+            }
+        };
+
         ActorResponse::r#async(
-            image_manager::image(msg.image)
-                .map_err(|e| Error::IoError(format!("image pull error: {}", e)))
-                .and_then(|cache_path| {
-                    untgz(cache_path, workspace_path).map_err(|e| Error::IoError(e))
-                })
+            provision
                 .into_actor(self)
-                .and_then(|_, act, _ctx| match act.get_session_mut(&sess_id) {
-                    Ok(mut session) => {
-                        session.status = PeerSessionStatus::CREATED;
-                        fut::ok(sess_id)
-                    }
-                    Err(e) => fut::err(e),
-                })
+                .and_then(
+                    |cache_path, act, _ctx| match act.get_session_mut(&sess_id) {
+                        Ok(mut session) => {
+                            session.status = PeerSessionStatus::CREATED;
+                            session.image = cache_path;
+                            fut::ok(sess_id)
+                        }
+                        Err(e) => fut::err(e),
+                    },
+                )
                 .map_err(move |e, act, _ctx| {
                     eprintln!("[fail] {}", e);
                     // destroy on hdman is synchronous
                     match act.deploys.destroy_deploy(&session_id).wait() {
-                        Ok(_) => Error::IoError(format!("creating session error: {:?}", e)),
+                        Ok(_) => match e {
+                            Error::DownloadHttpStatus(code) => Error::DownloadHttpStatus(code),
+                            e => Error::IoError(format!("creating session error: {:?}", e)),
+                        },
                         Err(e) => e,
                     }
                 }),
@@ -283,11 +671,106 @@ impl Handler<CreateSession> for HdMan {
     }
 }
 
+/// Session image, installed from the local filesystem or downloaded
+/// into the image cache
+enum ImageSource {
+    Local(LocalImage),
+    Remote(Image),
+}
+
+/// Rejects remote images whose hash is not of a supported algorithm,
+/// so that the downloaded image can be verified, or is not usable as a cache key
+fn check_remote_image(image: &Image) -> Result<(), Error> {
+    check_image_hash(image)?;
+    image_checksum(&image.hash)
+        .map(|_| ())
+        .map_err(Error::IncorrectOptions)
+}
+
+/// Rejects images whose hash cannot be used as a cache key
+fn check_image_hash(image: &Image) -> Result<(), Error> {
+    let hash = image.hash.to_hash_str();
+    let image_hash = gu_model::hash::ParsedHash::from_hash_bytes(hash.as_bytes()).map_err(|e| {
+        Error::IncorrectOptions(format!("invalid hash format for {}: {}", image.hash, e))
+    })?;
+
+    image_hash
+        .to_path()
+        .map(|_| ())
+        .map_err(|e| Error::IncorrectOptions(format!("invalid hash {}: {}", image.hash, e)))
+}
+
+/// Resolves to the image cached in `cache_dir`, downloading it unless already cached;
+/// concurrent fetches of the same image share one download. The cache verifies
+/// the image against its hash, once per download. Download progress is reported
+/// to the `StatusManager` under the image url.
+fn fetch_image(image: Image, cache_dir: PathBuf) -> impl Future<Item = PathBuf, Error = Error> {
+    let reporter = status::ProgressReporter::new(image.url.clone());
+
+    image_manager::image_in_with_progress(image, cache_dir, move |progress| {
+        reporter.report(progress)
+    })
+    .map_err(|e| match e {
+        image_manager::Error::HttpStatus(code) => Error::DownloadHttpStatus(code),
+        e => Error::IoError(format!("image pull error: {}", e)),
+    })
+}
+
+/// Replaces `${NAME}` placeholders in command arguments with values from `vars`;
+/// placeholders without value are left as is, or rejected if `strict`
+fn expand_placeholders(
+    args: Vec<String>,
+    vars: &BTreeMap<String, String>,
+    strict: bool,
+) -> Result<Vec<String>, String> {
+    args.into_iter()
+        .map(|arg| {
+            let mut expanded = String::with_capacity(arg.len());
+            let mut rest = arg.as_str();
+
+            while let Some(start) = rest.find("${") {
+                let end = match rest[start..].find('}') {
+                    Some(end) => start + end,
+                    None => break,
+                };
+                let name = &rest[start + 2..end];
+
+                expanded.push_str(&rest[..start]);
+                match vars.get(name) {
+                    Some(value) => expanded.push_str(value),
+                    None if strict => return Err(format!("unknown placeholder ${{{}}}", name)),
+                    None => expanded.push_str(&rest[start..=end]),
+                }
+                rest = &rest[end + 1..];
+            }
+            expanded.push_str(rest);
+            Ok(expanded)
+        })
+        .collect()
+}
+
 fn run_command(
     hd_man: &mut HdMan,
     session_id: String,
     command: Command,
 ) -> Box<dyn ActorFuture<Actor = HdMan, Item = String, Error = String>> {
+    let upload_timeout = hd_man.upload_timeout;
+    let cache_dir = hd_man.cache_dir.clone();
+    let draining = match hd_man.get_session_mut(&session_id) {
+        Ok(session) => session.draining,
+        Err(e) => return Box::new(fut::err(e.to_string())),
+    };
+    match command {
+        Command::Exec { .. } | Command::Start { .. } if draining => {
+            return Box::new(fut::err(Error::SessionDraining(session_id).to_string()));
+        }
+        Command::Start { .. } => {
+            if let Err(e) = hd_man.check_memory_budget() {
+                return Box::new(fut::err(e.to_string()));
+            }
+        }
+        _ => (),
+    }
     let session = match hd_man.get_session_mut(&session_id) {
         Ok(a) => a,
         Err(e) => return Box::new(fut::err(e.to_string())),
@@ -300,13 +783,29 @@ fn run_command(
             executable,
             args,
             working_dir,
+            mark_clean,
+            mut env,
+            strict_env,
+            output_encoding,
         } => {
             let executable = session.get_session_exec_path(&executable);
             let session_id = session_id.clone();
+            let cpu_affinity = session.cpu_affinity.clone();
             let session_dir = session.workspace.path().to_owned();
             let cwd = session_dir.join(working_dir.unwrap_or_default());
 
+            env.insert(
+                "SESSION_DIR".into(),
+                session_dir.to_string_lossy().into_owned(),
+            );
+            env.insert("CACHE_DIR".into(), cache_dir.to_string_lossy().into_owned());
+            // values of placeholders may be secret, so only the template is logged
             info!("executing sync: {} {:?}", executable, args);
+            let args = match expand_placeholders(args, &env, strict_env) {
+                Ok(args) => args,
+                Err(e) => return Box::new(fut::err(e)),
+            };
+
             Box::new(
                 fut::wrap_future(
                     SyncExecManager::from_registry()
@@ -314,6 +813,7 @@ fn run_command(
                             executable,
                             args,
                             cwd,
+                            cpu_affinity,
                         })
                         .flatten_fut()
                         .map_err(move |e| e.to_string()),
@@ -321,14 +821,14 @@ fn run_command(
                 .and_then(move |res, act: &mut HdMan, _ctx| {
                     info!("sync cmd result: {:?}", res);
                     let result = if let ExecResult::Run(output) = res {
-                        String::from_utf8_lossy(&output.stdout).to_string()
+                        encode_output(&output.stdout, output_encoding)
                     } else {
                         "".to_string()
                     };
 
                     match act.get_session_mut(&session_id) {
                         Ok(session) => {
-                            session.dirty = true;
+                            session.exec_finished(mark_clean);
                             fut::ok(result)
                         }
                         Err(e) => fut::err(e.to_string()),
@@ -336,37 +836,114 @@ fn run_command(
                 }),
             )
         }
-        Command::Start { executable, args } => {
+        Command::Start {
+            executable,
+            args,
+            ready_signal,
+            ready_timeout,
+        } => {
             let executable = session.get_session_exec_path(&executable);
 
             info!("executing async: {} {:?}", executable, args);
+            let mut vars = BTreeMap::new();
+            vars.insert(
+                "SESSION_DIR".to_string(),
+                session.workspace.path().to_string_lossy().into_owned(),
+            );
+            vars.insert(
+                "CACHE_DIR".to_string(),
+                cache_dir.to_string_lossy().into_owned(),
+            );
+            let args = match expand_placeholders(args, &vars, false) {
+                Ok(args) => args,
+                Err(e) => return Box::new(fut::err(e)),
+            };
             // TODO: critical section
             // TODO: env::set_current_dir(&base_dir)?;
 
-            let child_res = process::Command::new(&executable)
-                .args(&args)
-                .spawn()
-                .map_err(|e| Error::IoError(e.to_string()))
-                .map(|child| session.insert_process(child));
+            let child_id = generate_new_id(&session.processes);
+            let mut command = process::Command::new(&executable);
+            command.args(&args);
+            set_cpu_affinity(&mut command, &session.cpu_affinity);
+            let ready_line = match ready_signal {
+                Some(ReadySignal::StdoutLine(ref pattern)) => match Regex::new(pattern) {
+                    Ok(pattern) => Some(pattern),
+                    Err(e) => return Box::new(fut::err(format!("invalid ready pattern: {}", e))),
+                },
+                _ => None,
+            };
+            if let Some(ReadySignal::File(ref path)) = ready_signal {
+                if Path::new(path)
+                    .components()
+                    .any(|c| c == Component::ParentDir)
+                {
+                    return Box::new(fut::err(format!("invalid ready file: {}", path)));
+                }
+            }
+            match session
+                .open_log(&child_id)
+                .and_then(|log| Ok((log.try_clone()?, log)))
+            {
+                Ok((stdout, stderr)) => {
+                    command.stdout(stdout).stderr(stderr);
+                }
+                Err(e) => warn!("cannot open log of child {}: {}", child_id, e),
+            }
+            // stdout watched for the ready line is copied to the log by `wait_for_line`
+            if ready_line.is_some() {
+                command.stdout(process::Stdio::piped());
+            }
+            let stdout = match command.spawn() {
+                Ok(mut child) => {
+                    let stdout = child.stdout.take();
+                    session.insert_process_as(child_id.clone(), child);
+                    stdout
+                }
+                Err(e) => return Box::new(fut::err(Error::IoError(e.to_string()).to_string())),
+            };
 
-            Box::new(match child_res {
-                Ok(id) => fut::ok(id),
-                Err(e) => fut::err(e.to_string()),
-            })
+            let timeout = ready_timeout
+                .map(time::Duration::from_secs)
+                .unwrap_or(DEFAULT_READY_TIMEOUT);
+            let ready = match (ready_signal, ready_line, stdout) {
+                (Some(ReadySignal::File(path)), _, _) => wait_for_file(
+                    session.workspace.path().join(path.trim_start_matches('/')),
+                    timeout,
+                ),
+                (_, Some(pattern), Some(stdout)) => {
+                    wait_for_line(stdout, pattern, session.open_log(&child_id).ok())
+                }
+                _ => return Box::new(fut::ok(child_id)),
+            };
+
+            let not_ready = format!("child {} not ready within {}s", child_id, timeout.as_secs());
+            Box::new(
+                fut::wrap_future::<_, HdMan>(
+                    ready
+                        .map_err(|_| "ready signal lost".to_string())
+                        .and_then(move |ready| match ready {
+                            true => Ok(child_id),
+                            false => Err(format!("child {} did not signal readiness", child_id)),
+                        }),
+                )
+                .timeout(timeout, not_ready),
+            )
         }
         Command::Stop { child_id } => {
             let session_id = session_id.clone();
             info!("killing: {:?}", &child_id);
+            session.waiters.remove(&child_id);
 
             let kill_res = session
                 .processes
                 .remove(&child_id)
+                .map(|process| process.child)
                 .ok_or(Error::NoSuchChild(child_id).to_string());
 
             Box::new(
                 fut::result(kill_res).and_then(move |child, hd_man: &mut HdMan, _ctx| {
                     SyncExecManager::from_registry()
-                        .send(Exec::Kill(child))
+                        .send(Exec::kill(child))
                         .map_err(|e| format!("{}", e))
                         .and_then(|r| {
                             if let Ok(ExecResult::Kill(output)) = r {
@@ -380,7 +957,7 @@ fn run_command(
                             match hd_man.get_session_mut(&session_id) {
                                 Ok(session) => {
                                     if session.processes.is_empty() {
-                                        session.status = PeerSessionStatus::CONFIGURED;
+                                        session.status = session.idle_status();
                                     };
                                     fut::ok(output)
                                 }
@@ -390,7 +967,27 @@ fn run_command(
                 }),
             )
         }
-        Command::Wait => Box::new(fut::ok("Wait mock".to_string())),
+        Command::Wait { child_id: None, .. } => Box::new(fut::ok("Wait mock".to_string())),
+        Command::Wait {
+            child_id: Some(child_id),
+            timeout,
+        } => {
+            let wait = match session.wait_process(&child_id) {
+                Ok(wait) => fut::wrap_future::<_, HdMan>(wait.and_then(move |code| match code {
+                    Some(code) => Ok(code.to_string()),
+                    None => Err(format!("child {} terminated by signal", child_id)),
+                })),
+                Err(e) => return Box::new(fut::err(e.to_string())),
+            };
+            match timeout {
+                Some(secs) => Box::new(wait.timeout(
+                    time::Duration::from_secs(secs),
+                    format!("child did not exit within {}s", secs),
+                )),
+                None => Box::new(wait),
+            }
+        }
+        Command::ResetStatus(status) => Box::new(fut::result(session.reset_status(status))),
         Command::DownloadFile {
             uri,
             file_path,
@@ -430,21 +1027,64 @@ fn run_command(
             format,
         } => {
             let path = session.workspace.path().join(file_path);
-            Box::new(fut::wrap_future(handle_upload_file(uri, path, format)))
+            Box::new(fut::wrap_future(handle_upload_file(
+                uri,
+                path,
+                format,
+                upload_timeout,
+            )))
+        }
+        Command::WriteSecret {
+            contents,
+            file_path,
+        } => {
+            let path = session.workspace.path().join(file_path);
+            Box::new(fut::wrap_future(gu_hdman::download::cpu_pool().spawn_fn(
+                move || {
+                    write_secret(&path, contents.expose().as_bytes())
+                        .map_err(|e| format!("io: {}", e))?;
+                    Ok("OK".to_string())
+                },
+            )))
+        }
+        Command::ExportWorkspace { uri, format } => {
+            let path = session.workspace.path().to_owned();
+            Box::new(fut::wrap_future(export_step(
+                &uri,
+                path,
+                format,
+                upload_timeout,
+            )))
         }
         Command::AddTags(tags) => Box::new({
             session.workspace.add_tags(tags);
-            fut::ok(format!(
-                "tags inserted. Current tags are: {:?}",
-                &session.workspace.tags()
-            ))
+            fut::result(
+                session
+                    .workspace
+                    .save()
+                    .map_err(|e| e.to_string())
+                    .map(|_| {
+                        format!(
+                            "tags inserted. Current tags are: {:?}",
+                            &session.workspace.tags()
+                        )
+                    }),
+            )
         }),
         Command::DelTags(tags) => Box::new({
             session.workspace.remove_tags(tags);
-            fut::ok(format!(
-                "tags removed. Current tags are: {:?}",
-                &session.workspace.tags()
-            ))
+            fut::result(
+                session
+                    .workspace
+                    .save()
+                    .map_err(|e| e.to_string())
+                    .map(|_| {
+                        format!(
+                            "tags removed. Current tags are: {:?}",
+                            &session.workspace.tags()
+                        )
+                    }),
+            )
         }),
     }
 }
@@ -453,23 +1093,30 @@ fn run_commands(
     hd_man: &mut HdMan,
     session_id: String,
     commands: Vec<Command>,
-) -> impl ActorFuture<Actor = HdMan, Item = Vec<String>, Error = Vec<String>> {
-    let f: Box<dyn ActorFuture<Actor = HdMan, Item = Vec<String>, Error = Vec<String>>> =
-        Box::new(future::ok(Vec::new()).into_actor(hd_man));
+    on_error: OnError,
+) -> impl ActorFuture<Actor = HdMan, Item = TimedOutputs, Error = TimedOutputs> {
+    type Chain = Box<dyn ActorFuture<Actor = HdMan, Item = TimedOutputs, Error = TimedOutputs>>;
+    let f: Chain = Box::new(future::ok(TimedOutputs::default()).into_actor(hd_man));
 
     commands.into_iter().fold(f, |acc, command| {
         let session_id = session_id.clone();
-        Box::new(acc.and_then(|mut vec, act, _ctx| {
-            run_command(act, session_id, command).then(move |i, _, _| match i {
-                Ok(a) => {
-                    vec.push(a);
-                    fut::ok(vec)
-                }
-                Err(a) => {
-                    vec.push(a);
-                    fut::err(vec)
+        Box::new(acc.then(move |r, act, _ctx| -> Chain {
+            let (mut outputs, failed) = match r {
+                Ok(outputs) => (outputs, false),
+                Err(outputs) => (outputs, true),
+            };
+            if failed && on_error == OnError::StopChain {
+                return Box::new(fut::err(outputs));
+            }
+            let started = time::Instant::now();
+            Box::new(run_command(act, session_id, command).then(move |i, _, _| {
+                let succeeded = i.is_ok();
+                outputs.push(i.unwrap_or_else(|e| e), started.elapsed());
+                match succeeded && !failed {
+                    true => fut::ok(outputs),
+                    false => fut::err(outputs),
                 }
-            })
+            }))
         }))
     })
 }
@@ -487,8 +1134,46 @@ impl Handler<SessionUpdate> for HdMan {
         }
         let session_id = msg.session_id.clone();
 
-        ActorResponse::r#async(run_commands(self, session_id, msg.commands))
+        ActorResponse::r#async(
+            run_commands(self, session_id, msg.commands, msg.on_error)
+                .map(|timed, _, _| timed.outputs)
+                .map_err(|timed, _, _| timed.outputs),
+        )
+    }
+}
+
+impl Handler<TimedSessionUpdate> for HdMan {
+    type Result = ActorResponse<HdMan, TimedOutputs, TimedOutputs>;
+
+    fn handle(&mut self, msg: TimedSessionUpdate, _ctx: &mut Self::Context) -> Self::Result {
+        let msg = msg.0;
+        if !self.deploys.contains_deploy(&msg.session_id) {
+            let mut outputs = TimedOutputs::default();
+            outputs.push(
+                Error::NoSuchSession(msg.session_id).to_string(),
+                time::Duration::default(),
+            );
+            return ActorResponse::reply(Err(outputs));
+        }
+        let session_id = msg.session_id.clone();
+
+        ActorResponse::r#async(run_commands(self, session_id, msg.commands, msg.on_error))
+    }
+}
+
+/// Writes `contents` to a new file, readable and writable by the owner only
+fn write_secret(path: &Path, contents: &[u8]) -> io::Result<()> {
+    use std::io::Write;
+
+    let _ = fs::remove_file(path);
+    let mut options = OpenOptions::new();
+    options.create_new(true).write(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
     }
+    options.open(path)?.write_all(contents)
 }
 
 fn handle_download_file(
@@ -505,8 +1190,9 @@ fn handle_upload_file(
     url: String,
     file_path: PathBuf,
     format: ResourceFormat,
+    timeout: time::Duration,
 ) -> impl Future<Item = String, Error = String> {
-    upload_step(&url, file_path, format)
+    upload_step(&url, file_path, format, timeout)
 }
 
 // TODO: implement child process polling and status reporting
@@ -518,13 +1204,21 @@ struct SessionStatus {
 impl Handler<GetSessions> for HdMan {
     type Result = result::Result<Vec<PeerSessionInfo>, ()>;
 
-    fn handle(&mut self, _msg: GetSessions, _ctx: &mut Self::Context) -> Self::Result {
-        Ok(self.deploys.deploys_info())
+    fn handle(&mut self, msg: GetSessions, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.deploys.filtered_deploys_info(&msg))
+    }
+}
+
+impl Handler<GetSession> for HdMan {
+    type Result = result::Result<PeerSessionInfo, Error>;
+
+    fn handle(&mut self, msg: GetSession, _ctx: &mut Self::Context) -> Self::Result {
+        self.deploys.deploy_info(&msg.session_id)
     }
 }
 
 impl Handler<DestroySession> for HdMan {
-    type Result = ActorResponse<HdMan, String, Error>;
+    type Result = ActorResponse<HdMan, SessionClosed, Error>;
 
     fn handle(
         &mut self,
@@ -532,20 +1226,1408 @@ impl Handler<DestroySession> for HdMan {
         _ctx: &mut Self::Context,
     ) -> <Self as Handler<DestroySession>>::Result {
         ActorResponse::r#async(match self.deploys.destroy_deploy(&msg.session_id).wait() {
-            Ok(_) => fut::ok("Session closed".into()),
+            Ok(killed) => fut::ok(session_closed(killed)),
             Err(e) => fut::err(e),
         })
     }
 }
 
-impl Handler<status::GetEnvStatus> for HdMan {
-    type Result = MessageResult<status::GetEnvStatus>;
+impl Handler<DrainSession> for HdMan {
+    type Result = ActorResponse<HdMan, usize, Error>;
 
-    fn handle(
-        &mut self,
-        _msg: status::GetEnvStatus,
-        _ctx: &mut Self::Context,
-    ) -> <Self as Handler<status::GetEnvStatus>>::Result {
-        MessageResult(self.deploys.status())
+    fn handle(&mut self, msg: DrainSession, _ctx: &mut Self::Context) -> Self::Result {
+        let session_id = msg.session_id;
+        let session = match self.get_session_mut(&session_id) {
+            Ok(session) => session,
+            Err(e) => return ActorResponse::reply(Err(e)),
+        };
+        session.draining = true;
+
+        let child_ids: Vec<String> = session.processes.keys().cloned().collect();
+        let waits: Vec<_> = child_ids
+            .iter()
+            .filter_map(|child_id| {
+                let process = &session.processes[child_id];
+                if let Err(e) = terminate(&process.child) {
+                    warn!("cannot terminate child {}: {}", child_id, e);
+                }
+                session
+                    .wait_process(child_id)
+                    .ok()
+                    .map(|wait| wait.then(|_| Ok(())))
+            })
+            .collect();
+        info!("draining {} children of {}", waits.len(), session_id);
+
+        ActorResponse::r#async(
+            fut::wrap_future::<_, HdMan>(future::join_all(waits))
+                .timeout(time::Duration::from_secs(msg.timeout), ())
+                .then(move |_, act, _ctx| {
+                    let session = match act.get_session_mut(&session_id) {
+                        Ok(session) => session,
+                        Err(e) => return fut::err(e),
+                    };
+                    session.collect_finished();
+                    fut::ok(session.processes.len())
+                }),
+        )
+    }
+}
+
+/// Children are sent SIGTERM, then SIGKILL if they are still running after the
+/// kill timeout, and session directories are removed.
+impl Handler<envman::Shutdown> for HdMan {
+    type Result = ActorResponse<HdMan, usize, ()>;
+
+    fn handle(&mut self, _msg: envman::Shutdown, _ctx: &mut Self::Context) -> Self::Result {
+        ActorResponse::r#async(self.shutdown().into_actor(self))
+    }
+}
+
+/// Child process started in a session
+struct ChildProcess {
+    child: process::Child,
+    started_at: SystemTime,
+}
+
+impl ChildProcess {
+    fn new(child: process::Child) -> Self {
+        ChildProcess {
+            child,
+            started_at: SystemTime::now(),
+        }
+    }
+
+    fn info(&mut self, child_id: &str) -> ProcessInfo {
+        let status = match self.child.try_wait() {
+            Ok(None) => ProcessStatus::Running,
+            Ok(Some(exit_status)) => ProcessStatus::Exited(exit_status.code()),
+            Err(e) => {
+                warn!("cannot check status of child {}: {}", child_id, e);
+                ProcessStatus::Unknown
+            }
+        };
+
+        ProcessInfo {
+            child_id: child_id.to_string(),
+            pid: self.child.id(),
+            started_at: self.started_at,
+            status,
+        }
+    }
+}
+
+impl Handler<GetSessionProcesses> for HdMan {
+    type Result = result::Result<Vec<ProcessInfo>, Error>;
+
+    fn handle(&mut self, msg: GetSessionProcesses, _ctx: &mut Self::Context) -> Self::Result {
+        let session = self.get_session_mut(&msg.session_id)?;
+
+        Ok(session
+            .processes
+            .iter_mut()
+            .map(|(child_id, process)| process.info(child_id))
+            .collect())
+    }
+}
+
+impl Handler<GetSessionSize> for HdMan {
+    type Result = result::Result<SessionSize, Error>;
+
+    fn handle(&mut self, msg: GetSessionSize, _ctx: &mut Self::Context) -> Self::Result {
+        let session = self.get_session_mut(&msg.session_id)?;
+
+        // the workspace is walked again only once the last result is `SESSION_SIZE_TTL` old
+        match session.size {
+            Some((computed_at, size)) if computed_at.elapsed() < SESSION_SIZE_TTL => Ok(size),
+            _ => {
+                let DirSize { bytes, files } = dir_size(session.workspace.path())
+                    .map_err(|e| Error::IoError(e.to_string()))?;
+                let size = SessionSize { bytes, files };
+                session.size = Some((time::Instant::now(), size));
+                Ok(size)
+            }
+        }
+    }
+}
+
+/// Number of live sessions using each cached image.
+#[derive(Default)]
+struct ImageRefs(HashMap<PathBuf, usize>);
+
+impl ImageRefs {
+    fn from_images<'a>(images: impl IntoIterator<Item = &'a Path>) -> Self {
+        let mut refs = ImageRefs::default();
+        for image in images {
+            *refs.0.entry(image.to_path_buf()).or_insert(0) += 1;
+        }
+        refs
+    }
+
+    fn count(&self, image: &Path) -> usize {
+        self.0.get(image).cloned().unwrap_or_default()
+    }
+
+    /// Removes given images from the cache, skipping the ones used by live sessions.
+    /// Returns evicted images.
+    fn evict(&self, images: impl IntoIterator<Item = PathBuf>) -> Vec<PathBuf> {
+        images
+            .into_iter()
+            .filter(|image| match self.count(image) {
+                0 => match fs::remove_file(image) {
+                    Ok(_) => true,
+                    Err(e) => {
+                        warn!("cannot evict image {}: {}", image.display(), e);
+                        false
+                    }
+                },
+                refs => {
+                    info!(
+                        "image {} not evicted: used by {} session(s)",
+                        image.display(),
+                        refs
+                    );
+                    false
+                }
+            })
+            .collect()
+    }
+
+    /// Lists image files in cache `dir`
+    fn list(&self, dir: &Path) -> io::Result<Vec<CachedImage>> {
+        let mut images = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if !metadata.is_file() {
+                continue;
+            }
+            images.push(CachedImage {
+                file_name: entry.file_name().to_string_lossy().into_owned(),
+                size: metadata.len(),
+                last_access: metadata.accessed().ok(),
+                in_use: self.count(&entry.path()) > 0,
+            });
+        }
+        images.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+        Ok(images)
+    }
+}
+
+impl Handler<GetCachedImages> for HdMan {
+    type Result = result::Result<Vec<CachedImage>, Error>;
+
+    fn handle(&mut self, _msg: GetCachedImages, _ctx: &mut Self::Context) -> Self::Result {
+        self.image_refs()
+            .list(&self.cache_dir)
+            .map_err(|e| Error::IoError(e.to_string()))
+    }
+}
+
+impl Handler<GetImageRefs> for HdMan {
+    type Result = result::Result<BTreeMap<String, usize>, Error>;
+
+    fn handle(&mut self, _msg: GetImageRefs, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(self
+            .image_refs()
+            .0
+            .into_iter()
+            .filter_map(|(image, refs)| Some((image_file_name(&image)?, refs)))
+            .collect())
+    }
+}
+
+fn image_file_name(image: &Path) -> Option<String> {
+    image
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+}
+
+/// Path of the cached image of given file name; `None` unless the name is a plain
+/// file name, so that no file outside of the cache can be given
+fn cached_image_path(cache_dir: &Path, file_name: &str) -> Option<PathBuf> {
+    let mut components = Path::new(file_name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => Some(cache_dir.join(file_name)),
+        _ => None,
+    }
+}
+
+impl Handler<EvictImages> for HdMan {
+    type Result = result::Result<Vec<String>, Error>;
+
+    fn handle(&mut self, msg: EvictImages, _ctx: &mut Self::Context) -> Self::Result {
+        let cache_dir = self.cache_dir.clone();
+        let images = msg
+            .images
+            .iter()
+            .filter_map(|name| {
+                let image = cached_image_path(&cache_dir, name);
+                if image.is_none() {
+                    warn!("image {} not evicted: not a cached image name", name);
+                }
+                image
+            })
+            .collect::<Vec<_>>();
+
+        Ok(self
+            .image_refs()
+            .evict(images)
+            .iter()
+            .filter_map(|image| image_file_name(image))
+            .collect())
+    }
+}
+
+impl Handler<PrefetchImage> for HdMan {
+    type Result = ActorResponse<HdMan, String, Error>;
+
+    fn handle(&mut self, msg: PrefetchImage, _ctx: &mut Self::Context) -> Self::Result {
+        if LocalImage::is_local_url(&msg.image.url) {
+            return ActorResponse::reply(Err(Error::IncorrectOptions(
+                "local images are not cached".into(),
+            )));
+        }
+        if let Err(e) = check_remote_image(&msg.image) {
+            return ActorResponse::reply(Err(e));
+        }
+
+        ActorResponse::r#async(
+            fetch_image(msg.image, self.cache_dir.clone())
+                .and_then(|cache_path| {
+                    image_file_name(&cache_path)
+                        .ok_or_else(|| Error::IoError("image cached without a file name".into()))
+                })
+                .into_actor(self),
+        )
+    }
+}
+
+impl Handler<status::GetEnvStatus> for HdMan {
+    type Result = MessageResult<status::GetEnvStatus>;
+
+    fn handle(
+        &mut self,
+        _msg: status::GetEnvStatus,
+        _ctx: &mut Self::Context,
+    ) -> <Self as Handler<status::GetEnvStatus>>::Result {
+        MessageResult(self.deploys.env_status())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::{BTreeMap, HashMap, HashSet},
+        fs,
+        path::Path,
+        process,
+    };
+
+    use gu_model::envman::{
+        Command, DrainSession, Error, EvictImages, GetCachedImages, GetImageRefs, GetNodeCapacity,
+        GetSession, GetSessionProcesses, GetSessionSize, IdleStatus, OnError, OutputEncoding,
+        PrefetchImage, ProcessStatus, ReadySignal, Secret, SessionSize, SessionUpdate,
+        TimedSessionUpdate,
+    };
+    use gu_net::rpc::{peer::PeerSessionStatus, PublicMessage};
+
+    use actix::prelude::*;
+    use actix_web::{test::TestServer, App, HttpMessage, HttpRequest, HttpResponse};
+    use futures::prelude::*;
+    use serde::{de::DeserializeOwned, Serialize};
+
+    use super::{
+        encode_output, expand_placeholders, write_secret, ChildProcess, HdMan, HdSessionInfo,
+        ImageRefs, MemoryBudget,
+    };
+    use super::{wait_for_file, wait_for_line};
+    use crate::deployment::{DeployManager, IntoDeployInfo};
+    use crate::envman::Shutdown;
+    use crate::provision::download;
+    use crate::workspace::{Workspace, WorkspacesManager, DEFAULT_LOG_RETENTION};
+
+    fn test_hdman(tmp: &Path) -> HdMan {
+        HdMan {
+            deploys: DeployManager::default(),
+            cache_dir: tmp.join("images"),
+            workspaces_man: WorkspacesManager::in_dir("hd", tmp.to_path_buf()),
+            upload_timeout: std::time::Duration::from_secs(1),
+            log_retention: DEFAULT_LOG_RETENTION,
+            memory_budget: MemoryBudget::default(),
+            local_image_root: None,
+        }
+    }
+
+    /// Test server exposing public messages of the HdMan made by `hd_man`,
+    /// the same way the provider serves them at `/m`
+    fn remote_hdman<F>(hd_man: F) -> TestServer
+    where
+        F: Fn() -> HdMan + Send + Clone + 'static,
+    {
+        use std::sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        };
+
+        // the factory runs on every worker, but one HdMan binds the messages
+        let started = Arc::new(AtomicBool::new(false));
+        TestServer::with_factory(move || {
+            if !started.swap(true, Ordering::SeqCst) {
+                gu_net::rpc::start_actor(hd_man());
+            }
+            App::new().scope("/m", gu_net::rpc::mock::scope)
+        })
+    }
+
+    /// Sends `msg` to its public destination, returning the handler result
+    fn call_remote<M>(srv: &mut TestServer, msg: &M) -> M::Result
+    where
+        M: PublicMessage + Serialize,
+        M::Result: DeserializeOwned,
+    {
+        let request = srv
+            .post()
+            .uri(srv.url(&format!("/m/{}", M::ID)))
+            .json(msg)
+            .unwrap();
+        let response = srv.execute(request.send()).unwrap();
+        assert!(response.status().is_success());
+
+        serde_json::from_slice(&srv.execute(response.body()).unwrap()).unwrap()
+    }
+
+    fn session(dir: &tempfile::TempDir) -> HdSessionInfo {
+        session_in(dir.path())
+    }
+
+    fn session_in(dir: &Path) -> HdSessionInfo {
+        HdSessionInfo {
+            workspace: Workspace::new("hd".into(), dir.to_path_buf()),
+            status: PeerSessionStatus::CREATED,
+            dirty: false,
+            note: None,
+            config_files: HashSet::new(),
+            processes: HashMap::new(),
+            waiters: HashMap::new(),
+            workspace_exists: true,
+            image: None,
+            cpu_affinity: Vec::new(),
+            size: None,
+            draining: false,
+        }
+    }
+
+    #[test]
+    fn test_tags_are_restored_after_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let tmp = dir.path().to_path_buf();
+        let mut workspace = WorkspacesManager::in_dir("hd", tmp.clone()).workspace();
+        workspace.set_session_id("tagged".into());
+        workspace.add_tags(vec!["created".into()]);
+        workspace.create_dirs().unwrap();
+        workspace.save().unwrap();
+
+        let mut deploys = DeployManager::default();
+        deploys.insert_deploy(
+            "tagged".to_string(),
+            HdSessionInfo {
+                workspace,
+                ..session(&dir)
+            },
+        );
+        let hd_man = HdMan {
+            deploys,
+            ..test_hdman(&tmp)
+        };
+        let add_tags = SessionUpdate {
+            session_id: "tagged".into(),
+            commands: vec![Command::AddTags(vec!["added".into()])],
+            on_error: OnError::default(),
+        };
+
+        System::run(move || {
+            Arbiter::spawn(
+                gu_net::rpc::start_actor(hd_man)
+                    .send(add_tags)
+                    .then(move |r| {
+                        assert!(r.unwrap().is_ok());
+                        // provider restarted with the same work dir
+                        gu_net::rpc::start_actor(test_hdman(&tmp)).send(GetSession {
+                            session_id: "tagged".into(),
+                        })
+                    })
+                    .then(|r| {
+                        let info = r.unwrap().unwrap();
+                        assert_eq!(info.tags, vec!["added".to_string(), "created".to_string()]);
+                        assert_eq!(info.status, PeerSessionStatus::CREATED);
+                        Ok(System::current().stop())
+                    }),
+            )
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_secret() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("token");
+        fs::write(&path, "stale").unwrap();
+
+        write_secret(&path, b"s3cr3t").unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "s3cr3t");
+
+        let command = Command::WriteSecret {
+            contents: Secret::new("s3cr3t"),
+            file_path: "token".into(),
+        };
+        assert!(!format!("{:?}", command).contains("s3cr3t"));
+    }
+
+    #[test]
+    fn test_workspace_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut session = session(&dir);
+
+        session.check_workspace();
+        assert!(session.convert(&"id".to_string()).workspace_exists);
+
+        fs::remove_dir_all(dir.path()).unwrap();
+        session.check_workspace();
+        assert!(!session.convert(&"id".to_string()).workspace_exists);
+    }
+
+    #[test]
+    fn test_wait_for_process() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut session = session(&dir);
+        let child = process::Command::new("sh")
+            .args(&["-c", "sleep 0.2; exit 3"])
+            .spawn()
+            .unwrap();
+        let child_id = session.insert_process(child);
+
+        let wait = session.wait_process(&child_id).unwrap();
+        while !session.processes.is_empty() {
+            session.collect_finished();
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+
+        assert_eq!(wait.wait(), Ok(Some(3)));
+        assert_eq!(session.status, PeerSessionStatus::CONFIGURED);
+        assert!(session.wait_process(&child_id).is_err());
+    }
+
+    #[test]
+    fn test_ready_file_is_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let ready = dir.path().join("ready");
+        let mut child = process::Command::new("sh")
+            .args(&["-c", "sleep 0.2; touch ready; sleep 10"])
+            .current_dir(dir.path())
+            .spawn()
+            .unwrap();
+
+        let reported = wait_for_file(ready.clone(), std::time::Duration::from_secs(5)).wait();
+        let _ = child.kill();
+        let _ = child.wait();
+
+        assert!(reported.unwrap());
+        assert!(
+            !wait_for_file(dir.path().join("missing"), Default::default())
+                .wait()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_ready_line_is_reported() {
+        let spawn = |script: &str| {
+            process::Command::new("sh")
+                .args(&["-c", script])
+                .stdout(process::Stdio::piped())
+                .spawn()
+                .unwrap()
+        };
+        let pattern = regex::Regex::new("^listening on [0-9]+$").unwrap();
+
+        let mut child = spawn("echo starting; sleep 0.1; echo listening on 8080; echo more");
+        let ready = wait_for_line(child.stdout.take().unwrap(), pattern.clone(), None);
+        assert!(ready.wait().unwrap());
+        child.wait().unwrap();
+
+        let mut child = spawn("echo starting; exit 1");
+        let ready = wait_for_line(child.stdout.take().unwrap(), pattern, None);
+        assert!(!ready.wait().unwrap());
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn test_exec_marking_session_clean() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut session = session(&dir);
+
+        session.exec_finished(false);
+        assert_eq!(session.status, PeerSessionStatus::CONFIGURED);
+
+        session.exec_finished(true);
+        assert_eq!(session.status, PeerSessionStatus::CREATED);
+        assert!(!session.dirty);
+
+        let child = process::Command::new("sleep").arg("10").spawn().unwrap();
+        let child_id = session.insert_process(child);
+        session.exec_finished(true);
+        assert_eq!(session.status, PeerSessionStatus::RUNNING);
+
+        let mut process = session.processes.remove(&child_id).unwrap();
+        let _ = process.child.kill();
+        let _ = process.child.wait();
+        assert_eq!(session.idle_status(), PeerSessionStatus::CREATED);
+    }
+
+    #[test]
+    fn test_exec_args_expand_session_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let session = session(&dir);
+        let session_dir = session.workspace.path().to_string_lossy().into_owned();
+        let mut vars = BTreeMap::new();
+        vars.insert("SESSION_DIR".to_string(), session_dir.clone());
+        vars.insert("NAME".to_string(), "out".to_string());
+
+        let args = expand_placeholders(
+            vec!["${SESSION_DIR}/${NAME}.txt".into(), "${OTHER}-${".into()],
+            &vars,
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            args,
+            vec![format!("{}/out.txt", session_dir), "${OTHER}-${".into()]
+        );
+
+        let output = process::Command::new("echo")
+            .args(&args[..1])
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout).trim_end(),
+            format!("{}/out.txt", session_dir)
+        );
+
+        assert!(expand_placeholders(vec!["${OTHER}".into()], &vars, true).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_start_args_expand_session_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut deploys = DeployManager::default();
+        deploys.insert_deploy("touching".to_string(), session(&dir));
+        let hd_man = HdMan {
+            deploys,
+            ..test_hdman(dir.path())
+        };
+        let started = dir.path().join("started");
+
+        System::run(move || {
+            Arbiter::spawn(
+                gu_net::rpc::start_actor(hd_man)
+                    .send(SessionUpdate {
+                        session_id: "touching".into(),
+                        commands: vec![Command::Start {
+                            executable: "touch".into(),
+                            args: vec!["${SESSION_DIR}/started".into()],
+                            ready_signal: Some(ReadySignal::File("started".into())),
+                            ready_timeout: Some(5),
+                        }],
+                        on_error: OnError::StopChain,
+                    })
+                    .then(move |r| {
+                        assert!(r.unwrap().is_ok());
+                        assert!(started.exists());
+                        Ok(System::current().stop())
+                    }),
+            )
+        });
+    }
+
+    #[test]
+    fn test_process_infos_have_distinct_pids() {
+        let mut processes: Vec<ChildProcess> = (0..2)
+            .map(|_| ChildProcess::new(process::Command::new("sleep").arg("10").spawn().unwrap()))
+            .collect();
+
+        let infos: Vec<_> = processes
+            .iter_mut()
+            .enumerate()
+            .map(|(i, process)| process.info(&i.to_string()))
+            .collect();
+
+        for process in processes.iter_mut() {
+            let _ = process.child.kill();
+            let _ = process.child.wait();
+        }
+
+        assert_eq!(infos.len(), 2);
+        assert!(infos
+            .iter()
+            .all(|info| info.status == ProcessStatus::Running));
+        let pids: HashSet<u32> = infos.iter().map(|info| info.pid).collect();
+        assert_eq!(pids.len(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_remote_session_processes() {
+        let dir = tempfile::tempdir().unwrap();
+        let tmp = dir.path().to_path_buf();
+        let mut srv = remote_hdman(move || {
+            let mut session = session_in(&tmp);
+            let child = process::Command::new("sleep").arg("10").spawn().unwrap();
+            session.insert_process_as("sleeper".into(), child);
+            let mut deploys = DeployManager::default();
+            deploys.insert_deploy("busy".into(), session);
+            HdMan {
+                deploys,
+                ..test_hdman(&tmp)
+            }
+        });
+
+        let infos = call_remote(
+            &mut srv,
+            &GetSessionProcesses {
+                session_id: "hd::busy".into(),
+            },
+        )
+        .unwrap();
+        let missing = call_remote(
+            &mut srv,
+            &GetSessionProcesses {
+                session_id: "hd::missing".into(),
+            },
+        );
+        for info in &infos {
+            let _ = process::Command::new("kill")
+                .arg(info.pid.to_string())
+                .status();
+        }
+
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].child_id, "sleeper");
+        assert_eq!(infos[0].status, ProcessStatus::Running);
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn test_image_used_by_session_is_not_evicted() {
+        let dir = tempfile::tempdir().unwrap();
+        let used = dir.path().join("SHA1---used");
+        let unused = dir.path().join("SHA1---unused");
+        fs::write(&used, b"used").unwrap();
+        fs::write(&unused, b"unused").unwrap();
+
+        let refs = ImageRefs::from_images(vec![used.as_path()]);
+        assert_eq!(refs.count(&used), 1);
+        assert_eq!(refs.count(&unused), 0);
+
+        assert_eq!(
+            refs.evict(vec![used.clone(), unused.clone()]),
+            vec![unused.clone()]
+        );
+        assert!(used.exists());
+        assert!(!unused.exists());
+    }
+
+    #[test]
+    fn test_remote_eviction_is_limited_to_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_dir = dir.path().join("images");
+        fs::create_dir(&cache_dir).unwrap();
+        let used = cache_dir.join("SHA1---used");
+        let unused = cache_dir.join("SHA1---unused");
+        let outside = dir.path().join("outside");
+        for path in &[&used, &unused, &outside] {
+            fs::write(path, b"image").unwrap();
+        }
+
+        let tmp = dir.path().to_path_buf();
+        let used_image = used.clone();
+        let mut srv = remote_hdman(move || {
+            let mut session = session_in(&tmp);
+            session.image = Some(used_image.clone());
+            let mut deploys = DeployManager::default();
+            deploys.insert_deploy("used".into(), session);
+            HdMan {
+                deploys,
+                ..test_hdman(&tmp)
+            }
+        });
+
+        let image_refs = |env_type: &str| GetImageRefs {
+            env_type: env_type.into(),
+        };
+        let refs: BTreeMap<_, _> = vec![("SHA1---used".to_string(), 1)].into_iter().collect();
+        assert_eq!(call_remote(&mut srv, &image_refs("hd")).unwrap(), refs);
+        assert!(call_remote(&mut srv, &image_refs("unknown")).is_err());
+
+        let evicted = call_remote(
+            &mut srv,
+            &EvictImages {
+                env_type: "hd".into(),
+                images: vec![
+                    "SHA1---used".into(),
+                    "SHA1---unused".into(),
+                    "../outside".into(),
+                    outside.display().to_string(),
+                ],
+            },
+        );
+        assert_eq!(evicted.unwrap(), vec!["SHA1---unused".to_string()]);
+        assert!(used.exists());
+        assert!(!unused.exists());
+        assert!(outside.exists());
+    }
+
+    #[test]
+    fn test_downloaded_image_is_listed() {
+        let mut srv =
+            TestServer::new(|app| app.handler(|_: &HttpRequest| HttpResponse::Ok().body("image")));
+        let dir = tempfile::tempdir().unwrap();
+        let image = dir.path().join("SHA1---downloaded");
+        fs::create_dir(dir.path().join("images")).unwrap();
+
+        srv.execute(download(&srv.url("/image"), image.clone(), false))
+            .unwrap();
+
+        let images = ImageRefs::from_images(vec![image.as_path()])
+            .list(dir.path())
+            .unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].file_name, "SHA1---downloaded");
+        assert!(images[0].size > 0);
+        assert!(images[0].in_use);
+        assert!(!ImageRefs::default().list(dir.path()).unwrap()[0].in_use);
+    }
+
+    #[test]
+    fn test_remote_cached_images() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_dir = dir.path().join("images");
+        fs::create_dir(&cache_dir).unwrap();
+        let used = cache_dir.join("SHA1---used");
+        fs::write(&used, b"used").unwrap();
+        fs::write(cache_dir.join("SHA1---unused"), b"unused").unwrap();
+
+        let tmp = dir.path().to_path_buf();
+        let mut srv = remote_hdman(move || {
+            let mut session = session_in(&tmp);
+            session.image = Some(used.clone());
+            let mut deploys = DeployManager::default();
+            deploys.insert_deploy("used".into(), session);
+            HdMan {
+                deploys,
+                ..test_hdman(&tmp)
+            }
+        });
+
+        let images = call_remote(
+            &mut srv,
+            &GetCachedImages {
+                env_type: "hd".into(),
+            },
+        )
+        .unwrap();
+        let listed: Vec<_> = images
+            .iter()
+            .map(|image| (image.file_name.as_str(), image.size, image.in_use))
+            .collect();
+        assert_eq!(
+            listed,
+            vec![("SHA1---unused", 6, false), ("SHA1---used", 4, true)]
+        );
+    }
+
+    #[test]
+    fn test_prefetched_image_is_not_downloaded_again() {
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+
+        use flate2::{write::GzEncoder, Compression};
+        use sha1::Digest;
+
+        use gu_model::envman::{CreateSession, Image};
+
+        let mut archive = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        let content = b"prefetched";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        archive
+            .append_data(&mut header, "hello.txt", &content[..])
+            .unwrap();
+        let image_bytes = archive.into_inner().unwrap().finish().unwrap();
+        let hash: String = sha1::Sha1::digest(&image_bytes)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        let downloads = Arc::new(AtomicUsize::new(0));
+        let server_downloads = downloads.clone();
+        let srv = TestServer::new(move |app| {
+            let downloads = server_downloads.clone();
+            let image_bytes = image_bytes.clone();
+            app.handler(move |_: &HttpRequest| {
+                downloads.fetch_add(1, Ordering::SeqCst);
+                HttpResponse::Ok().body(image_bytes.clone())
+            })
+        });
+        let image = Image {
+            url: srv.url("/image.hdi"),
+            hash: format!("SHA1:{}", hash).into(),
+            cache_name: Some("prefetch-test".into()),
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("images")).unwrap();
+        let tmp = dir.path().to_path_buf();
+        let mut hd_srv = remote_hdman(move || test_hdman(&tmp));
+        // sessions are created through the environment manager, as by the hub
+        let create = CreateSession {
+            env_type: "hd".into(),
+            image: image.clone(),
+            name: "prefetched".into(),
+            tags: Vec::new(),
+            note: None,
+            cpu_affinity: Vec::new(),
+            options: serde_json::Value::Null,
+        };
+
+        let prefetch = PrefetchImage {
+            env_type: "hd".into(),
+            image,
+        };
+        let file_name = call_remote(&mut hd_srv, &prefetch).unwrap();
+        let session_id = call_remote(&mut hd_srv, &create);
+
+        assert!(dir.path().join("images").join(file_name).exists());
+        assert!(session_id.is_ok());
+        assert_eq!(downloads.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_node_capacity_reports_memory() {
+        let dir = tempfile::tempdir().unwrap();
+        let tmp = dir.path().to_path_buf();
+        let mut srv = remote_hdman(move || test_hdman(&tmp));
+
+        let capacity = call_remote(&mut srv, &GetNodeCapacity).unwrap();
+        assert!(capacity.total_memory > 0);
+        assert!(capacity.num_cores > 0);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_shutdown_destroys_all_sessions() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut deploys = DeployManager::default();
+        let mut pids = Vec::new();
+        let mut session_dirs = Vec::new();
+
+        for id in &["first", "second"] {
+            let session_dir = dir.path().join(id);
+            fs::create_dir(&session_dir).unwrap();
+            let mut session = HdSessionInfo {
+                workspace: Workspace::new("hd".into(), session_dir.clone()),
+                ..session(&dir)
+            };
+            let child = process::Command::new("sleep").arg("10").spawn().unwrap();
+            pids.push(child.id());
+            session.insert_process(child);
+
+            deploys.insert_deploy(id.to_string(), session);
+            session_dirs.push(session_dir);
+        }
+
+        let hd_man = HdMan {
+            deploys,
+            ..test_hdman(dir.path())
+        };
+
+        System::run(move || {
+            Arbiter::spawn(
+                gu_net::rpc::start_actor(hd_man)
+                    .send(Shutdown)
+                    .then(move |r| {
+                        assert_eq!(r.unwrap(), Ok(2));
+                        for pid in pids {
+                            // reaped children no longer exist, not even as zombies
+                            assert_ne!(unsafe { libc::kill(pid as libc::pid_t, 0) }, 0);
+                        }
+                        assert!(session_dirs.iter().all(|dir| !dir.exists()));
+                        Ok(System::current().stop())
+                    }),
+            )
+        });
+    }
+
+    #[test]
+    fn test_continue_on_error_runs_remaining_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let session_dir = dir.path().join("batch");
+        fs::create_dir(&session_dir).unwrap();
+        let mut deploys = DeployManager::default();
+        deploys.insert_deploy(
+            "batch".to_string(),
+            HdSessionInfo {
+                workspace: Workspace::new("hd".into(), session_dir),
+                ..session(&dir)
+            },
+        );
+        let hd_man = HdMan {
+            deploys,
+            ..test_hdman(dir.path())
+        };
+        let update = |on_error| SessionUpdate {
+            session_id: "batch".into(),
+            commands: vec![
+                Command::Open,
+                Command::Wait {
+                    child_id: Some("missing".into()),
+                    timeout: None,
+                },
+                Command::AddTags(vec!["after".into()]),
+            ],
+            on_error,
+        };
+
+        System::run(move || {
+            let hd_man = gu_net::rpc::start_actor(hd_man);
+            Arbiter::spawn(
+                hd_man
+                    .send(update(OnError::StopChain))
+                    .join(hd_man.send(update(OnError::Continue)))
+                    .then(|r| {
+                        let (stopped, continued) = r.unwrap();
+                        assert_eq!(stopped.unwrap_err().len(), 2);
+
+                        let outcomes = continued.unwrap_err();
+                        assert_eq!(outcomes.len(), 3);
+                        assert_eq!(outcomes[0], "Open mock");
+                        assert!(outcomes[2].contains("after"));
+                        Ok(System::current().stop())
+                    }),
+            )
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_duration_is_reported() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("nap.sh");
+        fs::write(&script, "#!/bin/sh\nsleep 0.3\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let tmp = dir.path().to_path_buf();
+        let mut srv = remote_hdman(move || {
+            let mut deploys = DeployManager::default();
+            deploys.insert_deploy("timed".into(), session_in(&tmp));
+            HdMan {
+                deploys,
+                ..test_hdman(&tmp)
+            }
+        });
+
+        let timed = call_remote(
+            &mut srv,
+            &TimedSessionUpdate(SessionUpdate {
+                session_id: "hd::timed".into(),
+                commands: vec![Command::Exec {
+                    executable: "nap.sh".into(),
+                    args: Vec::new(),
+                    working_dir: None,
+                    mark_clean: false,
+                    env: BTreeMap::new(),
+                    strict_env: false,
+                    output_encoding: OutputEncoding::default(),
+                }],
+                on_error: OnError::StopChain,
+            }),
+        )
+        .unwrap();
+
+        assert_eq!(timed.outputs.len(), 1);
+        assert_eq!(timed.durations.len(), 1);
+        assert!(timed.durations[0] >= std::time::Duration::from_millis(300));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_drained_child_exits_on_its_own() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut session = session(&dir);
+        let child = process::Command::new("sh")
+            .arg("-c")
+            .arg("trap 'exit 0' TERM; while true; do sleep 0.1; done")
+            .spawn()
+            .unwrap();
+        let child_id = session.insert_process(child);
+        // let the shell install its trap
+        std::thread::sleep(std::time::Duration::from_millis(200));
+
+        let mut deploys = DeployManager::default();
+        deploys.insert_deploy("draining".to_string(), session);
+        let hd_man = HdMan {
+            deploys,
+            ..test_hdman(dir.path())
+        };
+
+        System::run(move || {
+            let hd_man = gu_net::rpc::start_actor(hd_man);
+            Arbiter::spawn(
+                hd_man
+                    .send(SessionUpdate {
+                        session_id: "draining".into(),
+                        commands: vec![Command::Wait {
+                            child_id: Some(child_id),
+                            timeout: None,
+                        }],
+                        on_error: OnError::StopChain,
+                    })
+                    .join(hd_man.send(DrainSession {
+                        session_id: "draining".into(),
+                        timeout: 5,
+                    }))
+                    .and_then(move |(waited, drained)| {
+                        // exit code of the trap, not of a killing signal
+                        assert_eq!(waited, Ok(vec!["0".to_string()]));
+                        assert_eq!(drained.unwrap(), 0);
+
+                        hd_man.send(SessionUpdate {
+                            session_id: "draining".into(),
+                            commands: vec![Command::Exec {
+                                executable: "true".into(),
+                                args: Vec::new(),
+                                working_dir: None,
+                                mark_clean: false,
+                                env: BTreeMap::new(),
+                                strict_env: false,
+                                output_encoding: OutputEncoding::default(),
+                            }],
+                            on_error: OnError::StopChain,
+                        })
+                    })
+                    .then(|r| {
+                        assert_eq!(
+                            r.unwrap(),
+                            Err(vec![Error::SessionDraining("draining".into()).to_string()])
+                        );
+                        Ok(System::current().stop())
+                    }),
+            )
+        });
+    }
+
+    /// Reports the same memory usage for every process
+    struct FixedRss(u64);
+
+    impl gu_hardware::process::ProcessMemorySource for FixedRss {
+        fn rss(&mut self, _pid: u32) -> Option<u64> {
+            Some(self.0)
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_start_rejected_past_memory_budget() {
+        let budget = |rss| MemoryBudget {
+            limit: Some(100),
+            child_reserve: 20,
+            source: Box::new(FixedRss(rss)),
+        };
+        assert!(budget(60).check(&[1]).is_ok());
+        assert!(budget(80).check(&[1]).is_ok());
+        assert!(budget(81).check(&[1]).is_err());
+        assert!(budget(40).check(&[1, 2]).is_ok());
+        assert!(budget(41).check(&[1, 2]).is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let session_dir = dir.path().join("busy");
+        fs::create_dir(&session_dir).unwrap();
+        let mut session = HdSessionInfo {
+            workspace: Workspace::new("hd".into(), session_dir),
+            ..session(&dir)
+        };
+        session.insert_process(process::Command::new("sleep").arg("10").spawn().unwrap());
+        let mut deploys = DeployManager::default();
+        deploys.insert_deploy("busy".to_string(), session);
+        deploys.insert_deploy(
+            "draining".to_string(),
+            HdSessionInfo {
+                draining: true,
+                ..self::session(&dir)
+            },
+        );
+        let hd_man = HdMan {
+            deploys,
+            memory_budget: budget(200),
+            ..test_hdman(dir.path())
+        };
+
+        let start = |session_id: &str| SessionUpdate {
+            session_id: session_id.into(),
+            commands: vec![Command::Start {
+                executable: "sleep".into(),
+                args: vec!["10".into()],
+                ready_signal: None,
+                ready_timeout: None,
+            }],
+            on_error: OnError::StopChain,
+        };
+
+        System::run(move || {
+            let hd_man = gu_net::rpc::start_actor(hd_man);
+            Arbiter::spawn(
+                hd_man
+                    .send(start("busy"))
+                    .and_then(move |r| {
+                        assert_eq!(r, Err(vec![Error::MemoryBudgetExceeded(100).to_string()]));
+                        // a draining session is rejected before the budget is checked
+                        hd_man.send(start("draining"))
+                    })
+                    .then(|r| {
+                        assert_eq!(
+                            r.unwrap(),
+                            Err(vec![Error::SessionDraining("draining".into()).to_string()])
+                        );
+                        Ok(System::current().stop())
+                    }),
+            )
+        });
+    }
+
+    #[test]
+    fn test_ready_file_outside_session_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut deploys = DeployManager::default();
+        deploys.insert_deploy("ready".to_string(), session(&dir));
+        let hd_man = HdMan {
+            deploys,
+            ..test_hdman(dir.path())
+        };
+
+        System::run(move || {
+            let hd_man = gu_net::rpc::start_actor(hd_man);
+            Arbiter::spawn(
+                hd_man
+                    .send(SessionUpdate {
+                        session_id: "ready".into(),
+                        commands: vec![Command::Start {
+                            executable: "sleep".into(),
+                            args: vec!["10".into()],
+                            ready_signal: Some(ReadySignal::File("../ready".into())),
+                            ready_timeout: None,
+                        }],
+                        on_error: OnError::StopChain,
+                    })
+                    .and_then(move |r| {
+                        assert!(r.is_err());
+                        hd_man.send(GetSessionProcesses {
+                            session_id: "ready".into(),
+                        })
+                    })
+                    .then(|r| {
+                        assert!(r.unwrap().unwrap().is_empty());
+                        Ok(System::current().stop())
+                    }),
+            )
+        });
+    }
+
+    #[test]
+    fn test_finished_child_logs_are_pruned() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut session = session(&dir);
+        let child = process::Command::new("sleep").arg("10").spawn().unwrap();
+        let running = session.insert_process(child);
+
+        let logs = session.workspace.logs_dir();
+        fs::create_dir(&logs).unwrap();
+        for child_id in &["finished", running.as_str()] {
+            fs::write(logs.join(format!("{}.log", child_id)), "output").unwrap();
+        }
+        fs::write(logs.join("notes.txt"), "kept").unwrap();
+
+        let mut deploys = DeployManager::default();
+        deploys.insert_deploy("logs".to_string(), session);
+        let mut hd_man = HdMan {
+            deploys,
+            ..test_hdman(dir.path())
+        };
+
+        hd_man.scan_for_processes();
+        assert!(logs.join("finished.log").exists());
+
+        hd_man.log_retention = std::time::Duration::from_secs(0);
+        hd_man.scan_for_processes();
+        assert!(!logs.join("finished.log").exists());
+        assert!(logs.join(format!("{}.log", running)).exists());
+        assert!(logs.join("notes.txt").exists());
+
+        let session = hd_man.deploys.deploy_mut("logs").unwrap();
+        for process in session.processes.values_mut() {
+            let _ = process.child.kill();
+            let _ = process.child.wait();
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_started_child_output_is_logged() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("chatty.sh");
+        fs::write(&script, "#!/bin/sh\necho out\necho err >&2\n").unwrap();
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        let logs = dir.path().join("logs");
+
+        let mut deploys = DeployManager::default();
+        deploys.insert_deploy("chatty".to_string(), session(&dir));
+        let hd_man = HdMan {
+            deploys,
+            ..test_hdman(dir.path())
+        };
+        let update = |command| SessionUpdate {
+            session_id: "chatty".into(),
+            commands: vec![command],
+            on_error: OnError::StopChain,
+        };
+
+        System::run(move || {
+            let hd_man = gu_net::rpc::start_actor(hd_man);
+            Arbiter::spawn(
+                hd_man
+                    .send(update(Command::Start {
+                        executable: "chatty.sh".into(),
+                        args: Vec::new(),
+                        ready_signal: None,
+                        ready_timeout: None,
+                    }))
+                    .and_then(move |started| {
+                        let child_id = started.unwrap().remove(0);
+                        hd_man
+                            .send(update(Command::Wait {
+                                child_id: Some(child_id.clone()),
+                                timeout: Some(5),
+                            }))
+                            .map(|waited| (child_id, waited))
+                    })
+                    .then(move |r| {
+                        let (child_id, waited) = r.unwrap();
+                        assert_eq!(waited, Ok(vec!["0".to_string()]));
+                        let log = fs::read_to_string(logs.join(format!("{}.log", child_id)));
+                        assert_eq!(log.unwrap(), "out\nerr\n");
+                        Ok(System::current().stop())
+                    }),
+            )
+        });
+    }
+
+    #[test]
+    fn test_base64_output_keeps_invalid_utf8() {
+        let output = process::Command::new("printf")
+            .arg("a\\377\\376b")
+            .output()
+            .unwrap();
+        let bytes = vec![b'a', 0xff, 0xfe, b'b'];
+        assert_eq!(output.stdout, bytes);
+
+        let encoded = encode_output(&output.stdout, OutputEncoding::Base64);
+        assert_eq!(base64::decode(&encoded).unwrap(), bytes);
+        assert_eq!(
+            encode_output(&output.stdout, OutputEncoding::Utf8Lossy),
+            "a\u{fffd}\u{fffd}b"
+        );
+    }
+
+    #[test]
+    fn test_session_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let session_dir = dir.path().join("sized");
+        fs::create_dir_all(session_dir.join("sub")).unwrap();
+        fs::write(session_dir.join("a"), b"12345").unwrap();
+        fs::write(session_dir.join("sub/b"), b"123").unwrap();
+
+        let tmp = dir.path().to_path_buf();
+        let workspace_dir = session_dir.clone();
+        let mut srv = remote_hdman(move || {
+            let mut deploys = DeployManager::default();
+            deploys.insert_deploy(
+                "sized".to_string(),
+                HdSessionInfo {
+                    workspace: Workspace::new("hd".into(), workspace_dir.clone()),
+                    ..session_in(&tmp)
+                },
+            );
+            HdMan {
+                deploys,
+                ..test_hdman(&tmp)
+            }
+        });
+        let mut size = |session_id: &str| {
+            call_remote(
+                &mut srv,
+                &GetSessionSize {
+                    session_id: session_id.into(),
+                },
+            )
+        };
+        let expected = SessionSize { bytes: 8, files: 2 };
+
+        assert_eq!(size("hd::sized").unwrap(), expected);
+        assert!(size("hd::unknown").is_err());
+        // a recent result is reported without walking the workspace again
+        fs::write(session_dir.join("c"), b"1").unwrap();
+        assert_eq!(size("hd::sized").unwrap(), expected);
+    }
+
+    #[test]
+    fn test_reset_stuck_session_status() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut session = session(&dir);
+
+        // finished, but not yet collected by the scan
+        let child = process::Command::new("true").spawn().unwrap();
+        session.insert_process(child);
+        let process = session.processes.values_mut().next().unwrap();
+        process.child.wait().unwrap();
+        assert_eq!(session.status, PeerSessionStatus::RUNNING);
+
+        session.reset_status(IdleStatus::Configured).unwrap();
+        assert_eq!(session.status, PeerSessionStatus::CONFIGURED);
+        assert!(session.processes.is_empty());
+
+        let child = process::Command::new("sleep").arg("10").spawn().unwrap();
+        let child_id = session.insert_process(child);
+        assert!(session.reset_status(IdleStatus::Created).is_err());
+        assert_eq!(session.status, PeerSessionStatus::RUNNING);
+
+        let mut process = session.processes.remove(&child_id).unwrap();
+        let _ = process.child.kill();
+        let _ = process.child.wait();
+
+        session.status = PeerSessionStatus::DESTROYING;
+        assert!(session.reset_status(IdleStatus::Created).is_err());
     }
 }
@@ -8,6 +8,8 @@ use gu_base::*;
 mod connect;
 mod deployment;
 pub mod envman;
+#[cfg(feature = "testing")]
+mod fakeman;
 mod fchain;
 #[cfg(feature = "env-hd")]
 mod hdman;
@@ -1,20 +1,31 @@
 use std::{
-    fs,
+    collections::HashSet,
+    fs, io,
     path::{Path, PathBuf},
+    str::FromStr,
     time,
 };
 
 use actix_web::client::ClientResponse;
-use actix_web::http::header;
+use actix_web::http::{header, Uri};
 use actix_web::HttpMessage;
-use futures::{future, prelude::*};
-use log::{debug, info};
+use futures::{
+    future::{self, Loop},
+    prelude::*,
+};
+use log::{debug, error, info};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 use gu_actix::{async_result, async_try};
 use gu_base::files::read_async;
-use gu_base::files::{untgz_async, write_async};
-use gu_model::envman::ResourceFormat;
+use gu_base::files::{untgz_async, untgz_async_with_progress, write_async};
+use gu_model::envman::{ArchiveFormat, Error, ImageHash, ResourceFormat};
+
+use crate::status::ProgressReporter;
 
+/// Downloads `url` to `output_path`; `Tar` and `TarGz` archives are extracted
+/// into `output_path` directory.
 pub fn download_step(
     url: &str,
     output_path: PathBuf,
@@ -29,7 +40,7 @@ pub fn download_step(
 
     let dir_name = match format {
         ResourceFormat::Raw => output_path.parent().unwrap(),
-        ResourceFormat::Tar => output_path.as_ref(),
+        ResourceFormat::Tar | ResourceFormat::TarGz => output_path.as_ref(),
     };
 
     if !dir_name.exists() {
@@ -40,13 +51,29 @@ pub fn download_step(
         client_request
             .send()
             .map_err(|e| format!("send download request: {}", e))
-            .and_then(move |resp| {
+            .and_then(move |resp| -> Box<dyn Future<Item = (), Error = String>> {
                 match format {
-                    ResourceFormat::Raw => future::Either::A(
+                    ResourceFormat::Raw => Box::new(
                         write_async(resp.payload(), output_path)
                             .map_err(|_| "writing downloaded file failed".to_string()),
                     ),
-                    ResourceFormat::Tar => future::Either::B(
+                    // gzip stream is not seekable; archive is extracted once fully downloaded
+                    ResourceFormat::TarGz => {
+                        let archive_path =
+                            output_path.with_file_name(format!(".{}.tar.gz", Uuid::new_v4()));
+                        let cleanup_path = archive_path.clone();
+
+                        Box::new(
+                            write_async(resp.payload(), archive_path.clone())
+                                .map_err(|_| "writing downloaded archive failed".to_string())
+                                .and_then(move |_| untgz_async(archive_path, output_path))
+                                .then(move |r| {
+                                    let _ = fs::remove_file(cleanup_path);
+                                    r
+                                }),
+                        )
+                    }
+                    ResourceFormat::Tar => Box::new(
                         full::decode_tar(resp.payload())
                             .map_err(|e| format!("tar: {}", e))
                             .for_each(move |entry| {
@@ -80,28 +107,86 @@ pub fn download_step(
     )
 }
 
+/// Used when the provider config does not override it
+pub const DEFAULT_UPLOAD_TIMEOUT: time::Duration = time::Duration::from_secs(300);
+
+/// Largest session directory accepted by `export_step`, in bytes
+pub const MAX_EXPORT_SIZE: u64 = 1024 * 1024 * 1024;
+
 pub fn upload_step(
     url: &str,
     input_path: PathBuf,
     format: ResourceFormat,
+    timeout: time::Duration,
 ) -> impl Future<Item = String, Error = String> {
-    use actix_web::{client, error::ErrorInternalServerError};
-
     debug!(
         "streaming from {:?} to {} format: {:?}",
         &input_path, url, format
     );
     let source_stream: Box<dyn Stream<Item = bytes::Bytes, Error = String>> = match format {
         ResourceFormat::Tar => Box::new(stream_tar(input_path)),
+        ResourceFormat::TarGz => Box::new(stream_archive(input_path, ArchiveFormat::TarGz)),
         ResourceFormat::Raw => Box::new(stream_raw(input_path)),
     };
+
+    upload_stream(url, format.content_type(), source_stream, timeout)
+}
+
+/// Archives whole directory and uploads it; fails if its content exceeds `MAX_EXPORT_SIZE`
+pub fn export_step(
+    url: &str,
+    input_path: PathBuf,
+    format: ArchiveFormat,
+    timeout: time::Duration,
+) -> impl Future<Item = String, Error = String> {
+    let size = async_try!(dir_size(&input_path).map_err(|e| format!("export: {}", e))).bytes;
+    if size > MAX_EXPORT_SIZE {
+        return future::Either::B(future::err(format!(
+            "workspace too large to export: {} bytes (max {})",
+            size, MAX_EXPORT_SIZE
+        )));
+    }
+
+    debug!(
+        "exporting {:?} to {} format: {:?}",
+        &input_path, url, format
+    );
+    future::Either::A(upload_stream(
+        url,
+        format.content_type(),
+        stream_archive(input_path, format),
+        timeout,
+    ))
+}
+
+fn upload_stream<S>(
+    url: &str,
+    content_type: &'static str,
+    source_stream: S,
+    timeout: time::Duration,
+) -> impl Future<Item = String, Error = String>
+where
+    S: Stream<Item = bytes::Bytes, Error = String> + 'static,
+{
+    use actix_web::{
+        client::{self, SendRequestError},
+        error::ErrorInternalServerError,
+    };
+
     let url_desc = url.to_owned();
 
     future::result(
-        client::put(url).streaming(source_stream.map_err(|x| ErrorInternalServerError(x))),
+        client::put(url)
+            .header(header::CONTENT_TYPE, content_type)
+            .streaming(source_stream.map_err(|x| ErrorInternalServerError(x))),
     )
     .map_err(|e| e.to_string())
-    .and_then(|req| req.send().map_err(|e| e.to_string()))
+    .and_then(move |req| {
+        req.send().timeout(timeout).map_err(move |e| match e {
+            SendRequestError::Timeout => Error::UploadTimeout(timeout.as_secs()).to_string(),
+            e => e.to_string(),
+        })
+    })
     .and_then(move |res| {
         if res.status().is_success() {
             Ok(format!("{:?} file uploaded", url_desc))
@@ -112,28 +197,75 @@ pub fn upload_step(
 }
 
 pub fn stream_tar(input_path: PathBuf) -> impl Stream<Item = bytes::Bytes, Error = String> {
+    stream_archive(input_path, ArchiveFormat::Tar)
+}
+
+fn stream_archive(
+    input_path: PathBuf,
+    format: ArchiveFormat,
+) -> impl Stream<Item = bytes::Bytes, Error = String> {
+    use flate2::{write::GzEncoder, Compression};
     use gu_actix::pipe;
     use std::thread;
     use tar::Builder;
 
-    let (tx, rx) = pipe::sync_to_async(5);
+    fn build_tar<W: io::Write>(out: W, input_path: &Path) -> io::Result<W> {
+        let mut builder = Builder::new(out);
+        builder.append_dir_all(".", input_path)?;
+        builder.into_inner()
+    }
+
+    let (mut tx, rx) = pipe::sync_to_async::<bytes::Bytes, io::Error>(5);
 
     thread::spawn(move || {
-        let mut builder = Builder::new(tx);
-        builder
-            .append_dir_all(".", &input_path)
-            .unwrap_or_else(|e| {
-                panic!("Error while building the archive: {}", e);
-            });
-        builder.finish().unwrap();
+        let archive = match format {
+            ArchiveFormat::Tar => build_tar(&mut tx, &input_path).map(|_| ()),
+            ArchiveFormat::TarGz => {
+                build_tar(GzEncoder::new(&mut tx, Compression::default()), &input_path)
+                    .and_then(GzEncoder::finish)
+                    .map(|_| ())
+            }
+        };
+        // the stream fails with the error instead of ending as a truncated archive
+        if let Err(e) = archive {
+            let _ = tx.send(Err(e));
+        }
     });
 
     rx.map_err(|e| {
-        eprintln!("error={}", e);
+        error!("cannot build archive: {}", e);
         e.to_string()
     })
 }
 
+/// Disk usage of a directory tree
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct DirSize {
+    pub bytes: u64,
+    pub files: u64,
+}
+
+/// Sums sizes of all files under `dir`; symlinks are counted, but not followed.
+pub fn dir_size(dir: &Path) -> io::Result<DirSize> {
+    let mut size = DirSize::default();
+    let mut dirs = vec![dir.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let metadata = entry.path().symlink_metadata()?;
+            if metadata.is_dir() {
+                dirs.push(entry.path());
+            } else {
+                size.bytes += metadata.len();
+                size.files += 1;
+            }
+        }
+    }
+    Ok(size)
+}
+
 fn stream_raw(input_path: PathBuf) -> impl Stream<Item = bytes::Bytes, Error = String> {
     read_async(input_path)
 }
@@ -161,14 +293,54 @@ where
         })
 }
 
-// TODO: support redirect
+/// Maximum number of redirects followed by `download`
+const MAX_REDIRECTS: usize = 5;
+
+/// Resolves value of the `Location` header against the url that returned it.
+fn resolve_location(base: &str, location: &str) -> Result<String, String> {
+    let location_uri: Uri = location
+        .parse()
+        .map_err(|e| format!("invalid redirect location {}: {}", location, e))?;
+    if location_uri.scheme_part().is_some() {
+        return Ok(location.to_string());
+    }
+
+    let base_uri: Uri = base
+        .parse()
+        .map_err(|e| format!("invalid url {}: {}", base, e))?;
+    match (base_uri.scheme_part(), base_uri.authority_part()) {
+        (Some(scheme), Some(authority)) if location.starts_with('/') => {
+            Ok(format!("{}://{}{}", scheme, authority, location))
+        }
+        _ => Err(format!("unsupported redirect location: {}", location)),
+    }
+}
+
+fn check_redirect(from: &str, to: &str, visited: &HashSet<String>) -> Result<(), String> {
+    if visited.contains(to) {
+        Err(format!(
+            "redirect loop detected: {} was already visited",
+            to
+        ))
+    } else if visited.len() > MAX_REDIRECTS {
+        Err(format!("too many redirects (max {})", MAX_REDIRECTS))
+    } else if from.starts_with("https://") && !to.starts_with("https://") {
+        Err(format!(
+            "refusing redirect downgrade from {} to {}",
+            from, to
+        ))
+    } else {
+        Ok(())
+    }
+}
+
 // TODO: support https
 #[allow(unused)]
 pub fn download(
     url: &str,
     output_path: PathBuf,
     use_cache: bool,
-) -> impl Future<Item = (), Error = String> {
+) -> impl Future<Item = (), Error = Error> {
     info!("downloading from {} to {:?}", url, &output_path);
     use actix_web::client;
 
@@ -177,18 +349,47 @@ pub fn download(
         return future::Either::A(future::ok(()));
     }
 
-    let client_request = client::ClientRequest::get(url).finish().unwrap();
-
     future::Either::B(
-        client_request
-            .send()
-            .conn_timeout(time::Duration::from_secs(15))
-            .timeout(time::Duration::from_secs(3600))
-            .map_err(|e| format!("send download request: {}", e))
-            .and_then(|resp| {
-                write_async(resp.payload(), output_path)
-                    .map_err(|_| "writing downloaded file failed".to_string())
-            }),
+        future::loop_fn(
+            (url.to_owned(), HashSet::new()),
+            |(url, mut visited): (String, HashSet<String>)| {
+                visited.insert(url.clone());
+
+                client::ClientRequest::get(&url)
+                    .finish()
+                    .into_future()
+                    .map_err(|e| Error::IoError(format!("build download request: {}", e)))
+                    .and_then(|request| {
+                        request
+                            .send()
+                            .conn_timeout(time::Duration::from_secs(15))
+                            .timeout(time::Duration::from_secs(3600))
+                            .map_err(|e| Error::IoError(format!("send download request: {}", e)))
+                    })
+                    .and_then(move |resp| {
+                        if resp.status().is_redirection() {
+                            let location = resp
+                                .headers()
+                                .get(header::LOCATION)
+                                .and_then(|value| value.to_str().ok())
+                                .ok_or_else(|| format!("redirect from {} without location", url))?;
+                            let next_url = resolve_location(&url, location)?;
+                            check_redirect(&url, &next_url, &visited)?;
+
+                            debug!("following redirect from {} to {}", url, next_url);
+                            Ok(Loop::Continue((next_url, visited)))
+                        } else if resp.status().is_success() {
+                            Ok(Loop::Break(resp))
+                        } else {
+                            Err(Error::DownloadHttpStatus(resp.status().as_u16()))
+                        }
+                    })
+            },
+        )
+        .and_then(|resp| {
+            write_async(resp.payload(), output_path)
+                .map_err(|_| Error::IoError("writing downloaded file failed".to_string()))
+        }),
     )
 }
 
@@ -284,6 +485,514 @@ pub fn untgz<P: AsRef<Path> + ToOwned>(
         input_path.as_ref(),
         output_path.as_ref()
     );
+    let reporter = ProgressReporter::new(output_path.as_ref().to_string_lossy());
+
+    untgz_async_with_progress(input_path, output_path, move |progress| {
+        reporter.report(progress)
+    })
+}
+
+/// Image content already present on the local disk, given as `file://` url.
+/// Used for development, to skip download of the image.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LocalImage {
+    /// directory with extracted image content
+    Dir(PathBuf),
+    /// `.tar.gz` image archive
+    Archive(PathBuf),
+}
+
+impl LocalImage {
+    pub fn is_local_url(url: &str) -> bool {
+        url.starts_with("file://")
+    }
+
+    /// Returns `None` for non-`file://` urls. The image must be contained in `root`;
+    /// without `root` all local images are rejected.
+    pub fn from_url(url: &str, root: Option<&Path>) -> Option<Result<Self, String>> {
+        if !Self::is_local_url(url) {
+            return None;
+        }
+        let path = Path::new(&url["file://".len()..]);
+
+        Some(if !path.is_absolute() {
+            Err(format!("local image path must be absolute: {}", url))
+        } else {
+            Self::contained_path(path, root).and_then(|path| match fs::metadata(&path) {
+                Ok(ref meta) if meta.is_dir() => Ok(LocalImage::Dir(path)),
+                Ok(ref meta) if meta.is_file() => Ok(LocalImage::Archive(path)),
+                Ok(_) => Err(format!("unsupported local image: {}", url)),
+                Err(e) => Err(format!("local image {}: {}", url, e)),
+            })
+        })
+    }
+
+    /// Canonical `path`, if it is inside `root`
+    fn contained_path(path: &Path, root: Option<&Path>) -> Result<PathBuf, String> {
+        let root = root.ok_or_else(|| "local images are disabled".to_string())?;
+        let root = root
+            .canonicalize()
+            .map_err(|e| format!("local image root {}: {}", root.display(), e))?;
+        let path = path
+            .canonicalize()
+            .map_err(|e| format!("local image {}: {}", path.display(), e))?;
+
+        match path.starts_with(&root) {
+            true => Ok(path),
+            false => Err(format!(
+                "local image {} is outside of {}",
+                path.display(),
+                root.display()
+            )),
+        }
+    }
+
+    /// Copies or extracts image content into the session dir.
+    pub fn install(self, output_path: PathBuf) -> Box<dyn Future<Item = (), Error = String>> {
+        match self {
+            LocalImage::Dir(path) => {
+                info!("copying {:?} to {:?}", path, output_path);
+                Box::new(future::result(copy_dir(&path, &output_path)))
+            }
+            LocalImage::Archive(path) => Box::new(untgz(path, output_path)),
+        }
+    }
+}
+
+/// Copies directory recursively. Symlinks are followed only when they point
+/// to files contained in the source dir.
+fn copy_dir(src: &Path, dst: &Path) -> Result<(), String> {
+    let root = src
+        .canonicalize()
+        .map_err(|e| format!("{}: {}", src.display(), e))?;
+    copy_dir_contents(&root, &root, dst).map_err(|e| format!("copy {}: {}", src.display(), e))
+}
+
+fn copy_dir_contents(root: &Path, src: &Path, dst: &Path) -> io::Result<()> {
+    fs::create_dir_all(dst)?;
+
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let path = entry.path();
+        let target = dst.join(entry.file_name());
+        let file_type = entry.file_type()?;
+
+        if file_type.is_dir() {
+            copy_dir_contents(root, &path, &target)?;
+        } else if file_type.is_file() {
+            fs::copy(&path, &target)?;
+        } else if file_type.is_symlink() {
+            let resolved = path.canonicalize()?;
+            if !resolved.starts_with(root) || !resolved.is_file() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("link {} points outside of the image", path.display()),
+                ));
+            }
+            fs::copy(&resolved, &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Expected digest of a file, as `algo:hexvalue` of any algorithm known to
+/// `gu_model::hash`, e.g. `SHA1:a9993e36...` or `SHA3:3a985da7...`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Checksum(String);
+
+impl FromStr for Checksum {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut it = s.splitn(2, ':');
+        let (algo, value) = match (it.next(), it.next()) {
+            (Some(algo), Some(value)) => (algo, value.to_lowercase()),
+            _ => return Err(format!("invalid checksum format: {}", s)),
+        };
+
+        if !value.chars().all(|ch| ch.is_ascii_hexdigit()) {
+            return Err(format!("invalid checksum value: {}", value));
+        }
+        let checksum = format!("{}:{}", algo, value);
+        // rejects unknown algorithms, and values of wrong length for the algorithm
+        gu_model::hash::checker(&checksum)
+            .map_err(|e| format!("unsupported checksum {}: {}", s, e))?;
+        Ok(Checksum(checksum))
+    }
+}
+
+/// Checksum of an image; fails unless the hash is `algo:value` of a supported algorithm.
+pub fn image_checksum(hash: &ImageHash) -> Result<Checksum, String> {
+    hash.to_hash_str().parse()
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        collections::HashSet,
+        env, fs,
+        sync::{Arc, Mutex},
+        time,
+    };
+
+    use actix::System;
+
+    use actix_web::{
+        http, test::TestServer, App, AsyncResponder, HttpMessage, HttpRequest, HttpResponse,
+    };
+    use futures::prelude::*;
+    use uuid::Uuid;
+
+    use gu_model::envman::{ArchiveFormat, CreateSession, Error, ImageHash, ResourceFormat};
+
+    use super::{
+        check_redirect, download, download_step, export_step, image_checksum, resolve_location,
+        stream_tar, upload_step, Checksum, LocalImage,
+    };
+
+    #[test]
+    fn test_upload_timeout() {
+        // accepts connections, but never responds
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/upload", listener.local_addr().unwrap());
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data");
+        fs::write(&path, b"data").unwrap();
+
+        let started = time::Instant::now();
+        let result = System::new("test").block_on(upload_step(
+            &url,
+            path,
+            ResourceFormat::Raw,
+            time::Duration::from_secs(1),
+        ));
+
+        assert_eq!(result, Err(Error::UploadTimeout(1).to_string()));
+        assert!(started.elapsed() < time::Duration::from_secs(5));
+        drop(listener);
+    }
+
+    #[test]
+    fn test_install_local_image_dir() {
+        let src = tempfile::tempdir().unwrap();
+        let dst = tempfile::tempdir().unwrap();
+        fs::create_dir(src.path().join("bin")).unwrap();
+        fs::write(src.path().join("bin/app"), b"app").unwrap();
+        fs::write(src.path().join("spec.json"), b"{}").unwrap();
+
+        let url = format!("file://{}", src.path().display());
+        let image = LocalImage::from_url(&url, Some(src.path()))
+            .unwrap()
+            .unwrap();
+        assert_eq!(image, LocalImage::Dir(src.path().canonicalize().unwrap()));
+
+        image.install(dst.path().join("session")).wait().unwrap();
+        assert_eq!(
+            fs::read(dst.path().join("session/bin/app")).unwrap(),
+            b"app"
+        );
+        assert!(dst.path().join("session/spec.json").is_file());
+    }
+
+    #[test]
+    fn test_reject_invalid_local_image() {
+        let root = tempfile::tempdir().unwrap();
+        let root = Some(root.path());
+
+        assert!(LocalImage::from_url("http://some.url/a.tgz", root).is_none());
+        assert!(LocalImage::from_url("file://relative/dir", root)
+            .unwrap()
+            .is_err());
+        assert!(LocalImage::from_url("file:///non/existent/image.tgz", root)
+            .unwrap()
+            .is_err());
+    }
+
+    #[test]
+    fn test_reject_local_image_outside_root() {
+        let root = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        fs::create_dir(root.path().join("image")).unwrap();
+        let url = |path: &std::path::Path| format!("file://{}", path.display());
+
+        assert!(
+            LocalImage::from_url(&url(outside.path()), Some(root.path()))
+                .unwrap()
+                .is_err()
+        );
+        // `..` cannot leave the root either
+        assert!(
+            LocalImage::from_url(&url(&root.path().join("image/../..")), Some(root.path()))
+                .unwrap()
+                .is_err()
+        );
+        // disabled unless the root is configured
+        assert!(LocalImage::from_url(&url(&root.path().join("image")), None)
+            .unwrap()
+            .is_err());
+        assert!(
+            LocalImage::from_url(&url(&root.path().join("image")), Some(root.path()))
+                .unwrap()
+                .is_ok()
+        );
+    }
 
-    untgz_async(input_path, output_path)
+    #[cfg(unix)]
+    #[test]
+    fn test_reject_local_image_link_outside() {
+        let src = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        fs::write(outside.path().join("secret"), b"secret").unwrap();
+        std::os::unix::fs::symlink(outside.path().join("secret"), src.path().join("link")).unwrap();
+
+        let image = LocalImage::Dir(src.path().into());
+        assert!(image
+            .install(outside.path().join("session"))
+            .wait()
+            .is_err());
+    }
+
+    #[test]
+    fn test_session_image_sha256_is_accepted() {
+        let json = r#"{
+            "envType": "hd",
+            "image": {
+                "url": "http://some.url/image.tgz",
+                "hash": {
+                    "algo": "sha256",
+                    "value": "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+                }
+            },
+            "name": "sha256",
+            "tags": []
+        }"#;
+        let session: CreateSession<()> = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            image_checksum(&session.image.hash),
+            "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".parse()
+        );
+
+        assert!(image_checksum(&ImageHash::new("md5", "900150983cd24fb0")).is_err());
+        assert!(image_checksum(&"12345".into()).is_err());
+    }
+
+    #[test]
+    fn test_parse_checksum() {
+        assert!("SHA3:a9993e364706816aba3e25717850c26c9cd0d89d"
+            .parse::<Checksum>()
+            .is_err());
+        assert!("sha256:a9993e364706816aba3e25717850c26c9cd0d89d"
+            .parse::<Checksum>()
+            .is_err());
+        assert!("sha1-a9993e364706816aba3e25717850c26c9cd0d89d"
+            .parse::<Checksum>()
+            .is_err());
+        assert!("md5:900150983cd24fb0d6963f7d28e17f72"
+            .parse::<Checksum>()
+            .is_err());
+        assert!(
+            "sha3:3a985da74fe225b2045c172d6bd390bd855f086e3e9d525b46bfe24511431532"
+                .parse::<Checksum>()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_resolve_relative_location() {
+        assert_eq!(
+            resolve_location("http://some.url:8080/a/b.tgz", "/c.tgz").unwrap(),
+            "http://some.url:8080/c.tgz"
+        );
+        assert_eq!(
+            resolve_location("http://some.url/a.tgz", "https://cdn.url/a.tgz").unwrap(),
+            "https://cdn.url/a.tgz"
+        );
+    }
+
+    #[test]
+    fn test_reject_redirect_loop_and_downgrade() {
+        let mut visited = HashSet::new();
+        visited.insert("http://some.url/a.tgz".to_string());
+
+        assert!(
+            check_redirect("http://some.url/b.tgz", "http://some.url/a.tgz", &visited).is_err()
+        );
+        assert!(
+            check_redirect("https://some.url/a.tgz", "http://cdn.url/a.tgz", &visited).is_err()
+        );
+        assert!(check_redirect("http://some.url/a.tgz", "https://cdn.url/a.tgz", &visited).is_ok());
+    }
+
+    #[test]
+    fn test_download_follows_redirect() {
+        let mut srv = TestServer::with_factory(|| {
+            App::new()
+                .resource("/redirect", |r| {
+                    r.f(|_| {
+                        HttpResponse::Found()
+                            .header(http::header::LOCATION, "/image")
+                            .finish()
+                    })
+                })
+                .resource("/image", |r| {
+                    r.f(|_| HttpResponse::Ok().body("image content"))
+                })
+        });
+        let output_path = env::temp_dir().join(format!("gu-download-{}", Uuid::new_v4()));
+
+        srv.execute(download(&srv.url("/redirect"), output_path.clone(), false))
+            .unwrap();
+
+        assert_eq!(fs::read_to_string(&output_path).unwrap(), "image content");
+        let _ = fs::remove_file(output_path);
+    }
+
+    #[test]
+    fn test_upload_sets_content_type() {
+        let mut srv = TestServer::new(|app| {
+            app.handler(
+                |req: &HttpRequest| match req.headers().get(http::header::CONTENT_TYPE) {
+                    Some(content_type) if content_type == "application/x-tar" => {
+                        HttpResponse::Ok().finish()
+                    }
+                    _ => HttpResponse::BadRequest().finish(),
+                },
+            )
+        });
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("data"), b"data").unwrap();
+
+        let upload = |format| {
+            upload_step(
+                &srv.url("/upload"),
+                dir.path().into(),
+                format,
+                time::Duration::from_secs(5),
+            )
+        };
+        let tar_upload = upload(ResourceFormat::Tar);
+        let raw_upload = upload(ResourceFormat::Raw);
+
+        assert!(srv.execute(tar_upload).is_ok());
+        assert!(srv.execute(raw_upload).is_err());
+    }
+
+    #[test]
+    fn test_export_workspace() {
+        let uploaded = Arc::new(Mutex::new(Vec::new()));
+        let received = uploaded.clone();
+        let mut srv = TestServer::new(move |app| {
+            let received = received.clone();
+            app.handler(move |req: &HttpRequest| {
+                let received = received.clone();
+                req.body()
+                    .map(move |body| {
+                        received.lock().unwrap().extend_from_slice(&body);
+                        HttpResponse::Ok().finish()
+                    })
+                    .responder()
+            })
+        });
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.txt"), b"a").unwrap();
+        fs::create_dir(dir.path().join("sub")).unwrap();
+        fs::write(dir.path().join("sub/b.txt"), b"b").unwrap();
+
+        srv.execute(export_step(
+            &srv.url("/export"),
+            dir.path().into(),
+            ArchiveFormat::TarGz,
+            time::Duration::from_secs(5),
+        ))
+        .unwrap();
+
+        let archive = uploaded.lock().unwrap().clone();
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(archive.as_slice()));
+        let entries: HashSet<_> = archive
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().into_owned())
+            .collect();
+
+        assert!(entries.contains(std::path::Path::new("a.txt")));
+        assert!(entries.contains(std::path::Path::new("sub/b.txt")));
+    }
+
+    #[test]
+    fn test_archive_error_fails_stream() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let archive = stream_tar(dir.path().join("missing")).collect().wait();
+
+        assert!(archive.is_err());
+    }
+
+    fn serve(body: Vec<u8>) -> TestServer {
+        TestServer::new(move |app| {
+            let body = body.clone();
+            app.handler(move |_| HttpResponse::Ok().body(body.clone()))
+        })
+    }
+
+    #[test]
+    fn test_download_raw_stores_bytes() {
+        let mut srv = serve(b"raw content".to_vec());
+        let dir = tempfile::tempdir().unwrap();
+        let output_path = dir.path().join("data.bin");
+
+        srv.execute(download_step(
+            &srv.url("/data"),
+            output_path.clone(),
+            ResourceFormat::Raw,
+        ))
+        .unwrap();
+
+        assert_eq!(fs::read(&output_path).unwrap(), b"raw content");
+    }
+
+    #[test]
+    fn test_download_tar_gz_extracts_archive() {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        ));
+        let content = b"packed";
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "sub/file.txt", &content[..])
+            .unwrap();
+        let archive = builder.into_inner().unwrap().finish().unwrap();
+
+        let mut srv = serve(archive);
+        let dir = tempfile::tempdir().unwrap();
+        let output_path = dir.path().join("out");
+
+        srv.execute(download_step(
+            &srv.url("/data.tar.gz"),
+            output_path.clone(),
+            ResourceFormat::TarGz,
+        ))
+        .unwrap();
+
+        assert_eq!(
+            fs::read(output_path.join("sub/file.txt")).unwrap(),
+            b"packed"
+        );
+        // only the extracted directory is left, without the downloaded archive
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_download_reports_http_status() {
+        let mut srv = TestServer::new(|app| app.handler(|_| HttpResponse::NotFound().finish()));
+        let output_path = env::temp_dir().join(format!("gu-download-{}", Uuid::new_v4()));
+
+        match srv.execute(download(&srv.url("/image"), output_path, false)) {
+            Err(Error::DownloadHttpStatus(code)) => assert_eq!(code, 404),
+            r => panic!("unexpected result: {:?}", r),
+        }
+    }
 }
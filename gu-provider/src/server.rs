@@ -4,7 +4,10 @@
 use std::net::ToSocketAddrs;
 use std::{collections::HashSet, net::SocketAddr, path::PathBuf, sync::Arc};
 
-use ::actix::prelude::*;
+use ::actix::{
+    actors::signal::{ProcessSignals, Signal, SignalType, Subscribe},
+    prelude::*,
+};
 use actix_web::*;
 use clap::ArgMatches;
 use futures::{future, prelude::*};
@@ -30,8 +33,13 @@ use crate::connect::{
     self, AutoMdns, Connect, ConnectManager, ConnectModeMessage, ConnectionChange,
     ConnectionChangeMessage, Disconnect, ListSockets,
 };
+use crate::envman;
+#[cfg(feature = "testing")]
+use crate::fakeman::FakeMan;
 #[cfg(feature = "env-hd")]
-use crate::hdman::HdMan;
+use crate::hdman::{HdMan, DEFAULT_CHILD_MEMORY_RESERVE};
+use crate::provision::DEFAULT_UPLOAD_TIMEOUT;
+use crate::workspace::DEFAULT_LOG_RETENTION;
 
 #[derive(Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -46,6 +54,23 @@ pub(crate) struct ProviderConfig {
     publish_service: bool,
     #[serde(default = "ProviderConfig::default_connect_mode")]
     pub(crate) connect_mode: ConnectMode,
+    /// timeout of session file uploads, in seconds
+    #[serde(default = "ProviderConfig::default_upload_timeout")]
+    pub(crate) upload_timeout: u64,
+    /// how long logs of finished session children are kept, in seconds
+    #[serde(default = "ProviderConfig::default_log_retention")]
+    pub(crate) log_retention: u64,
+    /// memory of all session children, in KiB, past which no more children are
+    /// started; no limit by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) memory_budget: Option<u64>,
+    /// memory, in KiB, reserved within the budget for each newly started child
+    #[serde(default = "ProviderConfig::default_child_memory_reserve")]
+    pub(crate) child_memory_reserve: u64,
+    /// directory `file://` session images must be in; local images are rejected
+    /// if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) local_image_root: Option<PathBuf>,
 }
 
 impl Default for ProviderConfig {
@@ -56,6 +81,11 @@ impl Default for ProviderConfig {
             hub_addrs: HashSet::new(),
             publish_service: true,
             connect_mode: Self::default_connect_mode(),
+            upload_timeout: Self::default_upload_timeout(),
+            log_retention: Self::default_log_retention(),
+            memory_budget: None,
+            child_memory_reserve: Self::default_child_memory_reserve(),
+            local_image_root: None,
         }
     }
 }
@@ -64,6 +94,10 @@ impl ServerConfig for ProviderConfig {
     fn port(&self) -> u16 {
         Self::default_p2p_port()
     }
+
+    fn service_name() -> &'static str {
+        "gu-provider"
+    }
 }
 
 pub(crate) type ProviderClient = ServerClient<ProviderConfig>;
@@ -88,6 +122,18 @@ impl ProviderConfig {
     fn default_connect_mode() -> ConnectMode {
         ConnectMode::Manual
     }
+
+    fn default_upload_timeout() -> u64 {
+        DEFAULT_UPLOAD_TIMEOUT.as_secs()
+    }
+
+    fn default_log_retention() -> u64 {
+        DEFAULT_LOG_RETENTION.as_secs()
+    }
+
+    fn default_child_memory_reserve() -> u64 {
+        DEFAULT_CHILD_MEMORY_RESERVE
+    }
 }
 
 impl HasSectionId for ProviderConfig {
@@ -188,6 +234,8 @@ impl Module for ServerModule {
 
             #[cfg(feature = "env-hd")]
             let _ = HdMan::start(config_module);
+            #[cfg(feature = "testing")]
+            let _ = FakeMan::start();
 
             ProviderServer::from_registry().do_send(InitServer {
                 decorator,
@@ -228,8 +276,36 @@ impl SystemService for ProviderServer {}
 impl Actor for ProviderServer {
     type Context = Context<Self>;
 
-    fn started(&mut self, _ctx: &mut Self::Context) {
+    fn started(&mut self, ctx: &mut Self::Context) {
         info!("provider server actor started");
+        ProcessSignals::from_registry().do_send(Subscribe(ctx.address().recipient()));
+    }
+}
+
+/// Stops the system on SIGINT, SIGTERM or SIGQUIT, once every environment
+/// destroyed its sessions
+impl Handler<Signal> for ProviderServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: Signal, ctx: &mut Self::Context) {
+        match msg.0 {
+            SignalType::Int | SignalType::Term | SignalType::Quit => {
+                info!("destroying all sessions before shutdown");
+                ctx.spawn(
+                    envman::shutdown()
+                        .then(|r| {
+                            match r {
+                                Ok(destroyed) => info!("{} sessions destroyed", destroyed),
+                                Err(()) => warn!("sessions not destroyed"),
+                            }
+                            System::current().stop();
+                            Ok(())
+                        })
+                        .into_actor(self),
+                );
+            }
+            _ => (),
+        }
     }
 }
 
@@ -2,12 +2,21 @@ use std::collections::BTreeMap;
 
 use actix::prelude::*;
 use actix_web::{self, App, AsyncResponder, HttpRequest, HttpResponse, Responder};
+use bytes::Bytes;
 use futures::{future, prelude::*};
 use serde::{Deserialize, Serialize};
 
+use gu_actix::prelude::*;
+use gu_base::files::ExtractProgress;
 use gu_base::Module;
+use gu_hardware::actor::{Hardware, HardwareActor, HardwareQuery};
+use gu_hdman::download::ProgressStatus;
+use gu_model::envman::GetSessions;
+use gu_net::rpc::{peer::PeerSessionStatus, RemotingSystemService};
 use std::borrow::Cow;
 
+use crate::envman;
+
 pub fn module() -> impl Module {
     StatusModule
 }
@@ -21,28 +30,51 @@ struct SmPath {
 
 impl Module for StatusModule {
     fn decorate_webapp<S: 'static>(&self, app: App<S>) -> App<S> {
-        app.handler("/status", status_handler).resource("/sm", |r| {
-            r.get().with(|p: actix_web::Query<SmPath>| {
-                HttpResponse::Ok().streaming(
-                    super::provision::stream_tar(p.into_inner().p.into())
-                        .map_err(|e| actix_web::error::ErrorInternalServerError(e)),
-                )
+        app.handler("/status", status_handler)
+            .handler("/health", health_handler)
+            .resource("/sessions", |r| r.get().with(sessions_handler))
+            .resource("/sm", |r| {
+                r.get().with(|p: actix_web::Query<SmPath>| {
+                    HttpResponse::Ok().streaming(
+                        super::provision::stream_tar(p.into_inner().p.into())
+                            .map_err(|e| actix_web::error::ErrorInternalServerError(e)),
+                    )
+                })
             })
-        })
     }
 }
 
+/// Sessions of all environments, as NDJSON streamed one environment at a time
+fn sessions_handler(query: actix_web::Query<GetSessions>) -> impl Responder {
+    envman::stream_sessions(query.into_inner())
+        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("err: {}", e)))
+        .map(|lines| {
+            HttpResponse::Ok()
+                .content_type("application/x-ndjson")
+                .streaming(lines.map(Bytes::from).map_err(|()| {
+                    actix_web::error::ErrorInternalServerError("cannot list sessions")
+                }))
+        })
+        .responder()
+}
+
 #[derive(Serialize)]
 struct StatusBody {
-    envs: BTreeMap<String, EnvStatus>,
+    envs: BTreeMap<String, EnvStatusInfo>,
+    /// images being downloaded or extracted
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    images: BTreeMap<String, ImageProgress>,
 }
 
 fn status_handler<S: 'static>(_r: &HttpRequest<S>) -> impl Responder {
-    StatusManager::from_registry()
+    let manager = StatusManager::from_registry();
+
+    manager
         .send(ListEnvStatus)
+        .join(manager.send(ListProgress))
         .map_err(|e| actix_web::error::ErrorInternalServerError(format!("err: {}", e)))
-        .and_then(|envs| match envs {
-            Ok(envs) => Ok(HttpResponse::Ok().json(StatusBody { envs })),
+        .and_then(|(envs, images)| match envs {
+            Ok(envs) => Ok(HttpResponse::Ok().json(StatusBody { envs, images })),
             Err(e) => Err(actix_web::error::ErrorInternalServerError(format!(
                 "err: {}",
                 e
@@ -51,7 +83,80 @@ fn status_handler<S: 'static>(_r: &HttpRequest<S>) -> impl Responder {
         .responder()
 }
 
-#[derive(Serialize, Deserialize, PartialEq)]
+/// Summary for monitoring; failed subqueries are reported in `error`
+#[derive(Serialize, Debug)]
+struct HealthBody {
+    version: &'static str,
+    sessions: SessionCounts,
+    live_sessions: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hardware: Option<Hardware>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+fn health(
+    sessions: impl Future<Item = SessionCounts, Error = String>,
+) -> impl Future<Item = HealthBody, Error = ()> {
+    let hardware = HardwareActor::from_registry()
+        .send(HardwareQuery)
+        .map_err(|e| e.to_string())
+        .flatten_fut()
+        .map_err(|e| format!("hardware: {}", e));
+    let sessions = sessions.map_err(|e| format!("sessions: {}", e));
+
+    sessions
+        .then(|r| Ok::<_, ()>(r))
+        .join(hardware.then(|r| Ok(r)))
+        .and_then(
+            |(sessions, hardware): (Result<_, String>, Result<_, String>)| {
+                let errors: Vec<&str> = sessions
+                    .as_ref()
+                    .err()
+                    .into_iter()
+                    .chain(hardware.as_ref().err())
+                    .map(String::as_str)
+                    .collect();
+                let error = match errors.is_empty() {
+                    true => None,
+                    false => Some(errors.join(", ")),
+                };
+                let sessions = sessions.unwrap_or_default();
+
+                Ok(HealthBody {
+                    version: crate::VERSION,
+                    live_sessions: sessions.live(),
+                    sessions,
+                    hardware: hardware.ok(),
+                    error,
+                })
+            },
+        )
+}
+
+/// Session counts summed over all environments
+fn all_sessions() -> impl Future<Item = SessionCounts, Error = String> {
+    StatusManager::from_registry()
+        .send(ListEnvStatus)
+        .map_err(|e| e.to_string())
+        .flatten_fut()
+        .map(|envs| {
+            envs.values()
+                .fold(SessionCounts::default(), |mut counts, env| {
+                    counts.merge(&env.sessions);
+                    counts
+                })
+        })
+}
+
+fn health_handler<S: 'static>(_r: &HttpRequest<S>) -> impl Responder {
+    health(all_sessions())
+        .map_err(|_| actix_web::error::ErrorInternalServerError("health check failed"))
+        .map(|body| HttpResponse::Ok().json(body))
+        .responder()
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
 pub enum EnvStatus {
     Ready,
     Working,
@@ -59,10 +164,51 @@ pub enum EnvStatus {
     Disabled,
 }
 
+/// Number of sessions in each `PeerSessionStatus`
+#[derive(Serialize, Deserialize, PartialEq, Debug, Default)]
+pub struct SessionCounts {
+    pub pending: usize,
+    pub created: usize,
+    pub running: usize,
+    pub configured: usize,
+    pub destroying: usize,
+}
+
+impl SessionCounts {
+    pub fn add(&mut self, status: &PeerSessionStatus) {
+        match status {
+            PeerSessionStatus::PENDING => self.pending += 1,
+            PeerSessionStatus::CREATED => self.created += 1,
+            PeerSessionStatus::RUNNING => self.running += 1,
+            PeerSessionStatus::CONFIGURED => self.configured += 1,
+            PeerSessionStatus::DESTROYING => self.destroying += 1,
+        }
+    }
+
+    pub fn merge(&mut self, other: &SessionCounts) {
+        self.pending += other.pending;
+        self.created += other.created;
+        self.running += other.running;
+        self.configured += other.configured;
+        self.destroying += other.destroying;
+    }
+
+    /// Sessions not being destroyed
+    pub fn live(&self) -> usize {
+        self.pending + self.created + self.running + self.configured
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EnvStatusInfo {
+    pub status: EnvStatus,
+    pub sessions: SessionCounts,
+}
+
 pub struct GetEnvStatus;
 
 impl Message for GetEnvStatus {
-    type Result = EnvStatus;
+    type Result = EnvStatusInfo;
 }
 
 // status manager:
@@ -70,7 +216,7 @@ impl Message for GetEnvStatus {
 struct ListEnvStatus;
 
 impl Message for ListEnvStatus {
-    type Result = Result<BTreeMap<String, EnvStatus>, String>;
+    type Result = Result<BTreeMap<String, EnvStatusInfo>, String>;
 }
 
 #[derive(Message)]
@@ -86,9 +232,83 @@ impl AddProvider {
     }
 }
 
+/// Progress of an image download or extraction
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(tag = "stage", rename_all = "lowercase")]
+pub enum ImageProgress {
+    Download {
+        downloaded_bytes: u64,
+        total_bytes: Option<u64>,
+    },
+    Extract {
+        extracted_entries: u64,
+        /// `None` when the number of entries is not known upfront
+        total_entries: Option<u64>,
+        written_bytes: u64,
+    },
+}
+
+impl<'a> From<&'a ProgressStatus> for ImageProgress {
+    fn from(progress: &'a ProgressStatus) -> Self {
+        ImageProgress::Download {
+            downloaded_bytes: progress.downloaded_bytes,
+            total_bytes: progress.total_to_download,
+        }
+    }
+}
+
+impl<'a> From<&'a ExtractProgress> for ImageProgress {
+    fn from(progress: &'a ExtractProgress) -> Self {
+        ImageProgress::Extract {
+            extracted_entries: progress.extracted_entries,
+            total_entries: progress.total_entries,
+            written_bytes: progress.written_bytes,
+        }
+    }
+}
+
+/// Sets progress of the image operation `0`, or clears it if `None`
+#[derive(Message)]
+struct UpdateProgress(String, Option<ImageProgress>);
+
+struct ListProgress;
+
+impl Message for ListProgress {
+    type Result = BTreeMap<String, ImageProgress>;
+}
+
+/// Reports progress of a single image operation to the `StatusManager`;
+/// the operation is removed from the status when the reporter is dropped.
+/// Can be sent to other threads, but must be created within the actix system.
+pub struct ProgressReporter {
+    key: String,
+    manager: Addr<StatusManager>,
+}
+
+impl ProgressReporter {
+    pub fn new(key: impl Into<String>) -> ProgressReporter {
+        ProgressReporter {
+            key: key.into(),
+            manager: StatusManager::from_registry(),
+        }
+    }
+
+    pub fn report(&self, progress: impl Into<ImageProgress>) {
+        self.manager
+            .do_send(UpdateProgress(self.key.clone(), Some(progress.into())))
+    }
+}
+
+impl Drop for ProgressReporter {
+    fn drop(&mut self) {
+        self.manager.do_send(UpdateProgress(self.key.clone(), None))
+    }
+}
+
 #[derive(Default)]
 pub struct StatusManager {
     providers: BTreeMap<Cow<'static, str>, Recipient<GetEnvStatus>>,
+    images: BTreeMap<String, ImageProgress>,
 }
 
 impl Actor for StatusManager {
@@ -107,8 +327,31 @@ impl Handler<AddProvider> for StatusManager {
     }
 }
 
+impl Handler<UpdateProgress> for StatusManager {
+    type Result = ();
+
+    fn handle(&mut self, msg: UpdateProgress, _ctx: &mut Self::Context) -> Self::Result {
+        match msg.1 {
+            Some(progress) => {
+                self.images.insert(msg.0, progress);
+            }
+            None => {
+                self.images.remove(&msg.0);
+            }
+        }
+    }
+}
+
+impl Handler<ListProgress> for StatusManager {
+    type Result = MessageResult<ListProgress>;
+
+    fn handle(&mut self, _msg: ListProgress, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.images.clone())
+    }
+}
+
 impl Handler<ListEnvStatus> for StatusManager {
-    type Result = ActorResponse<StatusManager, BTreeMap<String, EnvStatus>, String>;
+    type Result = ActorResponse<StatusManager, BTreeMap<String, EnvStatusInfo>, String>;
 
     fn handle(&mut self, _msg: ListEnvStatus, _ctx: &mut Self::Context) -> Self::Result {
         ActorResponse::r#async(
@@ -119,7 +362,7 @@ impl Handler<ListEnvStatus> for StatusManager {
                 },
             ))
             .and_then(
-                |envs| -> Result<BTreeMap<String, EnvStatus>, actix::MailboxError> {
+                |envs| -> Result<BTreeMap<String, EnvStatusInfo>, actix::MailboxError> {
                     Ok(envs.into_iter().collect())
                 },
             )
@@ -131,3 +374,80 @@ impl Handler<ListEnvStatus> for StatusManager {
 
 impl Supervised for StatusManager {}
 impl SystemService for StatusManager {}
+
+#[cfg(test)]
+mod test {
+    use actix::prelude::*;
+    use futures::{future, prelude::*};
+
+    use super::{
+        health, ImageProgress, ListProgress, ProgressReporter, SessionCounts, StatusManager,
+    };
+
+    #[test]
+    fn test_health_reports_sessions_and_memory() {
+        System::run(|| {
+            let sessions = SessionCounts {
+                running: 2,
+                destroying: 1,
+                ..SessionCounts::default()
+            };
+
+            Arbiter::spawn(health(future::ok(sessions)).then(|r| {
+                let body = serde_json::to_value(r.unwrap()).unwrap();
+                assert_eq!(body["sessions"]["running"], 2);
+                assert_eq!(body["live_sessions"], 2);
+                assert!(body["hardware"]["ram"]["total"].as_u64().unwrap() > 0);
+                assert!(body.get("error").is_none());
+                Ok(System::current().stop())
+            }))
+        });
+    }
+
+    #[test]
+    fn test_health_reports_partial_data_on_error() {
+        System::run(|| {
+            Arbiter::spawn(health(future::err("unavailable".into())).then(|r| {
+                let body = serde_json::to_value(r.unwrap()).unwrap();
+                assert_eq!(body["live_sessions"], 0);
+                assert!(body["hardware"]["ram"].is_object());
+                assert_eq!(body["error"], "sessions: unavailable");
+                Ok(System::current().stop())
+            }))
+        });
+    }
+
+    #[test]
+    fn test_image_progress_is_cleared_when_reporter_dropped() {
+        System::run(|| {
+            let reporter = ProgressReporter::new("image");
+            reporter.report(ImageProgress::Extract {
+                extracted_entries: 3,
+                total_entries: None,
+                written_bytes: 10,
+            });
+            let manager = StatusManager::from_registry();
+
+            Arbiter::spawn(
+                manager
+                    .send(ListProgress)
+                    .and_then(move |images| {
+                        assert_eq!(
+                            images["image"],
+                            ImageProgress::Extract {
+                                extracted_entries: 3,
+                                total_entries: None,
+                                written_bytes: 10,
+                            }
+                        );
+                        drop(reporter);
+                        manager.send(ListProgress)
+                    })
+                    .then(|images| {
+                        assert!(images.unwrap().is_empty());
+                        Ok(System::current().stop())
+                    }),
+            )
+        });
+    }
+}
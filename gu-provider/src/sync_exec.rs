@@ -1,7 +1,14 @@
-use std::{path::PathBuf, process};
+use std::{
+    fmt,
+    io::{self, BufRead, BufReader, Read},
+    path::PathBuf,
+    process, result, thread,
+    time::{Duration, Instant},
+};
 
 use actix::{fut, prelude::*};
-use log::debug;
+use futures::sync::oneshot;
+use log::{debug, warn};
 
 use error::*;
 use gu_actix::*;
@@ -22,6 +29,9 @@ pub mod error {
             ExecutionError(exec: String, args: Vec<String>, output: process::Output) {
                  display("failed to execute command: {}, {:?}, {:?}", exec, args, output)
             }
+            KillTimeout(pid: u32) {
+                 display("process {} not reaped within timeout", pid)
+            }
         }
     );
 }
@@ -59,22 +69,132 @@ impl SyncExecManager {
 }
 
 /// Message for executing commands
-#[derive(Debug)]
 pub enum Exec {
     Run {
         executable: String,
         args: Vec<String>,
         cwd: PathBuf,
+        /// see [`set_cpu_affinity`]
+        cpu_affinity: Vec<usize>,
+    },
+    /// Runs command forwarding its output line by line to `subscriber`,
+    /// before the command finishes
+    Stream {
+        executable: String,
+        args: Vec<String>,
+        cwd: PathBuf,
+        subscriber: Recipient<OutputLine>,
+    },
+    /// Terminates the child (SIGTERM on unix); if it does not exit within `timeout`,
+    /// kills it forcibly (SIGKILL) and waits up to `timeout` again
+    Kill {
+        child: process::Child,
+        timeout: Duration,
     },
-    Kill(process::Child),
+}
+
+impl fmt::Debug for Exec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Exec::Run {
+                executable,
+                args,
+                cwd,
+                cpu_affinity,
+            } => f
+                .debug_struct("Run")
+                .field("executable", executable)
+                .field("args", args)
+                .field("cwd", cwd)
+                .field("cpu_affinity", cpu_affinity)
+                .finish(),
+            Exec::Stream {
+                executable,
+                args,
+                cwd,
+                ..
+            } => f
+                .debug_struct("Stream")
+                .field("executable", executable)
+                .field("args", args)
+                .field("cwd", cwd)
+                .finish(),
+            Exec::Kill { child, timeout } => f
+                .debug_struct("Kill")
+                .field("child", child)
+                .field("timeout", timeout)
+                .finish(),
+        }
+    }
+}
+
+pub(crate) const DEFAULT_KILL_TIMEOUT: Duration = Duration::from_secs(5);
+
+impl Exec {
+    pub fn kill(child: process::Child) -> Self {
+        Exec::Kill {
+            child,
+            timeout: DEFAULT_KILL_TIMEOUT,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum ExecResult {
     Run(process::Output),
+    /// exit status of streamed command; unlike `Run`, failure is not an error
+    Stream(process::ExitStatus),
     Kill(String),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// Single line of streamed command output, without line terminator
+#[derive(Debug, Clone)]
+pub struct OutputLine {
+    pub stream: OutputStream,
+    pub line: String,
+}
+
+impl Message for OutputLine {
+    type Result = ();
+}
+
+/// Passes lines from `input` to `on_line` until EOF, without line terminators;
+/// a trailing line without terminator is passed too
+pub(crate) fn read_lines<R: Read, F: FnMut(&str)>(input: R, mut on_line: F) -> io::Result<()> {
+    let mut reader = BufReader::new(input);
+    let mut buf = Vec::new();
+
+    loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            return Ok(());
+        }
+        let line = String::from_utf8_lossy(&buf);
+        on_line(line.trim_end_matches(|ch| ch == '\n' || ch == '\r'));
+    }
+}
+
+/// Forwards lines from `input` until EOF; a trailing line without terminator is forwarded too
+fn forward_lines<R: Read>(input: R, stream: OutputStream, subscriber: &Recipient<OutputLine>) {
+    let forwarded = read_lines(input, |line| {
+        if let Err(e) = subscriber.do_send(OutputLine {
+            stream,
+            line: line.to_string(),
+        }) {
+            debug!("output subscriber gone: {}", e);
+        }
+    });
+    if let Err(e) = forwarded {
+        debug!("cannot read {:?}: {}", stream, e);
+    }
+}
+
 impl Message for Exec {
     type Result = Result<ExecResult>;
 }
@@ -104,13 +224,14 @@ impl Handler<Exec> for SyncExec {
                 executable,
                 args,
                 cwd,
+                cpu_affinity,
             } => {
                 // TODO: critical section
                 // TODO: env::set_current_dir(&base_dir)?;
-                let output = process::Command::new(&executable)
-                    .current_dir(&cwd)
-                    .args(&args)
-                    .output();
+                let mut command = process::Command::new(&executable);
+                command.current_dir(&cwd).args(&args);
+                set_cpu_affinity(&mut command, &cpu_affinity);
+                let output = command.output();
                 match output {
                     Ok(output) => {
                         if output.status.success() {
@@ -127,12 +248,176 @@ impl Handler<Exec> for SyncExec {
                     Err(e) => Err(e.into()),
                 }
             }
-            Exec::Kill(mut child) => child
-                .kill()
-                .and_then(|_| child.wait().map_err(From::from))
-                .and_then(|_| Ok(ExecResult::Kill("Killed".into())))
-                .map_err(From::from),
+            Exec::Stream {
+                executable,
+                args,
+                cwd,
+                subscriber,
+            } => {
+                let mut child = process::Command::new(&executable)
+                    .current_dir(&cwd)
+                    .args(&args)
+                    .stdout(process::Stdio::piped())
+                    .stderr(process::Stdio::piped())
+                    .spawn()?;
+
+                let stderr = child.stderr.take().map(|stderr| {
+                    let subscriber = subscriber.clone();
+                    thread::spawn(move || forward_lines(stderr, OutputStream::Stderr, &subscriber))
+                });
+                if let Some(stdout) = child.stdout.take() {
+                    forward_lines(stdout, OutputStream::Stdout, &subscriber);
+                }
+                if let Some(handle) = stderr {
+                    let _ = handle.join();
+                }
+
+                Ok(ExecResult::Stream(child.wait()?))
+            }
+            Exec::Kill { mut child, timeout } => {
+                if terminate(&child)? && wait_timeout(&mut child, timeout)? {
+                    return Ok(ExecResult::Kill("Terminated".into()));
+                }
+                debug!("child {} did not terminate; killing", child.id());
+                child.kill()?;
+                match wait_timeout(&mut child, timeout)? {
+                    true => Ok(ExecResult::Kill("Killed".into())),
+                    false => Err(ErrorKind::KillTimeout(child.id()).into()),
+                }
+            }
+        }
+    }
+}
+
+/// Asks process to terminate. Returns `false` if it's not supported on this platform.
+#[cfg(unix)]
+pub(crate) fn terminate(child: &process::Child) -> Result<bool> {
+    match unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTERM) } {
+        0 => Ok(true),
+        _ => Err(std::io::Error::last_os_error().into()),
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn terminate(_child: &process::Child) -> Result<bool> {
+    Ok(false)
+}
+
+/// Terminates all `children` at once, on a separate thread: they are sent SIGTERM
+/// together, and those still running after one shared `timeout` are killed.
+/// Resolves once every child is reaped.
+pub(crate) fn kill_all(
+    children: Vec<(String, process::Child)>,
+    timeout: Duration,
+) -> oneshot::Receiver<()> {
+    let (tx, rx) = oneshot::channel();
+
+    thread::spawn(move || {
+        let deadline = Instant::now() + timeout;
+        let mut running: Vec<_> = children
+            .into_iter()
+            .filter_map(|(child_id, mut child)| match terminate(&child) {
+                Ok(true) => Some((child_id, child)),
+                Ok(false) => {
+                    reap(&child_id, child.kill().and_then(|_| child.wait()));
+                    None
+                }
+                Err(e) => {
+                    debug!("cannot terminate child {}: {}", child_id, e);
+                    Some((child_id, child))
+                }
+            })
+            .collect();
+
+        while !running.is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+            running = running
+                .into_iter()
+                .filter_map(|(child_id, mut child)| match child.try_wait() {
+                    Ok(None) => Some((child_id, child)),
+                    status => {
+                        reap(&child_id, status);
+                        None
+                    }
+                })
+                .collect();
+        }
+
+        for (child_id, mut child) in running {
+            debug!("child {} did not terminate; killing", child_id);
+            reap(&child_id, child.kill().and_then(|_| child.wait()));
+        }
+        let _ = tx.send(());
+    });
+
+    rx
+}
+
+fn reap<T>(child_id: &str, status: io::Result<T>) {
+    if let Err(e) = status {
+        warn!("cannot kill child {}: {}", child_id, e)
+    }
+}
+
+/// Checks that all `cores` are below the number of online CPUs.
+#[cfg(target_os = "linux")]
+pub fn check_cpu_affinity(cores: &[usize]) -> result::Result<(), String> {
+    let available = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) } as usize;
+    match cores.iter().find(|&&core| core >= available) {
+        Some(core) => Err(format!(
+            "invalid cpu core {}, {} available",
+            core, available
+        )),
+        None => Ok(()),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn check_cpu_affinity(cores: &[usize]) -> result::Result<(), String> {
+    match cores.is_empty() {
+        true => Ok(()),
+        false => Err("cpu affinity is supported on linux only".into()),
+    }
+}
+
+/// Pins process spawned by `command` to `cores`; does nothing for empty `cores`.
+#[cfg(target_os = "linux")]
+pub fn set_cpu_affinity(command: &mut process::Command, cores: &[usize]) {
+    use std::{io, mem, os::unix::process::CommandExt};
+
+    if cores.is_empty() {
+        return;
+    }
+
+    let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+    for &core in cores {
+        unsafe { libc::CPU_SET(core, &mut set) };
+    }
+    unsafe {
+        command.pre_exec(move || {
+            match libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) {
+                0 => Ok(()),
+                _ => Err(io::Error::last_os_error()),
+            }
+        });
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn set_cpu_affinity(_command: &mut process::Command, _cores: &[usize]) {}
+
+/// Returns `true` if the child was reaped within `timeout`.
+fn wait_timeout(child: &mut process::Child, timeout: Duration) -> Result<bool> {
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        if child.try_wait()?.is_some() {
+            return Ok(true);
+        }
+        if Instant::now() >= deadline {
+            return Ok(false);
         }
+        thread::sleep(Duration::from_millis(10));
     }
 }
 
@@ -144,12 +429,102 @@ impl From<MailboxError> for Error {
 
 #[cfg(test)]
 mod test {
+    use std::{
+        process, thread,
+        time::{Duration, Instant},
+    };
+
     use actix::prelude::*;
     use futures::Future;
 
     use gu_actix::flatten::FlattenFuture;
 
-    use super::{Exec, ExecResult, SyncExecManager};
+    use super::{
+        check_cpu_affinity, kill_all, set_cpu_affinity, Exec, ExecResult, OutputLine, OutputStream,
+        SyncExecManager,
+    };
+
+    #[derive(Default)]
+    struct Collector(Vec<(OutputLine, Instant)>);
+
+    impl Actor for Collector {
+        type Context = Context<Self>;
+    }
+
+    impl Handler<OutputLine> for Collector {
+        type Result = ();
+
+        fn handle(&mut self, msg: OutputLine, _ctx: &mut Self::Context) {
+            self.0.push((msg, Instant::now()));
+        }
+    }
+
+    struct GetLines;
+
+    impl Message for GetLines {
+        type Result = Vec<(OutputLine, Instant)>;
+    }
+
+    impl Handler<GetLines> for Collector {
+        type Result = MessageResult<GetLines>;
+
+        fn handle(&mut self, _msg: GetLines, _ctx: &mut Self::Context) -> Self::Result {
+            MessageResult(self.0.clone())
+        }
+    }
+
+    #[test]
+    fn test_sync_exec_stream_lines() {
+        System::run(|| {
+            let collector = Collector::default().start();
+
+            Arbiter::spawn(
+                SyncExecManager::from_registry()
+                    .send(Exec::Stream {
+                        executable: "/bin/sh".into(),
+                        args: vec![
+                            "-c".into(),
+                            "echo one; sleep 0.5; printf tw; sleep 0.1; echo o; echo err >&2"
+                                .into(),
+                        ],
+                        cwd: "/".into(),
+                        subscriber: collector.clone().recipient(),
+                    })
+                    .flatten_fut()
+                    .and_then(|o: ExecResult| match o {
+                        ExecResult::Stream(status) => {
+                            assert!(status.success());
+                            Ok(Instant::now())
+                        }
+                        r => panic!("wrong result: {:?}", r),
+                    })
+                    .map_err(|e| panic!("error: {}", e))
+                    // lines were queued in collector's mailbox before the result
+                    .and_then(move |finished| {
+                        collector
+                            .send(GetLines)
+                            .map(move |lines| (lines, finished))
+                            .map_err(|e| panic!("error: {}", e))
+                    })
+                    .then(|r: Result<_, ()>| {
+                        let (lines, finished) = r.unwrap();
+                        let stdout: Vec<_> = lines
+                            .iter()
+                            .filter(|(l, _)| l.stream == OutputStream::Stdout)
+                            .collect();
+
+                        assert_eq!(stdout.len(), 2);
+                        assert_eq!(stdout[0].0.line, "one");
+                        assert_eq!(stdout[1].0.line, "two");
+                        assert!(stdout[0].1 + Duration::from_millis(300) < finished);
+                        assert!(lines
+                            .iter()
+                            .any(|(l, _)| l.stream == OutputStream::Stderr && l.line == "err"));
+                        Ok(System::current().stop())
+                    }),
+            )
+        });
+    }
 
     #[test]
     fn test_sync_exec_fail() {
@@ -159,7 +534,8 @@ mod test {
                     .send(Exec::Run {
                         executable: "/bin/ls".into(),
                         args: vec!["/1234567890asdfghjkl".into()],
-                        cwd: "/".into()
+                        cwd: "/".into(),
+                        cpu_affinity: Vec::new(),
                     }).flatten_fut()
                     .and_then(|o: ExecResult| match o {
                         ExecResult::Run(o) => {
@@ -185,6 +561,7 @@ mod test {
                         executable: "/bin/echo".into(),
                         args: vec!["zima".into()],
                         cwd: "/".into(),
+                        cpu_affinity: Vec::new(),
                     })
                     .flatten_fut()
                     .and_then(|o: ExecResult| match o {
@@ -213,6 +590,7 @@ mod test {
                         executable: "/bin/pwd".into(),
                         args: vec![],
                         cwd: "/var/tmp".into(),
+                        cpu_affinity: Vec::new(),
                     })
                     .flatten_fut()
                     .and_then(|o: ExecResult| match o {
@@ -231,4 +609,93 @@ mod test {
             )
         });
     }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_sync_exec_kill_escalation() {
+        // ignored signals are inherited through exec
+        let child = process::Command::new("sh")
+            .args(&["-c", "trap '' TERM; exec sleep 30"])
+            .spawn()
+            .unwrap();
+        thread::sleep(Duration::from_millis(200));
+        let started = Instant::now();
+
+        System::run(move || {
+            Arbiter::spawn(
+                SyncExecManager::from_registry()
+                    .send(Exec::Kill {
+                        child,
+                        timeout: Duration::from_millis(300),
+                    })
+                    .flatten_fut()
+                    .and_then(move |o: ExecResult| match o {
+                        ExecResult::Kill(msg) => {
+                            assert_eq!(msg, "Killed");
+                            assert!(started.elapsed() < Duration::from_secs(2));
+                            Ok(())
+                        }
+                        r => panic!("wrong result: {:?}", r),
+                    })
+                    .map_err(|e| panic!("error: {}", e))
+                    .then(|_| Ok(System::current().stop())),
+            )
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_kill_all_shares_timeout() {
+        let children: Vec<_> = (0..3)
+            .map(|i| {
+                let child = process::Command::new("sh")
+                    .args(&["-c", "trap '' TERM; exec sleep 30"])
+                    .spawn()
+                    .unwrap();
+                (i.to_string(), child)
+            })
+            .collect();
+        let pids: Vec<_> = children.iter().map(|(_, child)| child.id()).collect();
+        thread::sleep(Duration::from_millis(200));
+        let started = Instant::now();
+
+        kill_all(children, Duration::from_millis(500))
+            .wait()
+            .unwrap();
+
+        // killed one after another, they would take three timeouts
+        assert!(started.elapsed() < Duration::from_millis(1200));
+        for pid in pids {
+            assert_ne!(unsafe { libc::kill(pid as libc::pid_t, 0) }, 0);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_cpu_affinity() {
+        assert!(check_cpu_affinity(&[0]).is_ok());
+        assert!(check_cpu_affinity(&[usize::max_value()]).is_err());
+
+        let mut command = process::Command::new("sleep");
+        command.arg("10");
+        set_cpu_affinity(&mut command, &[0]);
+        let mut child = command.spawn().unwrap();
+
+        let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+        let res = unsafe {
+            libc::sched_getaffinity(
+                child.id() as libc::pid_t,
+                std::mem::size_of::<libc::cpu_set_t>(),
+                &mut set,
+            )
+        };
+        let _ = child.kill();
+        let _ = child.wait();
+
+        assert_eq!(res, 0);
+        let pinned: Vec<usize> = (0..libc::CPU_SETSIZE as usize)
+            .filter(|&cpu| unsafe { libc::CPU_ISSET(cpu, &set) })
+            .collect();
+        assert_eq!(pinned, vec![0]);
+    }
 }
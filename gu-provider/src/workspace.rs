@@ -1,6 +1,7 @@
 use gu_model::dockerman::VolumeDef;
 use gu_persist::config::ConfigModule;
-use log::{debug, error};
+use log::{debug, error, warn};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::borrow::Cow;
 use std::collections::{BTreeSet, HashSet};
@@ -9,8 +10,12 @@ use std::fs::DirBuilder;
 use std::io;
 use std::iter::FromIterator;
 use std::path::PathBuf;
+use std::time::Duration;
 use uuid::Uuid;
 
+/// How long logs of finished children are kept, unless configured
+pub const DEFAULT_LOG_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
 pub struct WorkspacesManager {
     namespace: Cow<'static, str>,
     path: PathBuf,
@@ -38,30 +43,79 @@ impl WorkspacesManager {
             .ok()
     }
 
+    #[cfg(test)]
+    pub fn in_dir(name: impl Into<Cow<'static, str>>, path: PathBuf) -> Self {
+        WorkspacesManager {
+            namespace: name.into(),
+            path,
+        }
+    }
+
     pub fn workspace(&self) -> Workspace {
         Workspace::new(
             self.namespace.clone(),
             self.path.join(Uuid::new_v4().to_string()),
         )
     }
+
+    /// Workspaces saved before a restart, with their session ids; ones which
+    /// cannot be read, or were saved without session id, are skipped
+    pub fn saved_workspaces(&self) -> Vec<(String, Workspace)> {
+        let entries = match fs::read_dir(&self.path) {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!("cannot read session dir {:?}: {}", self.path, e);
+                return Vec::new();
+            }
+        };
+
+        entries
+            .filter_map(|entry| {
+                let meta_path = entry.ok()?.path();
+                let path = meta_path.with_extension("");
+                if meta_path.extension().map_or(true, |ext| ext != "json") || !path.is_dir() {
+                    return None;
+                }
+                match Workspace::load(self.namespace.clone(), path) {
+                    Ok(workspace) => Some((workspace.session_id.clone()?, workspace)),
+                    Err(e) => {
+                        warn!("cannot restore session from {:?}: {}", meta_path, e);
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
 }
 
 type Set<K> = BTreeSet<K>;
 
+/// Part of the workspace state saved next to the workspace dir
+#[derive(Serialize, Deserialize)]
+struct WorkspaceMeta {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    session_id: Option<String>,
+    tags: Set<String>,
+    #[serde(default)]
+    metadata: Value,
+}
+
 #[derive(Clone)]
 pub struct Workspace {
     name: Cow<'static, str>,
     path: PathBuf,
+    session_id: Option<String>,
     metadata: Value,
     tags: Set<String>,
     volumes: HashSet<VolumeDef>,
 }
 
 impl Workspace {
-    pub(self) fn new(name: Cow<'static, str>, path: PathBuf) -> Self {
+    pub(crate) fn new(name: Cow<'static, str>, path: PathBuf) -> Self {
         Self {
             name,
             path,
+            session_id: None,
             metadata: Value::Null,
             tags: Set::new(),
             volumes: HashSet::new(),
@@ -72,6 +126,11 @@ impl Workspace {
         &self.name.as_ref()
     }
 
+    /// Id of the session, saved along with tags so that the session can be restored
+    pub fn set_session_id(&mut self, session_id: String) {
+        self.session_id = Some(session_id);
+    }
+
     pub fn tags(&self) -> Vec<String> {
         Vec::from_iter(self.tags.iter().cloned())
     }
@@ -129,12 +188,92 @@ impl Workspace {
 
     pub fn clear_dir(&self) -> io::Result<()> {
         debug!("cleaning session dir {:?}", self.path);
+        match fs::remove_file(self.meta_path()) {
+            Err(ref e) if e.kind() != io::ErrorKind::NotFound => {
+                error!(
+                    "cannot remove session metadata {:?}: {}",
+                    self.meta_path(),
+                    e
+                )
+            }
+            _ => (),
+        }
         fs::remove_dir_all(&self.path)
     }
 
+    fn meta_path(&self) -> PathBuf {
+        self.path.with_extension("json")
+    }
+
+    /// Saves tags and metadata to a file next to the workspace dir.
+    ///
+    /// The file is replaced atomically, so a concurrent reader never sees
+    /// a partially written one.
+    pub fn save(&self) -> io::Result<()> {
+        let meta = WorkspaceMeta {
+            session_id: self.session_id.clone(),
+            tags: self.tags.clone(),
+            metadata: self.metadata.clone(),
+        };
+        let tmp_path = self.path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_vec(&meta)?)?;
+        fs::rename(tmp_path, self.meta_path())
+    }
+
+    /// Reads back workspace at `path` with session id, tags and metadata saved by `save`.
+    pub fn load(name: Cow<'static, str>, path: PathBuf) -> io::Result<Self> {
+        let mut workspace = Self::new(name, path);
+        let meta: WorkspaceMeta = serde_json::from_slice(&fs::read(workspace.meta_path())?)?;
+        workspace.session_id = meta.session_id;
+        workspace.tags = meta.tags;
+        workspace.metadata = meta.metadata;
+        Ok(workspace)
+    }
+
     pub fn path(&self) -> &PathBuf {
         &self.path
     }
+
+    /// Dir with per-child log files, named `<child id>.log`
+    pub fn logs_dir(&self) -> PathBuf {
+        self.path.join("logs")
+    }
+
+    /// Removes logs not modified within `retention`, except ones of children
+    /// for which `running` holds. Returns the number of removed files.
+    pub fn prune_logs(
+        &self,
+        retention: Duration,
+        running: impl Fn(&str) -> bool,
+    ) -> io::Result<usize> {
+        let dir = match fs::read_dir(self.logs_dir()) {
+            Ok(dir) => dir,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+
+        let mut removed = 0;
+        for entry in dir {
+            let path = entry?.path();
+            let child_id = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(child_id) if path.extension().map_or(false, |ext| ext == "log") => child_id,
+                _ => continue,
+            };
+            if running(child_id) {
+                continue;
+            }
+            let age = fs::metadata(&path)?
+                .modified()?
+                .elapsed()
+                .unwrap_or_default();
+            if age >= retention {
+                debug!("pruning log {:?}", path);
+                fs::remove_file(&path)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
 }
 
 #[cfg(test)]
@@ -176,4 +315,27 @@ mod tests {
         work.remove_tags(["tag1".to_string()].to_vec());
         assert_eq!(work.tags(), ["tag2".to_string()].to_vec());
     }
+
+    #[test]
+    fn saved_tags_are_loaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session");
+        let mut work = Workspace::new("work".into(), path.clone());
+        work.create_dirs().unwrap();
+        work.add_tags(vec!["created".to_string()]);
+        work.save().unwrap();
+
+        work.add_tags(vec!["added".to_string()]);
+        work.save().unwrap();
+        drop(work);
+
+        let restored = Workspace::load("work".into(), path.clone()).unwrap();
+        assert_eq!(
+            restored.tags(),
+            vec!["added".to_string(), "created".to_string()]
+        );
+
+        restored.clear_dir().unwrap();
+        assert!(Workspace::load("work".into(), path).is_err());
+    }
 }